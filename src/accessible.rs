@@ -0,0 +1,151 @@
+//! `gentor --accessible`: the chat as plain lines on stdout for screen
+//! readers. No alternate screen, raw mode, box drawing or emoji; every line
+//! starts with who is speaking, so it is announced before the text.
+use anyhow::Result;
+use std::{io::Write, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines, Stdin},
+    sync::{
+        mpsc::{self, UnboundedSender},
+        oneshot,
+    },
+};
+
+use crate::{
+    agent::{self, Turn},
+    settings::Settings,
+    tools::{ToolHost, Toolbox},
+};
+
+enum Event {
+    Tool(String),
+    Confirm(String, oneshot::Sender<bool>),
+}
+
+struct Host(UnboundedSender<Event>);
+
+impl ToolHost for Host {
+    fn notify(&self, line: String) {
+        let _ = self.0.send(Event::Tool(line));
+    }
+
+    fn confirm(&self, question: String) -> oneshot::Receiver<bool> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.0.send(Event::Confirm(question, tx));
+        rx
+    }
+}
+
+/// Reads prompts line by line until end of input or `/quit`.
+pub async fn run(settings: Settings, warning: Option<String>) -> Result<()> {
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    let toolbox = Arc::new(Toolbox::new(settings.web_search.clone(), settings.sandbox.clone(), settings.github.clone()));
+    toolbox.set_local_only(settings.local_only);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut history: Vec<Turn> = Vec::new();
+    if let Some(warning) = warning {
+        say("Warning", &warning);
+    }
+    say("Gentor", "Ready. Type a message and press Enter. Type /new to start a new conversation, /quit to leave.");
+    loop {
+        ask("You: ")?;
+        let Some(line) = lines.next_line().await? else { break };
+        let prompt = line.trim().to_string();
+        match prompt.as_str() {
+            "" => continue,
+            "/quit" | "/exit" => break,
+            "/new" => {
+                history.clear();
+                say("Gentor", "Started a new conversation.");
+                continue;
+            }
+            _ => {}
+        }
+        let dropped = agent::turns_to_drop(&settings, None, &history, &prompt);
+        let (sent, _) = agent::drop_oldest(&history, dropped);
+        if dropped > 0 {
+            say("Gentor", &format!("Left out the {} oldest exchange(s) to fit the context window.", dropped));
+        }
+        say("Gentor", "Thinking.");
+
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let mut task = tokio::spawn({
+            let (client, settings, toolbox, prompt) = (client.clone(), settings.clone(), toolbox.clone(), prompt.clone());
+            async move { agent::run_with_fallbacks(&client, &settings, &toolbox, None, &sent, &prompt, &Host(tx)).await }
+        });
+        let result = loop {
+            tokio::select! {
+                result = &mut task => break result?,
+                Some(event) = events.recv() => match event {
+                    Event::Tool(line) => say("Tool", &line),
+                    Event::Confirm(question, reply) => {
+                        say("Question", &format!("{} Type yes or no.", plain(&question)));
+                        let _ = reply.send(answer(&mut lines).await?);
+                    }
+                },
+            }
+        };
+        match result {
+            Ok(reply) => {
+                if reply.model != settings.model {
+                    say("Gentor", &format!("Answered by fallback model {}.", reply.model));
+                }
+                say("Assistant", &speakable(&reply.content));
+                let speed = reply.speed();
+                history.push(Turn { user: prompt, assistant: reply.content, pinned: false, speed });
+            }
+            Err(e) => say("Error", &e.to_string()),
+        }
+    }
+    say("Gentor", "Goodbye.");
+    Ok(())
+}
+
+/// Prints a message with its role announced first.
+fn say(role: &str, text: &str) {
+    println!("{}: {}", role, plain(text));
+}
+
+fn ask(prompt: &str) -> Result<()> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+/// Reads a yes or no; anything else, or the end of input, is no.
+async fn answer(lines: &mut Lines<BufReader<Stdin>>) -> Result<bool> {
+    ask("Answer: ")?;
+    let line = lines.next_line().await?.unwrap_or_default();
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Drops the leading emoji the transcript lines carry, which screen readers
+/// spell out by name.
+fn plain(text: &str) -> &str {
+    text.trim_start_matches(|c: char| c.is_whitespace() || (!c.is_ascii() && !c.is_alphanumeric()))
+}
+
+/// Replaces code fences with spoken markers, so the start and end of a code
+/// block are announced instead of read as three backticks.
+fn speakable(text: &str) -> String {
+    let mut in_code = false;
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| match line.trim_start().strip_prefix("```") {
+            Some(_) if in_code => {
+                in_code = false;
+                "End of code.".to_string()
+            }
+            Some(info) => {
+                in_code = true;
+                match info.trim() {
+                    "" => "Code:".to_string(),
+                    lang => format!("Code, {}:", lang),
+                }
+            }
+            None => line.to_string(),
+        })
+        .collect();
+    lines.join("\n")
+}
@@ -0,0 +1,1009 @@
+//! Requests to the provider: the context window budget, the tool loop,
+//! streaming, fallbacks and one-shot completions.
+use anyhow::{anyhow, bail, Result};
+use async_openai::{
+    config::OpenAIConfig,
+    error::OpenAIError,
+    types::{
+        ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, CompletionUsage, ChatCompletionRequestSystemMessage,
+        ChatCompletionRequestSystemMessageContent, ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
+        ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent, ChatCompletionTool, CreateChatCompletionRequest,
+        CreateChatCompletionRequestArgs, ReasoningEffort, ResponseFormat, ResponseFormatJsonSchema, Stop,
+    },
+    Client,
+};
+use futures::{stream::BoxStream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    local,
+    middleware::{self, Exchange},
+    mock, models, project,
+    settings::Settings,
+    tokens,
+    tools::{ToolHost, Toolbox},
+};
+
+/// Upper bound on model/tool round trips for a single prompt.
+const MAX_TOOL_ROUNDS: usize = 8;
+const SYSTEM_PROMPT: &str = "You are Gentor, an expert coding assistant. Help with programming tasks, code generation, debugging, and explanations. Be concise and helpful.";
+const SUMMARY_PROMPT: &str = "Summarize the earlier part of this conversation between a user and a coding assistant into a compact note for the assistant's future reference. Keep every decision, constraint, requirement, file name and open question; drop pleasantries and code that was only shown, not agreed on. Use terse bullet points. If a previous summary is given, fold it in.";
+const TITLE_PROMPT: &str = "Write a 5 to 8 word title for the conversation below. Reply with the title only, no quotes or trailing punctuation.";
+/// Least time between progress reports of a streamed reply.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Per provider, the index into [`Settings::keys`] of the key in use, moved
+/// on by [`run_with_keys`] when one is rate limited or out of quota.
+static KEY_IN_USE: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+/// Room kept for the reply when `max_tokens` is unset.
+const DEFAULT_REPLY_TOKENS: usize = 4096;
+/// Model families that reason before answering and reject sampling parameters.
+const REASONING_MODELS: &[&str] = &["o1", "o3", "o4", "gpt-5"];
+
+/// A model to try when the one before it in the chain fails; provider,
+/// base URL and key default to the primary's.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FallbackConfig {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// A named workflow from the config, switched to with `/persona`: its
+/// own system prompt, default model and temperature.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Persona {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+/// A final answer and whatever reasoning the model exposed on the way to it.
+pub struct Reply {
+    /// The provider and model that answered, which differ from the configured ones after a fallback.
+    pub provider: String,
+    pub model: String,
+    pub content: String,
+    pub reasoning: Option<String>,
+    pub usage: Usage,
+    pub timing: Timing,
+    /// The last characters of the key used, when the provider has several.
+    pub key: Option<String>,
+    /// Every round was answered from the response cache.
+    pub cached: bool,
+    /// Each round's request and raw response while `/debug` is on.
+    pub raw: Vec<Value>,
+}
+
+pub struct Timing {
+    /// Whole request, tool calls included.
+    pub total: Duration,
+    /// Until the first token arrived, or the first round came back when the
+    /// reply was not streamed.
+    pub first_token: Duration,
+    /// Spent waiting on the model, summed over rounds.
+    pub generation: Duration,
+    /// Spent receiving tokens, from each round's first token to its last.
+    pub streaming: Duration,
+}
+
+/// How fast a reply came, kept with its turn.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Speed {
+    pub ttft_ms: u64,
+    pub tokens_per_sec: f64,
+}
+
+/// A streamed reply so far, reported while it arrives.
+#[derive(Clone, Copy)]
+pub struct Progress {
+    /// From sending the request to the first token.
+    pub first_token: Duration,
+    /// Output tokens received, counted from their text.
+    pub tokens: usize,
+    /// Since the first token.
+    pub streaming: Duration,
+}
+
+impl Progress {
+    pub fn tokens_per_sec(&self) -> f64 {
+        let secs = self.streaming.as_secs_f64();
+        if secs > 0.0 { self.tokens as f64 / secs } else { 0.0 }
+    }
+}
+
+impl Reply {
+    /// Completion tokens per second while the model was producing them:
+    /// streaming time when the reply was streamed, model time otherwise.
+    pub fn tokens_per_sec(&self) -> f64 {
+        let secs = if self.timing.streaming > Duration::ZERO { self.timing.streaming } else { self.timing.generation }.as_secs_f64();
+        if secs > 0.0 { self.usage.completion as f64 / secs } else { 0.0 }
+    }
+
+    /// `None` for a reply from the response cache, which says nothing about the provider.
+    pub fn speed(&self) -> Option<Speed> {
+        (!self.cached).then(|| Speed { ttft_ms: self.timing.first_token.as_millis() as u64, tokens_per_sec: self.tokens_per_sec() })
+    }
+}
+
+/// Tokens billed for a reply, summed over its tool rounds.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt: u32,
+    pub completion: u32,
+    /// Part of `completion` spent on hidden reasoning.
+    pub reasoning: u32,
+    /// Part of `prompt` read from the provider's prompt cache.
+    pub cached: u32,
+}
+
+impl Usage {
+    fn add(&mut self, raw: &RawUsage) {
+        let usage = &raw.usage;
+        self.prompt += usage.prompt_tokens;
+        self.completion += usage.completion_tokens;
+        self.reasoning += usage.completion_tokens_details.as_ref().and_then(|d| d.reasoning_tokens).unwrap_or(0);
+        self.cached += usage
+            .prompt_tokens_details
+            .as_ref()
+            .and_then(|d| d.cached_tokens)
+            .or(raw.cache_read_input_tokens)
+            .unwrap_or(0);
+    }
+}
+
+#[derive(Deserialize)]
+struct RawResponse {
+    choices: Vec<RawChoice>,
+    usage: Option<RawUsage>,
+}
+
+#[derive(Deserialize)]
+struct RawUsage {
+    #[serde(flatten)]
+    usage: CompletionUsage,
+    /// Anthropic's name for prompt cache hits, passed through by some proxies.
+    cache_read_input_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawChoice {
+    message: RawMessage,
+}
+
+/// An assistant message as returned by OpenAI-compatible providers, which
+/// disagree on where reasoning traces go.
+#[derive(Deserialize)]
+struct RawMessage {
+    content: Option<String>,
+    tool_calls: Option<Vec<ChatCompletionMessageToolCall>>,
+    /// DeepSeek, vLLM
+    reasoning_content: Option<String>,
+    /// OpenRouter, Ollama
+    reasoning: Option<String>,
+    thinking: Option<String>,
+    /// Anthropic models behind LiteLLM
+    #[serde(default)]
+    thinking_blocks: Vec<ThinkingBlock>,
+}
+
+#[derive(Deserialize)]
+struct ThinkingBlock {
+    #[serde(default)]
+    thinking: String,
+}
+
+impl RawMessage {
+    /// Splits the message into its answer and reasoning, including
+    /// reasoning inlined as a leading `<think>` block.
+    fn split(&mut self) -> (String, Option<String>) {
+        let mut content = self.content.take().unwrap_or_default();
+        let mut parts: Vec<String> = [self.reasoning_content.take(), self.reasoning.take(), self.thinking.take()]
+            .into_iter()
+            .flatten()
+            .chain(self.thinking_blocks.drain(..).map(|b| b.thinking))
+            .filter(|r| !r.trim().is_empty())
+            .collect();
+        parts.dedup();
+        if let Some(rest) = content.trim_start().strip_prefix("<think>") {
+            if let Some((think, answer)) = rest.split_once("</think>") {
+                parts.push(think.trim().to_string());
+                content = answer.trim_start().to_string();
+            }
+        }
+        let reasoning = Some(parts.join("\n\n")).filter(|r| !r.is_empty());
+        (content, reasoning)
+    }
+}
+
+/// One completed exchange, replayed as context for later prompts.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub user: String,
+    pub assistant: String,
+    /// Kept when older turns are trimmed to fit the context window.
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+}
+
+impl Turn {
+    fn tokens(&self, model: &str) -> usize {
+        tokens::count(model, &self.user) + tokens::count(model, &self.assistant) + 2 * tokens::MESSAGE_OVERHEAD
+    }
+}
+
+/// The active persona's system prompt, or Gentor's own, followed by the
+/// project's additions from `.gentor.toml`.
+pub fn system_prompt(settings: &Settings) -> String {
+    let base = settings.system_prompt.as_deref().unwrap_or(SYSTEM_PROMPT);
+    match &project::current().system_prompt {
+        Some(extra) if !extra.trim().is_empty() => format!("{}\n\n{}", base, extra.trim()),
+        _ => base.to_string(),
+    }
+}
+
+/// The configured context window, or the one known for the model.
+pub fn context_window(settings: &Settings) -> usize {
+    settings.context_window.map_or_else(|| models::lookup(settings, &settings.model).context_window, |w| w as usize)
+}
+
+/// Prompt tokens for a request carrying the system prompt, any `summary`,
+/// `history` and `prompt`.
+pub fn prompt_tokens(settings: &Settings, summary: Option<&str>, history: &[Turn], prompt: &str) -> usize {
+    let model = &settings.model;
+    let summary = summary.map_or(0, |s| tokens::count(model, s) + tokens::MESSAGE_OVERHEAD);
+    let history: usize = history.iter().map(|turn| turn.tokens(model)).sum();
+    tokens::count(model, &system_prompt(settings)) + summary + history + tokens::count(model, prompt) + 2 * tokens::MESSAGE_OVERHEAD
+}
+
+/// Room kept free for the answer: `max_tokens`, or a quarter of the window
+/// up to a default.
+pub fn reply_tokens(settings: &Settings) -> usize {
+    settings.max_tokens.map_or(DEFAULT_REPLY_TOKENS.min(context_window(settings) / 4), |t| t as usize)
+}
+
+/// How many of the oldest unpinned `history` turns must be dropped so that
+/// the rest, the system prompt, any `summary`, `prompt` and room for the reply
+/// fit the model's context window. Counts come from [`tokens::count`], which
+/// only has the tokenizer of OpenAI models, so a request near the limit to
+/// another model may still overflow it.
+pub fn turns_to_drop(settings: &Settings, summary: Option<&str>, history: &[Turn], prompt: &str) -> usize {
+    let window = context_window(settings);
+    let mut total = prompt_tokens(settings, summary, history, prompt) + reply_tokens(settings);
+    let mut drop = 0;
+    for turn in history.iter().filter(|t| !t.pinned) {
+        if total <= window {
+            break;
+        }
+        total -= turn.tokens(&settings.model);
+        drop += 1;
+    }
+    drop
+}
+
+/// `history` for a request, without its `count` oldest unpinned turns,
+/// and those turns. The conversation itself keeps every turn.
+pub fn drop_oldest(history: &[Turn], count: usize) -> (Vec<Turn>, Vec<Turn>) {
+    let (mut kept, mut dropped) = (Vec::new(), Vec::new());
+    for turn in history {
+        if dropped.len() < count && !turn.pinned {
+            dropped.push(turn.clone());
+        } else {
+            kept.push(turn.clone());
+        }
+    }
+    (kept, dropped)
+}
+
+/// In local-only mode, refuses a base URL that is not on this machine.
+fn guard(settings: &Settings) -> Result<()> {
+    if settings.local_only && !mock::is_mock(settings) {
+        local::ensure(&settings.base_url)?;
+    }
+    Ok(())
+}
+
+/// A client for the provider in `settings`, with the key in use and sending
+/// its organization, project and extra headers with every request.
+pub fn client_for(settings: &Settings) -> Client<OpenAIConfig> {
+    let mut config = OpenAIConfig::new()
+        .with_api_key(key_in_use(settings))
+        .with_api_base(settings.base_url.clone());
+    if let Some(organization) = &settings.organization {
+        config = config.with_org_id(organization);
+    }
+    if let Some(project) = &settings.project {
+        config = config.with_project_id(project);
+    }
+    let headers: HeaderMap = settings.extra_headers.iter().filter_map(|(name, value)| header(name, value)).collect();
+    let http = reqwest::Client::builder().default_headers(headers).build().unwrap_or_default();
+    Client::with_config(config).with_http_client(http)
+}
+
+/// The key requests to the provider go out with: `api_key` until another
+/// was moved on to.
+fn key_in_use(settings: &Settings) -> String {
+    let keys = settings.keys();
+    let index = KEY_IN_USE.lock().unwrap().get(&settings.provider).copied().unwrap_or(0);
+    keys.get(index).map_or_else(|| settings.api_key.clone(), |key| key.to_string())
+}
+
+/// The key in use as its last characters, to tell keys apart in messages
+/// and stats without showing them.
+pub(crate) fn key_label(settings: &Settings) -> String {
+    let key = key_in_use(settings);
+    format!("…{}", &key[key.char_indices().rev().nth(3).map_or(0, |(i, _)| i)..])
+}
+
+/// Moves the provider on to its next key, back to the first after the last.
+fn rotate_key(settings: &Settings) {
+    let count = settings.keys().len().max(1);
+    let mut in_use = KEY_IN_USE.lock().unwrap();
+    let index = in_use.entry(settings.provider.clone()).or_insert(0);
+    *index = (*index + 1) % count;
+}
+
+/// `name: value` as an HTTP header, if it is a valid one.
+pub fn header(name: &str, value: &str) -> Option<(HeaderName, HeaderValue)> {
+    Some((HeaderName::from_bytes(name.as_bytes()).ok()?, HeaderValue::from_str(value).ok()?))
+}
+
+/// Lists models as the cheapest authenticated round trip to the provider.
+pub async fn test_connection(client: &Client<OpenAIConfig>, settings: &Settings) -> Result<Duration> {
+    guard(settings)?;
+    let start = Instant::now();
+    if !mock::is_mock(settings) {
+        client.models().list().await?;
+    }
+    Ok(start.elapsed())
+}
+
+pub async fn list_models(client: &Client<OpenAIConfig>, settings: &Settings) -> Result<Vec<String>> {
+    guard(settings)?;
+    if mock::is_mock(settings) {
+        return Ok(vec![mock::MODEL.to_string()]);
+    }
+    let mut models: Vec<String> = client.models().list().await?.data.into_iter().map(|m| m.id).collect();
+    models.sort();
+    Ok(models)
+}
+
+/// Condenses `turns`, together with any `previous` summary, into a note that
+/// stands in for them in later requests.
+pub async fn summarize(client: &Client<OpenAIConfig>, settings: &Settings, previous: Option<&str>, turns: &[Turn]) -> Result<String> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Previous summary:\n{}\n\n", previous));
+    }
+    for turn in turns {
+        transcript.push_str(&format!("User: {}\n\nAssistant: {}\n\n", turn.user, turn.assistant));
+    }
+    let settings = Settings { response_format: None, stop: Vec::new(), ..settings.clone() };
+    let summary = complete(client, &settings, SUMMARY_PROMPT, &transcript).await?;
+    Ok(summary.trim().to_string())
+}
+
+/// Short title describing a conversation that starts with `turn`.
+pub async fn title(client: &Client<OpenAIConfig>, settings: &Settings, turn: &Turn) -> Result<String> {
+    let transcript = format!("User: {}\n\nAssistant: {}", turn.user, turn.assistant);
+    let settings = Settings { response_format: None, stop: Vec::new(), max_tokens: None, ..settings.clone() };
+    let title = complete(client, &settings, TITLE_PROMPT, &transcript).await?;
+    let title = title.lines().next().unwrap_or_default().trim().trim_matches(['"', '\'', '.', '*', '#', ' ']);
+    if title.is_empty() {
+        bail!("the model returned an empty title");
+    }
+    Ok(title.to_string())
+}
+
+/// Answers `prompt` following the earlier `history` (and the `summary` of
+/// anything older), letting the model call tools from `toolbox` until it
+/// produces a final message. `host` is told about every call made.
+pub async fn run_agent(
+    client: &Client<OpenAIConfig>,
+    settings: &Settings,
+    toolbox: &Toolbox,
+    summary: Option<&str>,
+    history: &[Turn],
+    prompt: &str,
+    host: &dyn ToolHost,
+) -> Result<Reply> {
+    let format = response_format(settings)?;
+    let mut messages = vec![system_message(&system_prompt(settings), format.is_some())];
+    if let Some(summary) = summary {
+        messages.push(system_message(&format!("Summary of the earlier conversation:\n{}", summary), false));
+    }
+    for turn in history {
+        messages.push(user_message(&turn.user));
+        messages.push(assistant_message(&turn.assistant)?);
+    }
+    messages.push(user_message(prompt));
+    let tools = if models::lookup(settings, &settings.model).tools { toolbox.specs() } else { Vec::new() };
+    let mut reasoning = Vec::new();
+    let mut usage = Usage::default();
+    let start = Instant::now();
+    let mut first_token = None;
+    let mut generation = Duration::ZERO;
+    let mut streaming = Duration::ZERO;
+    let mut cached = true;
+    let mut raw = Vec::new();
+
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let req = build_request(settings, messages.clone(), tools.clone(), format.clone())?;
+        let round = Instant::now();
+        let Round { mut message, usage: round_usage, cached: round_cached, stream, record } = send(client, settings, req, Some(host)).await?;
+        cached &= round_cached;
+        generation += round.elapsed();
+        raw.extend(record);
+        match stream {
+            Some((first, received)) => {
+                first_token.get_or_insert(round.duration_since(start) + first);
+                streaming += received;
+            }
+            None => {
+                first_token.get_or_insert_with(|| start.elapsed());
+            }
+        }
+        if let Some(round_usage) = &round_usage {
+            usage.add(round_usage);
+        }
+        let calls = message.tool_calls.take().unwrap_or_default();
+        let (content, thought) = message.split();
+        reasoning.extend(thought);
+        if calls.is_empty() {
+            let content = match format {
+                Some(ResponseFormat::JsonSchema { json_schema }) => format_json(&content, json_schema.schema.as_ref())?,
+                Some(_) => format_json(&content, None)?,
+                None => content,
+            };
+            let reasoning = Some(reasoning.join("\n\n")).filter(|r| !r.is_empty());
+            let timing = Timing { total: start.elapsed(), first_token: first_token.unwrap_or_default(), generation, streaming };
+            let key = Some(key_label(settings)).filter(|_| settings.keys().len() > 1);
+            return Ok(Reply { provider: settings.provider.clone(), model: settings.model.clone(), content, reasoning, usage, timing, key, cached, raw });
+        }
+
+        let mut assistant = ChatCompletionRequestAssistantMessageArgs::default();
+        assistant.tool_calls(calls.clone());
+        if !content.is_empty() {
+            assistant.content(content);
+        }
+        messages.push(assistant.build()?.into());
+        for call in calls {
+            host.notify(format!("🔧 {}({})", call.function.name, call.function.arguments));
+            let output = match toolbox.call(&call.function.name, &call.function.arguments, host).await {
+                Ok(output) => output,
+                Err(e) => format!("error: {}", e),
+            };
+            messages.push(ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                content: ChatCompletionRequestToolMessageContent::Text(output),
+                tool_call_id: call.id,
+            }));
+        }
+    }
+    bail!("gave up after {} tool rounds", MAX_TOOL_ROUNDS)
+}
+
+/// Like [`run_agent`], but moves on to each of `settings.fallbacks` in turn
+/// when a model fails in a way retrying it will not fix.
+pub async fn run_with_fallbacks(
+    client: &Client<OpenAIConfig>,
+    settings: &Settings,
+    toolbox: &Toolbox,
+    summary: Option<&str>,
+    history: &[Turn],
+    prompt: &str,
+    host: &dyn ToolHost,
+) -> Result<Reply> {
+    let mut result = run_with_keys(client, settings, toolbox, summary, history, prompt, host).await;
+    let mut failed = settings.model.clone();
+    for fallback in &settings.fallbacks {
+        match &result {
+            Err(e) if should_fall_back(e) => {}
+            _ => break,
+        }
+        host.notify(format!("↪️ {} failed, trying {}", failed, fallback.model));
+        // another provider's account and gateway get its own entry's, not these
+        let other = fallback.provider.as_ref().filter(|provider| **provider != settings.provider).map(|provider| settings.providers.get(provider).cloned().unwrap_or_default());
+        let settings = Settings {
+            model: fallback.model.clone(),
+            provider: fallback.provider.clone().unwrap_or_else(|| settings.provider.clone()),
+            base_url: fallback.base_url.clone().unwrap_or_else(|| settings.base_url.clone()),
+            api_key: fallback.api_key.clone().unwrap_or_else(|| settings.api_key.clone()),
+            // a key of its own replaces the list too
+            api_keys: match (&fallback.api_key, &other) {
+                (Some(_), _) => Vec::new(),
+                (None, Some(other)) => other.api_keys.clone(),
+                (None, None) => settings.api_keys.clone(),
+            },
+            organization: other.as_ref().map_or_else(|| settings.organization.clone(), |other| other.organization.clone()),
+            project: other.as_ref().map_or_else(|| settings.project.clone(), |other| other.project.clone()),
+            extra_headers: other.as_ref().map_or_else(|| settings.extra_headers.clone(), |other| other.extra_headers.clone()),
+            ..settings.clone()
+        };
+        result = run_with_keys(&client_for(&settings), &settings, toolbox, summary, history, prompt, host).await;
+        failed = fallback.model.clone();
+    }
+    result
+}
+
+/// Like [`run_agent`], but moves on to the provider's next key when one is
+/// rate limited or out of quota, until each was tried once. Later requests
+/// start from the key moved on to.
+async fn run_with_keys(
+    client: &Client<OpenAIConfig>,
+    settings: &Settings,
+    toolbox: &Toolbox,
+    summary: Option<&str>,
+    history: &[Turn],
+    prompt: &str,
+    host: &dyn ToolHost,
+) -> Result<Reply> {
+    let keys = settings.keys().len();
+    if keys < 2 {
+        return run_agent(client, settings, toolbox, summary, history, prompt, host).await;
+    }
+    // `client` may have been made before an earlier request moved on
+    let mut result = run_agent(&client_for(settings), settings, toolbox, summary, history, prompt, host).await;
+    for _ in 1..keys {
+        match &result {
+            Err(e) if is_rate_limited(e) => {}
+            _ => break,
+        }
+        let limited = key_label(settings);
+        rotate_key(settings);
+        host.notify(format!("🔑 Key {} is rate limited, trying {}", limited, key_label(settings)));
+        result = run_agent(&client_for(settings), settings, toolbox, summary, history, prompt, host).await;
+    }
+    result
+}
+
+/// Rate limits and used-up quota, which another key of the same provider
+/// may not hit.
+fn is_rate_limited(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<OpenAIError>() {
+        Some(OpenAIError::Reqwest(e)) => e.status().is_some_and(|s| s == reqwest::StatusCode::TOO_MANY_REQUESTS),
+        Some(OpenAIError::ApiError(api)) => {
+            let message = api.message.to_ascii_lowercase();
+            [api.code.as_deref(), api.r#type.as_deref()]
+                .into_iter()
+                .flatten()
+                .any(|kind| matches!(kind, "rate_limit_exceeded" | "rate_limit_error" | "insufficient_quota"))
+                || ["rate limit", "quota"].iter().any(|needle| message.contains(needle))
+        }
+        _ => false,
+    }
+}
+
+/// Errors another model may not hit: unknown model, prompt too long for its
+/// context window, or the provider being down, overloaded or unreachable.
+/// Rate limits are left to the provider's other keys and any other error,
+/// such as a bad request, to the user.
+fn should_fall_back(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<OpenAIError>() {
+        Some(OpenAIError::Reqwest(e)) => e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| s.is_server_error()),
+        Some(OpenAIError::ApiError(api)) => {
+            // server errors come back untyped, with the response body as the message
+            let message = api.message.to_ascii_lowercase();
+            [api.code.as_deref(), api.r#type.as_deref()]
+                .into_iter()
+                .flatten()
+                .any(|kind| matches!(kind, "model_not_found" | "context_length_exceeded" | "server_error" | "overloaded_error"))
+                || (message.contains("model") && ["not found", "does not exist"].iter().any(|needle| message.contains(needle)))
+                || ["context length", "context window", "prompt is too long", "overloaded", "internal server error", "bad gateway", "unavailable", "gateway timeout"]
+                    .iter()
+                    .any(|needle| message.contains(needle))
+        }
+        // a stream refused before its first event only says `Invalid status code: 503 Service Unavailable`
+        Some(OpenAIError::StreamError(message)) => message
+            .split_once("status code: ")
+            .and_then(|(_, status)| status.get(..3)?.parse::<u16>().ok())
+            .is_some_and(|status| (500..600).contains(&status)),
+        _ => false,
+    }
+}
+
+/// A model round as returned by [`send`].
+struct Round {
+    message: RawMessage,
+    usage: Option<RawUsage>,
+    cached: bool,
+    /// Time to the first token and spent receiving tokens, when streamed.
+    stream: Option<(Duration, Duration)>,
+    /// The exchange as written to the debug log, while `/debug` is on.
+    record: Option<Value>,
+}
+
+/// Sends `req` through the [`middleware`] pipeline and returns the first
+/// choice's message, with its reasoning fields intact, and the usage
+/// reported for it. A request the middleware answered, such as one the
+/// response cache had, has no usage. With a `host` the reply is streamed and
+/// its progress reported.
+async fn send(client: &Client<OpenAIConfig>, settings: &Settings, req: CreateChatCompletionRequest, host: Option<&dyn ToolHost>) -> Result<Round> {
+    guard(settings)?;
+    let mut req = serde_json::to_value(&req)?;
+    if uses_cache_control(settings) {
+        mark_cache_breakpoints(&mut req);
+    }
+    if host.is_some() {
+        req["stream"] = json!(true);
+        req["stream_options"] = json!({ "include_usage": true });
+    }
+    let hooks = middleware::pipeline(settings);
+    let mut exchange = Exchange::new(settings, req, host.is_some());
+    middleware::before(&hooks, &mut exchange).await?;
+    let (raw, cached, stream) = match exchange.response.take() {
+        Some(raw) => {
+            middleware::after(&hooks, &mut exchange, Ok(&raw)).await;
+            (raw, true, None)
+        }
+        None => {
+            let req = exchange.request.clone();
+            let result = match host {
+                Some(host) => receive(client, settings, req, host, exchange.chunks.as_mut()).await.map(|(raw, first, received)| (raw, first.map(|first| (first, received)))),
+                None if mock::is_mock(settings) => mock::response(settings, &req).await.map(|raw| (raw, None)),
+                None => client.chat().create_byot(req).await.map(|raw| (raw, None)).map_err(Into::into),
+            };
+            if let Ok((_, Some((first, received)))) = &result {
+                exchange.first_token = Some(*first);
+                exchange.streaming = *received;
+            }
+            middleware::after(&hooks, &mut exchange, result.as_ref().map(|(raw, _)| raw)).await;
+            let (raw, stream) = result?;
+            (raw, false, stream)
+        }
+    };
+    let record = exchange.record;
+    let res: RawResponse = serde_json::from_value(raw)?;
+    let message = res.choices.into_iter().next().ok_or_else(|| anyhow!("the provider returned no choices"))?.message;
+    Ok(Round { message, usage: res.usage.filter(|_| !cached), cached, stream, record })
+}
+
+/// Streams `req` and puts the chunks back together in the shape of a
+/// response that was not streamed, reporting progress to `host` on the way
+/// and keeping the chunks in `raw` when given. Also returns the time to the
+/// first token, if one came, and the time spent receiving tokens after it.
+async fn receive(
+    client: &Client<OpenAIConfig>,
+    settings: &Settings,
+    req: Value,
+    host: &dyn ToolHost,
+    mut raw: Option<&mut Vec<Value>>,
+) -> Result<(Value, Option<Duration>, Duration)> {
+    let sent = Instant::now();
+    let mut chunks: BoxStream<'static, Result<Value, OpenAIError>> =
+        if mock::is_mock(settings) { mock::stream(settings, &req)? } else { client.chat().create_stream_byot(req).await? };
+    let mut message = Map::new();
+    let mut calls: Vec<Value> = Vec::new();
+    let mut usage = Value::Null;
+    let mut received = String::new();
+    let mut first: Option<Instant> = None;
+    let mut reported = sent;
+    let mut finished = false;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            // some servers close the connection without `[DONE]`
+            Err(OpenAIError::StreamError(_)) if finished => break,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(raw) = raw.as_deref_mut() {
+            raw.push(chunk.clone());
+        }
+        if chunk["usage"].is_object() {
+            usage = chunk["usage"].clone();
+        }
+        let choice = &chunk["choices"][0];
+        finished |= !choice["finish_reason"].is_null();
+        let Some(delta) = choice["delta"].as_object() else { continue };
+        for (field, value) in delta {
+            match (field.as_str(), value) {
+                ("tool_calls", Value::Array(parts)) => {
+                    for part in parts {
+                        let index = part["index"].as_u64().unwrap_or(0) as usize;
+                        if calls.len() <= index {
+                            calls.resize_with(index + 1, || json!({ "id": "", "type": "function", "function": { "name": "", "arguments": "" } }));
+                        }
+                        let call = &mut calls[index];
+                        for (path, text) in [("/id", &part["id"]), ("/function/name", &part["function"]["name"]), ("/function/arguments", &part["function"]["arguments"])] {
+                            if let (Some(Value::String(into)), Some(text)) = (call.pointer_mut(path), text.as_str()) {
+                                into.push_str(text);
+                                received.push_str(text);
+                            }
+                        }
+                    }
+                }
+                ("role", _) => {}
+                (_, Value::String(text)) => {
+                    match message.entry(field.clone()).or_insert_with(|| json!("")) {
+                        Value::String(into) => into.push_str(text),
+                        other => *other = json!(text),
+                    }
+                    received.push_str(text);
+                }
+                _ => {}
+            }
+        }
+        if received.is_empty() {
+            continue;
+        }
+        let first = *first.get_or_insert_with(Instant::now);
+        if reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            host.progress(Progress { first_token: first - sent, tokens: tokens::count(&settings.model, &received), streaming: first.elapsed() });
+        }
+    }
+    if !calls.is_empty() {
+        message.insert("tool_calls".to_string(), Value::Array(calls));
+    }
+    let response = json!({ "choices": [{ "message": message }], "usage": usage });
+    Ok((response, first.map(|first| first - sent), first.map_or(Duration::ZERO, |first| first.elapsed())))
+}
+
+/// Anthropic models cache only what is marked with `cache_control`; OpenAI
+/// caches long stable prefixes on its own.
+fn uses_cache_control(settings: &Settings) -> bool {
+    settings.provider.eq_ignore_ascii_case("anthropic") || settings.model.to_ascii_lowercase().contains("claude")
+}
+
+/// Marks the stable prefix of the conversation for Anthropic prompt caching:
+/// the system messages (prompt and summary) and the last message before the
+/// new prompt, which covers the history and the files attached in it.
+/// Anthropic allows at most four breakpoints.
+fn mark_cache_breakpoints(req: &mut Value) {
+    const MAX_BREAKPOINTS: usize = 4;
+    let Some(messages) = req.get_mut("messages").and_then(Value::as_array_mut) else { return };
+    let mut marks: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m["role"] == "system")
+        .map(|(i, _)| i)
+        .take(MAX_BREAKPOINTS - 1)
+        .collect();
+    if messages.len() >= 2 && !marks.contains(&(messages.len() - 2)) {
+        marks.push(messages.len() - 2);
+    }
+    for i in marks {
+        let content = &mut messages[i]["content"];
+        if let Some(text) = content.as_str() {
+            *content = json!([{ "type": "text", "text": text, "cache_control": { "type": "ephemeral" } }]);
+        } else if let Some(last) = content.as_array_mut().and_then(|parts| parts.last_mut()) {
+            last["cache_control"] = json!({ "type": "ephemeral" });
+        }
+    }
+}
+
+/// Whether `model` is an OpenAI reasoning model, with or without a `provider/` prefix.
+pub fn is_reasoning_model(model: &str) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model).to_ascii_lowercase();
+    REASONING_MODELS
+        .iter()
+        .any(|family| name.strip_prefix(family).is_some_and(|rest| rest.is_empty() || rest.starts_with('-')))
+}
+
+pub fn parse_reasoning_effort(effort: &str) -> Result<ReasoningEffort> {
+    match effort.trim().to_ascii_lowercase().as_str() {
+        "minimal" => Ok(ReasoningEffort::Minimal),
+        "low" => Ok(ReasoningEffort::Low),
+        "medium" => Ok(ReasoningEffort::Medium),
+        "high" => Ok(ReasoningEffort::High),
+        other => bail!("reasoning effort must be minimal, low, medium or high, got '{}'", other),
+    }
+}
+
+fn response_format(settings: &Settings) -> Result<Option<ResponseFormat>> {
+    let Some(format) = settings.response_format.as_deref() else {
+        return Ok(None);
+    };
+    if format == "json_object" {
+        return Ok(Some(ResponseFormat::JsonObject));
+    }
+    let Some(path) = format.strip_prefix("json_schema:") else {
+        bail!("unknown response format '{}'", format);
+    };
+    let schema: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| anyhow!("invalid JSON schema in {}: {}", path, e))?;
+    let name = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "response".to_string());
+    Ok(Some(ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema { description: None, name, schema: Some(schema), strict: Some(true) },
+    }))
+}
+
+/// Checks a structured response parses as JSON and carries the schema's
+/// top-level required properties, then pretty-prints it as a fenced block.
+fn format_json(response: &str, schema: Option<&Value>) -> Result<String> {
+    let value: Value = serde_json::from_str(response.trim())
+        .map_err(|e| anyhow!("response is not valid JSON ({}):\n{}", e, response))?;
+    let required = schema.and_then(|s| s.get("required")).and_then(Value::as_array);
+    for key in required.into_iter().flatten().filter_map(Value::as_str) {
+        if value.get(key).is_none() {
+            bail!("response is missing required property '{}':\n{}", key, response);
+        }
+    }
+    Ok(format!("```json\n{}\n```", serde_json::to_string_pretty(&value)?))
+}
+
+fn system_message(system: &str, json: bool) -> ChatCompletionRequestMessage {
+    // JSON mode requires the word "JSON" to appear in the conversation
+    let system = if json { format!("{} Respond with a single JSON value only.", system) } else { system.to_string() };
+    ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+        content: ChatCompletionRequestSystemMessageContent::Text(system),
+        name: None,
+    })
+}
+
+fn user_message(prompt: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(prompt.to_string()),
+        name: None,
+    })
+}
+
+fn assistant_message(content: &str) -> Result<ChatCompletionRequestMessage> {
+    Ok(ChatCompletionRequestAssistantMessageArgs::default().content(content).build()?.into())
+}
+
+/// Builds a request for `settings.model` with the configured sampling parameters.
+fn build_request(
+    settings: &Settings,
+    messages: Vec<ChatCompletionRequestMessage>,
+    tools: Vec<ChatCompletionTool>,
+    format: Option<ResponseFormat>,
+) -> Result<CreateChatCompletionRequest> {
+    let mut args = CreateChatCompletionRequestArgs::default();
+    args.model(&settings.model).messages(messages);
+    if !tools.is_empty() {
+        args.tools(tools);
+    }
+    if is_reasoning_model(&settings.model) {
+        // reasoning models only take `max_completion_tokens`, and reject
+        // sampling parameters and stop sequences
+        if let Some(max_tokens) = settings.max_tokens {
+            args.max_completion_tokens(max_tokens);
+        }
+        if let Some(effort) = &settings.reasoning_effort {
+            args.reasoning_effort(parse_reasoning_effort(effort)?);
+        }
+    } else {
+        // deprecated by OpenAI, but what Ollama, LM Studio and other
+        // compatible servers understand
+        #[allow(deprecated)]
+        if let Some(max_tokens) = settings.max_tokens {
+            args.max_tokens(max_tokens);
+        }
+        if let Some(temperature) = settings.temperature {
+            args.temperature(temperature);
+        }
+        if let Some(top_p) = settings.top_p {
+            args.top_p(top_p);
+        }
+        if let Some(penalty) = settings.frequency_penalty {
+            args.frequency_penalty(penalty);
+        }
+        if let Some(penalty) = settings.presence_penalty {
+            args.presence_penalty(penalty);
+        }
+        if !settings.stop.is_empty() {
+            args.stop(Stop::StringArray(settings.stop.clone()));
+        }
+    }
+    if let Some(format) = format {
+        args.response_format(format);
+    }
+    Ok(args.build()?)
+}
+
+/// Sends a single-turn request without tools.
+pub async fn complete(client: &Client<OpenAIConfig>, settings: &Settings, system: &str, prompt: &str) -> Result<String> {
+    let format = response_format(settings)?;
+    let messages = vec![system_message(system, format.is_some()), user_message(prompt)];
+    let mut message = send(client, settings, build_request(settings, messages, Vec::new(), format)?, None).await?.message;
+    Ok(message.split().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::error::ApiError;
+
+    fn api_error(message: &str, kind: Option<&str>, code: Option<&str>) -> anyhow::Error {
+        OpenAIError::ApiError(ApiError { message: message.to_string(), r#type: kind.map(str::to_string), param: None, code: code.map(str::to_string) }).into()
+    }
+
+    #[test]
+    fn only_errors_another_model_may_not_hit_fall_back() {
+        assert!(should_fall_back(&api_error("The model `gpt-9` does not exist", Some("invalid_request_error"), Some("model_not_found"))));
+        assert!(should_fall_back(&api_error("This model's maximum context length is 128000 tokens", Some("invalid_request_error"), Some("context_length_exceeded"))));
+        assert!(should_fall_back(&api_error("prompt is too long: 210000 tokens > 200000 maximum", Some("invalid_request_error"), None)));
+        assert!(should_fall_back(&api_error("Overloaded", Some("overloaded_error"), None)));
+        assert!(should_fall_back(&api_error("<html>502 Bad Gateway</html>", None, None)));
+        assert!(should_fall_back(&OpenAIError::StreamError("Invalid status code: 503 Service Unavailable".to_string()).into()));
+        // untyped client errors are the user's to fix
+        assert!(!should_fall_back(&api_error("messages: field required", None, None)));
+        assert!(!should_fall_back(&api_error("Invalid value for 'temperature'", Some("invalid_request_error"), None)));
+        assert!(!should_fall_back(&api_error("Rate limit reached", Some("requests"), Some("rate_limit_exceeded"))));
+        assert!(!should_fall_back(&OpenAIError::StreamError("Invalid status code: 401 Unauthorized".to_string()).into()));
+        assert!(!should_fall_back(&anyhow!("gave up after 25 tool rounds")));
+    }
+
+    #[test]
+    fn rate_limits_and_quota_are_told_apart() {
+        assert!(is_rate_limited(&api_error("Rate limit reached for gpt-4o", Some("requests"), Some("rate_limit_exceeded"))));
+        assert!(is_rate_limited(&api_error("You exceeded your current quota", Some("insufficient_quota"), None)));
+        assert!(is_rate_limited(&api_error("Number of request tokens has exceeded your per-minute rate limit", Some("rate_limit_error"), None)));
+        assert!(!is_rate_limited(&api_error("Overloaded", Some("overloaded_error"), None)));
+        assert!(!is_rate_limited(&anyhow!("rate limit")));
+    }
+
+    #[test]
+    fn reasoning_models_are_matched_by_family() {
+        for model in ["o1", "o3-mini", "openai/o4-mini", "gpt-5", "GPT-5-mini"] {
+            assert!(is_reasoning_model(model), "{}", model);
+        }
+        for model in ["gpt-4o", "gpt-4.1-mini", "o10", "gpt-50", "claude-sonnet-4", "openrouter/gpt-4o"] {
+            assert!(!is_reasoning_model(model), "{}", model);
+        }
+    }
+
+    fn split(message: Value) -> (String, Option<String>) {
+        serde_json::from_value::<RawMessage>(message).unwrap().split()
+    }
+
+    #[test]
+    fn reasoning_is_gathered_from_every_field() {
+        assert_eq!(split(json!({ "content": "42" })), ("42".to_string(), None));
+        assert_eq!(
+            split(json!({ "content": "42", "reasoning_content": "Add them.", "reasoning": "Add them.", "thinking": " " })),
+            ("42".to_string(), Some("Add them.".to_string()))
+        );
+        assert_eq!(
+            split(json!({ "content": "42", "thinking_blocks": [{ "type": "thinking", "thinking": "First." }, { "type": "thinking", "thinking": "Then." }] })),
+            ("42".to_string(), Some("First.\n\nThen.".to_string()))
+        );
+        assert_eq!(split(json!({ "content": "\n<think>\nAdd them.\n</think>\n\n42" })), ("42".to_string(), Some("Add them.".to_string())));
+        // an unclosed block is left as it came
+        assert_eq!(split(json!({ "content": "<think>Add" })), ("<think>Add".to_string(), None));
+    }
+
+    #[test]
+    fn the_stable_prefix_is_marked_for_caching() {
+        let mut req = json!({ "messages": [
+            { "role": "system", "content": "prompt" },
+            { "role": "system", "content": "summary" },
+            { "role": "user", "content": "first" },
+            { "role": "assistant", "content": [{ "type": "text", "text": "a" }, { "type": "text", "text": "b" }] },
+            { "role": "user", "content": "new" },
+        ] });
+        mark_cache_breakpoints(&mut req);
+        let marked = |i: usize| req["messages"][i]["content"].as_array().and_then(|parts| parts.last()).is_some_and(|part| part["cache_control"]["type"] == "ephemeral");
+        assert!(marked(0) && marked(1) && marked(3));
+        assert!(!marked(2) && !marked(4));
+        assert_eq!(req["messages"][0]["content"][0]["text"], "prompt");
+        assert!(req["messages"][3]["content"][0].get("cache_control").is_none());
+
+        let mut req = json!({ "messages": (0..6).map(|i| json!({ "role": "system", "content": i.to_string() })).collect::<Vec<_>>() });
+        mark_cache_breakpoints(&mut req);
+        let marks = req["messages"].as_array().unwrap().iter().filter(|m| m["content"].is_array()).count();
+        assert_eq!(marks, 4);
+    }
+}
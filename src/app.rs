@@ -0,0 +1,4010 @@
+//! The terminal app: its state, the event loop that feeds it key presses
+//! and background results, and the drawing of every screen.
+use async_openai::{config::OpenAIConfig, Client};
+use anyhow::Result;
+use crossterm::{
+    event::{DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::AbortHandle,
+};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+
+use crate::{
+    agent, cache, cargo, clipboard, codeblock, commands, context, crypto, debug, diff, editing, fix, git, github, history, i18n, images, keymap, links, local, lsp, mcp,
+    message::{Message, Role},
+    middleware,
+    models, notify, outline, palette, plugins, project, recovery, session,
+    settings::{self, Settings},
+    shell, stats, testgen, theme, tokens, tools, wrap,
+};
+
+/// Placeholder line shown while a request is in flight.
+const THINKING: &str = "🧠 Thinking...";
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+/// Lines moved per PageUp/PageDown in the chat pane.
+const SCROLL_STEP: u16 = 10;
+/// Replies longer than this are collapsed unless the settings say otherwise.
+const DEFAULT_COLLAPSE_LINES: usize = 40;
+/// How often the unsent input is saved with the session.
+const DRAFT_INTERVAL: Duration = Duration::from_secs(3);
+/// Answers that take longer than this ring the bell when they arrive.
+const NOTIFY_AFTER: Duration = Duration::from_secs(10);
+/// Share of a budget after which the status bar warns.
+const BUDGET_WARNING: f64 = 0.8;
+/// Depth and size of the listing sent with `/tree`; the automatic mode uses a lighter one.
+const TREE_DEPTH: usize = 4;
+const TREE_ENTRIES: usize = 400;
+const AUTO_TREE_DEPTH: usize = 2;
+const AUTO_TREE_ENTRIES: usize = 80;
+const PRDESC_PROMPT: &str = "You write pull request descriptions from a branch's commits and diff. Reply with the title alone on the first line (imperative, under 72 characters, no prefix), a blank line, then a Markdown body with a \"## Summary\" section (what changed and why, as short bullets) and a \"## Test plan\" section (how the change was or should be verified). Do not invent results that the commits and diff do not show.";
+const GENDOCS_PROMPT: &str = "Write a rustdoc comment (///) above each of them, in the register of the file's existing comments: say what the item is for and anything a caller must know (errors, panics, units, invariants), briefly, without restating the signature. Change nothing else: no code, formatting or other comments. Give the complete new contents of the file as one file-scoped code block (```rust path).";
+/// Branch diffs sent to `/prdesc` are cut to this many characters.
+const MAX_PR_DIFF: usize = 60_000;
+const COMMIT_PROMPT: &str = "You write git commit messages in the Conventional Commits format (type(scope): summary, optional body). Reply with the commit message only, without code fences or commentary.";
+
+#[derive(Clone)]
+enum AppState {
+    Chat,
+    Settings,
+    Commit,
+    Apply,
+    Passphrase,
+    ModelPicker,
+    McpPanel,
+    Confirm,
+    Compare,
+    ResponseDiff,
+    Stats,
+    Bookmarks,
+    Viewer,
+    Help,
+    Palette,
+    ShellCommand,
+    PrDescription,
+}
+
+/// A description from `/prdesc`, for the branch and base it describes.
+struct PrDescription {
+    branch: String,
+    base: String,
+    title: String,
+    body: String,
+}
+
+/// A message open full-screen in the viewer.
+struct Viewer {
+    message: usize,
+    scroll: u16,
+    /// Query being typed after `/`.
+    search: Option<String>,
+    /// Last search, highlighted and stepped through with `n`/`N`.
+    query: String,
+    /// Line of the current match, scrolled to on the next draw.
+    hit: Option<usize>,
+    jump: bool,
+    /// Shown in the title until the next key.
+    status: Option<String>,
+    /// Shows the request and raw response behind the message instead.
+    raw: bool,
+}
+
+/// One prompt answered by several models side by side.
+struct Comparison {
+    prompt: String,
+    /// Each model with its answer once it arrives.
+    answers: Vec<(String, Option<Result<agent::Reply>>)>,
+    selected: usize,
+}
+
+/// One file of a change set under review; nothing is written until the
+/// whole set is confirmed.
+struct PendingChange {
+    path: String,
+    old: String,
+    new: String,
+    /// `None` until the file is accepted or rejected.
+    accepted: Option<bool>,
+}
+
+/// Results of background tasks, sent to the event loop.
+pub enum AgentResponse {
+    Chat(Result<agent::Reply>),
+    /// `None` when nothing is staged.
+    CommitMessage(Result<Option<String>>),
+    ConnectionTest(Result<Duration>),
+    Models(Result<Vec<String>>),
+    McpStatus(String, String),
+    Confirm(String, oneshot::Sender<bool>),
+    PluginLoaded(Result<String>),
+    Plugin(Result<String>),
+    /// Summary replacing trimmed turns, and the turns it covers.
+    Summary(Result<String>, Vec<agent::Turn>),
+    Title(Result<String>),
+    /// Answer of the nth model in a comparison.
+    Compared(usize, Result<agent::Reply>),
+    /// Whether the `/cargo` command passed, and its output.
+    Cargo(Result<(bool, String)>),
+    /// Language server description of a symbol for `/explain`, and the question asked.
+    Symbol(String, Result<String>, String),
+    /// Compiler errors found by `/fix`.
+    Diagnostics(Result<Vec<cargo::Diagnostic>>),
+    /// Command written for `/cmd`.
+    Suggestion(Result<shell::Suggestion>),
+    /// A `/cmd` command that was run, whether it succeeded, and its output.
+    ShellOutput(String, Result<(bool, String)>),
+    /// Title and body written by `/prdesc`.
+    PrDescription(Result<(String, String)>),
+    /// URL of the pull request a description was pushed to.
+    PrPublished(Result<String>),
+    /// Output of `git commit`, run off the event loop since hooks can be slow.
+    Committed(Result<String>),
+}
+
+/// What the app reacts to: input from the terminal, time passing, and what
+/// background tasks send back. [`App::handle_event`] takes one at a time.
+pub enum AppEvent {
+    Key(KeyEvent),
+    Paste(String),
+    /// The terminal gained (true) or lost focus.
+    Focus(bool),
+    /// Sent every turn of the event loop, for timers.
+    Tick,
+    /// How far the reply being streamed has come.
+    Chunk(agent::Progress),
+    /// A line from a tool at work.
+    ToolResult(String),
+    /// A background task finished.
+    Response(AgentResponse),
+    /// Something failed outside the app, to be shown in the transcript.
+    Error(anyhow::Error),
+}
+
+impl AppEvent {
+    /// The event for what the terminal sent, if the app reacts to it.
+    pub fn from_terminal(event: Event) -> Option<Self> {
+        match event {
+            Event::Key(key) => Some(AppEvent::Key(key)),
+            Event::Paste(text) => Some(AppEvent::Paste(text)),
+            Event::FocusGained => Some(AppEvent::Focus(true)),
+            Event::FocusLost => Some(AppEvent::Focus(false)),
+            Event::Mouse(_) | Event::Resize(..) => None,
+        }
+    }
+}
+
+impl From<AgentResponse> for AppEvent {
+    fn from(response: AgentResponse) -> Self {
+        AppEvent::Response(response)
+    }
+}
+
+/// Routes tool notifications and confirmations from background tasks to the UI loop.
+struct Host(UnboundedSender<AppEvent>);
+
+impl tools::ToolHost for Host {
+    fn notify(&self, line: String) {
+        let _ = self.0.send(AppEvent::ToolResult(line));
+    }
+
+    fn confirm(&self, question: String) -> oneshot::Receiver<bool> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.0.send(AgentResponse::Confirm(question, tx).into());
+        rx
+    }
+
+    fn progress(&self, progress: agent::Progress) {
+        let _ = self.0.send(AppEvent::Chunk(progress));
+    }
+}
+
+/// The state of the terminal app: the transcript, the input box, the
+/// screen open and every background task in flight.
+pub struct App {
+    state: AppState,
+    input: String,
+    /// Byte offset of the cursor in `input`, clamped to its end.
+    cursor: usize,
+    /// Text last killed with Ctrl+W, Ctrl+U or Ctrl+K, for Ctrl+Y.
+    kill_buffer: String,
+    /// Ctrl+X was pressed; Ctrl+E next opens the input in an external editor.
+    ctrl_x: bool,
+    messages: Vec<Message>,
+    settings: Settings,
+    settings_input: Vec<String>,
+    settings_focus: usize,
+    /// Why fields of the settings screen could not be saved, by field.
+    settings_errors: BTreeMap<usize, String>,
+    confirm_save: bool,
+    last_confirm: Option<Instant>,
+    just_entered_settings: bool,
+    commit_input: String,
+    pending_changes: Vec<PendingChange>,
+    apply_selected: usize,
+    apply_scroll: u16,
+    pending_overwrite: Option<String>,
+    history: history::History,
+    history_search: Option<String>,
+    search_match: Option<usize>,
+    completion: Option<commands::Completion>,
+    connection_status: Option<String>,
+    passphrase: Option<String>,
+    passphrase_input: String,
+    reveal_key: bool,
+    session_model: Option<String>,
+    /// Name of the `/persona` in use.
+    persona: Option<String>,
+    session_stop: Option<Vec<String>>,
+    json_mode: bool,
+    /// `/debug on`: requests and raw responses go to the session's debug log.
+    debug: bool,
+    /// Pretty-printed requests and raw responses behind each reply received
+    /// while debugging, by the reply's message id.
+    raw: HashMap<u64, String>,
+    /// `--local-only` on the command line, on top of the setting.
+    force_local: bool,
+    models: Vec<String>,
+    model_selected: usize,
+    toolbox: Arc<tools::Toolbox>,
+    keymap: keymap::Keymap,
+    mcp_status: Vec<(String, String)>,
+    pending_confirm: Option<(String, oneshot::Sender<bool>)>,
+    /// Lines scrolled in a confirmation long enough to need it, such as a snippet.
+    confirm_scroll: u16,
+    attachments: Vec<context::Attachment>,
+    /// The saved conversation: exchanges sent as context with each prompt and
+    /// a model-written stand-in for turns trimmed from it.
+    session: session::Session,
+    /// Recent sessions listed in the sidebar while it is open.
+    sidebar: Option<Vec<(PathBuf, String, u64)>>,
+    /// Sidebar row; 0 is "new session", then the listed sessions.
+    sidebar_selected: usize,
+    /// Shows the pane listing what will be sent with the next prompt.
+    show_context: bool,
+    /// Start time and handle of the chat or commit request in flight.
+    request: Option<(Instant, AbortHandle)>,
+    /// Wrapped lines the chat pane is scrolled up from the bottom.
+    chat_scroll: u16,
+    /// Shows reasoning traces in full instead of a one-line summary.
+    expand_reasoning: bool,
+    /// Tokens billed for the last reply, when the provider reported them.
+    last_usage: Option<agent::Usage>,
+    /// Time to first token and throughput of the last reply.
+    last_speed: Option<agent::Speed>,
+    /// Latest progress of a streamed reply and when it was reported, so a
+    /// report from a cancelled request is not shown for the next one.
+    progress: Option<(Instant, agent::Progress)>,
+    /// Cost in USD of the last reply, when the model's price is known.
+    last_cost: Option<f64>,
+    /// Known cost in USD of the replies received this run.
+    total_cost: f64,
+    /// Last time the input was saved as the session's draft.
+    draft_saved: Instant,
+    /// `/budget override` was given: requests go through past the budgets.
+    budget_override: bool,
+    /// Models the next prompt is sent to side by side, set by `/compare`.
+    compare_next: Option<Vec<String>>,
+    comparison: Option<Comparison>,
+    /// Title and word diff shown by `/diffresp`.
+    response_diff: Option<(String, Vec<(diff::Change, String)>)>,
+    diff_scroll: u16,
+    /// Lines of the `/stats` panel, built when it opens.
+    stats_report: Vec<String>,
+    /// Prompt of the request in flight, recorded once its answer arrives.
+    pending_prompt: Option<String>,
+    bookmark_selected: usize,
+    /// Language server for `/explain`, started on first use.
+    lsp: Arc<tokio::sync::OnceCell<lsp::LspClient>>,
+    /// `/cargo` subcommand being fixed and the fixes asked for so far.
+    fix_loop: Option<(String, u32)>,
+    /// Opens the file changes of the next answer for review, for `/gentest`
+    /// and `/gendocs`.
+    apply_reply: bool,
+    /// Chat pane line to scroll to on the next draw.
+    jump_to: Option<usize>,
+    /// With vim keybindings, the input box is in normal mode.
+    vim_normal: bool,
+    /// First key of a two-key vim command (`gg`, `dd`).
+    vim_pending: Option<char>,
+    /// Shown in the input title in normal mode until the next key.
+    vim_status: Option<String>,
+    /// Query typed after `/` in normal mode.
+    transcript_search: Option<String>,
+    /// Last chat search and the message it found, for `n`/`N`.
+    transcript_match: Option<(String, usize)>,
+    /// Message selected with Alt+Up/Down.
+    focused: Option<usize>,
+    /// Long replies shown the other way round from their default: expanded
+    /// when older, collapsed when the latest.
+    toggled: HashSet<usize>,
+    viewer: Option<Viewer>,
+    /// Filter typed in the `/help` overlay and the page shown.
+    help_query: String,
+    help_page: usize,
+    palette: Option<palette::Palette>,
+    /// Sends the input on the next turn of the loop, as if Enter was pressed.
+    submit: bool,
+    /// Opens the input in an external editor once the loop has the terminal.
+    compose: bool,
+    colors: theme::Colors,
+    /// The terminal takes OSC 8 hyperlinks.
+    hyperlinks: bool,
+    /// Command from `/cmd` waiting for edit, run or copy.
+    suggestion: Option<shell::Suggestion>,
+    editing_suggestion: bool,
+    pr_description: Option<PrDescription>,
+    /// Whether the terminal has focus, once it has reported a change.
+    has_focus: Option<bool>,
+    /// Inline images, when the terminal draws kitty graphics.
+    kitty: Option<images::Kitty>,
+    /// Images fully in view in the last frame, with their top-left cell.
+    image_slots: Vec<(String, u16, u16)>,
+    /// The project: the working directory, or a scratch one in tests.
+    root: PathBuf,
+}
+
+impl App {
+    /// A new chat with `settings`, the history of earlier prompts and no
+    /// background task started yet; see [`App::start`].
+    pub fn new(settings: Settings) -> Self {
+        let settings_input = settings.fields();
+        let json_mode = settings.response_format.is_some();
+        let toolbox = Arc::new(tools::Toolbox::new(settings.web_search.clone(), settings.sandbox.clone(), settings.github.clone()));
+        let persona = project::current().persona.clone().filter(|name| settings.personas.iter().any(|p| &p.name == name));
+        let (keymap, keymap_warnings) = keymap::Keymap::new(&settings.keybindings);
+        Self {
+            state: AppState::Chat,
+            input: String::new(),
+            cursor: 0,
+            kill_buffer: String::new(),
+            ctrl_x: false,
+            messages: std::iter::once(i18n::t("ready").to_string()).chain(keymap_warnings).map(Message::notice).collect(),
+            settings,
+            settings_input,
+            settings_focus: 0,
+            settings_errors: BTreeMap::new(),
+            confirm_save: false,
+            last_confirm: None,
+            just_entered_settings: false,
+            commit_input: String::new(),
+            pending_changes: Vec::new(),
+            apply_selected: 0,
+            apply_scroll: 0,
+            pending_overwrite: None,
+            history: history::History::load(),
+            history_search: None,
+            search_match: None,
+            completion: None,
+            connection_status: None,
+            passphrase: None,
+            passphrase_input: String::new(),
+            reveal_key: false,
+            session_model: None,
+            persona,
+            session_stop: None,
+            json_mode,
+            debug: false,
+            raw: HashMap::new(),
+            force_local: false,
+            models: Vec::new(),
+            model_selected: 0,
+            toolbox,
+            keymap,
+            mcp_status: Vec::new(),
+            pending_confirm: None,
+            confirm_scroll: 0,
+            attachments: Vec::new(),
+            session: session::Session::new(),
+            sidebar: None,
+            sidebar_selected: 0,
+            show_context: false,
+            request: None,
+            chat_scroll: 0,
+            expand_reasoning: false,
+            last_usage: None,
+            last_speed: None,
+            progress: None,
+            last_cost: None,
+            total_cost: 0.0,
+            draft_saved: Instant::now(),
+            budget_override: false,
+            compare_next: None,
+            comparison: None,
+            response_diff: None,
+            diff_scroll: 0,
+            stats_report: Vec::new(),
+            pending_prompt: None,
+            bookmark_selected: 0,
+            lsp: Arc::default(),
+            fix_loop: None,
+            apply_reply: false,
+            jump_to: None,
+            vim_normal: false,
+            vim_pending: None,
+            vim_status: None,
+            transcript_search: None,
+            transcript_match: None,
+            focused: None,
+            toggled: HashSet::new(),
+            viewer: None,
+            help_query: String::new(),
+            help_page: 0,
+            palette: None,
+            submit: false,
+            compose: false,
+            colors: theme::detect(),
+            hyperlinks: links::supported(),
+            suggestion: None,
+            editing_suggestion: false,
+            pr_description: None,
+            has_focus: None,
+            kitty: images::kitty_supported().then(images::Kitty::default),
+            image_slots: Vec::new(),
+            root: PathBuf::from("."),
+        }
+    }
+
+    fn attach(&mut self, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace().peekable();
+        let smart = parts.next_if_eq(&"--smart").is_some();
+        let Some(path) = parts.next() else {
+            anyhow::bail!("usage: /attach [--smart] <path> [line-range]");
+        };
+        let range = parts.next().map(context::parse_range).transpose()?;
+        let filter = context::Filter::new(&self.root, &self.settings);
+        let mut attachment = context::Attachment::load(path, range, &filter)?;
+        if smart {
+            attachment = attachment.smart();
+        }
+        self.messages.push(Message::notice(i18n::fill("attach.added", &[&attachment.label()])));
+        self.attachments.push(attachment);
+        Ok(())
+    }
+
+    /// Plugins run as WASI processes that may reach the network, so none
+    /// is started in local-only mode.
+    fn load_plugins(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        if self.local_only() {
+            for path in plugins::discover() {
+                let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                self.messages.push(Message::notice(i18n::fill("plugin.skipped", &[&name])));
+            }
+            return;
+        }
+        let runtime = self.settings.wasm_runtime.clone().unwrap_or_else(|| plugins::DEFAULT_RUNTIME.to_string());
+        for path in plugins::discover() {
+            let response_tx = response_tx.clone();
+            let toolbox = self.toolbox.clone();
+            let runtime = runtime.clone();
+            tokio::spawn(async move {
+                let result = plugins::Plugin::load(&path, &runtime).await.map(|plugin| {
+                    let hooks = if plugin.hooks.is_empty() { String::new() } else { i18n::fill("plugin.hooks", &[&plugin.hooks.join(", ")]) };
+                    let status = i18n::fill("plugin.loaded", &[&plugin.name, &plugin.commands.len(), &plugin.tools.len(), &hooks]);
+                    let plugin = Arc::new(plugin);
+                    if !plugin.hooks.is_empty() {
+                        middleware::register(plugin.clone());
+                    }
+                    toolbox.add_plugin(plugin);
+                    status
+                });
+                let result = result.map_err(|e| anyhow::anyhow!("plugin {}: {}", path.display(), e));
+                let _ = response_tx.send(AgentResponse::PluginLoaded(result).into());
+            });
+        }
+    }
+
+    fn answer_confirm(&mut self, yes: bool) {
+        if let Some((question, reply)) = self.pending_confirm.take() {
+            self.messages.push(Message::notice(format!("{} {}", if yes { "✔" } else { "✘" }, question)));
+            let _ = reply.send(yes);
+        }
+        self.state = AppState::Chat;
+    }
+
+    /// Connects to the configured MCP servers in the background, registering
+    /// each one's tools as soon as its handshake completes. Local-only mode
+    /// keeps loopback SSE servers only: a stdio server is a local process
+    /// that may reach the network itself.
+    fn connect_mcp_servers(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        for config in self.settings.mcp_servers.clone() {
+            if self.local_only() {
+                match config.url.as_deref() {
+                    Some(url) if local::is_loopback(url) => {}
+                    Some(url) => {
+                        self.mcp_status.push((config.name.clone(), i18n::fill("mcp.skipped", &[&url])));
+                        continue;
+                    }
+                    None => {
+                        self.mcp_status.push((config.name.clone(), i18n::t("mcp.skipped_stdio").to_string()));
+                        continue;
+                    }
+                }
+            }
+            self.mcp_status.push((config.name.clone(), i18n::t("mcp.connecting").to_string()));
+            let response_tx = response_tx.clone();
+            let toolbox = self.toolbox.clone();
+            tokio::spawn(async move {
+                let status = match mcp::McpServer::connect(&config).await {
+                    Ok(server) => {
+                        let status = i18n::fill("mcp.connected", &[&server.tools.len(), &server.resources.len()]);
+                        toolbox.add_server(Arc::new(server));
+                        status
+                    }
+                    Err(e) => format!("⚠️ {}", e),
+                };
+                let _ = response_tx.send(AgentResponse::McpStatus(config.name, status).into());
+            });
+        }
+    }
+
+    /// The model for this conversation: a `/model` override, the persona's
+    /// model or the configured default.
+    fn model(&self) -> &str {
+        self.session_model.as_deref().unwrap_or_else(|| self.default_model())
+    }
+
+    fn default_model(&self) -> &str {
+        self.persona().and_then(|p| p.model.as_deref()).unwrap_or(&self.settings.model)
+    }
+
+    fn switch_model(&mut self, model: &str) {
+        self.session_model = (model != self.default_model()).then(|| model.to_string());
+        self.messages.push(Message::notice(i18n::fill("model.switched", &[&model])));
+    }
+
+    fn persona(&self) -> Option<&agent::Persona> {
+        let name = self.persona.as_deref()?;
+        self.settings.personas.iter().find(|p| p.name == name)
+    }
+
+    /// `/persona` lists the configured personas, `/persona off` goes back to
+    /// the defaults and `/persona <name>` switches, dropping any `/model`
+    /// override so the persona's model applies.
+    fn persona_command(&mut self, args: &str) {
+        match args.trim() {
+            "" if self.settings.personas.is_empty() => {
+                self.messages.push(Message::notice(i18n::fill("persona.none", &[&settings::user_file().display()])));
+            }
+            "" => {
+                let mut lines = vec![i18n::t("persona.list").to_string()];
+                for persona in &self.settings.personas {
+                    let active = if self.persona.as_deref() == Some(persona.name.as_str()) { format!(" {}", i18n::t("persona.active")) } else { String::new() };
+                    let model = persona.model.as_deref().unwrap_or(&self.settings.model);
+                    lines.push(format!("  {}{} · {}", persona.name, active, model));
+                }
+                self.messages.push(Message::notice(lines.join("\n")));
+            }
+            "off" | "none" => {
+                self.persona = None;
+                self.session_model = None;
+                self.messages.push(Message::notice(i18n::t("persona.off")));
+            }
+            name => match self.settings.personas.iter().find(|p| p.name == name) {
+                Some(persona) => {
+                    self.persona = Some(persona.name.clone());
+                    self.session_model = None;
+                    self.messages.push(Message::notice(i18n::fill("persona.switched", &[&name, &self.model()])));
+                }
+                None => self.messages.push(Message::notice(i18n::fill("persona.unknown", &[&name]))),
+            },
+        }
+    }
+
+    fn set_passphrase(&mut self, passphrase: Option<String>) {
+        self.passphrase = passphrase;
+        if let Err(e) = crypto::rekey(self.passphrase.as_deref(), &local_files()) {
+            self.messages.push(Message::notice(i18n::fill("encrypt.rekey_failed", &[&e])));
+        }
+        match self.settings.save(self.passphrase.as_deref()) {
+            Ok(warning) => {
+                let status = if self.passphrase.is_some() { i18n::t("encrypt.on") } else { i18n::t("encrypt.off") };
+                self.messages.push(Message::notice(status.to_string()));
+                self.messages.extend(warning.map(Message::notice));
+            }
+            Err(e) => self.messages.push(Message::notice(i18n::fill("settings.save_failed", &[&e]))),
+        }
+    }
+
+    /// Completes the word under the cursor, keeping the text after it. A
+    /// second Tab cycles through the candidates in place.
+    fn tab_complete(&mut self) {
+        if let Some(completion) = self.completion.as_mut() {
+            let next = completion.selected.map_or(0, |i| (i + 1) % completion.candidates.len());
+            completion.selected = Some(next);
+            let candidate = &completion.candidates[next];
+            self.input.replace_range(completion.start..self.cursor, candidate);
+            self.cursor = completion.start + candidate.len();
+            return;
+        }
+        let plugin_commands = self.toolbox.plugin_commands();
+        let personas: Vec<String> = self.settings.personas.iter().map(|p| p.name.clone()).collect();
+        let Some(completion) = commands::complete(&self.input[..self.cursor], &self.models, &personas, &plugin_commands) else { return };
+        let end = self.input[self.cursor..].find(char::is_whitespace).map_or(self.input.len(), |i| self.cursor + i);
+        let text = match completion.candidates.as_slice() {
+            [only] if only.ends_with('/') || self.input[end..].starts_with(char::is_whitespace) => only.clone(),
+            [only] => format!("{} ", only),
+            candidates => commands::common_prefix(candidates).to_string(),
+        };
+        self.input.replace_range(completion.start..end, &text);
+        self.cursor = completion.start + text.len();
+        if completion.candidates.len() > 1 {
+            self.completion = Some(completion);
+        }
+    }
+
+    fn history_prev(&mut self) {
+        if let Some(entry) = self.history.prev(&self.input).map(str::to_string) {
+            self.set_input(entry);
+        }
+    }
+
+    fn history_next(&mut self) {
+        if let Some(entry) = self.history.next() {
+            self.set_input(entry);
+        }
+    }
+
+    /// Replaces the input, leaving the cursor at its end.
+    fn set_input(&mut self, text: String) {
+        self.cursor = text.len();
+        self.input = text;
+    }
+
+    /// The cursor clamped to the input, which may have been cleared or
+    /// replaced since it moved.
+    fn cursor(&self) -> usize {
+        editing::boundary(&self.input, self.cursor)
+    }
+
+    fn insert(&mut self, text: &str) {
+        let at = self.cursor();
+        self.input.insert_str(at, text);
+        self.cursor = at + text.len();
+    }
+
+    /// Removes `start..end` from the input into the kill buffer.
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.kill_buffer = self.input.drain(start..end).collect();
+            self.cursor = start;
+        }
+    }
+
+    /// Typing, cursor movement and the readline shortcuts in the input box.
+    fn edit_key(&mut self, key: KeyEvent) {
+        let at = self.cursor();
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.cursor = editing::line_start(&self.input, at),
+            KeyCode::Char('e') if ctrl => self.cursor = editing::line_end(&self.input, at),
+            KeyCode::Char('w') if ctrl => self.kill(editing::rubout_start(&self.input, at), at),
+            KeyCode::Char('u') if ctrl => self.kill(editing::line_start(&self.input, at), at),
+            KeyCode::Char('k') if ctrl => self.kill(at, editing::line_end(&self.input, at)),
+            KeyCode::Char('y') if ctrl => self.insert(&self.kill_buffer.clone()),
+            KeyCode::Char('b') if alt => self.cursor = editing::word_left(&self.input, at),
+            KeyCode::Char('f') if alt => self.cursor = editing::word_right(&self.input, at),
+            KeyCode::Left if ctrl || alt => self.cursor = editing::word_left(&self.input, at),
+            KeyCode::Right if ctrl || alt => self.cursor = editing::word_right(&self.input, at),
+            KeyCode::Left => self.cursor = editing::prev_char(&self.input, at),
+            KeyCode::Right => self.cursor = editing::next_char(&self.input, at),
+            KeyCode::Home => self.cursor = editing::line_start(&self.input, at),
+            KeyCode::End => self.cursor = editing::line_end(&self.input, at),
+            KeyCode::Up | KeyCode::Down => {
+                if let Some(to) = editing::vertical(&self.input, at, key.code == KeyCode::Up) {
+                    self.cursor = to;
+                }
+            }
+            KeyCode::Delete => {
+                self.input.drain(at..editing::next_char(&self.input, at));
+            }
+            _ if ctrl || alt => {}
+            KeyCode::Char(c) => self.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Backspace => {
+                self.cursor = editing::prev_char(&self.input, at);
+                self.input.drain(self.cursor..at);
+            }
+            _ => {}
+        }
+    }
+
+    fn search_key(&mut self, key: KeyEvent) {
+        let Some(query) = self.history_search.as_mut() else { return };
+        match key.code {
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                // search further back from the current match
+                if let Some((i, _)) = self.history.search(query, self.search_match) {
+                    self.search_match = Some(i);
+                }
+                return;
+            }
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                editing::pop_char(query);
+            }
+            KeyCode::Enter => {
+                if let Some((_, entry)) = self.history.search(query, self.search_match.map(|i| i + 1)) {
+                    let entry = entry.to_string();
+                    self.set_input(entry);
+                }
+                self.history_search = None;
+                self.search_match = None;
+                return;
+            }
+            KeyCode::Esc => {
+                self.history_search = None;
+                self.search_match = None;
+                return;
+            }
+            _ => return,
+        }
+        self.search_match = self.history.search(query, None).map(|(i, _)| i);
+    }
+
+    /// A key in vim normal mode: motions scroll the chat pane, `/` searches it
+    /// and `:` starts a command.
+    fn vim_key(&mut self, key: KeyEvent) {
+        let pending = self.vim_pending.take();
+        self.vim_status = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match (pending, key.code) {
+            (_, KeyCode::Char('d')) if ctrl => self.chat_scroll = self.chat_scroll.saturating_sub(SCROLL_STEP),
+            (_, KeyCode::Char('u')) if ctrl => self.chat_scroll = self.chat_scroll.saturating_add(SCROLL_STEP),
+            (Some('g'), KeyCode::Char('g')) => self.chat_scroll = u16::MAX,
+            (Some('d'), KeyCode::Char('d')) => self.input.clear(),
+            (Some('z'), KeyCode::Char('a')) => self.toggle_collapse(),
+            (None, KeyCode::Char(c @ ('g' | 'd' | 'z'))) => self.vim_pending = Some(c),
+            (_, KeyCode::Char('o')) => self.open_viewer(),
+            (_, KeyCode::Char('[')) => self.focus_step(false),
+            (_, KeyCode::Char(']')) => self.focus_step(true),
+            (_, KeyCode::Char('i')) => self.vim_normal = false,
+            (_, KeyCode::Char('a')) => {
+                self.cursor = editing::next_char(&self.input, self.cursor());
+                self.vim_normal = false;
+            }
+            (_, KeyCode::Char('I')) => {
+                self.cursor = editing::line_start(&self.input, self.cursor());
+                self.vim_normal = false;
+            }
+            (_, KeyCode::Char('A')) => {
+                self.cursor = editing::line_end(&self.input, self.cursor());
+                self.vim_normal = false;
+            }
+            (_, KeyCode::Char('j') | KeyCode::Down) => self.chat_scroll = self.chat_scroll.saturating_sub(1),
+            (_, KeyCode::Char('k') | KeyCode::Up) => self.chat_scroll = self.chat_scroll.saturating_add(1),
+            (_, KeyCode::Char('G')) => self.chat_scroll = 0,
+            (_, KeyCode::Char('h')) => self.edit_key(KeyEvent::from(KeyCode::Left)),
+            (_, KeyCode::Char('l')) => self.edit_key(KeyEvent::from(KeyCode::Right)),
+            (_, KeyCode::Char('0')) => self.edit_key(KeyEvent::from(KeyCode::Home)),
+            (_, KeyCode::Char('$')) => self.edit_key(KeyEvent::from(KeyCode::End)),
+            (_, KeyCode::Char('b')) => self.cursor = editing::word_left(&self.input, self.cursor()),
+            (_, KeyCode::Char('w')) => self.cursor = editing::word_right(&self.input, self.cursor()),
+            (_, KeyCode::Char('x')) => self.edit_key(KeyEvent::from(KeyCode::Delete)),
+            (_, KeyCode::Char('/')) => self.transcript_search = Some(String::new()),
+            (_, KeyCode::Char('n')) => self.search_transcript(true),
+            (_, KeyCode::Char('N')) => self.search_transcript(false),
+            (_, KeyCode::Char(':')) => {
+                self.set_input("/".to_string());
+                self.vim_normal = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn transcript_search_key(&mut self, key: KeyEvent) {
+        let Some(query) = self.transcript_search.as_mut() else { return };
+        match key.code {
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                editing::pop_char(query);
+            }
+            KeyCode::Enter => {
+                let query = self.transcript_search.take().unwrap_or_default();
+                if !query.is_empty() {
+                    self.transcript_match = Some((query, self.messages.len()));
+                    self.search_transcript(true);
+                }
+            }
+            KeyCode::Esc => self.transcript_search = None,
+            _ => {}
+        }
+    }
+
+    /// Scrolls to the next message containing the last search, towards older
+    /// messages or newer ones.
+    fn search_transcript(&mut self, older: bool) {
+        let Some((query, from)) = self.transcript_match.clone() else { return };
+        let lowered = query.to_lowercase();
+        let matches = |m: &Message| m.line().to_lowercase().contains(&lowered);
+        let from = from.min(self.messages.len());
+        let found = if older {
+            self.messages[..from].iter().rposition(matches)
+        } else {
+            self.messages.iter().skip(from + 1).position(matches).map(|i| i + from + 1)
+        };
+        match found {
+            Some(at) => {
+                self.transcript_match = Some((query, at));
+                self.jump_to = Some(at);
+            }
+            None => self.vim_status = Some(i18n::fill("search.no_more", &[&query])),
+        }
+    }
+
+    /// Moves the selection to the previous or next message, starting from the
+    /// newest; stepping past it clears the selection.
+    fn focus_step(&mut self, forward: bool) {
+        self.focused = match (self.focused, forward) {
+            (None, false) => self.messages.len().checked_sub(1),
+            (None, true) => None,
+            (Some(i), false) => Some(i.saturating_sub(1)),
+            (Some(i), true) => Some(i + 1).filter(|&next| next < self.messages.len()),
+        };
+        self.jump_to = self.focused;
+    }
+
+    /// Expands or collapses the selected message, or the newest long reply.
+    fn toggle_collapse(&mut self) {
+        let limit = self.collapse_limit();
+        let long = |m: &Message| m.is(Role::Assistant) && codeblock::render(&m.line()).len() > limit;
+        let target = self.focused.filter(|&i| long(&self.messages[i])).or_else(|| self.messages.iter().rposition(long));
+        if let Some(i) = target {
+            if !self.toggled.remove(&i) {
+                self.toggled.insert(i);
+            }
+            self.jump_to = Some(i);
+        }
+    }
+
+    /// Opens the selected message, or the latest reply, in the viewer.
+    fn open_viewer(&mut self) {
+        let Some(message) = self.focused.or_else(|| self.messages.iter().rposition(|m| m.is(Role::Assistant))) else { return };
+        self.viewer = Some(Viewer { message, scroll: 0, search: None, query: String::new(), hit: None, jump: false, status: None, raw: false });
+        self.state = AppState::Viewer;
+    }
+
+    /// The text open in the viewer: the message, or what is behind it.
+    fn viewed(&self) -> String {
+        let Some(viewer) = &self.viewer else { return String::new() };
+        let Some(message) = self.messages.get(viewer.message) else { return String::new() };
+        match self.raw.get(&message.id) {
+            Some(raw) if viewer.raw => raw.clone(),
+            _ => message.line(),
+        }
+    }
+
+    fn viewer_key(&mut self, key: KeyEvent) {
+        let Some(viewer) = self.viewer.as_mut() else { return };
+        viewer.status = None;
+        if let Some(search) = viewer.search.as_mut() {
+            match key.code {
+                KeyCode::Char(c) => search.push(c),
+                KeyCode::Backspace => editing::pop_char(search),
+                KeyCode::Enter => {
+                    viewer.query = viewer.search.take().unwrap_or_default();
+                    viewer.hit = None;
+                    self.viewer_step(true);
+                }
+                KeyCode::Esc => viewer.search = None,
+                _ => {}
+            }
+            return;
+        }
+        let message = self.viewed();
+        let Some(viewer) = self.viewer.as_mut() else { return };
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => viewer.scroll = viewer.scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => viewer.scroll = viewer.scroll.saturating_add(1),
+            KeyCode::PageUp => viewer.scroll = viewer.scroll.saturating_sub(SCROLL_STEP),
+            KeyCode::PageDown | KeyCode::Char(' ') => viewer.scroll = viewer.scroll.saturating_add(SCROLL_STEP),
+            KeyCode::Home | KeyCode::Char('g') => viewer.scroll = 0,
+            KeyCode::End | KeyCode::Char('G') => viewer.scroll = u16::MAX,
+            KeyCode::Char('/') => viewer.search = Some(String::new()),
+            KeyCode::Char('n') => self.viewer_step(true),
+            KeyCode::Char('N') => self.viewer_step(false),
+            KeyCode::Char('r') => {
+                if self.messages.get(viewer.message).is_some_and(|m| self.raw.contains_key(&m.id)) {
+                    viewer.raw = !viewer.raw;
+                    viewer.scroll = 0;
+                    viewer.hit = None;
+                } else {
+                    viewer.status = Some(if self.debug { i18n::t("viewer.no_raw").to_string() } else { i18n::t("viewer.no_raw_debug").to_string() });
+                }
+            }
+            KeyCode::Char('y') => {
+                let copied = clipboard::copy(message.trim_start_matches("🤖 ").trim_start_matches("> "));
+                viewer.status = Some(copied.map_or_else(|e| i18n::fill("viewer.copy_failed", &[&e]), |_| i18n::t("viewer.copied").to_string()));
+            }
+            KeyCode::Char(c @ '1'..='9') => {
+                let n = c.to_digit(10).unwrap_or_default() as usize;
+                viewer.status = Some(match codeblock::parse(&message).get(n - 1) {
+                    Some(block) => clipboard::copy(&block.code).map_or_else(|e| i18n::fill("viewer.copy_failed", &[&e]), |_| i18n::fill("viewer.copied_block", &[&n])),
+                    None => i18n::fill("viewer.no_block", &[&n]),
+                });
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.viewer = None;
+                self.state = AppState::Chat;
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves to the next or previous line matching the viewer's query.
+    fn viewer_step(&mut self, forward: bool) {
+        let Some(viewer) = self.viewer.as_mut() else { return };
+        if viewer.query.is_empty() {
+            return;
+        }
+        let query = viewer.query.to_lowercase();
+        let viewed = self.viewed();
+        let Some(viewer) = self.viewer.as_mut() else { return };
+        let lines: Vec<&str> = viewed.split('\n').collect();
+        let matches = |i: &usize| lines[*i].to_lowercase().contains(&query);
+        let found = match (viewer.hit, forward) {
+            (None, _) => (0..lines.len()).find(matches),
+            (Some(hit), true) => (hit + 1..lines.len()).find(matches),
+            (Some(hit), false) => (0..hit).rev().find(matches),
+        };
+        match found {
+            Some(line) => {
+                viewer.hit = Some(line);
+                viewer.jump = true;
+            }
+            None => viewer.status = Some(i18n::fill("search.no_more", &[&viewer.query])),
+        }
+    }
+
+    fn open_palette(&mut self) {
+        use palette::{Action, Entry};
+
+        let mut entries: Vec<Entry> = commands::COMMANDS
+            .iter()
+            .map(|c| Entry {
+                label: c.name.to_string(),
+                detail: c.description.to_string(),
+                action: Action::Command { name: c.name.to_string(), needs_args: c.usage.contains('<') },
+            })
+            .collect();
+        for name in self.toolbox.plugin_commands() {
+            let detail = self.toolbox.plugin_command_description(&name).unwrap_or_default();
+            entries.push(Entry { label: name.clone(), detail, action: Action::Command { name, needs_args: false } });
+        }
+        let fields = self.settings.fields();
+        for (i, label) in settings::FIELDS.iter().enumerate() {
+            if label.ends_with(" (on/off)") {
+                entries.push(Entry { label: i18n::fill("palette.toggle", &[&i18n::field(i).trim_end_matches(" (on/off)")]), detail: i18n::fill("palette.now", &[&fields[i]]), action: Action::Toggle(i) });
+            }
+        }
+        for (path, name, _) in session::list() {
+            entries.push(Entry { label: i18n::fill("palette.session", &[&name]), detail: i18n::t("palette.open_session").to_string(), action: Action::Session(path) });
+        }
+        let mut models = self.models.clone();
+        models.push(self.settings.model.clone());
+        models.sort();
+        models.dedup();
+        for model in models {
+            let detail = models::lookup(&self.settings, &model).describe();
+            entries.push(Entry { label: i18n::fill("palette.model", &[&model]), detail, action: Action::Model(model) });
+        }
+        self.palette = Some(palette::Palette::new(entries));
+        self.state = AppState::Palette;
+    }
+
+    fn palette_key(&mut self, key: KeyEvent) {
+        let Some(palette) = self.palette.as_mut() else { return };
+        match key.code {
+            KeyCode::Char(c) => {
+                palette.query.push(c);
+                palette.selected = 0;
+            }
+            KeyCode::Backspace => {
+                editing::pop_char(&mut palette.query);
+                palette.selected = 0;
+            }
+            KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+            KeyCode::Down if palette.selected + 1 < palette.visible().len() => palette.selected += 1,
+            KeyCode::Enter => {
+                let action = palette.visible().get(palette.selected).map(|e| e.action.clone());
+                self.palette = None;
+                self.state = AppState::Chat;
+                if let Some(action) = action {
+                    self.run_action(action);
+                }
+            }
+            KeyCode::Esc => {
+                self.palette = None;
+                self.state = AppState::Chat;
+            }
+            _ => {}
+        }
+    }
+
+    fn run_action(&mut self, action: palette::Action) {
+        match action {
+            palette::Action::Command { name, needs_args: true } => self.set_input(format!("{} ", name)),
+            palette::Action::Command { name, needs_args: false } => {
+                self.set_input(name);
+                self.submit = true;
+            }
+            palette::Action::Toggle(i) => {
+                let mut fields = self.settings.fields();
+                fields[i] = if fields[i] == "on" { "off" } else { "on" }.to_string();
+                let state = fields[i].clone();
+                self.settings_input = fields;
+                match self.save_settings() {
+                    Ok(warning) => {
+                        self.messages.push(Message::notice(i18n::fill("settings.toggled", &[&i18n::field(i).trim_end_matches(" (on/off)"), &state])));
+                        self.messages.extend(warning.map(Message::notice));
+                    }
+                    Err(e) => self.messages.push(Message::notice(i18n::fill("settings.save_failed", &[&e]))),
+                }
+            }
+            palette::Action::Session(path) => {
+                if self.pending_prompt.is_some() {
+                    self.messages.push(Message::notice(i18n::t("session.busy")));
+                } else {
+                    self.open_session(session::Session::load(&path));
+                }
+            }
+            palette::Action::Model(model) => self.switch_model(&model),
+        }
+    }
+
+    fn open_help(&mut self) {
+        self.help_query.clear();
+        self.help_page = 0;
+        self.state = AppState::Help;
+    }
+
+    /// `/help` lines from the command registry, plugin commands and the key
+    /// tables, keeping only those matching the typed filter.
+    fn help_lines(&self) -> Vec<(bool, String)> {
+        let mut sections = vec![(i18n::t("help.commands"), commands::COMMANDS.iter().map(|c| (c.usage.to_string(), c.description.to_string())).collect::<Vec<_>>())];
+        let plugins: Vec<(String, String)> = self
+            .toolbox
+            .plugin_commands()
+            .into_iter()
+            .map(|name| {
+                let description = self.toolbox.plugin_command_description(&name).unwrap_or_default();
+                (name, description)
+            })
+            .collect();
+        if !plugins.is_empty() {
+            sections.push((i18n::t("help.plugin_commands"), plugins));
+        }
+        let keys = |table: &[(&str, &str)]| table.iter().map(|(k, d)| (k.to_string(), d.to_string())).collect::<Vec<_>>();
+        sections.push((i18n::t("help.keys"), keys(commands::KEYS)));
+        if self.settings.vim_mode {
+            sections.push((i18n::t("help.vim_keys"), keys(commands::VIM_KEYS)));
+        }
+        let query = self.help_query.to_lowercase();
+        let mut lines = Vec::new();
+        for (title, entries) in sections {
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(name, description)| format!("  {:<30} {}", name, description))
+                .filter(|line| line.to_lowercase().contains(&query))
+                .collect();
+            if !entries.is_empty() {
+                lines.push((true, title.to_string()));
+                lines.extend(entries.into_iter().map(|line| (false, line)));
+            }
+        }
+        lines
+    }
+
+    fn help_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.help_query.push(c);
+                self.help_page = 0;
+            }
+            KeyCode::Backspace => {
+                editing::pop_char(&mut self.help_query);
+                self.help_page = 0;
+            }
+            KeyCode::PageDown | KeyCode::Down | KeyCode::Right => self.help_page += 1,
+            KeyCode::PageUp | KeyCode::Up | KeyCode::Left => self.help_page = self.help_page.saturating_sub(1),
+            KeyCode::Esc | KeyCode::Enter | KeyCode::F(1) => self.state = AppState::Chat,
+            _ => {}
+        }
+    }
+
+    /// Drops the selection and expanded replies once message indices shift.
+    fn forget_selection(&mut self) {
+        self.focused = None;
+        self.toggled.clear();
+    }
+
+    fn collapse_limit(&self) -> usize {
+        match self.settings.collapse_lines.unwrap_or(DEFAULT_COLLAPSE_LINES) {
+            0 => usize::MAX,
+            n => n,
+        }
+    }
+
+    fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n");
+        match self.state {
+            AppState::Chat => self.insert(&text),
+            AppState::Commit => self.commit_input.push_str(&text),
+            AppState::ShellCommand if self.editing_suggestion => {
+                if let Some(suggestion) = &mut self.suggestion {
+                    suggestion.command.push_str(text.trim_matches(['\r', '\n']));
+                }
+            }
+            AppState::Settings => {
+                // settings fields are single-line, so drop the newline a copied key usually carries
+                self.settings_input[self.settings_focus].push_str(text.trim_matches(['\r', '\n']));
+                self.settings_errors.remove(&self.settings_focus);
+            }
+            _ => {}
+        }
+    }
+
+    fn save_code(&mut self, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        let (Some(n), Some(path), None) = (parts.next(), parts.next(), parts.next()) else {
+            anyhow::bail!("usage: /savecode <n> <path>");
+        };
+        let n: usize = n.parse().map_err(|_| anyhow::anyhow!("'{}' is not a block number", n))?;
+        let blocks = self.last_response().as_deref().map(codeblock::parse).unwrap_or_default();
+        let block = n
+            .checked_sub(1)
+            .and_then(|i| blocks.get(i))
+            .ok_or_else(|| anyhow::anyhow!("the last response has {} code block(s)", blocks.len()))?;
+
+        let confirmed = self.pending_overwrite.take().as_deref() == Some(path);
+        if Path::new(path).exists() && !confirmed {
+            self.messages.push(Message::notice(i18n::fill("savecode.exists", &[&path])));
+            self.pending_overwrite = Some(path.to_string());
+            return Ok(());
+        }
+        write_file(path, &block.code)?;
+        self.messages.push(Message::notice(i18n::fill("savecode.saved", &[&n, &path])));
+        Ok(())
+    }
+
+    fn last_response(&self) -> Option<Cow<'_, str>> {
+        self.messages.iter().rev().find(|m| m.is(Role::Assistant)).map(|m| m.text())
+    }
+
+    fn collect_changes(&mut self) -> Result<usize> {
+        let Some(response) = self.last_response() else {
+            return Ok(0);
+        };
+        let mut changes = Vec::new();
+        for block in codeblock::parse(&response) {
+            let Some(path) = block.path else { continue };
+            let target = match inside_root(&self.root, &path) {
+                Ok(target) => target,
+                Err(e) => {
+                    self.messages.push(Message::notice(i18n::fill("apply.refused", &[&path, &e])));
+                    continue;
+                }
+            };
+            let old = match fs::read_to_string(&target) {
+                Ok(old) => old,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    let reason = if e.kind() == io::ErrorKind::InvalidData { "not a text file".to_string() } else { e.to_string() };
+                    self.messages.push(Message::notice(i18n::fill("apply.refused", &[&path, &reason])));
+                    continue;
+                }
+            };
+            if old != block.code {
+                changes.push(PendingChange { path, old, new: block.code, accepted: None });
+            }
+        }
+        self.pending_changes = changes;
+        self.apply_selected = 0;
+        self.apply_scroll = 0;
+        Ok(self.pending_changes.len())
+    }
+
+    /// Accepts or rejects the selected file and moves on to the next one
+    /// still undecided.
+    fn decide_change(&mut self, accept: bool) {
+        let Some(change) = self.pending_changes.get_mut(self.apply_selected) else { return };
+        change.accepted = Some(accept);
+        let count = self.pending_changes.len();
+        if let Some(next) = (1..count).map(|n| (self.apply_selected + n) % count).find(|&i| self.pending_changes[i].accepted.is_none()) {
+            self.select_change(next);
+        }
+    }
+
+    fn select_change(&mut self, index: usize) {
+        if index < self.pending_changes.len() && index != self.apply_selected {
+            self.apply_selected = index;
+            self.apply_scroll = 0;
+        }
+    }
+
+    /// Writes the accepted files of the change set and leaves the rest
+    /// untouched. Each path is checked against the project root again, as
+    /// a symlink may have changed while the set was under review.
+    fn write_changes(&mut self) {
+        let mut skipped = 0;
+        for change in std::mem::take(&mut self.pending_changes) {
+            if change.accepted != Some(true) {
+                skipped += 1;
+                continue;
+            }
+            let written = inside_root(&self.root, &change.path).and_then(|target| write_file(&target, &change.new).map_err(Into::into));
+            match written {
+                Ok(()) => self.messages.push(Message::notice(i18n::fill("apply.wrote", &[&change.path]))),
+                Err(e) => self.messages.push(Message::notice(i18n::fill("apply.write_failed", &[&change.path, &e]))),
+            }
+        }
+        if skipped > 0 {
+            self.messages.push(Message::notice(i18n::fill("apply.skipped", &[&skipped])));
+        }
+        self.state = AppState::Chat;
+    }
+
+    fn local_only(&self) -> bool {
+        self.force_local || self.settings.local_only
+    }
+
+    fn client(&self) -> Client<OpenAIConfig> {
+        agent::client_for(&self.settings)
+    }
+
+    /// Settings for the next request, with session overrides applied.
+    fn request_settings(&self) -> Settings {
+        let response_format = match (self.json_mode, &self.settings.response_format) {
+            (false, _) => None,
+            (true, Some(format)) => Some(format.clone()),
+            (true, None) => Some("json_object".to_string()),
+        };
+        let persona = self.persona();
+        Settings {
+            model: self.model().to_string(),
+            temperature: persona.and_then(|p| p.temperature).or(self.settings.temperature),
+            system_prompt: persona.and_then(|p| p.system_prompt.clone()),
+            stop: self.stop_sequences().to_vec(),
+            response_format,
+            local_only: self.local_only(),
+            debug_log: if self.debug { debug::log_path(self.session.created) } else { None },
+            budget_override: self.budget_override,
+            ..self.settings.clone()
+        }
+    }
+
+    fn stop_sequences(&self) -> &[String] {
+        self.session_stop.as_deref().unwrap_or(&self.settings.stop)
+    }
+
+    /// `/stop` lists the active stop sequences, `/stop clear` drops them and
+    /// `/stop <seq>` adds one, all for this session only.
+    fn stop_command(&mut self, args: &str) {
+        match args.trim() {
+            "" => {}
+            "clear" => self.session_stop = Some(Vec::new()),
+            seq => {
+                let mut stop = self.stop_sequences().to_vec();
+                stop.extend(settings::parse_stop(seq));
+                self.session_stop = Some(stop);
+            }
+        }
+        let stop = self.stop_sequences().to_vec();
+        if stop.is_empty() {
+            self.messages.push(Message::notice(i18n::t("stop.none")));
+        } else {
+            self.messages.push(Message::notice(i18n::fill("stop.list", &[&settings::format_stop(&stop)])));
+            if stop.len() > 4 {
+                self.messages.push(Message::notice(i18n::t("stop.too_many")));
+            }
+        }
+    }
+
+    /// `/debug on` and `/debug off` switch recording of requests and raw
+    /// responses for this session; `/debug` alone shows where they go.
+    fn debug_command(&mut self, args: &str) {
+        match args.trim() {
+            "on" => self.debug = true,
+            "off" => self.debug = false,
+            "" => {}
+            other => {
+                self.messages.push(Message::notice(i18n::fill("debug.unknown", &[&other])));
+                return;
+            }
+        }
+        let log = debug::log_path(self.session.created).map_or_else(|| i18n::t("debug.no_dir").to_string(), |path| path.display().to_string());
+        self.messages.push(Message::notice(if self.debug {
+            i18n::fill("debug.on", &[&log])
+        } else {
+            i18n::fill("debug.off", &[&log])
+        }));
+    }
+
+    /// Chat pane lines showing `turn`: from its prompt to its answer. The
+    /// prompt line holds only the typed text, attachments go in front of it.
+    fn exchange_lines(&self, turn: &agent::Turn) -> Option<std::ops::Range<usize>> {
+        let end = self.messages.iter().rposition(|m| m.is(Role::Assistant) && m.text() == turn.assistant)?;
+        let start = self.messages[..end].iter().rposition(|m| m.is(Role::User) && turn.user.ends_with(&*m.text()))?;
+        Some(start..end + 1)
+    }
+
+    fn report(&mut self, result: Result<()>) {
+        if let Err(e) = result {
+            self.messages.push(Message::notice(i18n::fill("error", &[&e])));
+        }
+    }
+
+    fn open_settings(&mut self) {
+        self.state = AppState::Settings;
+        self.settings_errors.clear();
+        self.confirm_save = false;
+        self.last_confirm = None;
+        self.just_entered_settings = true;
+        self.connection_status = None;
+        self.reveal_key = false;
+    }
+
+    /// `/model` lists the provider's models to pick from; `/model <name>` switches.
+    fn model_command(&mut self, args: &str, response_tx: &UnboundedSender<AppEvent>) {
+        if !args.is_empty() {
+            self.switch_model(args);
+            return;
+        }
+        self.messages.push(Message::notice(i18n::t("model.loading")));
+        let response_tx_clone = response_tx.clone();
+        let client = self.client();
+        let settings = self.request_settings();
+
+        tokio::spawn(async move {
+            let result = agent::list_models(&client, &settings).await;
+            let _ = response_tx_clone.send(AgentResponse::Models(result).into());
+        });
+    }
+
+    fn compare_command(&mut self, args: &str) {
+        let models: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        if models.len() < 2 {
+            self.messages.push(Message::notice(i18n::fill("usage", &[&"/compare <model> <model> [model...]"])));
+        } else {
+            self.messages.push(Message::notice(i18n::fill("compare.next", &[&models.join(", ")])));
+            self.compare_next = Some(models);
+        }
+    }
+
+    fn diffresp_command(&mut self) {
+        match self.diff_responses() {
+            Some(diff) => {
+                self.response_diff = Some(diff);
+                self.diff_scroll = 0;
+                self.state = AppState::ResponseDiff;
+            }
+            None => self.messages.push(Message::notice(i18n::t("compare.need_two"))),
+        }
+    }
+
+    fn json_command(&mut self) {
+        self.json_mode = !self.json_mode;
+        let status = if self.json_mode { i18n::t("json.on") } else { i18n::t("json.off") };
+        self.messages.push(Message::notice(status.to_string()));
+    }
+
+    fn title_command(&mut self, title: &str) {
+        if title.is_empty() {
+            self.messages.push(Message::notice(i18n::fill("session.name", &[&self.session.display_name()])));
+        } else {
+            self.session.title = Some(title.to_string());
+            self.save_session();
+            self.messages.push(Message::notice(i18n::fill("session.renamed", &[&title])));
+        }
+    }
+
+    fn bookmarks_command(&mut self) {
+        if self.session.bookmarks.is_empty() {
+            self.messages.push(Message::notice(i18n::t("bookmark.none")));
+        } else {
+            self.bookmark_selected = 0;
+            self.state = AppState::Bookmarks;
+        }
+    }
+
+    fn tree_command(&mut self) {
+        let filter = context::Filter::new(&self.root, &self.settings);
+        let tree = context::project_tree(&self.root, &filter, TREE_DEPTH, TREE_ENTRIES);
+        self.attachments.push(context::Attachment::tree(tree));
+        self.messages.push(Message::notice(i18n::t("attach.tree")));
+    }
+
+    fn detach_command(&mut self) {
+        self.attachments.clear();
+        self.messages.push(Message::notice(i18n::t("attach.cleared")));
+    }
+
+    /// `/cache` shows the response cache; `/cache clear` empties it.
+    fn cache_command(&mut self, args: &str) {
+        match args {
+            "" => {
+                let (count, bytes) = cache::usage();
+                let state = if self.settings.response_cache { i18n::t("cache.on") } else { i18n::t("cache.off") };
+                self.messages.push(Message::notice(i18n::fill("cache.status", &[&state, &count, &(bytes / 1024)])));
+            }
+            "clear" => match cache::clear() {
+                Ok(count) => self.messages.push(Message::notice(i18n::fill("cache.cleared", &[&count]))),
+                Err(e) => self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+            },
+            _ => self.messages.push(Message::notice(i18n::fill("usage", &[&"/cache [clear]"]))),
+        }
+    }
+
+    fn stats_command(&mut self, args: &str) {
+        self.stats_report = match args {
+            "" => stats::report(),
+            "full" => stats::dashboard(),
+            _ => {
+                self.messages.push(Message::notice(i18n::fill("usage", &[&"/stats [full]"])));
+                return;
+            }
+        };
+        self.state = AppState::Stats;
+    }
+
+    fn cargo_command(&mut self, subcommand: &str, response_tx: &UnboundedSender<AppEvent>) {
+        if !cargo::SUBCOMMANDS.contains(&subcommand) {
+            self.messages.push(Message::notice(i18n::fill("usage", &[&format!("/cargo <{}>", cargo::SUBCOMMANDS.join("|"))])));
+        } else if self.request.is_some() {
+            self.messages.push(Message::notice(i18n::t("busy")));
+        } else {
+            self.fix_loop = Some((subcommand.to_string(), 0));
+            self.run_cargo(response_tx);
+        }
+    }
+
+    /// `/explain <symbol> [question]` looks the symbol up with the language
+    /// server, then asks about it.
+    fn explain_command(&mut self, args: &str, response_tx: &UnboundedSender<AppEvent>) {
+        let (name, question) = args.split_once(' ').unwrap_or((args, ""));
+        let (name, question) = (name.to_string(), question.trim().to_string());
+        if name.is_empty() {
+            self.messages.push(Message::notice(i18n::fill("usage", &[&"/explain <symbol> [question]"])));
+            return;
+        }
+        if self.request.is_some() {
+            self.messages.push(Message::notice(i18n::t("busy")));
+            return;
+        }
+        let command = match self.settings.lsp_command.is_empty() {
+            true => vec![lsp::DEFAULT_COMMAND.to_string()],
+            false => self.settings.lsp_command.clone(),
+        };
+        let starting = if self.lsp.initialized() { String::new() } else { format!(" {}", i18n::t("explain.starting")) };
+        self.messages.push(Message::notice(i18n::fill("explain.looking_up", &[&name, &starting])));
+        let (tx, lsp, root) = (response_tx.clone(), self.lsp.clone(), self.root.clone());
+        let filter = context::Filter::new(&self.root, &self.settings);
+        let task = tokio::spawn(async move {
+            let result = match lsp.get_or_try_init(|| lsp::LspClient::start(&command, &root)).await {
+                Ok(client) => client.describe_symbol(&name, &filter).await,
+                Err(e) => Err(e),
+            };
+            let _ = tx.send(AgentResponse::Symbol(name, result, question).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    fn fix_command(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        if self.request.is_some() {
+            self.messages.push(Message::notice(i18n::t("busy")));
+            return;
+        }
+        self.messages.push(Message::notice(i18n::fill("cargo.running", &[&"build"])));
+        let tx = response_tx.clone();
+        let task = tokio::spawn(async move {
+            let _ = tx.send(AgentResponse::Diagnostics(cargo::diagnostics().await).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    /// `/commit` asks for a commit message for the staged diff, which is
+    /// read off the UI thread since a large one takes a while.
+    fn commit_command(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        self.messages.push(Message::notice(THINKING));
+        let response_tx_clone = response_tx.clone();
+        let client = self.client();
+        let settings = Settings { response_format: None, ..self.request_settings() };
+
+        let task = tokio::spawn(async move {
+            let result = async {
+                let diff = tokio::task::spawn_blocking(git::staged_diff).await??;
+                if diff.trim().is_empty() {
+                    return Ok(None);
+                }
+                agent::complete(&client, &settings, COMMIT_PROMPT, &diff).await.map(Some)
+            }
+            .await;
+            let _ = response_tx_clone.send(AgentResponse::CommitMessage(result).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    fn encrypt_command(&mut self) {
+        self.passphrase_input.clear();
+        self.state = AppState::Passphrase;
+    }
+
+    fn decrypt_command(&mut self) {
+        if self.passphrase.is_some() {
+            self.set_passphrase(None);
+        } else {
+            self.messages.push(Message::notice(i18n::t("encrypt.not_encrypted")));
+        }
+    }
+
+    fn apply_command(&mut self) {
+        match self.collect_changes() {
+            Ok(0) => self.messages.push(Message::notice(i18n::t("apply.none"))),
+            Ok(_) => self.state = AppState::Apply,
+            Err(e) => self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+        }
+    }
+
+    /// A command registered by `plugin`, run in the background.
+    fn plugin_command(&mut self, plugin: Arc<plugins::Plugin>, name: &str, args: &str, response_tx: &UnboundedSender<AppEvent>) {
+        let (name, args) = (name.to_string(), args.to_string());
+        let host = Host(response_tx.clone());
+
+        tokio::spawn(async move {
+            let result = plugin.run("command", &name, &args, &host).await;
+            let _ = host.0.send(AgentResponse::Plugin(result).into());
+        });
+    }
+
+    /// `/delete` lists the exchanges sent as context and `/delete <n>` removes
+    /// the nth from the context, the chat pane and the saved session, and
+    /// its prompt from the prompt history, the draft and the debug log.
+    fn delete_command(&mut self, args: &str) {
+        let turns = &self.session.conversation;
+        match args.trim().parse::<usize>() {
+            Ok(n) if (1..=turns.len()).contains(&n) => {
+                let turn = self.session.conversation.remove(n - 1);
+                let lines = self.exchange_lines(&turn);
+                // the prompt as typed, without the files attached to it
+                let typed = lines.as_ref().map_or_else(|| turn.user.clone(), |lines| self.messages[lines.start].text().into_owned());
+                if let Some(lines) = lines {
+                    self.messages.splice(lines, [Message::notice(i18n::fill("delete.done", &[&n]))]);
+                    self.forget_selection();
+                } else {
+                    self.messages.push(Message::notice(i18n::fill("delete.done", &[&n])));
+                }
+                self.history.remove(&typed);
+                if self.session.draft == typed {
+                    self.session.draft.clear();
+                }
+                // a summary written since may quote it
+                if let Some(summary) = &mut self.session.summary {
+                    for text in [&turn.user, &typed] {
+                        if !text.is_empty() {
+                            *summary = summary.replace(text.as_str(), "[deleted]");
+                        }
+                    }
+                }
+                if let Some(path) = debug::log_path(self.session.created) {
+                    for text in [&turn.user, &typed] {
+                        if let Err(e) = debug::scrub(&path, text) {
+                            self.messages.push(Message::notice(i18n::fill("debug.scrub_failed", &[&e])));
+                            break;
+                        }
+                    }
+                }
+                self.save_session();
+            }
+            _ if turns.is_empty() => self.messages.push(Message::notice(i18n::t("delete.empty"))),
+            _ => {
+                let list: Vec<String> = turns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| {
+                        let typed = t.user.lines().last().unwrap_or_default();
+                        let preview: String = typed.chars().take(60).collect();
+                        format!("  {}. {}{}", i + 1, if t.pinned { "📌 " } else { "" }, preview)
+                    })
+                    .collect();
+                self.messages.push(Message::notice(format!("{}\n{}", i18n::t("delete.list"), list.join("\n"))));
+            }
+        }
+    }
+
+    /// `/undo` drops the last exchange from the context, the chat pane and the
+    /// saved session, and puts its prompt back in the input box.
+    fn undo(&mut self) {
+        if self.request.is_some() {
+            self.messages.push(Message::notice(i18n::t("busy")));
+            return;
+        }
+        let Some(turn) = self.session.conversation.pop() else {
+            self.messages.push(Message::notice(i18n::t("undo.nothing")));
+            return;
+        };
+        let answer = format!("{}{}", Role::Assistant.prefix(), turn.assistant);
+        self.session.bookmarks.retain(|b| *b != answer);
+        match self.exchange_lines(&turn) {
+            Some(lines) => {
+                self.set_input(self.messages[lines.start].text().into_owned());
+                self.messages.drain(lines);
+                self.forget_selection();
+            }
+            None => self.set_input(turn.user),
+        }
+        self.save_session();
+    }
+
+    /// The budget closest to its limit, as `(period, spent, limit)`.
+    fn budget(&self) -> Option<(&'static str, f64, f64)> {
+        let (day, month) = stats::spent();
+        [(i18n::t("budget.daily"), day, self.settings.daily_budget), (i18n::t("budget.monthly"), month, self.settings.monthly_budget)]
+            .into_iter()
+            .filter_map(|(period, spent, limit)| Some((period, spent, limit?)))
+            .max_by(|a, b| (a.1 / a.2).total_cmp(&(b.1 / b.2)))
+    }
+
+    /// `/budget` shows the spend against each budget; `/budget override`
+    /// lets requests through for the rest of the session once one is used up.
+    fn budget_command(&mut self, args: &str) {
+        match args.trim() {
+            "" => {
+                let (day, month) = stats::spent();
+                let limit = |limit: Option<f64>| limit.map_or(i18n::t("budget.none").to_string(), |l| i18n::fill("budget.limit", &[&format!("{:.2}", l)]));
+                self.messages.push(Message::notice(i18n::fill(
+                    "budget.spent",
+                    &[&format!("{:.4}", day), &limit(self.settings.daily_budget), &format!("{:.4}", month), &limit(self.settings.monthly_budget)],
+                )));
+            }
+            "override" => {
+                self.budget_override = true;
+                self.messages.push(Message::notice(i18n::t("budget.overridden")));
+            }
+            other => self.messages.push(Message::notice(i18n::fill("unknown_option", &[&other, &"/budget [override]"]))),
+        }
+    }
+
+    /// `/tokens` lists everything the next request will carry with its
+    /// estimated size and the running total against the context window.
+    fn tokens_command(&mut self) {
+        let settings = self.request_settings();
+        let window = agent::context_window(&settings);
+        let mut rows: Vec<(String, usize)> = vec![(i18n::t("tokens.system").to_string(), tokens::count(&settings.model, &agent::system_prompt(&settings)))];
+        if let Some(summary) = &self.session.summary {
+            rows.push((i18n::t("tokens.summary").to_string(), tokens::count(&settings.model, summary)));
+        }
+        for (i, turn) in self.session.conversation.iter().enumerate() {
+            // the typed text comes after any attached files
+            let typed: String = turn.user.lines().last().unwrap_or_default().chars().take(40).collect();
+            let answer: String = turn.assistant.lines().next().unwrap_or_default().chars().take(40).collect();
+            rows.push((i18n::fill("tokens.you", &[&(i + 1), &typed]), tokens::count(&settings.model, &turn.user)));
+            rows.push((i18n::fill("tokens.answer", &[&(i + 1), &answer]), tokens::count(&settings.model, &turn.assistant)));
+        }
+        for attachment in &self.attachments {
+            rows.push((format!("📎 {}", attachment.label()), tokens::count(&settings.model, &attachment.fenced())));
+        }
+        let mut lines = vec![i18n::t("tokens.heading").to_string()];
+        let mut total = 0;
+        for (label, count) in rows {
+            total += count + tokens::MESSAGE_OVERHEAD;
+            lines.push(format!("  {:>7} {:>8}  {}", count, total, label));
+        }
+        let reply = agent::reply_tokens(&settings);
+        let free = window.saturating_sub(total + reply);
+        lines.push(i18n::fill("tokens.next", &[&total, &window, &(total * 100 / window.max(1)), &reply, &free]));
+        let dropped = agent::turns_to_drop(&settings, self.session.summary.as_deref(), &self.session.conversation, "");
+        if dropped > 0 {
+            lines.push(i18n::fill("tokens.trimmed", &[&dropped]));
+        }
+        self.messages.push(Message::notice(lines.join("\n")));
+    }
+
+    /// `/links` lists the URLs in the last answer; `/links <n>` opens one
+    /// in the browser.
+    fn links_command(&mut self, args: &str) {
+        let urls = self.last_response().as_deref().map(links::find).unwrap_or_default();
+        match args.trim() {
+            "" if urls.is_empty() => self.messages.push(Message::notice(i18n::t("links.none"))),
+            "" => {
+                let list: Vec<String> = urls.iter().enumerate().map(|(i, url)| format!("  {}. {}", i + 1, url)).collect();
+                self.messages.push(Message::notice(format!("{}\n{}", i18n::t("links.list"), list.join("\n"))));
+            }
+            n => match n.parse::<usize>().ok().and_then(|n| urls.get(n.checked_sub(1)?)) {
+                Some(url) => match links::open(url) {
+                    Ok(()) => self.messages.push(Message::notice(i18n::fill("links.opened", &[&url]))),
+                    Err(e) => self.messages.push(Message::notice(i18n::fill("open_failed", &[&url, &e]))),
+                },
+                None => self.messages.push(Message::notice(i18n::t("links.unknown"))),
+            },
+        }
+    }
+
+    /// Rings the bell, and sends a desktop notification when enabled, unless
+    /// the terminal says it is in focus; terminals that never report focus
+    /// (tmux without `focus-events`) always get notified.
+    fn notify_done(&mut self, answer: &str) {
+        if self.has_focus == Some(true) {
+            return;
+        }
+        notify::bell();
+        if self.settings.desktop_notifications {
+            let first = answer.trim().lines().next().unwrap_or_default();
+            notify::desktop(i18n::t("notify.ready"), &first.chars().take(200).collect::<String>());
+        }
+    }
+
+    /// `/images` lists the images in the last answer; `/images <n>` opens
+    /// one in the desktop's viewer.
+    fn images_command(&mut self, args: &str) {
+        let found = self.last_response().as_deref().map(images::find).unwrap_or_default();
+        match args.trim() {
+            "" if found.is_empty() => self.messages.push(Message::notice(i18n::t("images.none"))),
+            "" => {
+                let list: Vec<String> = found.iter().enumerate().map(|(i, (alt, src))| format!("  {}. {} ({})", i + 1, alt, images::label(src))).collect();
+                self.messages.push(Message::notice(format!("{}\n{}", i18n::t("images.list"), list.join("\n"))));
+            }
+            n => match n.parse::<usize>().ok().and_then(|n| found.get(n.checked_sub(1)?)) {
+                Some((_, src)) if src.starts_with("data:") => self.messages.push(Message::notice(i18n::t("images.embedded"))),
+                Some((_, src)) => match links::open(src) {
+                    Ok(()) => self.messages.push(Message::notice(i18n::fill("images.opened", &[&src]))),
+                    Err(e) => self.messages.push(Message::notice(i18n::fill("open_failed", &[&src, &e]))),
+                },
+                None => self.messages.push(Message::notice(i18n::t("images.unknown"))),
+            },
+        }
+    }
+
+    /// `/clear` empties the chat pane while the model keeps the conversation;
+    /// `/clear context` forgets the conversation too.
+    fn clear_command(&mut self, args: &str) {
+        let thinking = self.messages.last().is_some_and(|m| m.text() == THINKING);
+        self.messages.clear();
+        self.forget_selection();
+        match args.trim() {
+            "" => self.messages.push(Message::notice(i18n::fill("clear.chat", &[&self.session.conversation.len()]))),
+            "context" => {
+                self.session.conversation.clear();
+                self.session.summary = None;
+                self.messages.push(Message::notice(i18n::t("clear.context")));
+                self.save_session();
+            }
+            other => self.messages.push(Message::notice(i18n::fill("unknown_option", &[&other, &"/clear [context]"]))),
+        }
+        if thinking {
+            self.messages.push(Message::notice(THINKING));
+        }
+    }
+
+    /// `/reset` saves the current session, where the sidebar still lists it,
+    /// and starts an empty one.
+    fn reset_session(&mut self) {
+        if self.pending_prompt.is_some() {
+            self.messages.push(Message::notice(i18n::t("session.busy")));
+            return;
+        }
+        let archived = (!self.session.conversation.is_empty()).then(|| {
+            self.save_session();
+            self.session.display_name()
+        });
+        self.open_session(Ok(session::Session::new()));
+        if let Some(name) = archived {
+            self.messages.push(Message::notice(i18n::fill("session.archived", &[&name])));
+        }
+        if self.sidebar.is_some() {
+            self.sidebar = Some(session::list());
+        }
+    }
+
+    /// The exchange numbered `args` by `/delete`, or the latest one.
+    fn exchange_index(&self, args: &str) -> Option<usize> {
+        let len = self.session.conversation.len();
+        match args.trim() {
+            "" => len.checked_sub(1),
+            n => n.parse::<usize>().ok().filter(|n| (1..=len).contains(n)).map(|n| n - 1),
+        }
+    }
+
+    /// `/pin [n]` toggles whether an exchange survives context trimming.
+    fn pin_command(&mut self, args: &str) {
+        let Some(i) = self.exchange_index(args) else {
+            self.messages.push(Message::notice(i18n::t("delete.unknown")));
+            return;
+        };
+        let turn = &mut self.session.conversation[i];
+        turn.pinned = !turn.pinned;
+        let state = if turn.pinned { i18n::t("pin.pinned") } else { i18n::t("pin.unpinned") };
+        self.messages.push(Message::notice(i18n::fill("pin.status", &[&(i + 1), &state])));
+        self.save_session();
+    }
+
+    /// `/bookmark [n]` toggles a bookmark on the answer of an exchange, the
+    /// last answer by default.
+    fn bookmark_command(&mut self, args: &str) {
+        let answer = match args.trim() {
+            "" => self.last_response().map(Cow::into_owned),
+            _ => self.exchange_index(args).map(|i| self.session.conversation[i].assistant.clone()),
+        };
+        let Some(answer) = answer.map(|a| format!("{}{}", Role::Assistant.prefix(), a)) else {
+            self.messages.push(Message::notice(i18n::t("bookmark.no_answer")));
+            return;
+        };
+        if let Some(at) = self.session.bookmarks.iter().position(|b| *b == answer) {
+            self.session.bookmarks.remove(at);
+            self.messages.push(Message::notice(i18n::t("bookmark.removed")));
+        } else {
+            self.session.bookmarks.push(answer);
+            self.messages.push(Message::notice(i18n::t("bookmark.added")));
+        }
+        self.save_session();
+    }
+
+    fn jump_to_bookmark(&mut self) {
+        let Some(bookmark) = self.session.bookmarks.get(self.bookmark_selected) else { return };
+        match self.messages.iter().position(|m| m.line() == *bookmark) {
+            Some(at) => self.jump_to = Some(at),
+            None => self.messages.push(Message::notice(i18n::t("bookmark.gone"))),
+        }
+    }
+
+    fn edited_settings(&self) -> Result<Settings> {
+        self.settings.with_fields(&self.settings_input)
+    }
+
+    /// The fields of the settings screen that cannot be saved, by field.
+    fn check_settings(&self) -> Result<(), BTreeMap<usize, String>> {
+        match self.edited_settings() {
+            Err(e) => match e.downcast::<settings::FieldErrors>() {
+                Ok(settings::FieldErrors(errors)) => Err(errors),
+                Err(_) => Ok(()),
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Ends the request in flight, with how long it took.
+    fn finish_request(&mut self) -> Option<Duration> {
+        self.pop_thinking();
+        self.request.take().map(|(started, _)| started.elapsed())
+    }
+
+    fn pop_thinking(&mut self) {
+        if let Some(last_msg) = self.messages.last() {
+            if last_msg.text() == THINKING {
+                self.messages.pop();
+            }
+        }
+    }
+
+    fn toggle_sidebar(&mut self) {
+        self.sidebar = match self.sidebar {
+            Some(_) => None,
+            None => Some(session::list()),
+        };
+        self.sidebar_selected = 0;
+    }
+
+    fn sidebar_step(&mut self, down: bool) {
+        let rows = self.sidebar.as_ref().map_or(0, Vec::len) + 1;
+        self.sidebar_selected = if down { (self.sidebar_selected + 1).min(rows - 1) } else { self.sidebar_selected.saturating_sub(1) };
+    }
+
+    /// Switches the chat pane to the selected sidebar session, or a new one.
+    fn open_selected_session(&mut self) {
+        if self.pending_prompt.is_some() {
+            self.messages.push(Message::notice(i18n::t("session.busy")));
+            return;
+        }
+        let selected = match self.sidebar_selected {
+            0 => Ok(session::Session::new()),
+            i => match self.sidebar.as_ref().and_then(|list| list.get(i - 1)) {
+                Some((path, ..)) => session::Session::load(path),
+                None => return,
+            },
+        };
+        if self.open_session(selected) {
+            self.sidebar = Some(session::list());
+        }
+    }
+
+    /// Shows `selected` in the chat pane; false when it could not be loaded.
+    fn open_session(&mut self, selected: Result<session::Session>) -> bool {
+        match selected {
+            Ok(session) => {
+                self.save_draft();
+                self.session = session;
+                self.messages = self.session.messages.clone();
+                self.set_input(self.session.draft.clone());
+                self.history.set_session(&self.session.prompts);
+                self.forget_selection();
+                self.attachments.clear();
+                true
+            }
+            Err(e) => {
+                self.messages.push(Message::notice(i18n::fill("error.open_session", &[&e])));
+                false
+            }
+        }
+    }
+
+    /// Saves the session and remembers it, with the unsent input, for the
+    /// next launch after a crash or a termination signal.
+    pub fn save_for_recovery(&mut self) {
+        let mut input = self.input.clone();
+        if input.is_empty() && self.pending_prompt.is_some() {
+            // the prompt in flight is lost; offer the typed text again
+            let typed = self.messages.iter().rev().find(|m| m.is(Role::User));
+            input = typed.map(|m| m.text().into_owned()).unwrap_or_default();
+        }
+        self.session.draft = input.clone();
+        self.save_session();
+        if let Some(path) = self.session.path() {
+            let _ = recovery::save(path, &input);
+        }
+    }
+
+    /// Gets the app ready for its event loop: local-only mode when
+    /// `force_local` or the settings ask for it, the MCP servers and plugins
+    /// started, and the session left by a crash reopened. `passphrase` is the
+    /// one the config file was decrypted with, kept to save it again.
+    pub fn start(&mut self, passphrase: Option<String>, force_local: bool, response_tx: &UnboundedSender<AppEvent>) {
+        self.passphrase = passphrase;
+        self.force_local = force_local;
+        self.toolbox.set_local_only(self.local_only());
+        self.toolbox.set_root(&self.root);
+        self.connect_mcp_servers(response_tx);
+        self.load_plugins(response_tx);
+        self.recover();
+    }
+
+    /// Adds a line to the transcript, such as a warning from loading the settings.
+    pub fn push_message(&mut self, line: String) {
+        self.messages.push(Message::notice(line));
+    }
+
+    /// Reopens the session saved by `save_for_recovery`, if Gentor did not
+    /// exit cleanly last time.
+    fn recover(&mut self) {
+        let Some(marker) = recovery::take() else { return };
+        if self.open_session(session::Session::load(&marker.session)) {
+            self.set_input(marker.input);
+            self.messages.push(Message::notice(i18n::t("session.recovered")));
+        }
+    }
+
+    /// Saves the input with the session when it changed since last time.
+    pub fn save_draft(&mut self) {
+        self.draft_saved = Instant::now();
+        if self.input != self.session.draft {
+            self.session.draft = self.input.clone();
+            self.save_session();
+        }
+    }
+
+    fn save_session(&mut self) {
+        self.session.messages = self.messages.iter().filter(|m| m.text() != THINKING).cloned().collect();
+        self.session.prompts = self.history.session().to_vec();
+        if let Err(e) = self.session.save() {
+            self.push_before_thinking(Message::notice(i18n::fill("error.save_session", &[&e])));
+        }
+    }
+
+    /// Word diff between the first two answers of the last comparison, or
+    /// else between the last two assistant answers.
+    fn diff_responses(&self) -> Option<(String, Vec<(diff::Change, String)>)> {
+        let compared: Vec<(&str, &str)> = self
+            .comparison
+            .iter()
+            .flat_map(|c| &c.answers)
+            .filter_map(|(model, answer)| match answer {
+                Some(Ok(reply)) => Some((model.as_str(), reply.content.as_str())),
+                _ => None,
+            })
+            .collect();
+        let (title, old, new) = if let [(a, old), (b, new), ..] = compared[..] {
+            (format!("{} → {}", a, b), Cow::Borrowed(old), Cow::Borrowed(new))
+        } else {
+            let mut answers = self.messages.iter().rev().filter(|m| m.is(Role::Assistant)).map(Message::text);
+            let new = answers.next()?;
+            let old = answers.next()?;
+            (i18n::t("compare.previous_last").to_string(), old, new)
+        };
+        let changes = diff::words(&old, &new).into_iter().map(|(change, piece)| (change, piece.to_string())).collect();
+        Some((title, changes))
+    }
+
+    /// Continues the conversation with the selected comparison answer.
+    fn pick_comparison(&mut self) {
+        let Some(comparison) = &self.comparison else { return };
+        let (model, answer) = &comparison.answers[comparison.selected];
+        let Some(Ok(reply)) = answer else { return };
+        self.session.conversation.push(agent::Turn { user: comparison.prompt.clone(), assistant: reply.content.clone(), pinned: false, speed: reply.speed() });
+        if let Some(reasoning) = &reply.reasoning {
+            self.messages.push(Message::new(Role::Reasoning, reasoning.trim()));
+        }
+        let message = Message::assistant(reply.content.trim(), model, reply.usage);
+        let model = model.clone();
+        if model != self.model() {
+            self.switch_model(&model);
+        }
+        self.messages.push(message);
+        self.save_session();
+        self.state = AppState::Chat;
+    }
+
+    fn cancel_request(&mut self) {
+        if let Some((_, handle)) = self.request.take() {
+            handle.abort();
+            self.pop_thinking();
+            self.pending_prompt = None;
+            self.fix_loop = None;
+            self.messages.push(Message::notice(i18n::t("request.cancelled")));
+        }
+    }
+
+    /// Sends the input box as a prompt, with pending attachments in front of
+    /// it and the conversation trimmed to fit the context window.
+    fn send_prompt(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        if let Some((period, spent, limit)) = self.budget().filter(|b| b.1 >= b.2 && !self.budget_override) {
+            self.fix_loop = None;
+            self.messages.push(Message::notice(i18n::fill("budget.used_up", &[&period, &format!("{:.2}", limit), &format!("{:.2}", spent)])));
+            return;
+        }
+        let mut prompt = self.input.clone();
+        let has_tree = self.attachments.iter().any(|a| a.label() == "project tree");
+        if self.settings.auto_tree && !has_tree {
+            let filter = context::Filter::new(&self.root, &self.settings);
+            let tree = context::project_tree(&self.root, &filter, AUTO_TREE_DEPTH, AUTO_TREE_ENTRIES);
+            self.attachments.insert(0, context::Attachment::tree(tree));
+        }
+        self.messages.push(Message::user(prompt.clone(), self.attachments.iter().map(|a| a.label().to_string()).collect()));
+        if !self.attachments.is_empty() {
+            let files: Vec<String> = self.attachments.drain(..).map(|a| a.focused(&prompt).fenced()).collect();
+            prompt = format!("{}\n\n{}", files.join("\n\n"), prompt);
+        }
+        let settings = self.request_settings();
+        let dropped = agent::turns_to_drop(&settings, self.session.summary.as_deref(), &self.session.conversation, &prompt);
+        // trimmed turns stay in the session, and summarized ones until their summary is back
+        let (history, trimmed) = agent::drop_oldest(&self.session.conversation, dropped);
+        let summarize = dropped > 0 && self.settings.summarize_history;
+        if dropped > 0 && !summarize {
+            self.messages.push(Message::notice(i18n::fill("request.trimmed", &[&dropped])));
+        }
+        self.messages.push(Message::notice(THINKING));
+        self.input.clear();
+        let compare = self.compare_next.take();
+
+        let response_tx_clone = response_tx.clone();
+        let client = self.client();
+        let toolbox = self.toolbox.clone();
+        let mut summary = self.session.summary.clone();
+        self.chat_scroll = 0;
+        if let Some(models) = &compare {
+            let answers = models.iter().map(|m| (m.clone(), None)).collect();
+            self.comparison = Some(Comparison { prompt: prompt.clone(), answers, selected: 0 });
+            self.state = AppState::Compare;
+        } else {
+            self.pending_prompt = Some(prompt.clone());
+        }
+
+        let task = tokio::spawn(async move {
+            let host = Host(response_tx_clone);
+            if summarize {
+                let result = agent::summarize(&client, &settings, summary.as_deref(), &trimmed).await;
+                if let Ok(text) = &result {
+                    summary = Some(text.clone());
+                }
+                let _ = host.0.send(AgentResponse::Summary(result, trimmed).into());
+            }
+            let Some(models) = compare else {
+                let result =
+                    agent::run_with_fallbacks(&client, &settings, &toolbox, summary.as_deref(), &history, &prompt, &host).await;
+                let _ = host.0.send(AgentResponse::Chat(result).into());
+                return;
+            };
+            let runs = models.into_iter().enumerate().map(|(i, model)| {
+                let settings = Settings { model, ..settings.clone() };
+                let (client, toolbox, summary, history, prompt, host) = (&client, &toolbox, &summary, &history, &prompt, &host);
+                async move {
+                    let result = agent::run_agent(client, &settings, toolbox, summary.as_deref(), history, prompt, host).await;
+                    let _ = host.0.send(AgentResponse::Compared(i, result).into());
+                }
+            });
+            futures::future::join_all(runs).await;
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    /// Swaps the summarized `turns` for their `summary` in one step, so a
+    /// summary that failed or was cancelled loses nothing. A summary covering
+    /// a turn deleted while it was written is discarded instead, returning
+    /// false, and the turns that remain are summarized by the next request.
+    fn replace_with_summary(&mut self, summary: String, turns: &[agent::Turn]) -> bool {
+        let covers = |next: &agent::Turn, turn: &agent::Turn| !turn.pinned && next.user == turn.user && next.assistant == turn.assistant;
+        let mut covered = turns.iter().peekable();
+        for turn in &self.session.conversation {
+            if covered.peek().is_some_and(|next| covers(next, turn)) {
+                covered.next();
+            }
+        }
+        if covered.peek().is_some() {
+            return false;
+        }
+        let mut covered = turns.iter().peekable();
+        self.session.conversation.retain(|turn| {
+            let matches = covered.peek().is_some_and(|next| covers(next, turn));
+            if matches {
+                covered.next();
+            }
+            !matches
+        });
+        self.session.summary = Some(summary);
+        self.save_session();
+        true
+    }
+
+    /// `/cmd <task>` asks the model for a shell command, shown for editing,
+    /// running or copying once it arrives.
+    fn cmd_command(&mut self, task: &str, response_tx: &UnboundedSender<AppEvent>) {
+        let task = task.trim().to_string();
+        if task.is_empty() {
+            self.messages.push(Message::notice(i18n::fill("usage", &[&"/cmd <what the command should do>"])));
+            return;
+        }
+        self.messages.push(Message::user(format!("/cmd {}", task), Vec::new()));
+        self.messages.push(Message::notice(THINKING));
+        let tx = response_tx.clone();
+        let settings = self.request_settings();
+        let task = tokio::spawn(async move {
+            let _ = tx.send(AgentResponse::Suggestion(shell::suggest(&settings, &task).await).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    /// Keys of the `/cmd` popup. Running is the only way a suggested command
+    /// executes, and it shows the exact command in the chat first.
+    fn suggestion_key(&mut self, key: KeyEvent, response_tx: &UnboundedSender<AppEvent>) {
+        let Some(suggestion) = &mut self.suggestion else {
+            self.state = AppState::Chat;
+            return;
+        };
+        if self.editing_suggestion {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_suggestion = false,
+                KeyCode::Backspace => editing::pop_char(&mut suggestion.command),
+                KeyCode::Char(c) => suggestion.command.push(c),
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Char('e') => self.editing_suggestion = true,
+            KeyCode::Char('r') => {
+                let command = suggestion.command.clone();
+                self.suggestion = None;
+                self.state = AppState::Chat;
+                self.messages.push(Message::notice(format!("💻 $ {}", command)));
+                self.messages.push(Message::notice(THINKING));
+                let tx = response_tx.clone();
+                let task = tokio::spawn(async move {
+                    let result = shell::capture(&command).await;
+                    let _ = tx.send(AgentResponse::ShellOutput(command, result).into());
+                });
+                self.request = Some((Instant::now(), task.abort_handle()));
+            }
+            KeyCode::Char('c') => {
+                match clipboard::copy(&suggestion.command) {
+                    Ok(()) => self.messages.push(Message::notice(i18n::fill("cmd.copied", &[&suggestion.command]))),
+                    Err(e) => self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+                }
+                self.suggestion = None;
+                self.state = AppState::Chat;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.suggestion = None;
+                self.state = AppState::Chat;
+            }
+            _ => {}
+        }
+    }
+
+    /// `/prdesc [base]` writes a pull request title and description from the
+    /// branch's commits and diff against `base` (the default branch when
+    /// omitted), to copy or push to GitHub.
+    fn prdesc_command(&mut self, args: &str, response_tx: &UnboundedSender<AppEvent>) {
+        let collected = (|| -> Result<_> {
+            let base = match args.trim() {
+                "" => git::default_base()?,
+                base => base.to_string(),
+            };
+            let branch = git::current_branch()?;
+            let log = git::branch_log(&base)?;
+            let mut diff = git::branch_diff(&base)?;
+            if let Some((cut, _)) = diff.char_indices().nth(MAX_PR_DIFF) {
+                diff.truncate(cut);
+                diff.push_str("\n[diff truncated]");
+            }
+            Ok((base, branch, log, diff))
+        })();
+        let (base, branch, log, diff) = match collected {
+            Ok(collected) => collected,
+            Err(e) => return self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+        };
+        if log.trim().is_empty() {
+            self.messages.push(Message::notice(i18n::fill("prdesc.no_commits", &[&branch, &base])));
+            return;
+        }
+        self.messages.push(Message::notice(i18n::fill("prdesc.describing", &[&branch, &base])));
+        self.messages.push(Message::notice(THINKING));
+        self.pr_description = Some(PrDescription { branch, base, title: String::new(), body: String::new() });
+        let tx = response_tx.clone();
+        let client = self.client();
+        let settings = Settings { response_format: None, ..self.request_settings() };
+        let task = tokio::spawn(async move {
+            let text = format!("Commits:\n{}\n\nDiff:\n```diff\n{}\n```", log.trim(), diff);
+            let result = agent::complete(&client, &settings, PRDESC_PROMPT, &text).await.map(|answer| split_title(&answer));
+            let _ = tx.send(AgentResponse::PrDescription(result).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    fn prdesc_key(&mut self, key: KeyEvent, response_tx: &UnboundedSender<AppEvent>) {
+        let Some(description) = &self.pr_description else {
+            self.state = AppState::Chat;
+            return;
+        };
+        match key.code {
+            KeyCode::Char('c') => {
+                match clipboard::copy(&format!("{}\n\n{}", description.title, description.body)) {
+                    Ok(()) => self.messages.push(Message::notice(i18n::t("prdesc.copied"))),
+                    Err(e) => self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+                }
+                self.state = AppState::Chat;
+            }
+            KeyCode::Char('p') if self.local_only() => {
+                self.messages.push(Message::notice(i18n::t("prdesc.local_only")));
+                self.state = AppState::Chat;
+            }
+            KeyCode::Char('p') => {
+                let config = self.settings.github.clone().unwrap_or_default();
+                let base = description.base.strip_prefix("origin/").unwrap_or(&description.base).to_string();
+                let (branch, title, body) = (description.branch.clone(), description.title.clone(), description.body.clone());
+                self.messages.push(Message::notice(i18n::fill("prdesc.pushing", &[&branch])));
+                self.messages.push(Message::notice(THINKING));
+                let tx = response_tx.clone();
+                let task = tokio::spawn(async move {
+                    let _ = tx.send(AgentResponse::PrPublished(github::publish_pr(&config, &branch, &base, &title, &body).await).into());
+                });
+                self.request = Some((Instant::now(), task.abort_handle()));
+                self.state = AppState::Chat;
+            }
+            KeyCode::Esc => self.state = AppState::Chat,
+            _ => {}
+        }
+    }
+
+    /// `/gentest <path> [item]` asks for tests of a file, or of one item in
+    /// it, in the style of an existing test file, and opens the test file
+    /// the answer writes for review before anything is saved.
+    fn gentest_command(&mut self, args: &str, response_tx: &UnboundedSender<AppEvent>) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        let (Some(path), item) = (parts.next(), parts.next()) else {
+            anyhow::bail!("usage: /gentest <path> [item]");
+        };
+        let filter = context::Filter::new(&self.root, &self.settings);
+        let location = testgen::location(path);
+        let mut target = context::Attachment::load(path, None, &filter)?;
+        // a test file written in the source file itself needs all of it back
+        if let Some(item) = item.filter(|_| location.as_deref() != Some(path)) {
+            target = target.scoped(item);
+        }
+        self.attachments.push(target);
+        if let Some(location) = location.as_deref().filter(|l| *l != path && Path::new(l).exists()) {
+            self.attachments.push(context::Attachment::load(location, None, &filter)?);
+        }
+        if let Some(example) = testgen::example(path, location.as_deref()) {
+            self.attachments.push(context::Attachment::load(&example, None, &filter)?.truncated(testgen::MAX_EXAMPLE_LINES));
+        }
+        let subject = match item {
+            Some(item) => format!("`{}` in {}", item, path),
+            None => path.to_string(),
+        };
+        let destination = match &location {
+            Some(location) => format!("Write them in {}.", location),
+            None => "Write them in the test file this project's conventions call for.".to_string(),
+        };
+        self.input = format!("Tests for {}. {} {}", subject, destination, testgen::GENTEST_PROMPT);
+        self.send_prompt(response_tx);
+        // not when the budget refused the request
+        self.apply_reply = self.pending_prompt.is_some();
+        Ok(())
+    }
+
+    /// `/gendocs <path>` asks for rustdoc comments on the file's public items
+    /// that have none, and opens the documented file for review.
+    fn gendocs_command(&mut self, args: &str, response_tx: &UnboundedSender<AppEvent>) -> Result<()> {
+        let path = args.trim();
+        if path.is_empty() {
+            anyhow::bail!("usage: /gendocs <path>");
+        }
+        if !path.ends_with(".rs") {
+            anyhow::bail!("/gendocs writes rustdoc comments, for Rust files only");
+        }
+        let filter = context::Filter::new(&self.root, &self.settings);
+        let file = context::Attachment::load(path, None, &filter)?;
+        let missing = outline::undocumented(file.content());
+        if missing.is_empty() {
+            self.messages.push(Message::notice(i18n::fill("gendocs.complete", &[&path])));
+            return Ok(());
+        }
+        let list: Vec<String> = missing.iter().map(|(line, item)| format!("- line {}: `{}`", line, item)).collect();
+        self.attachments.push(file);
+        self.input = format!("These public items of {} have no doc comment:\n{}\n\n{}", path, list.join("\n"), GENDOCS_PROMPT);
+        self.send_prompt(response_tx);
+        self.apply_reply = self.pending_prompt.is_some();
+        Ok(())
+    }
+
+    fn run_cargo(&mut self, response_tx: &UnboundedSender<AppEvent>) {
+        let Some((subcommand, _)) = self.fix_loop.clone() else { return };
+        self.messages.push(Message::notice(i18n::fill("cargo.running", &[&subcommand])));
+        let tx = response_tx.clone();
+        let task = tokio::spawn(async move {
+            let _ = tx.send(AgentResponse::Cargo(cargo::run(&subcommand).await).into());
+        });
+        self.request = Some((Instant::now(), task.abort_handle()));
+    }
+
+    /// Ends the fix loop when cargo passes or the attempts run out, otherwise
+    /// asks the model to fix the failure with the files it names attached.
+    fn cargo_finished(&mut self, result: Result<(bool, String)>, response_tx: &UnboundedSender<AppEvent>) {
+        let Some((subcommand, attempts)) = self.fix_loop.clone() else { return };
+        let limit = self.settings.fix_attempts.unwrap_or(cargo::DEFAULT_FIX_ATTEMPTS);
+        match result {
+            Ok((true, _)) => {
+                self.fix_loop = None;
+                let fixes = if attempts > 0 { format!(" {}", i18n::fill("cargo.after_fixes", &[&attempts])) } else { String::new() };
+                self.messages.push(Message::notice(i18n::fill("cargo.passes", &[&subcommand, &fixes])));
+            }
+            Ok((false, output)) if attempts >= limit => {
+                self.fix_loop = None;
+                self.messages.push(Message::notice(format!("{}\n{}", i18n::fill("cargo.still_fails", &[&subcommand, &attempts]), cargo::excerpt(&output))));
+            }
+            Ok((false, output)) => {
+                self.fix_loop = Some((subcommand.clone(), attempts + 1));
+                let filter = context::Filter::new(&self.root, &self.settings);
+                for path in cargo::failing_files(&output) {
+                    match context::Attachment::load(&path, None, &filter) {
+                        Ok(attachment) => self.attachments.push(attachment),
+                        Err(e) => self.messages.push(Message::notice(i18n::fill("cargo.not_attaching", &[&path, &e]))),
+                    }
+                }
+                self.input = format!(
+                    "`cargo {}` fails (fix {} of {}). Explain the cause briefly, then give the complete new contents of every file you change as file-scoped code blocks.\n\n```\n{}\n```",
+                    subcommand,
+                    attempts + 1,
+                    limit,
+                    cargo::excerpt(&output)
+                );
+                self.send_prompt(response_tx);
+            }
+            Err(e) => {
+                self.fix_loop = None;
+                self.messages.push(Message::notice(i18n::fill("error", &[&e])));
+            }
+        }
+    }
+
+    /// Opens the proposed fix for review, or ends the loop when there is none.
+    fn review_fix(&mut self) {
+        match self.collect_changes() {
+            Ok(0) => {
+                self.fix_loop = None;
+                self.messages.push(Message::notice(i18n::t("fix.no_changes")));
+            }
+            Ok(_) => self.state = AppState::Apply,
+            Err(e) => {
+                self.fix_loop = None;
+                self.messages.push(Message::notice(i18n::fill("error", &[&e])));
+            }
+        }
+    }
+
+    /// Keeps the thinking indicator last while the agent is still working.
+    fn push_before_thinking(&mut self, message: Message) {
+        let at = self.messages.len() - usize::from(self.messages.last().is_some_and(|m| m.text() == THINKING));
+        self.messages.insert(at, message);
+    }
+
+    /// Reacts to one event; false once the app should quit. The screen is
+    /// left to the event loop, so this needs no terminal.
+    pub fn handle_event(&mut self, event: AppEvent, response_tx: &UnboundedSender<AppEvent>) -> bool {
+        match event {
+            AppEvent::Key(key) => return key_event(self, key, response_tx),
+            AppEvent::Paste(text) => self.paste(&text),
+            AppEvent::Focus(focused) => self.has_focus = Some(focused),
+            AppEvent::Tick => {
+                if recovery::interrupted() {
+                    return false;
+                }
+                if self.draft_saved.elapsed() > DRAFT_INTERVAL {
+                    self.save_draft();
+                }
+                if self.last_confirm.is_some_and(|time| time.elapsed() > Duration::from_secs(2)) {
+                    self.confirm_save = false;
+                    self.last_confirm = None;
+                }
+            }
+            AppEvent::Chunk(progress) => self.progress = Some((Instant::now(), progress)),
+            AppEvent::ToolResult(line) => self.push_before_thinking(Message::new(Role::Tool, line)),
+            AppEvent::Response(response) => response_event(self, response, response_tx),
+            AppEvent::Error(e) => self.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+        }
+        true
+    }
+
+    fn save_settings(&mut self) -> Result<Option<String>> {
+        self.settings = self.edited_settings()?;
+        self.toolbox.set_local_only(self.local_only());
+        i18n::select(self.settings.language.as_deref());
+        self.settings.save(self.passphrase.as_deref())
+    }
+}
+
+/// Where the event loop reads terminal events from.
+pub trait EventSource {
+    /// The next event, waiting up to `timeout` for one.
+    fn next(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+}
+
+/// The event loop of the TUI, until `/exit`, Esc or a termination signal.
+pub async fn run<B: Backend>(
+    app: &mut App,
+    terminal: &mut Terminal<B>,
+    events: &mut impl EventSource,
+    response_tx: UnboundedSender<AppEvent>,
+    mut response_rx: UnboundedReceiver<AppEvent>,
+) -> Result<()> {
+    while step(app, terminal, events, &response_tx, &mut response_rx)? {}
+    Ok(())
+}
+
+/// One turn of the event loop: ticks, takes in a finished background task,
+/// draws the screen and handles the next event. False once the app should quit.
+fn step<B: Backend>(
+    app: &mut App,
+    terminal: &mut Terminal<B>,
+    events: &mut impl EventSource,
+    response_tx: &UnboundedSender<AppEvent>,
+    response_rx: &mut UnboundedReceiver<AppEvent>,
+) -> Result<bool> {
+    if !app.handle_event(AppEvent::Tick, response_tx) {
+        return Ok(false);
+    }
+    if let Ok(event) = response_rx.try_recv() {
+        if !app.handle_event(event, response_tx) {
+            return Ok(false);
+        }
+    }
+    draw(app, terminal)?;
+
+    // a command picked from the palette runs as if typed and sent
+    let event = if std::mem::take(&mut app.submit) {
+        Some(Event::Key(KeyEvent::from(KeyCode::Enter)))
+    } else {
+        events.next(Duration::from_millis(50))?
+    };
+    if let Some(Event::Resize(width, height)) = event {
+        // repaint everything; wrapping and scroll limits follow the new size on the next draw
+        terminal.resize(Rect::new(0, 0, width, height))?;
+    }
+    if let Some(event) = event.and_then(AppEvent::from_terminal) {
+        if !app.handle_event(event, response_tx) {
+            return Ok(false);
+        }
+    }
+    if std::mem::take(&mut app.compose) {
+        match compose_externally(&app.input, terminal) {
+            Ok(text) => app.set_input(text.trim_end_matches('\n').to_string()),
+            Err(e) => {
+                app.handle_event(AppEvent::Error(e), response_tx);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Draws the screen, then the inline images over it.
+fn draw<B: Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<()> {
+    terminal.draw(|f| {
+        ui(f, app);
+        f.render_widget(theme::Adapt(app.colors), f.size());
+        if app.hyperlinks {
+            let urls: Vec<String> = app.messages.iter().filter(|m| m.is(Role::Assistant)).flat_map(|m| links::find(&m.text())).collect();
+            f.render_widget(links::Hyperlinks(&urls), f.size());
+        }
+    })?;
+    if let Some(kitty) = &mut app.kitty {
+        // after the draw has flushed, so the images go over the text
+        kitty.show(&mut io::stdout().lock(), &app.image_slots)?;
+    }
+    Ok(())
+}
+
+/// Handles a key in whichever screen is open. False once the app should quit.
+fn key_event(app: &mut App, key: KeyEvent, response_tx: &UnboundedSender<AppEvent>) -> bool {
+    match app.state.clone() {
+        AppState::Chat => {
+            if key.kind == KeyEventKind::Press {
+                if key.code != KeyCode::Tab {
+                    app.completion = None;
+                }
+                let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                let alt = key.modifiers.contains(KeyModifiers::ALT);
+                let chord = std::mem::take(&mut app.ctrl_x);
+                let single_line = !app.input.contains('\n');
+                let action = app.keymap.action(&key);
+                match key.code {
+                    _ if app.history_search.is_some() => app.search_key(key),
+                    _ if action == Some("history_search") => {
+                        app.history_search = Some(String::new());
+                        app.search_match = None;
+                    }
+                    _ if action == Some("sidebar") => app.toggle_sidebar(),
+                    _ if action == Some("context") => app.show_context = !app.show_context,
+                    _ if action == Some("reasoning") => app.expand_reasoning = !app.expand_reasoning,
+                    _ if action == Some("help") => app.open_help(),
+                    KeyCode::Char('x') if ctrl => app.ctrl_x = true,
+                    KeyCode::Char('e') if ctrl && chord => app.compose = true,
+                    _ if app.transcript_search.is_some() => app.transcript_search_key(key),
+                    KeyCode::Esc if app.settings.vim_mode && app.request.is_none() => app.vim_normal = true,
+                    KeyCode::Char('v') if app.settings.vim_mode && app.vim_normal && app.vim_pending.is_none() => app.compose = true,
+                    _ if app.settings.vim_mode && app.vim_normal && key.code != KeyCode::Enter => app.vim_key(key),
+                    KeyCode::Up if alt => app.focus_step(false),
+                    KeyCode::Down if alt => app.focus_step(true),
+                    _ if action == Some("collapse") => app.toggle_collapse(),
+                    KeyCode::Up if app.sidebar.is_some() => app.sidebar_step(false),
+                    KeyCode::Down if app.sidebar.is_some() => app.sidebar_step(true),
+                    KeyCode::Enter if app.sidebar.is_some() && app.input.is_empty() => app.open_selected_session(),
+                    KeyCode::Enter if app.focused.is_some() && app.input.is_empty() => app.open_viewer(),
+                    KeyCode::Tab => app.tab_complete(),
+                    KeyCode::Up if single_line => app.history_prev(),
+                    KeyCode::Down if single_line => app.history_next(),
+                    // Ctrl+P is the previous prompt while a line is typed, as in shells
+                    KeyCode::Char('p') if ctrl && single_line && !app.input.is_empty() => app.history_prev(),
+                    _ if action == Some("palette") => app.open_palette(),
+                    KeyCode::Char('n') if ctrl && single_line => app.history_next(),
+                    KeyCode::Enter => {
+                        let input = app.input.trim().to_string();
+                        app.history.push(&input);
+                        let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input.as_str(), ""));
+                        if let Some(command) = commands::find(name) {
+                            app.input.clear();
+                            return run_command(app, command, args.trim(), response_tx);
+                        } else if let Some(plugin) = app.toolbox.plugin_for_command(name) {
+                            app.input.clear();
+                            app.plugin_command(plugin, name, args.trim(), response_tx);
+                        } else if name.starts_with('/') && !name[1..].contains('/') {
+                            app.input.clear();
+                            app.messages.push(Message::notice(i18n::fill("command.unknown", &[&name])));
+                        } else if !app.input.is_empty() {
+                            app.send_prompt(response_tx);
+                        }
+                    }
+                    KeyCode::Char(_)
+                    | KeyCode::Backspace
+                    | KeyCode::Delete
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::Up
+                    | KeyCode::Down => app.edit_key(key),
+                    KeyCode::PageUp => app.chat_scroll = app.chat_scroll.saturating_add(SCROLL_STEP),
+                    KeyCode::PageDown => app.chat_scroll = app.chat_scroll.saturating_sub(SCROLL_STEP),
+                    KeyCode::Esc if app.request.is_some() => app.cancel_request(),
+                    KeyCode::Esc if app.focused.is_some() => app.focused = None,
+                    KeyCode::Esc => return false,
+                    _ => {}
+                }
+            }
+        }
+        AppState::Settings => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Enter => {
+                        if app.just_entered_settings {
+                            app.just_entered_settings = false;
+                        } else if let Err(errors) = app.check_settings() {
+                            // stay in the form, on the first field to fix
+                            app.settings_focus = errors.keys().next().copied().unwrap_or(app.settings_focus);
+                            app.settings_errors = errors;
+                            app.confirm_save = false;
+                            app.last_confirm = None;
+                        } else if app.confirm_save {
+                            match app.save_settings() {
+                                Ok(warning) => {
+                                    app.messages.push(Message::notice(i18n::t("settings.saved")));
+                                    app.messages.extend(warning.map(Message::notice));
+                                }
+                                Err(e) => app.messages.push(Message::notice(i18n::fill("settings.save_failed", &[&e]))),
+                            }
+                            app.confirm_save = false;
+                            app.last_confirm = None;
+                            app.state = AppState::Chat;
+                        } else {
+                            app.confirm_save = true;
+                            app.last_confirm = Some(Instant::now());
+                        }
+                    }
+                    KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        match app.edited_settings() {
+                            Ok(settings) => {
+                                let settings = Settings { local_only: settings.local_only || app.force_local, ..settings };
+                                app.connection_status = Some(i18n::t("settings.testing").to_string());
+                                let response_tx_clone = response_tx.clone();
+                                let client = agent::client_for(&settings);
+
+                                tokio::spawn(async move {
+                                    let result = agent::test_connection(&client, &settings).await;
+                                    let _ = response_tx_clone.send(AgentResponse::ConnectionTest(result).into());
+                                });
+                            }
+                            Err(e) => app.connection_status = Some(format!("⚠️ {}", e)),
+                        }
+                    }
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.reveal_key = !app.reveal_key;
+                    }
+                    KeyCode::Char(c) => {
+                        app.settings_input[app.settings_focus].push(c);
+                        app.settings_errors.remove(&app.settings_focus);
+                    }
+                    KeyCode::Backspace => {
+                        editing::pop_char(&mut app.settings_input[app.settings_focus]);
+                        app.settings_errors.remove(&app.settings_focus);
+                    }
+                    KeyCode::Up if app.settings_focus > 0 => {
+                        app.settings_focus -= 1;
+                    }
+                    KeyCode::Down if app.settings_focus + 1 < settings::FIELDS.len() => {
+                        app.settings_focus += 1;
+                    }
+                    KeyCode::Esc => {
+                        app.confirm_save = false;
+                        app.last_confirm = None;
+                        app.state = AppState::Chat;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppState::Commit => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => {
+                        app.commit_input.push('\n');
+                    }
+                    KeyCode::Enter => {
+                        let message = app.commit_input.trim().to_string();
+                        app.messages.push(Message::notice(i18n::t("commit.committing")));
+                        let tx = response_tx.clone();
+                        tokio::spawn(async move {
+                            let result = tokio::task::spawn_blocking(move || git::commit(&message)).await.map_err(anyhow::Error::from).and_then(|r| r);
+                            let _ = tx.send(AgentResponse::Committed(result).into());
+                        });
+                        app.commit_input.clear();
+                        app.state = AppState::Chat;
+                    }
+                    KeyCode::Char(c) => {
+                        app.commit_input.push(c);
+                    }
+                    KeyCode::Backspace => {
+                        editing::pop_char(&mut app.commit_input);
+                    }
+                    KeyCode::Esc => {
+                        app.messages.push(Message::notice(i18n::t("commit.cancelled")));
+                        app.commit_input.clear();
+                        app.state = AppState::Chat;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppState::ShellCommand => {
+            if key.kind == KeyEventKind::Press {
+                app.suggestion_key(key, response_tx);
+            }
+        }
+        AppState::PrDescription => {
+            if key.kind == KeyEventKind::Press {
+                app.prdesc_key(key, response_tx);
+            }
+        }
+        AppState::Confirm => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Char('y') => app.answer_confirm(true),
+                    KeyCode::Char('n') | KeyCode::Esc => app.answer_confirm(false),
+                    KeyCode::Up => app.confirm_scroll = app.confirm_scroll.saturating_sub(1),
+                    KeyCode::Down => app.confirm_scroll = app.confirm_scroll.saturating_add(1),
+                    KeyCode::PageUp => app.confirm_scroll = app.confirm_scroll.saturating_sub(SCROLL_STEP),
+                    KeyCode::PageDown => app.confirm_scroll = app.confirm_scroll.saturating_add(SCROLL_STEP),
+                    _ => {}
+                }
+            }
+        }
+        AppState::McpPanel => {
+            if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Esc | KeyCode::Enter) {
+                app.state = AppState::Chat;
+            }
+        }
+        AppState::ModelPicker => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Up => app.model_selected = app.model_selected.saturating_sub(1),
+                    KeyCode::Down if app.model_selected + 1 < app.models.len() => app.model_selected += 1,
+                    KeyCode::Enter => {
+                        let model = app.models[app.model_selected].clone();
+                        app.switch_model(&model);
+                        app.state = AppState::Chat;
+                    }
+                    KeyCode::Esc => app.state = AppState::Chat,
+                    _ => {}
+                }
+            }
+        }
+        AppState::Bookmarks => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Up => app.bookmark_selected = app.bookmark_selected.saturating_sub(1),
+                    KeyCode::Down if app.bookmark_selected + 1 < app.session.bookmarks.len() => app.bookmark_selected += 1,
+                    KeyCode::Enter => {
+                        app.jump_to_bookmark();
+                        app.state = AppState::Chat;
+                    }
+                    KeyCode::Esc => app.state = AppState::Chat,
+                    _ => {}
+                }
+            }
+        }
+        AppState::Stats => {
+            if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q')) {
+                app.state = AppState::Chat;
+            }
+        }
+        AppState::Viewer => {
+            if key.kind == KeyEventKind::Press {
+                app.viewer_key(key);
+            }
+        }
+        AppState::Help => {
+            if key.kind == KeyEventKind::Press {
+                app.help_key(key);
+            }
+        }
+        AppState::Palette => {
+            if key.kind == KeyEventKind::Press {
+                app.palette_key(key);
+            }
+        }
+        AppState::ResponseDiff => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Up => app.diff_scroll = app.diff_scroll.saturating_sub(1),
+                    KeyCode::Down => app.diff_scroll = app.diff_scroll.saturating_add(1),
+                    KeyCode::PageUp => app.diff_scroll = app.diff_scroll.saturating_sub(SCROLL_STEP),
+                    KeyCode::PageDown => app.diff_scroll = app.diff_scroll.saturating_add(SCROLL_STEP),
+                    KeyCode::Esc | KeyCode::Char('q') => app.state = AppState::Chat,
+                    _ => {}
+                }
+            }
+        }
+        AppState::Compare => {
+            if key.kind == KeyEventKind::Press {
+                let count = app.comparison.as_ref().map_or(0, |c| c.answers.len());
+                match key.code {
+                    KeyCode::Left | KeyCode::BackTab => {
+                        if let Some(c) = &mut app.comparison {
+                            c.selected = (c.selected + count - 1) % count;
+                        }
+                    }
+                    KeyCode::Right | KeyCode::Tab => {
+                        if let Some(c) = &mut app.comparison {
+                            c.selected = (c.selected + 1) % count;
+                        }
+                    }
+                    KeyCode::Enter => app.pick_comparison(),
+                    KeyCode::Esc => {
+                        if let Some((_, handle)) = app.request.take() {
+                            handle.abort();
+                            app.pop_thinking();
+                        }
+                        app.messages.push(Message::notice(i18n::t("compare.discarded")));
+                        app.state = AppState::Chat;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppState::Passphrase => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Enter if !app.passphrase_input.is_empty() => {
+                        let passphrase = std::mem::take(&mut app.passphrase_input);
+                        app.set_passphrase(Some(passphrase));
+                        app.state = AppState::Chat;
+                    }
+                    KeyCode::Char(c) => app.passphrase_input.push(c),
+                    KeyCode::Backspace => {
+                        app.passphrase_input.pop();
+                    }
+                    KeyCode::Esc => {
+                        app.passphrase_input.clear();
+                        app.state = AppState::Chat;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppState::Apply => {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Char('y' | 'n') => app.decide_change(key.code == KeyCode::Char('y')),
+                    KeyCode::Char('a') => {
+                        for change in &mut app.pending_changes {
+                            change.accepted.get_or_insert(true);
+                        }
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => app.select_change(app.apply_selected.saturating_sub(1)),
+                    KeyCode::Down | KeyCode::Char('j') => app.select_change(app.apply_selected + 1),
+                    KeyCode::PageUp => app.apply_scroll = app.apply_scroll.saturating_sub(10),
+                    KeyCode::PageDown => app.apply_scroll = app.apply_scroll.saturating_add(10),
+                    KeyCode::Enter => {
+                        app.write_changes();
+                        if app.fix_loop.is_some() {
+                            app.run_cargo(response_tx);
+                        }
+                    }
+                    KeyCode::Esc => {
+                        app.messages.push(Message::notice(i18n::fill("apply.cancelled", &[&app.pending_changes.len()])));
+                        app.pending_changes.clear();
+                        app.state = AppState::Chat;
+                        if app.fix_loop.take().is_some() {
+                            app.messages.push(Message::notice(i18n::t("fix.stopped")));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Runs the chat command `command` with its trimmed `args`; false once the
+/// app should quit.
+fn run_command(app: &mut App, command: &commands::Command, args: &str, response_tx: &UnboundedSender<AppEvent>) -> bool {
+    if command.usage == command.name && !args.is_empty() {
+        app.messages.push(Message::notice(i18n::fill("usage", &[&command.usage])));
+        return true;
+    }
+    match command.name {
+        "/exit" => return false,
+        "/help" => app.open_help(),
+        "/setting" => app.open_settings(),
+        "/model" => app.model_command(args, response_tx),
+        "/persona" => app.persona_command(args),
+        "/stop" => app.stop_command(args),
+        "/compare" => app.compare_command(args),
+        "/diffresp" => app.diffresp_command(),
+        "/debug" => app.debug_command(args),
+        "/json" => app.json_command(),
+        "/mcp" => app.state = AppState::McpPanel,
+        "/attach" => {
+            let result = app.attach(args);
+            app.report(result);
+        }
+        "/title" => app.title_command(args),
+        "/budget" => app.budget_command(args),
+        "/cmd" => app.cmd_command(args, response_tx),
+        "/gentest" => {
+            let result = app.gentest_command(args, response_tx);
+            app.report(result);
+        }
+        "/gendocs" => {
+            let result = app.gendocs_command(args, response_tx);
+            app.report(result);
+        }
+        "/prdesc" => app.prdesc_command(args, response_tx),
+        "/images" => app.images_command(args),
+        "/links" => app.links_command(args),
+        "/tokens" => app.tokens_command(),
+        "/clear" => app.clear_command(args),
+        "/reset" => app.reset_session(),
+        "/delete" => app.delete_command(args),
+        "/undo" => app.undo(),
+        "/pin" => app.pin_command(args),
+        "/bookmark" => app.bookmark_command(args),
+        "/bookmarks" => app.bookmarks_command(),
+        "/tree" => app.tree_command(),
+        "/context" => app.show_context = !app.show_context,
+        "/detach" => app.detach_command(),
+        "/cache" => app.cache_command(args),
+        "/stats" => app.stats_command(args),
+        "/cargo" => app.cargo_command(args, response_tx),
+        "/explain" => app.explain_command(args, response_tx),
+        "/fix" => app.fix_command(response_tx),
+        "/commit" => app.commit_command(response_tx),
+        "/encrypt" => app.encrypt_command(),
+        "/decrypt" => app.decrypt_command(),
+        "/apply" => app.apply_command(),
+        "/savecode" => {
+            let result = app.save_code(args);
+            app.report(result);
+        }
+        name => unreachable!("{} is listed without a handler", name),
+    }
+    true
+}
+
+/// Takes in the result of a background task.
+fn response_event(app: &mut App, response: AgentResponse, response_tx: &UnboundedSender<AppEvent>) {
+    let mut took = None;
+    if matches!(
+        response,
+        AgentResponse::Chat(_) | AgentResponse::CommitMessage(_) | AgentResponse::Cargo(_) | AgentResponse::Diagnostics(_) | AgentResponse::Symbol(..)
+            | AgentResponse::Suggestion(_) | AgentResponse::ShellOutput(..) | AgentResponse::PrDescription(_) | AgentResponse::PrPublished(_)
+    ) {
+        took = app.finish_request();
+    }
+    match response {
+        AgentResponse::Chat(Ok(reply)) => {
+            app.comparison = None;
+            let info = models::lookup(&app.settings, &reply.model);
+            app.last_cost = info.cost(reply.usage.prompt, reply.usage.completion);
+            if reply.cached {
+                app.messages.push(Message::notice(i18n::t("cache.hit")));
+            }
+            if let Some(user) = app.pending_prompt.take() {
+                app.session.conversation.push(agent::Turn { user, assistant: reply.content.clone(), pinned: false, speed: reply.speed() });
+            }
+            app.last_usage = Some(reply.usage).filter(|u| u.prompt + u.completion > 0);
+            app.last_speed = reply.speed();
+            app.total_cost += app.last_cost.unwrap_or(0.0);
+            if reply.model != app.model() {
+                app.messages.push(Message::notice(i18n::fill("model.fallback", &[&reply.model])));
+            }
+            if let Some(reasoning) = reply.reasoning {
+                app.messages.push(Message::new(Role::Reasoning, reasoning.trim()));
+            }
+            let message = Message::assistant(reply.content.trim(), &reply.model, reply.usage);
+            if !reply.raw.is_empty() {
+                app.raw.insert(message.id, reply.raw.iter().map(|record| serde_json::to_string_pretty(record).unwrap_or_default()).collect::<Vec<_>>().join("\n\n"));
+            }
+            app.messages.push(message);
+            app.save_session();
+            if took.is_some_and(|took| took >= NOTIFY_AFTER) {
+                app.notify_done(&reply.content);
+            }
+            if app.fix_loop.is_some() {
+                app.review_fix();
+            } else if std::mem::take(&mut app.apply_reply) {
+                match app.collect_changes() {
+                    Ok(0) => app.messages.push(Message::notice(i18n::t("apply.none_in_answer"))),
+                    Ok(_) => app.state = AppState::Apply,
+                    Err(e) => app.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+                }
+            }
+            if app.session.title.is_none() && app.session.conversation.len() == 1 {
+                let tx = response_tx.clone();
+                let client = app.client();
+                let settings = app.request_settings();
+                let turn = app.session.conversation[0].clone();
+                tokio::spawn(async move {
+                    let _ = tx.send(AgentResponse::Title(agent::title(&client, &settings, &turn).await).into());
+                });
+            }
+        }
+        AgentResponse::Title(Ok(title)) => {
+            if app.session.title.is_none() {
+                app.session.title = Some(title);
+                app.save_session();
+            }
+        }
+        // titles are a nicety; the session keeps its timestamp name
+        AgentResponse::Title(Err(_)) => {}
+        AgentResponse::Compared(index, result) => {
+            if let Some(comparison) = &mut app.comparison {
+                comparison.answers[index].1 = Some(result);
+                if comparison.answers.iter().all(|(_, answer)| answer.is_some()) {
+                    app.pop_thinking();
+                    app.request = None;
+                }
+            }
+        }
+        AgentResponse::CommitMessage(Ok(Some(message))) => {
+            app.commit_input = message.trim().to_string();
+            app.state = AppState::Commit;
+        }
+        AgentResponse::CommitMessage(Ok(None)) => app.messages.push(Message::notice(i18n::t("commit.nothing_staged"))),
+        AgentResponse::Confirm(question, reply) => {
+            app.pending_confirm = Some((question, reply));
+            app.confirm_scroll = 0;
+            app.state = AppState::Confirm;
+        }
+        AgentResponse::PluginLoaded(Ok(status)) => app.messages.push(Message::notice(status)),
+        AgentResponse::Plugin(Ok(text)) => app.messages.push(Message::notice(format!("🔌 {}", text.trim()))),
+        AgentResponse::PluginLoaded(Err(e)) | AgentResponse::Plugin(Err(e)) => {
+            app.messages.push(Message::notice(format!("⚠️ {}", e)));
+        }
+        AgentResponse::McpStatus(name, status) => {
+            if let Some(entry) = app.mcp_status.iter_mut().find(|(n, _)| *n == name) {
+                entry.1 = status;
+            }
+        }
+        AgentResponse::Models(Ok(models)) => {
+            app.model_selected = models.iter().position(|m| m == app.model()).unwrap_or(0);
+            app.models = models;
+            if app.models.is_empty() {
+                app.messages.push(Message::notice(i18n::t("model.none")));
+            } else {
+                app.state = AppState::ModelPicker;
+            }
+        }
+        AgentResponse::Models(Err(e)) => {
+            app.messages.push(Message::notice(i18n::fill("model.list_failed", &[&e])));
+        }
+        AgentResponse::ConnectionTest(result) => {
+            app.connection_status = Some(match result {
+                Ok(latency) => i18n::fill("settings.connected", &[&latency.as_millis()]),
+                Err(e) => format!("⚠️ {}", e),
+            });
+        }
+        AgentResponse::Summary(Ok(summary), turns) => {
+            let notice = if app.replace_with_summary(summary, &turns) {
+                i18n::fill("request.summarized", &[&turns.len()])
+            } else {
+                i18n::t("request.summary_dropped").to_string()
+            };
+            app.push_before_thinking(Message::notice(notice));
+        }
+        AgentResponse::Summary(Err(e), _) => {
+            app.push_before_thinking(Message::notice(i18n::fill("error.summarize", &[&e])));
+        }
+        AgentResponse::Cargo(result) => app.cargo_finished(result, response_tx),
+        AgentResponse::Suggestion(Ok(suggestion)) => {
+            app.suggestion = Some(suggestion);
+            app.editing_suggestion = false;
+            app.state = AppState::ShellCommand;
+        }
+        AgentResponse::ShellOutput(command, Ok((passed, output))) => {
+            let status = if passed { "✅".to_string() } else { i18n::fill("cmd.failed", &[&command]) };
+            let output = if output.is_empty() { String::new() } else { format!("\n```\n{}\n```", output) };
+            app.messages.push(Message::notice(format!("{}{}", status, output)));
+        }
+        AgentResponse::PrDescription(Ok((title, body))) => {
+            if let Some(description) = &mut app.pr_description {
+                app.messages.push(Message::notice(format!("📝 {}\n\n{}", title, body)));
+                description.title = title;
+                description.body = body;
+                app.state = AppState::PrDescription;
+            }
+        }
+        AgentResponse::PrPublished(Ok(url)) => app.messages.push(Message::notice(i18n::fill("prdesc.published", &[&url]))),
+        AgentResponse::Committed(Ok(output)) => app.messages.push(Message::notice(format!("✅ {}", output.trim()))),
+        AgentResponse::Suggestion(Err(e))
+        | AgentResponse::ShellOutput(_, Err(e))
+        | AgentResponse::PrDescription(Err(e))
+        | AgentResponse::PrPublished(Err(e))
+        | AgentResponse::Committed(Err(e)) => app.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+        AgentResponse::Diagnostics(Ok(errors)) if errors.is_empty() => app.messages.push(Message::notice(i18n::t("fix.no_errors"))),
+        AgentResponse::Diagnostics(Ok(errors)) => {
+            let filter = context::Filter::new(&app.root, &app.settings);
+            let groups: Vec<String> = cargo::group(errors).iter().map(|(key, errors)| cargo::describe(key, errors, &filter)).collect();
+            app.input = format!("{}\n\n{}", fix::PROMPT, groups.join("\n\n"));
+            app.send_prompt(response_tx);
+        }
+        AgentResponse::Diagnostics(Err(e)) => app.messages.push(Message::notice(i18n::fill("error", &[&e]))),
+        AgentResponse::Symbol(name, Ok(description), question) => {
+            app.attachments.push(context::Attachment::symbol(&name, description));
+            app.input = if question.is_empty() {
+                format!("Explain `{}`: what it does, how it is used and anything surprising about it.", name)
+            } else {
+                question
+            };
+            app.send_prompt(response_tx);
+        }
+        AgentResponse::Symbol(name, Err(e), _) => app.messages.push(Message::notice(i18n::fill("explain.failed", &[&name, &e]))),
+        AgentResponse::Chat(Err(e)) => {
+            app.pending_prompt = None;
+            app.fix_loop = None;
+            app.apply_reply = false;
+            app.messages.push(Message::notice(i18n::fill("error", &[&e])));
+        }
+        AgentResponse::CommitMessage(Err(e)) => {
+            app.pending_prompt = None;
+            app.fix_loop = None;
+            app.messages.push(Message::notice(i18n::fill("error", &[&e])));
+        }
+    }
+}
+
+fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let size = f.size();
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(4), Constraint::Length(1)].as_ref())
+        .split(size);
+    render_status(f, app, rows[1]);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Length(if app.sidebar.is_some() { 32 } else { 0 }),
+                Constraint::Min(20),
+                Constraint::Length(if app.show_context { 44 } else { 0 }),
+            ]
+            .as_ref(),
+        )
+        .split(rows[0]);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(u16::from(!app.attachments.is_empty())), Constraint::Length(3)].as_ref())
+        .split(columns[1]);
+    let panes = Panes { screen: rows[0], chat: chunks[0], attachments: chunks[1], input: chunks[2] };
+
+    if let Some(sessions) = &app.sidebar {
+        render_sidebar(f, app, sessions, columns[0]);
+    }
+    if app.show_context {
+        render_context(f, app, columns[2]);
+    }
+    render_chat(f, app, panes.chat);
+    render_attachments(f, app, panes.attachments);
+
+    match app.state {
+        AppState::Commit => render_commit(f, app, panes.screen),
+        AppState::ShellCommand => render_shell_command(f, app, panes.screen),
+        AppState::PrDescription => render_pr_description(f, app, panes.screen),
+        AppState::Confirm => render_confirm(f, app, panes.screen),
+        AppState::McpPanel => render_mcp_panel(f, app, panes.screen),
+        AppState::Bookmarks => render_bookmarks(f, app, panes.screen),
+        AppState::Stats => render_stats(f, app, panes.screen),
+        AppState::Palette => render_palette(f, app, panes.screen),
+        AppState::Help => render_help(f, app, panes.screen),
+        AppState::Viewer => render_viewer(f, app, panes.screen),
+        AppState::ResponseDiff => render_response_diff(f, app, panes.screen),
+        AppState::Compare => render_compare(f, app, panes.screen),
+        AppState::ModelPicker => render_model_picker(f, app, panes.screen),
+        AppState::Passphrase => render_passphrase(f, app, panes.screen),
+        AppState::Apply => render_apply(f, app, panes.screen),
+        AppState::Settings => render_settings(f, app, panes.screen),
+        AppState::Chat => render_input(f, app, &panes),
+    }
+}
+
+/// Where [`ui`] puts the panes the popups and the input box are drawn over.
+struct Panes {
+    screen: Rect,
+    chat: Rect,
+    attachments: Rect,
+    input: Rect,
+}
+
+fn render_status<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    f.render_widget(Paragraph::new(status_line(app)).style(Style::default().bg(Color::DarkGray)), area);
+}
+
+fn render_sidebar<B: Backend>(f: &mut Frame<B>, app: &App, sessions: &[(PathBuf, String, u64)], area: Rect) {
+    let rows = std::iter::once((i18n::t("sidebar.new").to_string(), None))
+        .chain(sessions.iter().map(|(_, name, created)| (name.clone(), Some(*created))));
+    let items: Vec<ListItem> = rows
+        .enumerate()
+        .map(|(i, (name, created))| {
+            let current = created == Some(app.session.created);
+            let mut style = if current { Style::default().fg(Color::Cyan) } else { Style::default() };
+            if i == app.sidebar_selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            ListItem::new(Span::styled(name, style))
+        })
+        .collect();
+    let mut state = ListState::default();
+    state.select(Some(app.sidebar_selected));
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(i18n::t("title.sessions")));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_context<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let context = Paragraph::new(context_lines(app))
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.context")))
+        .wrap(tui::widgets::Wrap { trim: false });
+    f.render_widget(context, area);
+}
+
+/// The messages, scrolled and with room left for the inline images drawn after
+/// the frame.
+fn render_chat<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let inner_width = area.width.saturating_sub(2).max(1) as usize;
+    let text = |lines: &mut Vec<Spans>, text: &str, style: Style| {
+        lines.extend(wrap::wrap(text, inner_width, false).into_iter().map(|row| Spans::from(Span::styled(row, style))));
+    };
+    let mut lines: Vec<Spans> = Vec::new();
+    // first row of each message, for jumping to bookmarks
+    let mut starts = Vec::new();
+    // first row of the space left for each inline image
+    let mut image_rows = Vec::new();
+    let limit = app.collapse_limit();
+    let latest_answer = app.messages.iter().rposition(|m| m.is(Role::Assistant));
+    for (i, m) in app.messages.iter().enumerate() {
+        starts.push(lines.len());
+        if m.text() == THINKING {
+            match &app.request {
+                Some((started, _)) => {
+                    let elapsed = started.elapsed();
+                    let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+                    text(&mut lines, &i18n::fill("chat.thinking", &[&frame, &elapsed.as_secs()]), Style::default());
+                }
+                None => text(&mut lines, i18n::t("chat.thinking_idle"), Style::default()),
+            }
+            continue;
+        }
+        match m.role {
+            Role::Reasoning if app.settings.hide_reasoning => {}
+            Role::Reasoning if !app.expand_reasoning => {
+                let count = m.text().lines().count();
+                text(&mut lines, &i18n::fill("chat.reasoning", &[&Role::Reasoning.prefix(), &count]), dim);
+            }
+            Role::Reasoning => m.line().split('\n').for_each(|l| text(&mut lines, l, dim)),
+            _ => {
+                let rendered = codeblock::render(&m.line());
+                let total = rendered.len();
+                let collapsed = m.is(Role::Assistant) && total > limit && (Some(i) != latest_answer) != app.toggled.contains(&i);
+                let shown = if collapsed { limit } else { total };
+                for line in rendered.into_iter().take(shown) {
+                    match line {
+                        codeblock::Rendered::Text(line) => text(&mut lines, &line, Style::default()),
+                        codeblock::Rendered::Header(header) => text(&mut lines, &header, Style::default().fg(Color::Cyan)),
+                        codeblock::Rendered::Code(number, code) => {
+                            // continuation rows keep the gutter so the code stays aligned
+                            let gutter = editing::width(&number);
+                            let blank = format!("│{}", " ".repeat(gutter.saturating_sub(1)));
+                            for (row, part) in wrap::wrap(&code, inner_width.saturating_sub(gutter), true).into_iter().enumerate() {
+                                let gutter = if row == 0 { number.clone() } else { blank.clone() };
+                                lines.push(Spans::from(vec![Span::styled(gutter, dim), Span::raw(part)]));
+                            }
+                        }
+                        codeblock::Rendered::Footer => lines.push(Spans::from(Span::styled("└─", Style::default().fg(Color::Cyan)))),
+                        // rows still too wide after narrowing the columns wrap like text
+                        codeblock::Rendered::Table(table) => table.layout(inner_width).iter().for_each(|row| text(&mut lines, row, Style::default())),
+                        codeblock::Rendered::Image(alt, src) if app.kitty.is_some() && images::drawable(&src) => {
+                            text(&mut lines, &format!("🖼 {}", if alt.is_empty() { images::label(&src) } else { alt }), dim);
+                            image_rows.push((lines.len(), src));
+                            lines.extend((0..images::ROWS).map(|_| Spans::from("")));
+                        }
+                        codeblock::Rendered::Image(alt, src) => {
+                            let label = if alt.is_empty() { String::new() } else { format!("{} ", alt) };
+                            text(&mut lines, &i18n::fill("chat.image", &[&label, &images::label(&src)]), Style::default().fg(Color::Cyan));
+                        }
+                    }
+                }
+                if collapsed {
+                    text(&mut lines, &i18n::fill("chat.collapsed", &[&(total - limit)]), dim);
+                }
+            }
+        }
+        if app.focused == Some(i) {
+            if let Some(first) = lines.get_mut(starts[i]) {
+                first.0.iter_mut().for_each(|span| span.style = span.style.add_modifier(Modifier::REVERSED));
+            }
+        }
+    }
+    // scroll so the newest lines stay visible unless the user scrolled up
+    let visible = area.height.saturating_sub(2) as usize;
+    let bottom = lines.len().saturating_sub(visible).min(u16::MAX as usize) as u16;
+    if let Some(&start) = app.jump_to.take().and_then(|at| starts.get(at)) {
+        app.chat_scroll = bottom.saturating_sub(start.min(u16::MAX as usize) as u16);
+    }
+    app.chat_scroll = app.chat_scroll.min(bottom);
+    // images are drawn after the frame, and only while nothing covers the pane
+    let top = (bottom - app.chat_scroll) as usize;
+    app.image_slots = image_rows
+        .into_iter()
+        .filter(|(row, _)| matches!(app.state, AppState::Chat) && *row >= top && row + images::ROWS as usize <= top + visible)
+        .map(|(row, src)| (src, area.x + 1, area.y + 1 + (row - top) as u16))
+        .collect();
+    let messages_paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(i18n::fill("title.chat", &[&app.model(), &app.session.display_name()])))
+        .scroll((bottom - app.chat_scroll, 0));
+
+    f.render_widget(messages_paragraph, area);
+}
+
+fn render_attachments<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chips: Vec<Span> = app
+        .attachments
+        .iter()
+        .map(|a| Span::styled(format!(" 📎 {} ", a.label()), Style::default().fg(Color::Black).bg(Color::Cyan)))
+        .flat_map(|chip| [chip, Span::raw(" ")])
+        .collect();
+    f.render_widget(Paragraph::new(Spans::from(chips)), area);
+}
+
+/// The input box, or the search prompt in its place, and the completion
+/// list above it.
+fn render_input<B: Backend>(f: &mut Frame<B>, app: &App, panes: &Panes) {
+    if app.history_search.is_some() {
+        let query = app.history_search.as_deref().unwrap_or_default();
+        let found = app.search_match.and_then(|i| app.history.search(query, Some(i + 1)));
+        let input = Paragraph::new(found.map_or("", |(_, entry)| entry))
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title(i18n::fill("title.history_search", &[&query])));
+        f.render_widget(input, panes.input);
+    } else if app.transcript_search.is_some() {
+        let query = app.transcript_search.as_deref().unwrap_or_default();
+        let input = Paragraph::new(format!("/{}", query))
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title(i18n::t("title.chat_search")));
+        f.render_widget(input, panes.input);
+        f.set_cursor(panes.input.x + editing::width(query) as u16 + 2, panes.input.y + 1);
+    } else {
+        let title = match (app.settings.vim_mode, app.vim_normal) {
+            (true, true) => i18n::fill("title.normal", &[&app.vim_status.as_deref().unwrap_or(i18n::t("title.normal_keys"))]),
+            (true, false) => i18n::t("title.insert").to_string(),
+            _ => i18n::t("title.input").to_string(),
+        };
+        let input = Paragraph::new(app.input.as_str())
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(input, panes.input);
+        let (column, row) = editing::cursor_position(&app.input, app.cursor());
+        let row = row.min(panes.input.height.saturating_sub(3) as usize);
+        f.set_cursor(panes.input.x + column as u16 + 1, panes.input.y + row as u16 + 1);
+
+        if let Some(completion) = &app.completion {
+            let labels: Vec<String> = completion
+                .candidates
+                .iter()
+                .map(|c| match (commands::find(c), app.toolbox.plugin_command_description(c)) {
+                    (Some(cmd), _) => format!("{:<22} {}", cmd.usage, cmd.description),
+                    (None, Some(description)) => format!("{:<22} {}", c, description),
+                    (None, None) => c.clone(),
+                })
+                .collect();
+            let height = (labels.len() as u16 + 2).min(panes.chat.height);
+            let width = labels.iter().map(|l| l.chars().count() as u16 + 2).max().unwrap_or(0).min(panes.screen.width);
+            let area = Rect::new(panes.input.x, panes.input.y.saturating_sub(height), width, height);
+            let lines: Vec<Spans> = labels
+                .into_iter()
+                .enumerate()
+                .map(|(i, label)| {
+                    let style = if Some(i) == completion.selected {
+                        Style::default().fg(Color::Black).bg(Color::White)
+                    } else {
+                        Style::default()
+                    };
+                    Spans::from(Span::styled(label, style))
+                })
+                .collect();
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL)), area);
+        }
+    }
+}
+
+fn render_commit<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(80, 50, size);
+    let commit = Paragraph::new(app.commit_input.as_str())
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.commit")))
+        .wrap(tui::widgets::Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(commit, area);
+}
+
+fn render_shell_command<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(80, 50, size);
+    let (command, explanation) = app.suggestion.as_ref().map_or(("", ""), |s| (s.command.as_str(), s.explanation.as_str()));
+    let cursor = if app.editing_suggestion { "▏" } else { "" };
+    let mut lines = vec![Spans::from(vec![Span::styled(format!("$ {}", command), Style::default().fg(Color::Cyan)), Span::raw(cursor)]), Spans::from("")];
+    lines.extend(explanation.lines().map(|line| Spans::from(line.to_string())));
+    let title = if app.editing_suggestion { i18n::t("title.cmd_edit") } else { i18n::t("title.cmd") };
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)).wrap(tui::widgets::Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn render_pr_description<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(80, 70, size);
+    let mut lines = Vec::new();
+    if let Some(description) = &app.pr_description {
+        lines.push(Spans::from(Span::styled(description.title.as_str(), Style::default().add_modifier(Modifier::BOLD))));
+        lines.push(Spans::from(""));
+        lines.extend(description.body.lines().map(Spans::from));
+    }
+    let popup = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.prdesc")))
+        .wrap(tui::widgets::Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn render_confirm<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let question = app.pending_confirm.as_ref().map_or("", |(q, _)| q.as_str());
+    let area = if question.lines().count() > 3 { centered_rect(80, 70, size) } else { centered_rect(60, 30, size) };
+    let prompt = Paragraph::new(question)
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.confirm")))
+        .wrap(tui::widgets::Wrap { trim: false })
+        .scroll((app.confirm_scroll, 0));
+    f.render_widget(Clear, area);
+    f.render_widget(prompt, area);
+}
+
+fn render_mcp_panel<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(70, 50, size);
+    let lines: Vec<Spans> = if app.mcp_status.is_empty() {
+        vec![Spans::from(i18n::fill("mcp.none", &[&settings::user_file().display()]))]
+    } else {
+        app.mcp_status
+            .iter()
+            .map(|(name, status)| Spans::from(vec![Span::styled(format!("{:<20}", name), Style::default().fg(Color::Cyan)), Span::raw(status.as_str())]))
+            .collect()
+    };
+    let panel = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.mcp")))
+        .wrap(tui::widgets::Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(panel, area);
+}
+
+fn render_bookmarks<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(70, 50, size);
+    let items: Vec<ListItem> = app
+        .session
+        .bookmarks
+        .iter()
+        .map(|b| {
+            let first = b.trim_start_matches("🤖 ").lines().next().unwrap_or_default();
+            ListItem::new(format!("🔖 {}", first.chars().take(80).collect::<String>()))
+        })
+        .collect();
+    let mut state = ListState::default();
+    state.select(Some(app.bookmark_selected));
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.bookmarks")))
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::White));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_stats<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(90, 80, size);
+    let lines: Vec<Spans> = app
+        .stats_report
+        .iter()
+        .map(|l| {
+            // section headings are the unindented lines
+            if l.starts_with(' ') || l.is_empty() {
+                Spans::from(l.as_str())
+            } else {
+                Spans::from(Span::styled(l.as_str(), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)))
+            }
+        })
+        .collect();
+    let panel = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(i18n::t("title.stats")));
+    f.render_widget(Clear, area);
+    f.render_widget(panel, area);
+}
+
+fn render_palette<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    if let Some(palette) = &app.palette {
+        let area = centered_rect(70, 60, size);
+        let visible = palette.visible();
+        let rows = area.height.saturating_sub(2) as usize;
+        let offset = palette.selected.saturating_sub(rows.saturating_sub(1));
+        let lines: Vec<Spans> = visible
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(rows)
+            .map(|(i, entry)| {
+                let style = if i == palette.selected { Style::default().fg(Color::Black).bg(Color::White) } else { Style::default() };
+                Spans::from(vec![
+                    Span::styled(format!("{:<36}", entry.label), style),
+                    Span::styled(format!(" {}", entry.detail), Style::default().fg(Color::DarkGray)),
+                ])
+            })
+            .collect();
+        let title = i18n::fill("title.palette", &[&palette.query, &visible.len()]);
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+    }
+}
+
+fn render_help<B: Backend>(f: &mut Frame<B>, app: &mut App, size: Rect) {
+    let area = centered_rect(90, 90, size);
+    let lines = app.help_lines();
+    let per_page = area.height.saturating_sub(2).max(1) as usize;
+    let pages = lines.len().div_ceil(per_page).max(1);
+    app.help_page = app.help_page.min(pages - 1);
+    let shown: Vec<Spans> = lines
+        .into_iter()
+        .skip(app.help_page * per_page)
+        .take(per_page)
+        .map(|(heading, line)| {
+            if heading {
+                Spans::from(Span::styled(line, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)))
+            } else {
+                Spans::from(line)
+            }
+        })
+        .collect();
+    let filter = if app.help_query.is_empty() { String::new() } else { format!(": {}", app.help_query) };
+    let title = i18n::fill("title.help", &[&(app.help_page + 1), &pages, &filter]);
+    let help = Paragraph::new(shown).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+fn render_viewer<B: Backend>(f: &mut Frame<B>, app: &mut App, size: Rect) {
+    let message = app.viewed();
+    if let Some(viewer) = app.viewer.as_mut() {
+        let query = viewer.query.to_lowercase();
+        let highlight = Style::default().fg(Color::Black).bg(Color::Yellow);
+        // raw lines, so search hits and code block numbers line up with the text
+        let inner_width = size.width.saturating_sub(2).max(1) as usize;
+        let mut lines: Vec<Spans> = Vec::new();
+        let mut starts = Vec::new();
+        for l in message.split('\n') {
+            starts.push(lines.len());
+            let style = if !query.is_empty() && l.to_lowercase().contains(&query) { highlight } else { Style::default() };
+            lines.extend(wrap::wrap(l, inner_width, false).into_iter().map(|row| Spans::from(Span::styled(row, style))));
+        }
+        let bottom = lines.len().saturating_sub(size.height.saturating_sub(2) as usize).min(u16::MAX as usize) as u16;
+        if std::mem::take(&mut viewer.jump) {
+            let above = starts.get(viewer.hit.unwrap_or_default()).copied().unwrap_or_default();
+            viewer.scroll = above.min(u16::MAX as usize) as u16;
+        }
+        viewer.scroll = viewer.scroll.min(bottom);
+        let title = match (&viewer.search, &viewer.status) {
+            (Some(search), _) => i18n::fill("title.viewer_search", &[search]),
+            (None, Some(status)) => status.clone(),
+            (None, None) if viewer.raw => i18n::t("title.viewer_raw").to_string(),
+            (None, None) => i18n::t("title.viewer").to_string(),
+        };
+        let view = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)).scroll((viewer.scroll, 0));
+        f.render_widget(Clear, size);
+        f.render_widget(view, size);
+    }
+}
+
+fn render_response_diff<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    if let Some((title, changes)) = &app.response_diff {
+        let area = centered_rect(90, 90, size);
+        let mut lines = vec![Vec::new()];
+        for (change, piece) in changes {
+            let style = match change {
+                diff::Change::Same => Style::default(),
+                diff::Change::Added => Style::default().fg(Color::Green).add_modifier(Modifier::UNDERLINED),
+                diff::Change::Removed => Style::default().fg(Color::Red).add_modifier(Modifier::CROSSED_OUT),
+            };
+            for (i, part) in piece.split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(Vec::new());
+                }
+                if !part.is_empty() {
+                    lines.last_mut().expect("starts with a line").push(Span::styled(part.to_string(), style));
+                }
+            }
+        }
+        let lines: Vec<Spans> = lines.into_iter().map(Spans::from).collect();
+        let view = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(i18n::fill("title.diff", &[&title])))
+            .wrap(tui::widgets::Wrap { trim: false })
+            .scroll((app.diff_scroll, 0));
+        f.render_widget(Clear, area);
+        f.render_widget(view, area);
+    }
+}
+
+fn render_compare<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    if let Some(comparison) = &app.comparison {
+        let count = comparison.answers.len() as u32;
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, count); count as usize])
+            .split(size);
+        f.render_widget(Clear, size);
+        for (i, ((model, answer), area)) in comparison.answers.iter().zip(columns).enumerate() {
+            let text = match answer {
+                None => i18n::t("waiting").to_string(),
+                Some(Ok(reply)) => reply.content.trim().to_string(),
+                Some(Err(e)) => i18n::fill("error", &[&e]),
+            };
+            let mut block = Block::default().borders(Borders::ALL).title(model.as_str());
+            if i == comparison.selected {
+                block = block
+                    .title(i18n::fill("title.compare", &[model]))
+                    .border_style(Style::default().fg(Color::Cyan));
+            }
+            f.render_widget(Paragraph::new(text).block(block).wrap(tui::widgets::Wrap { trim: false }), area);
+        }
+    }
+}
+
+fn render_model_picker<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(60, 60, size);
+    let visible = area.height.saturating_sub(2) as usize;
+    let offset = app.model_selected.saturating_sub(visible.saturating_sub(1));
+    let lines: Vec<Spans> = app
+        .models
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(visible)
+        .map(|(i, model)| {
+            let style = if i == app.model_selected {
+                Style::default().fg(Color::Black).bg(Color::White)
+            } else {
+                Style::default()
+            };
+            let info = models::lookup(&app.settings, model);
+            Spans::from(vec![
+                Span::styled(format!("{:<32}", model), style),
+                Span::styled(format!(" {}", info.describe()), Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    let picker = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.model")));
+    f.render_widget(Clear, area);
+    f.render_widget(picker, area);
+}
+
+fn render_passphrase<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(60, 20, size);
+    let masked = "*".repeat(app.passphrase_input.chars().count());
+    let prompt = Paragraph::new(masked.as_str())
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("title.passphrase")));
+    f.render_widget(Clear, area);
+    f.render_widget(prompt, area);
+    f.set_cursor(area.x + masked.len() as u16 + 1, area.y + 1);
+}
+
+fn render_apply<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let area = centered_rect(90, 80, size);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(area);
+    let files: Vec<ListItem> = app
+        .pending_changes
+        .iter()
+        .map(|change| {
+            let (mark, color) = match change.accepted {
+                None => ("[ ]", Color::Reset),
+                Some(true) => ("[✓]", Color::Green),
+                Some(false) => ("[✗]", Color::Red),
+            };
+            let new = if change.old.is_empty() { format!(" {}", i18n::t("apply.new")) } else { String::new() };
+            ListItem::new(Span::styled(format!("{} {}{}", mark, change.path, new), Style::default().fg(color)))
+        })
+        .collect();
+    let accepted = app.pending_changes.iter().filter(|c| c.accepted == Some(true)).count();
+    let title = i18n::fill("title.apply", &[&accepted, &app.pending_changes.len()]);
+    let list = List::new(files)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default();
+    list_state.select(Some(app.apply_selected));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, columns[0], &mut list_state);
+    if let Some(change) = app.pending_changes.get(app.apply_selected) {
+        let lines: Vec<Spans> = diff::lines(&change.old, &change.new)
+            .into_iter()
+            .map(|(kind, line)| match kind {
+                diff::Change::Same => Spans::from(format!("  {}", line)),
+                diff::Change::Added => Spans::from(Span::styled(format!("+ {}", line), Style::default().fg(Color::Green))),
+                diff::Change::Removed => Spans::from(Span::styled(format!("- {}", line), Style::default().fg(Color::Red))),
+            })
+            .collect();
+        let diff_view = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(i18n::fill("title.apply_diff", &[&change.path])))
+            .scroll((app.apply_scroll, 0));
+        f.render_widget(diff_view, columns[1]);
+    }
+}
+
+fn render_settings<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    let settings_block = Block::default().borders(Borders::ALL).title(i18n::t("settings.title"));
+    f.render_widget(Clear, size);
+    f.render_widget(settings_block, size);
+
+    // one boxed row per field, scrolled to keep the focused field visible,
+    // followed by the save and connection rows
+    let inner = Rect::new(size.x + 2, size.y + 2, size.width.saturating_sub(4), size.height.saturating_sub(4));
+    let rows = (inner.height / 3).saturating_sub(2).max(1) as usize;
+    let offset = app.settings_focus.saturating_sub(rows - 1);
+    let row = |i: u16| Rect::new(inner.x, inner.y + i * 3, inner.width, 3).intersection(inner);
+
+    let key_title = i18n::t(if app.reveal_key { "settings.api_key_hide" } else { "settings.api_key_reveal" });
+    let masked_key = mask_secret(&app.settings_input[settings::API_KEY_FIELD]);
+    let visible = offset..(offset + rows).min(settings::FIELDS.len());
+
+    for (n, i) in visible.clone().enumerate() {
+        let style = if i == app.settings_focus {
+            Style::default().fg(Color::Black).bg(Color::White)
+        } else {
+            Style::default()
+        };
+        let (title, text) = if i == settings::API_KEY_FIELD {
+            (key_title, if app.reveal_key { app.settings_input[i].as_str() } else { masked_key.as_str() })
+        } else {
+            (i18n::field(i), app.settings_input[i].as_str())
+        };
+        let block = match app.settings_errors.get(&i) {
+            Some(error) => Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Red)).title(format!("{} ⚠️ {}", title, error)),
+            None => Block::default().borders(Borders::ALL).title(title),
+        };
+        let para = Paragraph::new(text).style(style).block(block);
+        f.render_widget(para, row(n as u16));
+    }
+
+    let shown = visible.len() as u16;
+    let save_text = i18n::t(if app.confirm_save { "settings.save_confirm" } else { "settings.save" });
+    f.render_widget(Paragraph::new(save_text).block(Block::default().borders(Borders::ALL)), row(shown));
+    let status = Paragraph::new(app.connection_status.as_deref().unwrap_or(i18n::t("settings.test")))
+        .block(Block::default().borders(Borders::ALL).title(i18n::t("settings.connection")));
+    f.render_widget(status, row(shown + 1));
+
+    let focused = row((app.settings_focus - offset) as u16);
+    let cursor = if app.settings_focus == settings::API_KEY_FIELD && !app.reveal_key {
+        masked_key.chars().count()
+    } else {
+        editing::width(&app.settings_input[app.settings_focus])
+    };
+    f.set_cursor(focused.x + cursor as u16 + 1, focused.y + 1);
+}
+
+/// Hides all but the last four characters of a secret, or all of it when it is too short to spare them.
+fn mask_secret(secret: &str) -> String {
+    let len = secret.chars().count();
+    let visible = if len > 8 { 4 } else { 0 };
+    secret
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < len - visible { '•' } else { c })
+        .collect()
+}
+
+/// Profile, provider, model, estimated context usage and request state.
+fn status_line(app: &App) -> Spans<'_> {
+    let settings = app.request_settings();
+    let attached: String = app.attachments.iter().map(|a| a.content()).collect();
+    let used = agent::prompt_tokens(&settings, app.session.summary.as_deref(), &app.session.conversation, &(attached + &app.input));
+    let window = agent::context_window(&settings);
+    let streamed = app.progress.filter(|(at, _)| app.request.as_ref().is_some_and(|(started, _)| at >= started));
+    let state = match (&app.request, streamed) {
+        (Some(_), Some((_, progress))) => i18n::fill("status.streaming", &[&format!("{:.0}", progress.tokens_per_sec()), &format!("{:.1}", progress.first_token.as_secs_f64())]),
+        (Some((started, _)), None) => i18n::fill("status.waiting", &[&started.elapsed().as_secs()]),
+        (None, _) => i18n::t("status.idle").to_string(),
+    };
+    let file = settings::user_file();
+    let file = file.file_name().unwrap_or_default().to_string_lossy();
+    let profile = if app.passphrase.is_some() { format!("{} 🔒", file) } else { file.to_string() };
+    let usage_color = match used * 100 / window.max(1) {
+        0..=69 => Color::Green,
+        70..=89 => Color::Yellow,
+        _ => Color::Red,
+    };
+    let sep = || Span::raw(" │ ");
+    let mut spans = vec![
+        Span::raw(format!(" {}", profile)),
+        sep(),
+        Span::raw(settings.provider.clone()),
+        sep(),
+        Span::styled(settings.model.clone(), Style::default().fg(Color::Cyan)),
+        sep(),
+    ];
+    if let Some(persona) = &app.persona {
+        spans.extend([Span::styled(format!("🎭 {}", persona), Style::default().fg(Color::Magenta)), sep()]);
+    }
+    spans.extend([
+        Span::styled(i18n::fill("status.tokens", &[&tokens::short(used), &tokens::short(window)]), Style::default().fg(usage_color)),
+        sep(),
+        Span::raw(state),
+    ]);
+    if settings.local_only {
+        spans.extend([sep(), Span::styled(i18n::t("status.local_only"), Style::default().fg(Color::Magenta))]);
+    }
+    if let Some((period, spent, limit)) = app.budget().filter(|b| b.1 >= b.2 * BUDGET_WARNING) {
+        let color = if spent >= limit { Color::Red } else { Color::Yellow };
+        spans.extend([sep(), Span::styled(format!("💸 {} ${:.2}/${:.2}", period, spent, limit), Style::default().fg(color))]);
+    }
+    if let Some(usage) = &app.last_usage {
+        let mut last = i18n::fill("status.last_in", &[&tokens::short(usage.prompt as usize)]);
+        if usage.cached > 0 {
+            last.push_str(&format!(" {}", i18n::fill("status.cached", &[&tokens::short(usage.cached as usize)])));
+        }
+        last.push_str(&i18n::fill("status.out", &[&tokens::short(usage.completion as usize)]));
+        if usage.reasoning > 0 {
+            last.push_str(&format!(" {}", i18n::fill("status.reasoning", &[&tokens::short(usage.reasoning as usize)])));
+        }
+        if let Some(speed) = &app.last_speed {
+            last.push_str(&i18n::fill("status.speed", &[&format!("{:.1}", speed.ttft_ms as f64 / 1000.0), &format!("{:.0}", speed.tokens_per_sec)]));
+        }
+        if let Some(cost) = app.last_cost {
+            last.push_str(&i18n::fill("status.cost", &[&format!("{:.4}", cost), &format!("{:.4}", app.total_cost)]));
+        }
+        spans.extend([sep(), Span::raw(last)]);
+    }
+    Spans::from(spans)
+}
+
+/// Lines of the context pane: attachments, automatic context, replayed
+/// history and the file changes `/apply` would pick up.
+fn context_lines(app: &App) -> Vec<Spans<'_>> {
+    /// Preview lines shown per attachment.
+    const PREVIEW: usize = 6;
+    let heading = |text: &'static str| Spans::from(Span::styled(text, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines = vec![heading(i18n::t("context.attachments"))];
+    if app.attachments.is_empty() {
+        lines.push(Spans::from(Span::styled(format!("  {}", i18n::t("context.no_attachments")), dim)));
+    }
+    for attachment in &app.attachments {
+        lines.push(Spans::from(format!("  {}", i18n::fill("context.attachment", &[&attachment.label(), &tokens::count(&app.settings.model, attachment.content())]))));
+        lines.extend(attachment.content().lines().take(PREVIEW).map(|l| Spans::from(Span::styled(format!("     {}", l), dim))));
+    }
+    if app.settings.auto_tree {
+        lines.push(Spans::from(format!("  {}", i18n::t("context.auto_tree"))));
+    }
+
+    lines.push(Spans::from(""));
+    lines.push(heading(i18n::t("context.history")));
+    let turns = &app.session.conversation;
+    let history_tokens: usize = turns.iter().map(|t| tokens::count(&app.settings.model, &t.user) + tokens::count(&app.settings.model, &t.assistant)).sum();
+    lines.push(Spans::from(format!("  {}", i18n::fill("context.exchanges", &[&turns.len(), &history_tokens]))));
+    if let Some(summary) = &app.session.summary {
+        lines.push(Spans::from(format!("  {}", i18n::fill("context.summary", &[&tokens::count(&app.settings.model, summary)]))));
+    }
+
+    lines.push(Spans::from(""));
+    lines.push(heading(i18n::t("context.pending")));
+    let blocks: Vec<_> = app.last_response().as_deref().map(codeblock::parse).unwrap_or_default().into_iter().filter_map(|b| b.path).collect();
+    if blocks.is_empty() {
+        lines.push(Spans::from(Span::styled(format!("  {}", i18n::t("context.none")), dim)));
+    }
+    for path in blocks {
+        let status = if Path::new(&path).exists() { i18n::t("context.modified") } else { i18n::t("context.new") };
+        lines.push(Spans::from(format!("  {} ({})", path, status)));
+    }
+    lines
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+/// The first line of a generated PR description as its title, without the
+/// heading marks or label models sometimes add, and the rest as its body.
+fn split_title(answer: &str) -> (String, String) {
+    let answer = answer.trim();
+    let (title, body) = answer.split_once('\n').unwrap_or((answer, ""));
+    let title = title.trim().trim_start_matches('#').trim();
+    let title = title.strip_prefix("Title:").unwrap_or(title).trim().trim_matches(['*', '`']);
+    (title.to_string(), body.trim().to_string())
+}
+
+/// Suspends the TUI to edit `input` in `$VISUAL` or `$EDITOR` (vi when
+/// neither is set) and returns the saved text.
+fn compose_externally<B: Backend>(input: &str, terminal: &mut Terminal<B>) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("gentor-prompt-{}.md", std::process::id()));
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let result = fs::write(&path, input).map_err(anyhow::Error::from).and_then(|_| {
+        disable_raw_mode()?;
+        execute!(io::stdout(), DisableBracketedPaste, DisableFocusChange, LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        // through the shell so editors configured with arguments (`code --wait`) work
+        let status = std::process::Command::new("sh").arg("-c").arg(format!("{} \"$1\"", editor)).arg("sh").arg(&path).status();
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste, EnableFocusChange)?;
+        terminal.clear()?;
+        match status {
+            Ok(status) if status.success() => Ok(fs::read_to_string(&path)?),
+            Ok(status) => Err(anyhow::anyhow!("{} exited with {}", editor, status)),
+            Err(e) => Err(anyhow::anyhow!("could not start {}: {}", editor, e)),
+        }
+    });
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Every file of local data kept encrypted while a passphrase is set.
+fn local_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = [stats::stats_file(), history::history_file(), recovery::recovery_file()].into_iter().flatten().collect();
+    for dir in [session::session_dir(), cache::cache_dir(), debug::log_dir()].into_iter().flatten() {
+        if let Ok(entries) = fs::read_dir(dir) {
+            files.extend(entries.flatten().map(|entry| entry.path()).filter(|path| path.is_file()));
+        }
+    }
+    files
+}
+
+/// `path` from a model's answer, resolved inside `root` with symlinks
+/// followed. Absolute paths, paths leading out of the project and dangling
+/// symlinks, which could be written through to anywhere, are refused.
+fn inside_root(root: &Path, path: &str) -> Result<PathBuf> {
+    let root = root.canonicalize()?;
+    let mut resolved = root.clone();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+                match resolved.canonicalize() {
+                    Ok(real) => resolved = real,
+                    Err(_) if fs::symlink_metadata(&resolved).is_ok_and(|m| m.file_type().is_symlink()) => {
+                        anyhow::bail!("a symlink to a missing file")
+                    }
+                    Err(_) => {}
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => anyhow::bail!("absolute paths are not written"),
+        }
+        if !resolved.starts_with(&root) {
+            anyhow::bail!("outside the project");
+        }
+    }
+    Ok(resolved)
+}
+
+fn write_file(path: impl AsRef<Path>, contents: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,523 @@
+//! The TUI driven the way a user drives it: key events fed to the event
+//! loop, the mock provider answering, and assertions on the drawn screen.
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::{
+    collections::VecDeque,
+    env, fs, io,
+    sync::MutexGuard,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tui::{backend::TestBackend, Terminal};
+
+use super::{step, App, AppEvent, AppState, EventSource, PrDescription};
+use crate::{i18n, message::Message, mock, settings::{self, Settings}};
+
+const WIDTH: u16 = 160;
+const HEIGHT: u16 = 40;
+/// Longest wait for the screen to show what a test expects.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events queued by a test, handed to the loop one per turn.
+#[derive(Default)]
+struct Script(VecDeque<Event>);
+
+impl EventSource for Script {
+    fn next(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+        Ok(self.0.pop_front())
+    }
+}
+
+struct Harness {
+    app: App,
+    terminal: Terminal<TestBackend>,
+    events: Script,
+    response_tx: UnboundedSender<AppEvent>,
+    response_rx: UnboundedReceiver<AppEvent>,
+    /// Held for the life of the harness, see [`settings::scratch_home`].
+    _scratch: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    /// The app with the mock provider, its config and sessions kept in a
+    /// scratch home emptied for each test and a scratch project of its own.
+    /// The process environment and working directory are left alone, since
+    /// other tests read them at the same time.
+    fn new(settings: Settings) -> Self {
+        let scratch = settings::scratch_home();
+        let root = settings::home_dir().unwrap().join("project");
+        fs::create_dir_all(&root).unwrap();
+        i18n::select(Some("en"));
+        let mut app = App::new(Settings { provider: mock::PROVIDER.to_string(), model: mock::MODEL.to_string(), ..settings });
+        app.kitty = None;
+        app.toolbox.set_root(&root);
+        app.root = root;
+        let (response_tx, response_rx) = mpsc::unbounded_channel();
+        Self { app, terminal: Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap(), events: Script::default(), response_tx, response_rx, _scratch: scratch }
+    }
+
+    fn press(&mut self, code: KeyCode) {
+        self.events.0.push_back(Event::Key(KeyEvent::from(code)));
+    }
+
+    fn ctrl(&mut self, c: char) {
+        self.events.0.push_back(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)));
+    }
+
+    fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(KeyCode::Char(c));
+        }
+    }
+
+    fn submit(&mut self, text: &str) {
+        self.type_text(text);
+        self.press(KeyCode::Enter);
+    }
+
+    /// One turn of the event loop; false once the app has quit.
+    fn turn(&mut self) -> bool {
+        step(&mut self.app, &mut self.terminal, &mut self.events, &self.response_tx, &mut self.response_rx).unwrap()
+    }
+
+    /// Handles every queued event and draws the result.
+    fn settle(&mut self) -> String {
+        while !self.events.0.is_empty() {
+            assert!(self.turn(), "the app quit");
+        }
+        self.turn();
+        self.screen()
+    }
+
+    /// Keeps the loop turning, with time for background tasks in between,
+    /// until the screen satisfies `done`.
+    async fn until(&mut self, what: &str, done: impl Fn(&str) -> bool) -> String {
+        let start = Instant::now();
+        loop {
+            self.turn();
+            let screen = self.screen();
+            if done(&screen) {
+                return screen;
+            }
+            assert!(start.elapsed() < TIMEOUT, "timed out waiting for {}; the screen shows:\n{}", what, screen);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// The drawn screen as text, a line per row.
+    fn screen(&self) -> String {
+        let buffer = self.terminal.backend().buffer();
+        buffer.content.chunks(buffer.area.width as usize).map(|row| row.iter().map(|cell| cell.symbol.as_str()).collect::<String>().trim_end().to_string()).collect::<Vec<_>>().join("\n")
+    }
+}
+
+fn script(name: &str, steps: &str) -> String {
+    let path = env::temp_dir().join(format!("gentor-tui-tests-{}-{}.json", std::process::id(), name));
+    fs::write(&path, steps).unwrap();
+    path.display().to_string()
+}
+
+#[test]
+fn typed_text_shows_in_the_input_box() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.type_text("hello word");
+    harness.press(KeyCode::Backspace);
+    harness.type_text("ld");
+    let screen = harness.settle();
+    assert_eq!(harness.app.input, "hello world");
+    assert!(screen.contains("hello world"), "{}", screen);
+}
+
+#[test]
+fn tab_completes_the_word_under_the_cursor() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.type_text("/mo gpt");
+    for _ in 0.." gpt".len() {
+        harness.press(KeyCode::Left);
+    }
+    harness.press(KeyCode::Tab);
+    harness.settle();
+    assert_eq!((harness.app.input.as_str(), harness.app.cursor), ("/model gpt", "/model".len()));
+
+    harness.app.set_input("/bo tail".to_string());
+    harness.app.cursor = "/bo".len();
+    harness.press(KeyCode::Tab);
+    harness.settle();
+    assert_eq!(harness.app.input, "/bookmark tail");
+    harness.press(KeyCode::Tab);
+    harness.press(KeyCode::Tab);
+    harness.settle();
+    assert_eq!((harness.app.input.as_str(), harness.app.cursor), ("/bookmarks tail", "/bookmarks".len()));
+}
+
+#[test]
+fn escape_quits() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.press(KeyCode::Esc);
+    assert!(!harness.turn());
+}
+
+#[test]
+fn events_are_handled_without_a_terminal() {
+    let mut harness = Harness::new(Settings::defaults());
+    let tx = harness.response_tx.clone();
+    let app = &mut harness.app;
+    assert!(app.handle_event(AppEvent::Paste("pasted\r\n".to_string()), &tx));
+    assert!(app.handle_event(AppEvent::ToolResult("🔧 read_file src/lib.rs".to_string()), &tx));
+    assert!(app.handle_event(AppEvent::Error(anyhow::anyhow!("disk full")), &tx));
+    assert_eq!(app.input, "pasted\n");
+    let lines: Vec<String> = app.messages.iter().map(Message::line).collect();
+    assert!(lines.iter().any(|line| line == "🔧 read_file src/lib.rs"), "{:?}", lines);
+    assert!(lines.last().is_some_and(|line| line.contains("disk full")), "{:?}", lines);
+    assert!(!app.handle_event(AppEvent::Key(KeyEvent::from(KeyCode::Esc)), &tx));
+}
+
+#[test]
+fn commands_run_on_enter() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/debug on");
+    let screen = harness.settle();
+    assert!(harness.app.debug);
+    assert!(screen.contains("Debug on"), "{}", screen);
+    assert!(harness.app.input.is_empty());
+
+    harness.submit("/help");
+    let screen = harness.settle();
+    assert!(matches!(harness.app.state, AppState::Help));
+    assert!(screen.contains("/debug"), "{}", screen);
+}
+
+#[test]
+fn ctrl_p_recalls_a_prompt_while_typing_and_opens_the_palette_otherwise() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/debug on");
+    harness.type_text("/de");
+    harness.ctrl('p');
+    harness.settle();
+    assert_eq!(harness.app.input, "/debug on");
+    assert!(harness.app.palette.is_none());
+
+    harness.app.set_input(String::new());
+    harness.ctrl('p');
+    harness.settle();
+    assert!(harness.app.palette.is_some());
+}
+
+#[test]
+fn commands_match_their_whole_name() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/stopx END");
+    let screen = harness.settle();
+    assert!(screen.contains("Unknown command /stopx"), "{}", screen);
+    assert!(harness.app.session_stop.is_none());
+
+    harness.submit("/reset now");
+    let screen = harness.settle();
+    assert!(screen.contains("Usage: /reset"), "{}", screen);
+}
+
+#[test]
+fn settings_are_edited_and_saved() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/setting");
+    let screen = harness.settle();
+    assert!(screen.contains("Settings Editor"), "{}", screen);
+
+    // the model is the second field
+    harness.press(KeyCode::Down);
+    for _ in 0..mock::MODEL.len() {
+        harness.press(KeyCode::Backspace);
+    }
+    harness.type_text("scripted");
+    let screen = harness.settle();
+    assert!(screen.contains("scripted"), "{}", screen);
+
+    // the first Enter after opening the editor is ignored, the next asks to confirm
+    harness.press(KeyCode::Enter);
+    harness.press(KeyCode::Enter);
+    harness.press(KeyCode::Enter);
+    let screen = harness.settle();
+    assert!(matches!(harness.app.state, AppState::Chat));
+    assert_eq!(harness.app.settings.model, "scripted");
+    assert!(screen.contains("Settings saved"), "{}", screen);
+}
+
+#[tokio::test]
+async fn a_prompt_gets_the_canned_reply() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("what is a borrow checker");
+    harness.settle();
+    let screen = harness.until("the reply", |screen| screen.contains("nothing was sent to a model")).await;
+    assert!(screen.contains("> what is a borrow checker"), "{}", screen);
+    assert!(harness.app.request.is_none());
+    assert_eq!(harness.app.session.conversation.len(), 1);
+}
+
+#[tokio::test]
+async fn streaming_progress_shows_in_the_status_bar() {
+    let steps = r#"[{ "content": "Streamed slowly, a few characters at a time, so the status bar can catch it.", "chunk_chars": 2, "first_token_ms": 50, "delay_ms": 40 }]"#;
+    let mut harness = Harness::new(Settings { mock_script: Some(script("streaming", steps)), ..Settings::defaults() });
+    harness.submit("stream please");
+    harness.settle();
+    let screen = harness.until("progress", |screen| screen.contains("streaming") && screen.contains("tok/s, first token")).await;
+    assert!(harness.app.request.is_some(), "{}", screen);
+    let screen = harness.until("the reply", |screen| screen.contains("so the status bar can catch it")).await;
+    assert!(harness.app.last_speed.is_some(), "{}", screen);
+    assert!(screen.contains("idle"), "{}", screen);
+}
+
+#[tokio::test]
+async fn provider_errors_are_shown() {
+    let steps = r#"[{ "error": { "message": "Rate limit reached", "code": "rate_limit_exceeded" } }]"#;
+    let mut harness = Harness::new(Settings { mock_script: Some(script("error", steps)), ..Settings::defaults() });
+    harness.submit("hello");
+    harness.settle();
+    let screen = harness.until("the error", |screen| screen.contains("Rate limit reached")).await;
+    assert!(harness.app.request.is_none(), "{}", screen);
+    assert!(harness.app.session.conversation.is_empty());
+}
+
+#[tokio::test]
+async fn a_rate_limited_key_is_rotated() {
+    let steps = r#"[{ "error": { "message": "Rate limit reached", "code": "rate_limit_exceeded" } }, { "content": "Answered with the second key." }]"#;
+    let keys = Settings { api_key: "sk-first-key-1111".to_string(), api_keys: vec!["sk-second-key-2222".to_string()], ..Settings::defaults() };
+    let mut harness = Harness::new(Settings { mock_script: Some(script("rotation", steps)), ..keys });
+    harness.submit("hello");
+    harness.settle();
+    let screen = harness.until("the reply", |screen| screen.contains("Answered with the second key")).await;
+    assert!(screen.contains("Key …1111 is rate limited, trying …2222"), "{}", screen);
+    assert_eq!(harness.app.session.conversation.len(), 1);
+}
+
+#[test]
+fn invalid_settings_are_not_saved() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/setting");
+    harness.settle();
+
+    // empty the model, the second field, and break the base URL, the fourth
+    harness.press(KeyCode::Down);
+    for _ in 0..mock::MODEL.len() {
+        harness.press(KeyCode::Backspace);
+    }
+    harness.press(KeyCode::Down);
+    harness.press(KeyCode::Down);
+    for _ in 0..harness.app.settings.base_url.len() {
+        harness.press(KeyCode::Backspace);
+    }
+    harness.type_text("localhost:11434");
+    harness.press(KeyCode::Enter);
+    harness.press(KeyCode::Enter);
+    let screen = harness.settle();
+    assert!(matches!(harness.app.state, AppState::Settings));
+    assert!(screen.contains("Model must not be empty"), "{}", screen);
+    assert!(screen.contains("Base URL must be http or https"), "{}", screen);
+    assert_eq!(harness.app.settings_focus, 1);
+    assert_eq!(harness.app.settings.model, mock::MODEL);
+
+    // fixing a field clears its error
+    harness.type_text("scripted");
+    let screen = harness.settle();
+    assert!(!screen.contains("Model must not be empty"), "{}", screen);
+    assert!(screen.contains("Base URL must be http or https"), "{}", screen);
+}
+
+/// Three long exchanges, more than a tiny context window holds.
+fn long_history(harness: &mut Harness) {
+    for i in 0..3 {
+        harness.app.session.conversation.push(crate::agent::Turn { user: format!("question {} {}", i, "x ".repeat(2_000)), assistant: "y ".repeat(2_000), pinned: false, speed: None });
+    }
+}
+
+#[tokio::test]
+async fn turns_are_kept_when_their_summary_fails() {
+    let steps = r#"[{ "error": { "message": "Summarizer unavailable" } }, { "content": "Answered without a summary." }]"#;
+    let settings = Settings { mock_script: Some(script("summary-error", steps)), context_window: Some(3_000), summarize_history: true, ..Settings::defaults() };
+    let mut harness = Harness::new(settings);
+    long_history(&mut harness);
+    harness.submit("hello");
+    harness.settle();
+    let screen = harness.until("the reply", |screen| screen.contains("Answered without a summary")).await;
+    assert!(screen.contains("Summarizer unavailable"), "{}", screen);
+    assert!(harness.app.session.summary.is_none());
+    assert_eq!(harness.app.session.conversation.len(), 4);
+}
+
+#[tokio::test]
+async fn summarized_turns_are_replaced_once_the_summary_is_back() {
+    let steps = r#"[{ "content": "The user asked three questions." }, { "content": "Answered with a summary." }]"#;
+    let settings = Settings { mock_script: Some(script("summary", steps)), context_window: Some(3_000), summarize_history: true, ..Settings::defaults() };
+    let mut harness = Harness::new(settings);
+    long_history(&mut harness);
+    harness.submit("hello");
+    harness.settle();
+    harness.until("the reply", |screen| screen.contains("Answered with a summary")).await;
+    assert_eq!(harness.app.session.summary.as_deref(), Some("The user asked three questions."));
+    assert!(harness.app.session.conversation.len() < 4);
+    assert!(!harness.app.session.conversation[0].user.starts_with("question 0"));
+}
+
+#[tokio::test]
+async fn deleted_turns_are_kept_out_of_the_summary() {
+    let mut harness = Harness::new(Settings::defaults());
+    for user in ["a secret to forget 589", "another question"] {
+        harness.app.session.conversation.push(crate::agent::Turn { user: user.to_string(), assistant: "ok".to_string(), pinned: false, speed: None });
+    }
+    harness.app.session.summary = Some("Earlier the user wrote \"a secret to forget 589\".".to_string());
+    // a summary of both turns, still being written when the first is deleted
+    let covered = harness.app.session.conversation.clone();
+    harness.submit("/delete 1");
+    harness.settle();
+    assert_eq!(harness.app.session.summary.as_deref(), Some("Earlier the user wrote \"[deleted]\"."));
+    assert!(!harness.app.replace_with_summary("The user shared a secret and asked another question.".to_string(), &covered));
+    assert_eq!(harness.app.session.summary.as_deref(), Some("Earlier the user wrote \"[deleted]\"."));
+    assert_eq!(harness.app.session.conversation.len(), 1);
+}
+
+#[tokio::test]
+async fn trimmed_turns_stay_in_the_session() {
+    let steps = r#"[{ "content": "Answered from the recent turns." }]"#;
+    let settings = Settings { mock_script: Some(script("trim", steps)), context_window: Some(3_000), ..Settings::defaults() };
+    let mut harness = Harness::new(settings);
+    long_history(&mut harness);
+    harness.submit("hello");
+    harness.settle();
+    let screen = harness.until("the reply", |screen| screen.contains("Answered from the recent turns")).await;
+    assert!(screen.contains("left out of this request"), "{}", screen);
+    assert_eq!(harness.app.session.conversation.len(), 4);
+}
+
+#[tokio::test]
+async fn deleted_prompts_are_scrubbed_everywhere() {
+    let mut harness = Harness::new(Settings::defaults());
+    harness.submit("/debug on");
+    harness.submit("a prompt to forget 589");
+    harness.settle();
+    harness.until("the reply", |screen| screen.contains("nothing was sent to a model")).await;
+    harness.submit("/delete 1");
+    let screen = harness.settle();
+    assert!(screen.contains("Exchange 1 deleted"), "{}", screen);
+    assert!(harness.app.history.search("a prompt to forget 589", None).is_none());
+    let history = fs::read_to_string(crate::history::history_file().unwrap()).unwrap_or_default();
+    assert!(!history.contains("a prompt to forget 589"));
+    let log = fs::read_to_string(crate::debug::log_path(harness.app.session.created).unwrap()).unwrap();
+    assert!(!log.contains("a prompt to forget 589") && log.contains("[deleted]"), "{}", log);
+}
+
+#[tokio::test]
+async fn cached_answers_are_logged() {
+    let mut harness = Harness::new(Settings { response_cache: true, ..Settings::defaults() });
+    harness.submit("/debug on");
+    for _ in 0..2 {
+        // the same request again, without the first turn as history
+        harness.submit("/clear context");
+        harness.submit("a prompt asked twice 646");
+        harness.settle();
+        harness.until("the reply", |screen| !screen.contains("Thinking")).await;
+    }
+    assert!(harness.screen().contains("Answered from the response cache"), "{}", harness.screen());
+    let log = fs::read_to_string(crate::debug::log_path(harness.app.session.created).unwrap()).unwrap();
+    let entries: Vec<&str> = log.lines().filter(|line| line.contains("a prompt asked twice 646")).collect();
+    assert_eq!(entries.len(), 2, "{}", log);
+    assert!(!entries[0].contains("\"cached\"") && entries[1].contains("\"cached\":true"), "{}", log);
+}
+
+#[tokio::test]
+async fn local_only_mode_starts_no_plugins_or_stdio_mcp_servers() {
+    let server = |name: &str, url: Option<&str>| crate::mcp::McpServerConfig { name: name.to_string(), command: url.is_none().then(|| "mcp-files".to_string()), args: Vec::new(), env: Default::default(), url: url.map(str::to_string) };
+    let servers = vec![server("files", None), server("remote", Some("https://mcp.example.com/sse"))];
+    let mut harness = Harness::new(Settings { mcp_servers: servers, ..Settings::defaults() });
+    let plugins = crate::plugins::plugin_dir().unwrap();
+    fs::create_dir_all(&plugins).unwrap();
+    fs::write(plugins.join("notes.wasm"), b"").unwrap();
+    let response_tx = harness.response_tx.clone();
+    harness.app.start(None, true, &response_tx);
+    let screen = harness.settle();
+    assert!(screen.contains("Skipped plugin notes in local-only mode"), "{}", screen);
+    let statuses: Vec<&str> = harness.app.mcp_status.iter().map(|(_, status)| status.as_str()).collect();
+    assert_eq!(statuses, [i18n::t("mcp.skipped_stdio").to_string(), i18n::fill("mcp.skipped", &[&"https://mcp.example.com/sse"])]);
+    assert!(harness.app.toolbox.plugin_commands().is_empty());
+}
+
+#[test]
+fn pull_requests_are_not_pushed_in_local_only_mode() {
+    let mut harness = Harness::new(Settings { local_only: true, ..Settings::defaults() });
+    let (title, body) = ("Add watch mode".to_string(), "Re-runs a prompt on save.".to_string());
+    harness.app.pr_description = Some(PrDescription { branch: "watch".to_string(), base: "origin/main".to_string(), title, body });
+    harness.app.state = AppState::PrDescription;
+    harness.press(KeyCode::Char('p'));
+    let screen = harness.settle();
+    assert!(screen.contains("Not pushed in local-only mode"), "{}", screen);
+    assert!(harness.app.request.is_none());
+    assert!(matches!(harness.app.state, AppState::Chat));
+}
+
+#[test]
+fn changes_outside_the_project_are_left_out() {
+    let mut harness = Harness::new(Settings::defaults());
+    let answer = "```text ../escape.txt\nout\n```\n\n```text /tmp/gentor-escape.txt\nout\n```\n\n```text notes/inside.txt\nin\n```";
+    harness.app.messages.push(Message::assistant(answer, mock::MODEL, Default::default()));
+    harness.submit("/apply");
+    harness.settle();
+    assert!(matches!(harness.app.state, AppState::Apply));
+    let paths: Vec<&str> = harness.app.pending_changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(paths, ["notes/inside.txt"]);
+    harness.press(KeyCode::Esc);
+    let screen = harness.settle();
+    assert!(screen.contains("Left out ../escape.txt: outside the project."), "{}", screen);
+    assert!(screen.contains("Left out /tmp/gentor-escape.txt: absolute paths are not written."), "{}", screen);
+}
+
+#[cfg(unix)]
+#[test]
+fn accepted_changes_are_written_inside_the_project_only() {
+    let mut harness = Harness::new(Settings::defaults());
+    let answer = "```text notes/a.txt\nfirst\n```\n\n```text link/b.txt\nsecond\n```";
+    harness.app.messages.push(Message::assistant(answer, mock::MODEL, Default::default()));
+    harness.submit("/apply");
+    harness.settle();
+    assert_eq!(harness.app.pending_changes.len(), 2);
+
+    // the link is made to point out of the project while the set is reviewed
+    let root = harness.app.root.clone();
+    let outside = root.parent().unwrap().join("outside");
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+    harness.press(KeyCode::Char('a'));
+    harness.press(KeyCode::Enter);
+    let screen = harness.settle();
+    assert_eq!(fs::read_to_string(root.join("notes/a.txt")).unwrap(), "first\n");
+    assert!(!outside.join("b.txt").exists());
+    assert!(screen.contains("Failed to write link/b.txt: outside the project"), "{}", screen);
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_and_binary_files_are_left_out() {
+    let mut harness = Harness::new(Settings::defaults());
+    let root = harness.app.root.clone();
+    let outside = root.parent().unwrap().join("outside-missing.txt");
+    std::os::unix::fs::symlink(&outside, root.join("dangling.txt")).unwrap();
+    fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff, 0xfe]).unwrap();
+    let answer = "```text dangling.txt
+out
+```
+
+```text logo.png
+not an image
+```
+
+```text notes.txt
+in
+```";
+    harness.app.messages.push(Message::assistant(answer, mock::MODEL, Default::default()));
+    harness.submit("/apply");
+    harness.settle();
+    let paths: Vec<&str> = harness.app.pending_changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(paths, ["notes.txt"]);
+    harness.press(KeyCode::Esc);
+    let screen = harness.settle();
+    assert!(screen.contains("Left out dangling.txt: a symlink to a missing file."), "{}", screen);
+    assert!(screen.contains("Left out logo.png: not a text file."), "{}", screen);
+    assert!(!outside.exists());
+}
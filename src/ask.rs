@@ -0,0 +1,31 @@
+//! `gentor ask <prompt…>`: one prompt, the answer on stdout, for scripts.
+//! Input piped in (`git diff | gentor ask "review this"`) is attached to
+//! the prompt.
+use anyhow::{bail, Result};
+use std::io::{self, IsTerminal, Read};
+
+use crate::{agent, i18n, settings::Settings};
+
+/// Piped input sent along, in characters.
+const MAX_STDIN: usize = 200_000;
+
+pub async fn run(settings: Settings, prompt: &str) -> Result<()> {
+    let mut prompt = prompt.trim().to_string();
+    if !io::stdin().is_terminal() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        if input.chars().count() > MAX_STDIN {
+            bail!("{}", i18n::fill("ask.stdin_too_long", &[&MAX_STDIN]));
+        }
+        if !input.trim().is_empty() {
+            prompt = format!("{}\n\n```\n{}\n```", prompt, input.trim_end());
+        }
+    }
+    if prompt.is_empty() {
+        bail!("{}", i18n::fill("cli.usage", &[&"gentor ask <prompt…>"]));
+    }
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    println!("{}", agent::complete(&client, &settings, &agent::system_prompt(&settings), &prompt).await?.trim());
+    Ok(())
+}
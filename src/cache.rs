@@ -0,0 +1,84 @@
+//! Content-addressed cache of chat completions under `~/.config/gentor/cache/`,
+//! keyed on the SHA-256 of the full serialized request.
+use anyhow::Result;
+use futures::future::{self, BoxFuture};
+use ring::digest;
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf};
+
+use crate::{
+    crypto,
+    middleware::{Exchange, Middleware},
+    settings,
+};
+
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("cache"))
+}
+
+/// Hex SHA-256 of `request`, which carries the model, every message and all parameters.
+pub fn key(request: &Value) -> String {
+    let hash = digest::digest(&digest::SHA256, request.to_string().as_bytes());
+    hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Answers a request sent before from disk, and keeps new answers, while
+/// `response_cache` is on.
+pub struct Cache;
+
+impl Cache {
+    /// Streaming is left out: a streamed answer is kept reassembled.
+    fn key(exchange: &Exchange) -> String {
+        let mut request = exchange.request.clone();
+        if let Some(request) = request.as_object_mut() {
+            request.remove("stream");
+            request.remove("stream_options");
+        }
+        key(&json!({ "base_url": exchange.settings.base_url, "request": request }))
+    }
+}
+
+impl Middleware for Cache {
+    fn before<'a>(&'a self, exchange: &'a mut Exchange<'_>) -> BoxFuture<'a, Result<()>> {
+        exchange.response = get(&Self::key(exchange));
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn after<'a>(&'a self, exchange: &'a mut Exchange<'_>, result: Result<&'a Value, &'a anyhow::Error>) -> BoxFuture<'a, ()> {
+        if let (Ok(response), false) = (result, exchange.cached) {
+            put(&Self::key(exchange), response);
+        }
+        Box::pin(future::ready(()))
+    }
+}
+
+pub fn get(key: &str) -> Option<Value> {
+    let data = crypto::read_to_string(&cache_dir()?.join(format!("{}.json", key))).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+pub fn put(key: &str, response: &Value) {
+    let Some(dir) = cache_dir() else { return };
+    if fs::create_dir_all(&dir).is_ok() {
+        let _ = crypto::write(&dir.join(format!("{}.json", key)), response.to_string());
+    }
+}
+
+/// Number of cached responses and their total size in bytes.
+pub fn usage() -> (usize, u64) {
+    let Some(entries) = cache_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return (0, 0);
+    };
+    entries
+        .flatten()
+        .filter_map(|e| e.metadata().ok())
+        .fold((0, 0), |(count, bytes), meta| (count + 1, bytes + meta.len()))
+}
+
+pub fn clear() -> Result<usize> {
+    let (count, _) = usage();
+    if let Some(dir) = cache_dir().filter(|dir| dir.exists()) {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(count)
+}
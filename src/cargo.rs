@@ -0,0 +1,135 @@
+//! Running cargo for the `/cargo` fix loop and `fix`: picking out the source
+//! files failures point at and the compiler's structured diagnostics.
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::{path::Path, process::Stdio};
+use tokio::process::Command;
+
+use crate::context::{Attachment, Filter};
+
+pub const SUBCOMMANDS: &[&str] = &["test", "check", "clippy"];
+pub const DEFAULT_FIX_ATTEMPTS: u32 = 5;
+/// Output sent to the model is cut to its last lines.
+const MAX_OUTPUT_LINES: usize = 200;
+/// Source files attached with a failure.
+const MAX_FILES: usize = 5;
+/// Lines shown above and below an error's location.
+const SNIPPET_CONTEXT: usize = 10;
+
+/// Runs `cargo <subcommand>` in the current directory and returns whether it
+/// passed, with its combined output. Clippy warnings count as failures.
+pub async fn run(subcommand: &str) -> Result<(bool, String)> {
+    if !SUBCOMMANDS.contains(&subcommand) {
+        bail!("unsupported cargo command {} (supported: {})", subcommand, SUBCOMMANDS.join(", "));
+    }
+    let mut cmd = Command::new("cargo");
+    cmd.arg(subcommand).args(["--color", "never"]);
+    if subcommand == "clippy" {
+        cmd.args(["--all-targets", "--", "-D", "warnings"]);
+    }
+    let output = cmd
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| anyhow!("could not run cargo: {}", e))?;
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout));
+    Ok((output.status.success(), text))
+}
+
+/// The last lines of `output`, where the failures are summarized.
+pub fn excerpt(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    lines[lines.len().saturating_sub(MAX_OUTPUT_LINES)..].join("\n")
+}
+
+/// Project files named by diagnostics (`--> src/lib.rs:3:5`) and panics
+/// (`panicked at src/lib.rs:3:5`), in order of appearance.
+pub fn failing_files(output: &str) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim_start();
+        let Some(location) = line.strip_prefix("--> ").or_else(|| line.split_once("panicked at ").map(|(_, rest)| rest)) else {
+            continue;
+        };
+        let path = location.split(':').next().unwrap_or_default().trim();
+        let path = Path::new(path);
+        if path.is_relative() && path.is_file() && !files.iter().any(|f| Path::new(f) == path) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    files.truncate(MAX_FILES);
+    files
+}
+
+/// A compiler error from `cargo build --message-format=json`.
+pub struct Diagnostic {
+    /// Error code such as `E0308`, when rustc gives one.
+    pub code: Option<String>,
+    pub message: String,
+    /// The error as rustc prints it.
+    pub rendered: String,
+    /// Primary span: file and first line.
+    pub location: Option<(String, usize)>,
+}
+
+/// Builds the project and returns its compiler errors.
+pub async fn diagnostics() -> Result<Vec<Diagnostic>> {
+    let output = Command::new("cargo")
+        .args(["build", "--message-format=json", "--color", "never"])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| anyhow!("could not run cargo: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let errors: Vec<Diagnostic> = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        // spanless errors are summaries like "aborting due to 2 previous errors"
+        .filter(|v| v["reason"] == "compiler-message" && v["message"]["level"] == "error")
+        .filter(|v| v["message"]["spans"].as_array().is_some_and(|spans| !spans.is_empty()))
+        .map(|v| {
+            let message = &v["message"];
+            let primary = message["spans"].as_array().into_iter().flatten().find(|s| s["is_primary"] == true);
+            Diagnostic {
+                code: message["code"]["code"].as_str().map(str::to_string),
+                message: message["message"].as_str().unwrap_or_default().to_string(),
+                rendered: message["rendered"].as_str().unwrap_or_default().trim_end().to_string(),
+                location: primary.and_then(|s| Some((s["file_name"].as_str()?.to_string(), s["line_start"].as_u64()? as usize))),
+            }
+        })
+        .collect();
+    if errors.is_empty() && !output.status.success() {
+        bail!("cargo build failed: {}", excerpt(&String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(errors)
+}
+
+/// Errors grouped by code (or by message when there is none), largest group first.
+pub fn group(errors: Vec<Diagnostic>) -> Vec<(String, Vec<Diagnostic>)> {
+    let mut groups: Vec<(String, Vec<Diagnostic>)> = Vec::new();
+    for error in errors {
+        let key = error.code.clone().unwrap_or_else(|| error.message.clone());
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, members)) => members.push(error),
+            None => groups.push((key, vec![error])),
+        }
+    }
+    groups.sort_by_key(|(_, members)| std::cmp::Reverse(members.len()));
+    groups
+}
+
+/// A group's errors as rustc printed them, each followed by the code around it.
+pub fn describe(key: &str, errors: &[Diagnostic], filter: &Filter) -> String {
+    let mut text = format!("## {} ({} error(s))", key, errors.len());
+    for error in errors {
+        text.push_str(&format!("\n\n```\n{}\n```", error.rendered));
+        let Some((file, line)) = &error.location else { continue };
+        let range = (line.saturating_sub(SNIPPET_CONTEXT).max(1), line + SNIPPET_CONTEXT);
+        if let Ok(snippet) = Attachment::load(file, Some(range), filter) {
+            text.push_str(&format!("\n\n{}", snippet.fenced()));
+        }
+    }
+    text
+}
@@ -0,0 +1,149 @@
+//! `gentor changelog <from>..<to>`: release notes for a range of commits,
+//! grouped by Conventional Commits type, written into `CHANGELOG.md` after
+//! the change is shown and approved.
+use anyhow::{bail, Result};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    agent,
+    diff::{self, Change},
+    i18n,
+    session,
+    settings::Settings,
+};
+
+const CHANGELOG: &str = "CHANGELOG.md";
+/// Unchanged lines shown around each change in the approval diff.
+const CONTEXT: usize = 2;
+/// Conventional Commits types and their section names, in release-note order.
+const GROUPS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("docs", "Documentation"),
+    ("test", "Tests"),
+    ("build", "Build"),
+    ("ci", "CI"),
+    ("chore", "Chores"),
+];
+const CHANGELOG_PROMPT: &str = "You write release notes for a changelog from a list of commits grouped by type. Reply with Markdown only: the release heading given, then a \"### <group>\" section per non-empty group in the order given, each with bullets rewritten for users (what changed and why it matters, not how), merging duplicates and dropping commits with no user-visible effect. Call out breaking changes (marked with !) first under \"### Breaking Changes\".";
+
+pub async fn run(settings: Settings, range: &str) -> Result<()> {
+    let Some((from, to)) = range.split_once("..").filter(|(from, _)| !from.is_empty()) else {
+        bail!("usage: gentor changelog <from>..<to>");
+    };
+    let to = if to.is_empty() { "HEAD" } else { to };
+    let commits = log(from, to)?;
+    if commits.is_empty() {
+        bail!("{}", i18n::fill("changelog.no_commits", &[&from, &to]));
+    }
+    let heading = if to == "HEAD" { "## Unreleased".to_string() } else { format!("## {} ({})", to, today()) };
+    let mut prompt = format!("Release heading: {}\n", heading);
+    for (name, subjects) in grouped(&commits) {
+        prompt.push_str(&format!("\n{}:\n{}\n", name, subjects.iter().map(|s| format!("- {}", s)).collect::<Vec<_>>().join("\n")));
+    }
+    println!("{}", i18n::fill("changelog.writing", &[&commits.len()]));
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    let notes = agent::complete(&client, &settings, CHANGELOG_PROMPT, &prompt).await?;
+    let notes = notes.trim().trim_start_matches("```markdown").trim_start_matches("```").trim_end_matches("```").trim();
+
+    let old = fs::read_to_string(CHANGELOG).unwrap_or_default();
+    let new = insert(&old, &heading, notes);
+    print_diff(&old, &new);
+    print!("{} ", i18n::fill("changelog.confirm", &[&CHANGELOG]));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        fs::write(Path::new(CHANGELOG), new)?;
+        println!("{}", i18n::fill("changelog.updated", &[&CHANGELOG]));
+    } else {
+        println!("{}", i18n::fill("changelog.unchanged", &[&CHANGELOG]));
+    }
+    Ok(())
+}
+
+/// Subjects of the commits in `from..to`, oldest first, merges left out.
+fn log(from: &str, to: &str) -> Result<Vec<String>> {
+    let output = Command::new("git").args(["log", "--reverse", "--no-merges", "--format=%s", &format!("{}..{}", from, to)]).output()?;
+    if !output.status.success() {
+        bail!("git log failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).filter(|s| !s.is_empty()).collect())
+}
+
+/// Commits under their section names, in [`GROUPS`] order with the rest as
+/// "Other Changes". A `!` before the colon marks a breaking change.
+fn grouped(commits: &[String]) -> Vec<(&'static str, Vec<&str>)> {
+    let mut groups: Vec<(&'static str, Vec<&str>)> = GROUPS.iter().map(|(_, name)| (*name, Vec::new())).collect();
+    groups.push(("Other Changes", Vec::new()));
+    for subject in commits {
+        let kind = subject.split_once(':').map(|(prefix, _)| prefix.split('(').next().unwrap_or_default().trim_end_matches('!')).unwrap_or_default();
+        let at = GROUPS.iter().position(|(name, _)| name.eq_ignore_ascii_case(kind)).unwrap_or(GROUPS.len());
+        groups[at].1.push(subject);
+    }
+    groups.retain(|(_, subjects)| !subjects.is_empty());
+    groups
+}
+
+/// The changelog with `notes` as its newest release, replacing an earlier
+/// section under the same heading, or a new file when there is none.
+fn insert(old: &str, heading: &str, notes: &str) -> String {
+    let notes = if notes.starts_with(heading) { notes.to_string() } else { format!("{}\n\n{}", heading, notes) };
+    if old.trim().is_empty() {
+        return format!("# Changelog\n\n{}\n", notes);
+    }
+    let lines: Vec<&str> = old.lines().collect();
+    let section = |line: &&str| line.starts_with("## ");
+    let (start, end) = match lines.iter().position(|line| line.trim() == heading) {
+        Some(start) => (start, lines[start + 1..].iter().position(section).map_or(lines.len(), |n| start + 1 + n)),
+        // new releases go above the first release, after the title and intro
+        None => {
+            let first = lines.iter().position(section).unwrap_or(lines.len());
+            (first, first)
+        }
+    };
+    let mut new: Vec<String> = lines[..start].iter().map(|l| l.to_string()).collect();
+    if new.last().is_some_and(|l| !l.trim().is_empty()) {
+        new.push(String::new());
+    }
+    new.push(notes);
+    new.push(String::new());
+    new.extend(lines[end..].iter().map(|l| l.to_string()));
+    format!("{}\n", new.join("\n").trim_end())
+}
+
+/// Prints the changed lines with a little context, `+`/`-` marked.
+fn print_diff(old: &str, new: &str) {
+    let changes = diff::lines(old, new);
+    let near = |i: usize| changes[i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(changes.len())].iter().any(|(c, _)| *c != Change::Same);
+    let mut skipped = false;
+    for (i, (change, line)) in changes.iter().enumerate() {
+        match change {
+            Change::Added => println!("+ {}", line),
+            Change::Removed => println!("- {}", line),
+            Change::Same if near(i) => println!("  {}", line),
+            Change::Same => {
+                if !skipped {
+                    println!("  …");
+                }
+                skipped = true;
+                continue;
+            }
+        }
+        skipped = false;
+    }
+}
+
+fn today() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    session::timestamp(now)[..10].to_string()
+}
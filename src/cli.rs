@@ -0,0 +1,389 @@
+//! The command line: global flags and subcommands declared in [`COMMANDS`]
+//! and [`GLOBAL_FLAGS`], parsed into a [`Cli`] and described by `--help`.
+//! Global flags are accepted before or after the subcommand; everything
+//! after `--` is taken as positional arguments.
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::settings::{Origin, Override};
+
+pub struct FlagDef {
+    pub long: &'static str,
+    pub short: Option<char>,
+    /// Name of the value the flag takes, `None` for a switch.
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub struct CommandDef {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Positional arguments: `<NAME>` is required, `[NAME]` optional, and a
+    /// trailing `...` takes the rest.
+    pub args: &'static [&'static str],
+    pub flags: &'static [FlagDef],
+    pub subcommands: &'static [CommandDef],
+}
+
+const fn flag(long: &'static str, value: Option<&'static str>, help: &'static str) -> FlagDef {
+    FlagDef { long, short: None, value, help }
+}
+
+const fn leaf(name: &'static str, about: &'static str, args: &'static [&'static str], flags: &'static [FlagDef]) -> CommandDef {
+    CommandDef { name, about, args, flags, subcommands: &[] }
+}
+
+pub const GLOBAL_FLAGS: &[FlagDef] = &[
+    flag("model", Some("NAME"), "Model to use instead of the configured one"),
+    flag("profile", Some("NAME"), "Settings profile from [profiles] in the config"),
+    flag("set", Some("KEY=VALUE"), "Override any setting, read as JSON unless it is a string; repeatable"),
+    flag("local-only", None, "Use only the local model and keep files on this machine"),
+    flag("accessible", None, "Start the line-based interface for screen readers instead of the TUI"),
+    FlagDef { long: "help", short: Some('h'), value: None, help: "Print help" },
+    FlagDef { long: "version", short: Some('V'), value: None, help: "Print version" },
+];
+
+pub const COMMANDS: &[CommandDef] = &[
+    leaf("ask", "Send one prompt and print the answer; piped input is attached", &["<PROMPT>..."], &[]),
+    leaf("fix", "Explain and propose fixes for the errors of cargo build", &[], &[]),
+    leaf("watch", "Run a prompt whenever a file under the path changes", &["<PATH>"], &[flag("prompt", Some("TEXT"), "Prompt sent with the changed files")]),
+    leaf("serve", "Serve the agent over HTTP for editor integrations", &[], &[flag("port", Some("PORT"), "Port to listen on")]),
+    leaf("cmd", "Suggest a shell command for a task and offer to run it", &["<TASK>..."], &[]),
+    leaf("explain", "Explain a command line part by part", &["<COMMAND>..."], &[]),
+    leaf("changelog", "Draft release notes from the commits in a range", &["<RANGE>"], &[]),
+    CommandDef {
+        name: "config",
+        about: "Show or convert the settings",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            leaf("show", "Print the effective settings", &[], &[flag("origin", None, "Show where each value came from")]),
+            leaf("convert", "Rewrite settings.json as config.toml", &[], &[]),
+        ],
+    },
+    CommandDef {
+        name: "sessions",
+        about: "Work with saved conversations",
+        args: &[],
+        flags: &[],
+        subcommands: &[leaf("list", "List saved sessions, newest first", &[], &[])],
+    },
+    leaf("completions", "Print a completion script for bash, zsh or fish", &["<SHELL>"], &[]),
+    leaf("man", "Print the man page", &[], &[]),
+    leaf("help", "Print help for gentor or a command", &["[COMMAND]..."], &[]),
+];
+
+pub enum Command {
+    Ask { prompt: String },
+    Fix,
+    Watch { path: String, prompt: String },
+    Serve { port: Option<u16> },
+    Cmd { task: String },
+    Explain { command: String },
+    Changelog { range: String },
+    ConfigShow { origin: bool },
+    ConfigConvert,
+    SessionsList,
+    Completions { shell: String },
+    Man,
+    /// Help text to print.
+    Help(String),
+    Version,
+}
+
+pub struct Cli {
+    /// `None` starts the TUI.
+    pub command: Option<Command>,
+    /// Settings from `--model`, `--profile`, `--local-only` and `--set`.
+    pub overrides: Vec<Override>,
+    pub local_only: bool,
+    pub accessible: bool,
+}
+
+/// Flags and positional arguments as given, before they are checked against
+/// what the command needs.
+struct Matches {
+    path: Vec<&'static CommandDef>,
+    flags: BTreeMap<&'static str, Vec<String>>,
+    args: Vec<String>,
+}
+
+pub fn parse(args: &[String]) -> Result<Cli> {
+    let matches = matches(args)?;
+    let overrides = overrides(&matches)?;
+    let switch = |name| matches.flags.contains_key(name);
+    let command = if switch("help") {
+        Some(Command::Help(help(&matches.path)))
+    } else if switch("version") {
+        Some(Command::Version)
+    } else {
+        command(&matches)?
+    };
+    Ok(Cli { command, overrides, local_only: switch("local-only"), accessible: switch("accessible") })
+}
+
+fn matches(args: &[String]) -> Result<Matches> {
+    let mut matches = Matches { path: Vec::new(), flags: BTreeMap::new(), args: Vec::new() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            matches.args.extend(args.by_ref().cloned());
+            break;
+        }
+        let name = if let Some(long) = arg.strip_prefix("--") {
+            Some(long.split_once('=').map_or(long, |(name, _)| name))
+        } else if arg.len() > 1 && arg.starts_with('-') && !is_number(arg) {
+            let mut chars = arg[1..].chars();
+            let (Some(short), None) = (chars.next(), chars.next()) else { bail!("unexpected argument '{}'\n\n{}", arg, usage_hint(&matches.path)) };
+            let def = flags_of(&matches.path).find(|def| def.short == Some(short));
+            Some(def.map_or("", |def| def.long))
+        } else {
+            None
+        };
+        let Some(name) = name else {
+            let subcommands = matches.path.last().map_or(COMMANDS, |def| def.subcommands);
+            match subcommands.iter().find(|def| def.name == arg) {
+                Some(def) if matches.args.is_empty() => matches.path.push(def),
+                None if !subcommands.is_empty() && matches.args.is_empty() => {
+                    let names: Vec<&str> = subcommands.iter().map(|def| def.name).collect();
+                    bail!("unknown command '{}' (available: {})\n\n{}", arg, names.join(", "), usage_hint(&matches.path));
+                }
+                _ => matches.args.push(arg.clone()),
+            }
+            continue;
+        };
+        let Some(def) = flags_of(&matches.path).find(|def| def.long == name) else {
+            bail!("unexpected argument '{}'\n\n{}", arg, usage_hint(&matches.path));
+        };
+        let value = match (def.value, arg.split_once('=')) {
+            (None, Some(_)) if arg.starts_with("--") => bail!("--{} takes no value", def.long),
+            (None, _) => String::new(),
+            (Some(_), Some((_, value))) if arg.starts_with("--") => value.to_string(),
+            (Some(value), _) => args.next().cloned().ok_or_else(|| anyhow!("--{} needs a value: --{} <{}>", def.long, def.long, value))?,
+        };
+        matches.flags.entry(def.long).or_default().push(value);
+    }
+    Ok(matches)
+}
+
+/// Negative numbers are values, not flags.
+fn is_number(arg: &str) -> bool {
+    arg.parse::<f64>().is_ok()
+}
+
+/// The flags valid at `path`: the current command's and the global ones.
+fn flags_of(path: &[&'static CommandDef]) -> impl Iterator<Item = &'static FlagDef> {
+    path.last().map_or(&[][..], |def| def.flags).iter().chain(GLOBAL_FLAGS)
+}
+
+fn overrides(matches: &Matches) -> Result<Vec<Override>> {
+    let mut overrides = Vec::new();
+    for name in ["local-only", "model", "profile", "set"] {
+        for value in matches.flags.get(name).into_iter().flatten() {
+            let (key, value) = match name {
+                "local-only" => ("local_only", "true"),
+                "set" => value.split_once('=').ok_or_else(|| anyhow!("--set needs <key>=<value>, got '{}'", value))?,
+                name => (name, value.as_str()),
+            };
+            overrides.push(Override { key: key.to_string(), value: Value::String(value.to_string()), origin: Origin::Cli(format!("--{}", name)) });
+        }
+    }
+    Ok(overrides)
+}
+
+/// The typed command, once its arguments are checked.
+fn command(matches: &Matches) -> Result<Option<Command>> {
+    let Some(def) = matches.path.last() else {
+        if let Some(arg) = matches.args.first() {
+            bail!("unexpected argument '{}'\n\n{}", arg, usage_hint(&[]));
+        }
+        return Ok(None);
+    };
+    let names: Vec<&str> = matches.path.iter().map(|def| def.name).collect();
+    if names == ["help"] {
+        return Ok(Some(Command::Help(help(&resolve(&matches.args)?))));
+    }
+    if !def.subcommands.is_empty() {
+        bail!("'{}' needs a command\n\n{}", names.join(" "), help(&matches.path));
+    }
+    let required = def.args.iter().filter(|arg| arg.starts_with('<')).count();
+    let variadic = def.args.last().is_some_and(|arg| arg.ends_with("..."));
+    if matches.args.len() < required {
+        bail!("missing {}\n\n{}", def.args[matches.args.len()], usage_hint(&matches.path));
+    }
+    if !variadic && matches.args.len() > def.args.len() {
+        bail!("unexpected argument '{}'\n\n{}", matches.args[def.args.len()], usage_hint(&matches.path));
+    }
+    let value = |name| matches.flags.get(name).and_then(|values| values.last()).cloned();
+    let rest = matches.args.join(" ");
+    let first = matches.args.first().cloned().unwrap_or_default();
+    Ok(Some(match names.as_slice() {
+        ["ask"] => Command::Ask { prompt: rest },
+        ["fix"] => Command::Fix,
+        ["watch"] => {
+            let prompt = value("prompt").ok_or_else(|| anyhow!("missing --prompt <TEXT>\n\n{}", usage_hint(&matches.path)))?;
+            Command::Watch { path: first, prompt }
+        }
+        ["serve"] => Command::Serve {
+            port: value("port").map(|port| port.parse().map_err(|_| anyhow!("invalid port '{}'", port))).transpose()?,
+        },
+        ["cmd"] => Command::Cmd { task: rest },
+        ["explain"] => Command::Explain { command: rest },
+        ["changelog"] => Command::Changelog { range: first },
+        ["config", "show"] => Command::ConfigShow { origin: value("origin").is_some() },
+        ["config", "convert"] => Command::ConfigConvert,
+        ["sessions", "list"] => Command::SessionsList,
+        ["completions"] => Command::Completions { shell: first },
+        ["man"] => Command::Man,
+        _ => unreachable!("every command in COMMANDS is matched"),
+    }))
+}
+
+/// The definitions along a command path such as `config show`.
+fn resolve(names: &[String]) -> Result<Vec<&'static CommandDef>> {
+    let mut path: Vec<&'static CommandDef> = Vec::new();
+    for name in names {
+        let subcommands = path.last().map_or(COMMANDS, |def| def.subcommands);
+        let def = subcommands.iter().find(|def| def.name == name).ok_or_else(|| anyhow!("unknown command '{}'", name))?;
+        path.push(def);
+    }
+    Ok(path)
+}
+
+fn usage(path: &[&CommandDef]) -> String {
+    let mut usage = String::from("gentor");
+    for def in path {
+        usage.push(' ');
+        usage.push_str(def.name);
+    }
+    usage.push_str(" [OPTIONS]");
+    match path.last() {
+        None => usage.push_str(" [COMMAND]"),
+        Some(def) if !def.subcommands.is_empty() => usage.push_str(" <COMMAND>"),
+        Some(def) => {
+            for arg in def.args {
+                usage.push(' ');
+                usage.push_str(arg);
+            }
+        }
+    }
+    usage
+}
+
+fn usage_hint(path: &[&CommandDef]) -> String {
+    let names: Vec<&str> = path.iter().map(|def| def.name).collect();
+    let help = if names.is_empty() { "gentor --help".to_string() } else { format!("gentor {} --help", names.join(" ")) };
+    format!("Usage: {}\n\nFor more information, try '{}'.", usage(path), help)
+}
+
+/// `--help` for the command at `path`, or for gentor itself.
+pub fn help(path: &[&'static CommandDef]) -> String {
+    let about = path.last().map_or(env!("CARGO_PKG_DESCRIPTION"), |def| def.about);
+    let mut help = format!("{}\n\nUsage: {}\n", about, usage(path));
+    let subcommands = path.last().map_or(COMMANDS, |def| def.subcommands);
+    if !subcommands.is_empty() {
+        help.push_str("\nCommands:\n");
+        let width = subcommands.iter().map(|def| def.name.len()).max().unwrap_or(0);
+        for def in subcommands {
+            help.push_str(&format!("  {:width$}  {}\n", def.name, def.about));
+        }
+    }
+    help.push_str("\nOptions:\n");
+    let flags: Vec<(String, &str)> = flags_of(path).map(|def| (flag_usage(def), def.help)).collect();
+    let width = flags.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+    for (usage, about) in flags {
+        help.push_str(&format!("  {:width$}  {}\n", usage, about));
+    }
+    help
+}
+
+/// `-h, --help` or `    --model <NAME>`.
+pub fn flag_usage(def: &FlagDef) -> String {
+    let short = def.short.map_or("    ".to_string(), |short| format!("-{}, ", short));
+    let value = def.value.map_or(String::new(), |value| format!(" <{}>", value));
+    format!("{}--{}{}", short, def.long, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Cli> {
+        parse(&line.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    fn error(line: &str) -> String {
+        parse_line(line).err().expect("the line should be refused").to_string()
+    }
+
+    #[test]
+    fn no_command_starts_the_tui() {
+        let cli = parse_line("").unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.overrides.is_empty());
+    }
+
+    #[test]
+    fn subcommands_and_their_arguments() {
+        let Some(Command::Ask { prompt }) = parse_line("ask why is it slow").unwrap().command else { panic!("not ask") };
+        assert_eq!(prompt, "why is it slow");
+        assert!(matches!(parse_line("config show --origin").unwrap().command, Some(Command::ConfigShow { origin: true })));
+        assert!(matches!(parse_line("config convert").unwrap().command, Some(Command::ConfigConvert)));
+        assert!(matches!(parse_line("sessions list").unwrap().command, Some(Command::SessionsList)));
+        let Some(Command::Help(help)) = parse_line("help config").unwrap().command else { panic!("not help") };
+        assert!(help.contains("Usage: gentor config [OPTIONS] <COMMAND>"), "{}", help);
+    }
+
+    #[test]
+    fn values_after_an_equals_sign_or_as_the_next_argument() {
+        let joined = parse_line("serve --port=9000").unwrap();
+        let separate = parse_line("serve --port 9000").unwrap();
+        for cli in [joined, separate] {
+            assert!(matches!(cli.command, Some(Command::Serve { port: Some(9000) })));
+        }
+        let Some(Command::Watch { path, prompt }) = parse_line("watch src --prompt=review").unwrap().command else { panic!("not watch") };
+        assert_eq!((path.as_str(), prompt.as_str()), ("src", "review"));
+    }
+
+    #[test]
+    fn global_flags_go_before_or_after_the_command() {
+        for line in ["--model gpt-4o ask hi", "ask hi --model gpt-4o", "ask --model=gpt-4o hi"] {
+            let cli = parse_line(line).unwrap();
+            assert_eq!(cli.overrides.len(), 1, "{}", line);
+            assert_eq!((cli.overrides[0].key.as_str(), &cli.overrides[0].value), ("model", &Value::String("gpt-4o".to_string())));
+        }
+        let cli = parse_line("--local-only --set temperature=0.2").unwrap();
+        assert!(cli.local_only);
+        let keys: Vec<&str> = cli.overrides.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, ["local_only", "temperature"]);
+        assert!(matches!(parse_line("-V").unwrap().command, Some(Command::Version)));
+    }
+
+    #[test]
+    fn everything_after_a_double_dash_is_an_argument() {
+        let Some(Command::Explain { command }) = parse_line("explain -- ls --all -l").unwrap().command else { panic!("not explain") };
+        assert_eq!(command, "ls --all -l");
+    }
+
+    #[test]
+    fn unknown_flags_and_commands_are_refused() {
+        assert!(error("ask --verbose hi").starts_with("unexpected argument '--verbose'"));
+        assert!(error("-x").starts_with("unexpected argument '-x'"));
+        assert!(error("frobnicate").starts_with("unknown command 'frobnicate' (available: ask, fix,"));
+        assert!(error("config frobnicate").starts_with("unknown command 'frobnicate' (available: show, convert)"));
+        assert!(error("fix now").starts_with("unexpected argument 'now'"));
+        assert_eq!(error("ask --local-only=yes hi"), "--local-only takes no value");
+        assert_eq!(error("serve --port http"), "invalid port 'http'");
+    }
+
+    #[test]
+    fn missing_arguments_are_named() {
+        assert!(error("ask").starts_with("missing <PROMPT>..."));
+        assert!(error("changelog").starts_with("missing <RANGE>"));
+        assert!(error("watch src").starts_with("missing --prompt <TEXT>"));
+        assert!(error("config").starts_with("'config' needs a command"));
+        assert_eq!(error("--model"), "--model needs a value: --model <NAME>");
+        assert!(error("--set temperature").starts_with("--set needs <key>=<value>"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Fenced code blocks in replies: the file paths they name for `/apply`
+//! and `/savecode`, and their layout in the chat pane.
+use crate::{
+    images,
+    table::{self, Table},
+};
+
+pub struct CodeBlock {
+    pub path: Option<String>,
+    pub code: String,
+}
+
+/// Extracts fenced code blocks from a markdown response. A block is
+/// file-scoped when its info string names a path (```rust // src/lib.rs,
+/// ```rust src/lib.rs, ```rust:src/lib.rs) or its first line is a comment
+/// holding only a path.
+pub fn parse(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            continue;
+        };
+        let mut path = parse_info(info.trim());
+        let mut body = Vec::new();
+        for line in lines.by_ref() {
+            if line.trim_start().starts_with("```") {
+                break;
+            }
+            body.push(line);
+        }
+        if path.is_none() {
+            if let Some(first) = body.first().and_then(|l| comment_path(l)) {
+                path = Some(first);
+                body.remove(0);
+            }
+        }
+        let mut code = body.join("\n");
+        code.push('\n');
+        blocks.push(CodeBlock { path, code });
+    }
+    blocks
+}
+
+/// A line of a message as the chat pane draws it.
+pub enum Rendered {
+    Text(String),
+    /// Top of a code block: its number for `/savecode`, language and path.
+    Header(String),
+    /// Line number, padded to the block's widest, and the code.
+    Code(String, String),
+    Footer,
+    /// A Markdown table, laid out once the pane width is known.
+    Table(Table),
+    /// A Markdown image on a line of its own: alt text and source.
+    Image(String, String),
+}
+
+/// Lays out a message with a header on each code block and line numbers in
+/// its gutter, and picks out Markdown tables and images. Blocks are numbered and split
+/// like [`parse`] does, so the numbers match what `/savecode` writes; an
+/// unclosed block (a reply still streaming) runs to the end.
+pub fn render(text: &str) -> Vec<Rendered> {
+    let mut out = Vec::new();
+    let mut lines = text.split('\n').peekable();
+    let mut count = 0;
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            if let Some((alt, src)) = images::parse_line(line) {
+                out.push(Rendered::Image(alt, src));
+                continue;
+            }
+            let header = table::cells(line);
+            match (header, lines.peek().and_then(|next| table::separator(next))) {
+                (Some(header), Some(aligns)) => {
+                    lines.next();
+                    let mut rows = Vec::new();
+                    while let Some(row) = lines.peek().and_then(|next| table::cells(next)) {
+                        lines.next();
+                        rows.push(row);
+                    }
+                    out.push(Rendered::Table(Table { header, aligns, rows }));
+                }
+                _ => out.push(Rendered::Text(line.to_string())),
+            }
+            continue;
+        };
+        count += 1;
+        let info = info.trim();
+        let mut path = parse_info(info);
+        let mut body: Vec<&str> = lines.by_ref().take_while(|l| !l.trim_start().starts_with("```")).collect();
+        if path.is_none() {
+            if let Some(first) = body.first().and_then(|l| comment_path(l)) {
+                path = Some(first);
+                body.remove(0);
+            }
+        }
+        let lang = info.split([' ', ':']).next().unwrap_or_default();
+        let label = [lang, path.as_deref().unwrap_or_default()].iter().filter(|s| !s.is_empty()).copied().collect::<Vec<_>>().join(" · ");
+        out.push(Rendered::Header(format!("┌─ [{}] {}", count, if label.is_empty() { "code" } else { &label })));
+        let digits = body.len().to_string().len();
+        out.extend(body.iter().enumerate().map(|(i, code)| Rendered::Code(format!("│ {:>width$} ", i + 1, width = digits), code.to_string())));
+        out.push(Rendered::Footer);
+    }
+    out
+}
+
+fn parse_info(info: &str) -> Option<String> {
+    if let Some((_, path)) = info.split_once(':') {
+        if looks_like_path(path) {
+            return Some(path.to_string());
+        }
+    }
+    info.split_whitespace()
+        .skip(1)
+        .filter(|p| !is_comment_marker(p))
+        .find(|p| looks_like_path(p))
+        .map(str::to_string)
+}
+
+fn comment_path(line: &str) -> Option<String> {
+    let line = line.trim();
+    let rest = ["//", "#", "--", "/*", "<!--"]
+        .iter()
+        .find_map(|m| line.strip_prefix(m))?;
+    let rest = rest.trim().trim_end_matches("*/").trim_end_matches("-->").trim();
+    let rest = rest.strip_prefix("File:").or_else(|| rest.strip_prefix("file:")).unwrap_or(rest).trim();
+    looks_like_path(rest).then(|| rest.to_string())
+}
+
+fn is_comment_marker(s: &str) -> bool {
+    matches!(s, "//" | "#" | "--" | "/*" | "*/" | "<!--" | "-->")
+}
+
+/// A path token: path characters only, naming a file with an extension
+/// (`lib.rs`) or nested under a directory (`bin/run`). A shebang
+/// (`#!/bin/sh`) or an ellipsis (`// ...`) is not one.
+fn looks_like_path(s: &str) -> bool {
+    let path_char = |c: char| c.is_alphanumeric() || "_-.+@~/".contains(c);
+    if s.starts_with("http") || !s.chars().all(path_char) {
+        return false;
+    }
+    let file = s.rsplit('/').next().unwrap_or(s);
+    let extension = file
+        .rsplit_once('.')
+        .is_some_and(|(stem, ext)| stem.contains(char::is_alphanumeric) && !ext.is_empty() && ext.chars().all(char::is_alphanumeric));
+    let nested = s.split('/').filter(|part| part.contains(char::is_alphanumeric)).count() > 1;
+    extension || nested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_headers_name_the_file() {
+        let blocks = parse("```rust\n// src/lib.rs\nfn main() {}\n```\n```rust:src/main.rs\nfn main() {}\n```\n```python # scripts/run.py\nprint()\n```");
+        let paths: Vec<_> = blocks.iter().map(|b| b.path.as_deref()).collect();
+        assert_eq!(paths, [Some("src/lib.rs"), Some("src/main.rs"), Some("scripts/run.py")]);
+        assert_eq!(blocks[0].code, "fn main() {}\n");
+    }
+
+    #[test]
+    fn shebangs_stay_in_the_code() {
+        let blocks = parse("```sh\n#!/bin/sh\necho hi\n```\n```python\n#!/usr/bin/env python3\nprint()\n```");
+        assert!(blocks.iter().all(|b| b.path.is_none()));
+        assert_eq!(blocks[0].code, "#!/bin/sh\necho hi\n");
+        assert_eq!(blocks[1].code, "#!/usr/bin/env python3\nprint()\n");
+    }
+
+    #[test]
+    fn ellipses_and_code_are_not_paths() {
+        let blocks = parse("```rust\n// ...\nfn main() {}\n```\n```js\n// foo.bar()\n```\n```\n# ./..\n```");
+        assert!(blocks.iter().all(|b| b.path.is_none()));
+        assert_eq!(blocks[0].code, "// ...\nfn main() {}\n");
+    }
+}
@@ -0,0 +1,178 @@
+//! The slash commands and chat keys listed by `/help`, and Tab completion
+//! of command names and their arguments.
+use std::{fs, path::Path};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    None,
+    Path,
+    Model,
+    Persona,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub arg: Arg,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command { name: "/help", usage: "/help", description: "List commands and keys (also F1)", arg: Arg::None },
+    Command { name: "/setting", usage: "/setting", description: "Open the settings editor", arg: Arg::None },
+    Command { name: "/model", usage: "/model [name]", description: "Switch the model for this session, or pick one from a list", arg: Arg::Model },
+    Command { name: "/persona", usage: "/persona [name|off]", description: "List personas or switch to one (system prompt, model, temperature)", arg: Arg::Persona },
+    Command { name: "/stop", usage: "/stop [seq|clear]", description: "Show, add or clear stop sequences for this session", arg: Arg::None },
+    Command { name: "/compare", usage: "/compare <model> <model>...", description: "Send the next prompt to several models side by side", arg: Arg::Model },
+    Command { name: "/diffresp", usage: "/diffresp", description: "Word diff between the last two answers or compared answers", arg: Arg::None },
+    Command { name: "/debug", usage: "/debug [on|off]", description: "Record requests and raw responses, keys redacted, to a debug log for this session", arg: Arg::None },
+    Command { name: "/json", usage: "/json", description: "Toggle structured JSON output for this session", arg: Arg::None },
+    Command { name: "/mcp", usage: "/mcp", description: "Show MCP server status", arg: Arg::None },
+    Command { name: "/attach", usage: "/attach [--smart] <path> [lines]", description: "Attach a file (or a line range like 10-20) to the next message; --smart sends only what the prompt mentions", arg: Arg::Path },
+    Command { name: "/title", usage: "/title [text]", description: "Show or rename the current session", arg: Arg::None },
+    Command { name: "/budget", usage: "/budget [override]", description: "Show spend against the daily and monthly budgets, or allow requests past them", arg: Arg::None },
+    Command { name: "/cmd", usage: "/cmd <task>", description: "Ask for a shell command doing the task, then edit, run or copy it", arg: Arg::None },
+    Command { name: "/gentest", usage: "/gentest <path> [item]", description: "Write unit tests for a file or one item in it, reviewed before they are saved", arg: Arg::Path },
+    Command { name: "/gendocs", usage: "/gendocs <path>", description: "Write rustdoc comments for the file's undocumented public items, reviewed before they are saved", arg: Arg::Path },
+    Command { name: "/prdesc", usage: "/prdesc [base]", description: "Write a pull request title and description for the branch, to copy or push to GitHub", arg: Arg::None },
+    Command { name: "/images", usage: "/images [n]", description: "List the images in the last answer, or open one in the image viewer", arg: Arg::None },
+    Command { name: "/links", usage: "/links [n]", description: "List the URLs in the last answer, or open one in the browser", arg: Arg::None },
+    Command { name: "/tokens", usage: "/tokens", description: "Estimated tokens per message and how full the context window is", arg: Arg::None },
+    Command { name: "/clear", usage: "/clear [context]", description: "Empty the chat pane; with context, also forget the conversation", arg: Arg::None },
+    Command { name: "/reset", usage: "/reset", description: "Archive this session and start a new one", arg: Arg::None },
+    Command { name: "/delete", usage: "/delete [n]", description: "List exchanges in the context or remove the nth", arg: Arg::None },
+    Command { name: "/undo", usage: "/undo", description: "Drop the last exchange and put its prompt back in the input", arg: Arg::None },
+    Command { name: "/pin", usage: "/pin [n]", description: "Keep an exchange (the last by default) when trimming the context", arg: Arg::None },
+    Command { name: "/bookmark", usage: "/bookmark [n]", description: "Bookmark the answer of an exchange (the last by default)", arg: Arg::None },
+    Command { name: "/bookmarks", usage: "/bookmarks", description: "Jump to a bookmarked answer", arg: Arg::None },
+    Command { name: "/tree", usage: "/tree", description: "Attach a listing of the project layout to the next message", arg: Arg::None },
+    Command { name: "/context", usage: "/context", description: "Toggle the pane showing what the next prompt will carry", arg: Arg::None },
+    Command { name: "/detach", usage: "/detach", description: "Remove all pending attachments", arg: Arg::None },
+    Command { name: "/cache", usage: "/cache [clear]", description: "Show or empty the local response cache", arg: Arg::None },
+    Command { name: "/stats", usage: "/stats [full]", description: "Latency, time to first token and tokens/s per model; full: requests, errors, tokens and cost per day and model", arg: Arg::None },
+    Command { name: "/cargo", usage: "/cargo <test|check|clippy>", description: "Run cargo and let the model fix failures until it passes", arg: Arg::None },
+    Command { name: "/explain", usage: "/explain <symbol> [question]", description: "Ask about a symbol with its definition, type and references from the language server", arg: Arg::None },
+    Command { name: "/fix", usage: "/fix", description: "Explain the project's compiler errors and propose fixes", arg: Arg::None },
+    Command { name: "/commit", usage: "/commit", description: "Generate a commit message from the staged diff", arg: Arg::None },
+    Command { name: "/encrypt", usage: "/encrypt", description: "Encrypt settings with a passphrase", arg: Arg::None },
+    Command { name: "/decrypt", usage: "/decrypt", description: "Store settings in plaintext again", arg: Arg::None },
+    Command { name: "/apply", usage: "/apply", description: "Review and write file-scoped code blocks from the last response", arg: Arg::None },
+    Command { name: "/savecode", usage: "/savecode <n> <path>", description: "Save the nth code block of the last response to a file", arg: Arg::Path },
+    Command { name: "/exit", usage: "/exit", description: "Quit Gentor", arg: Arg::None },
+];
+
+/// Keys of the chat screen, as shown by `/help`.
+pub const KEYS: &[(&str, &str)] = &[
+    ("Enter", "Send the prompt, or open the selected message"),
+    ("Esc", "Cancel the request in flight, clear the selection, or quit"),
+    ("Tab", "Complete a command, path or model"),
+    ("Up/Down", "Previous/next prompt from history (move between lines in a multi-line prompt)"),
+    ("Ctrl+P", "Command palette (previous prompt from history while typing a line)"),
+    ("Ctrl+N", "Next prompt from history"),
+    ("Ctrl+R", "Search the prompt history"),
+    ("PageUp/PageDown", "Scroll the chat"),
+    ("Alt+Up/Alt+Down", "Select a message"),
+    ("Alt+E", "Expand or collapse the selected (or latest) long reply"),
+    ("Left/Right, Home/End", "Move the cursor"),
+    ("Ctrl+Left/Right, Alt+B/Alt+F", "Move by word"),
+    ("Ctrl+A/Ctrl+E", "Start/end of the line"),
+    ("Ctrl+W, Ctrl+U, Ctrl+K", "Kill the word before, the line before, the line after the cursor"),
+    ("Ctrl+Y", "Yank the last kill"),
+    ("Ctrl+X Ctrl+E", "Edit the prompt in $VISUAL or $EDITOR"),
+    ("Ctrl+B", "Toggle the session sidebar"),
+    ("Ctrl+O", "Toggle the context pane"),
+    ("Ctrl+T", "Expand or collapse reasoning traces"),
+    ("F1", "This help"),
+];
+
+/// Keys of vim normal mode, shown by `/help` while 'Vim Keybindings' is on.
+pub const VIM_KEYS: &[(&str, &str)] = &[
+    ("Esc", "Normal mode"),
+    ("i/a/I/A", "Insert mode (at, after, at line start, at line end)"),
+    ("j/k, Ctrl+D/Ctrl+U", "Scroll the chat by a line, by a page"),
+    ("gg/G", "Scroll to the top/bottom"),
+    ("h/l/w/b/0/$", "Move the cursor"),
+    ("x, dd", "Delete a character, clear the input"),
+    ("/, n/N", "Search the chat, next older/newer match"),
+    ("[/]", "Select the previous/next message"),
+    ("za", "Expand or collapse the selected reply"),
+    ("o", "Open the selected message in the viewer"),
+    ("v", "Edit the prompt in $VISUAL or $EDITOR"),
+    (":", "Start a command"),
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+pub struct Completion {
+    /// Byte offset in the input where the completed word starts.
+    pub start: usize,
+    pub candidates: Vec<String>,
+    pub selected: Option<usize>,
+}
+
+/// Candidates for the word at the end of `input`: command names while the
+/// first word is being typed, argument values afterwards. `models` is the
+/// last model list fetched from the provider, `personas` the configured
+/// persona names, `extra` the commands registered by plugins.
+pub fn complete(input: &str, models: &[String], personas: &[String], extra: &[String]) -> Option<Completion> {
+    if !input.starts_with('/') {
+        return None;
+    }
+    let start = input.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &input[start..];
+    let candidates: Vec<String> = if start == 0 {
+        COMMANDS
+            .iter()
+            .map(|c| c.name)
+            .chain(extra.iter().map(String::as_str))
+            .filter(|n| n.starts_with(word))
+            .map(str::to_string)
+            .collect()
+    } else {
+        let name = input.split_whitespace().next().unwrap_or_default();
+        match find(name).map(|c| c.arg) {
+            Some(Arg::Path) => complete_path(word),
+            Some(Arg::Model) => models.iter().filter(|m| m.starts_with(word)).cloned().collect(),
+            Some(Arg::Persona) => personas.iter().filter(|p| p.starts_with(word)).cloned().collect(),
+            _ => Vec::new(),
+        }
+    };
+    (!candidates.is_empty()).then_some(Completion { start, candidates, selected: None })
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) else {
+        return Vec::new();
+    };
+    let mut out: Vec<String> = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if e.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let first = candidates.first().map(String::as_str).unwrap_or_default();
+    let len = candidates.iter().skip(1).fold(first.len(), |len, c| {
+        first[..len]
+            .char_indices()
+            .zip(c.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(c.len()), |((i, _), _)| i)
+    });
+    &first[..len]
+}
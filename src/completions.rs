@@ -0,0 +1,202 @@
+//! `gentor completions <shell>`: bash, zsh and fish completion scripts
+//! written from the command definitions in [`cli`], so they list the same
+//! commands and flags as `--help`.
+use anyhow::{bail, Result};
+
+use crate::cli::{self, CommandDef, FlagDef};
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+pub fn script(shell: &str) -> Result<String> {
+    Ok(match shell {
+        "bash" => bash(),
+        "zsh" => zsh(),
+        "fish" => fish(),
+        other => bail!("unsupported shell '{}' (available: {})", other, SHELLS.join(", ")),
+    })
+}
+
+/// Every command with its path, such as `["config", "show"]`, parents first.
+fn commands() -> Vec<(Vec<&'static str>, &'static CommandDef)> {
+    fn walk(prefix: &[&'static str], defs: &'static [CommandDef], found: &mut Vec<(Vec<&'static str>, &'static CommandDef)>) {
+        for def in defs {
+            let mut path = prefix.to_vec();
+            path.push(def.name);
+            found.push((path.clone(), def));
+            walk(&path, def.subcommands, found);
+        }
+    }
+    let mut found = Vec::new();
+    walk(&[], cli::COMMANDS, &mut found);
+    found
+}
+
+/// The flags of a command, or of gentor itself: its own and the global ones.
+fn flags(def: Option<&'static CommandDef>) -> impl Iterator<Item = &'static FlagDef> {
+    def.map_or(&[][..], |def| def.flags).iter().chain(cli::GLOBAL_FLAGS)
+}
+
+/// Every flag that takes a value, so the value is not taken for a command.
+fn value_flags() -> Vec<String> {
+    let mut names: Vec<String> = commands()
+        .into_iter()
+        .flat_map(|(_, def)| def.flags)
+        .chain(cli::GLOBAL_FLAGS)
+        .filter(|flag| flag.value.is_some())
+        .map(|flag| format!("--{}", flag.long))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn flag_words(def: Option<&'static CommandDef>) -> Vec<String> {
+    flags(def)
+        .flat_map(|flag| flag.short.map(|short| format!("-{}", short)).into_iter().chain([format!("--{}", flag.long)]))
+        .collect()
+}
+
+/// Shell-quoted between single quotes.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Lines of a `case` matching each command by `<parent path>/<name>`, which
+/// the scripts use to follow the words typed so far.
+fn transitions() -> String {
+    commands().iter().map(|(path, _)| format!("\"{}/{}\"", path[..path.len() - 1].join(" "), path[path.len() - 1])).collect::<Vec<_>>().join("|")
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for (path, def) in [(Vec::new(), None)].into_iter().chain(commands().into_iter().map(|(path, def)| (path, Some(def)))) {
+        let subcommands: Vec<&str> = def.map_or(cli::COMMANDS, |def| def.subcommands).iter().map(|def| def.name).collect();
+        cases.push_str(&format!(
+            "        {})\n            commands={}\n            flags={}\n            ;;\n",
+            quote(&path.join(" ")),
+            quote(&subcommands.join(" ")),
+            quote(&flag_words(def).join(" "))
+        ));
+    }
+    format!(
+        r#"# bash completion for gentor, from `gentor completions bash`
+_gentor() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local cmdpath="" word skip=0 i commands="" flags=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${{COMP_WORDS[i]}}"
+        if ((skip)); then skip=0; continue; fi
+        case "$word" in
+            {values}) skip=1 ;;
+            -*) ;;
+            *) case "$cmdpath/$word" in {transitions}) cmdpath="${{cmdpath:+$cmdpath }}$word" ;; esac ;;
+        esac
+    done
+    case "$prev" in {values}) return ;; esac
+    case "$cmdpath" in
+{cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$flags" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "$commands" -- "$cur"))
+    fi
+}}
+complete -o default -F _gentor gentor
+"#,
+        values = value_flags().join("|"),
+        transitions = transitions(),
+        cases = cases,
+    )
+}
+
+fn zsh() -> String {
+    let describe = |name: String, about: &str| quote(&format!("{}:{}", name.replace(':', r"\:"), about));
+    let mut cases = String::new();
+    for (path, def) in [(Vec::new(), None)].into_iter().chain(commands().into_iter().map(|(path, def)| (path, Some(def)))) {
+        let subcommands: Vec<String> = def.map_or(cli::COMMANDS, |def| def.subcommands).iter().map(|sub| describe(sub.name.to_string(), sub.about)).collect();
+        let options: Vec<String> = flags(def)
+            .flat_map(|flag| flag.short.map(|short| describe(format!("-{}", short), flag.help)).into_iter().chain([describe(format!("--{}", flag.long), flag.help)]))
+            .collect();
+        let files = def.is_some_and(|def| !def.args.is_empty());
+        cases.push_str(&format!(
+            "    {})\n      commands=({})\n      options=({})\n      files={}\n      ;;\n",
+            quote(&path.join(" ")),
+            subcommands.join(" "),
+            options.join(" "),
+            u8::from(files)
+        ));
+    }
+    format!(
+        r#"#compdef gentor
+# zsh completion for gentor, from `gentor completions zsh`
+
+_gentor() {{
+  local cmdpath="" word skip=0 i files=0
+  local -a commands options
+  for ((i = 2; i < CURRENT; i++)); do
+    word=${{words[i]}}
+    if ((skip)); then skip=0; continue; fi
+    case $word in
+      {values}) skip=1 ;;
+      -*) ;;
+      *) case "$cmdpath/$word" in {transitions}) cmdpath="${{cmdpath:+$cmdpath }}$word" ;; esac ;;
+    esac
+  done
+  case ${{words[CURRENT-1]}} in {values}) _message value; return ;; esac
+  case $cmdpath in
+{cases}  esac
+  if [[ $PREFIX == -* ]]; then
+    _describe -t options option options
+  elif ((${{#commands}})); then
+    _describe -t commands command commands
+  elif ((files)); then
+    _files
+  fi
+}}
+
+_gentor "$@"
+"#,
+        values = value_flags().join("|"),
+        transitions = transitions(),
+        cases = cases,
+    )
+}
+
+fn fish() -> String {
+    let mut lines = vec!["# fish completion for gentor, from `gentor completions fish`".to_string(), "complete -c gentor -f".to_string()];
+    let flag_line = |condition: Option<String>, flag: &FlagDef| {
+        let mut line = String::from("complete -c gentor");
+        if let Some(condition) = condition {
+            line.push_str(&format!(" -n {}", quote(&condition)));
+        }
+        if let Some(short) = flag.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        line.push_str(&format!(" -l {}", flag.long));
+        if flag.value.is_some() {
+            line.push_str(" -r");
+        }
+        line.push_str(&format!(" -d {}", quote(flag.help)));
+        line
+    };
+    for flag in cli::GLOBAL_FLAGS {
+        lines.push(flag_line(None, flag));
+    }
+    for (path, def) in commands() {
+        // a command is offered once its parent is typed and none of its siblings
+        let parent = &path[..path.len() - 1];
+        let siblings = parent.iter().fold(cli::COMMANDS, |defs, name| defs.iter().find(|def| def.name == *name).map_or(&[][..], |def| def.subcommands));
+        let sibling_names: Vec<&str> = siblings.iter().map(|def| def.name).collect();
+        let mut offered: Vec<String> = parent.iter().map(|name| format!("__fish_seen_subcommand_from {}", name)).collect();
+        offered.push(format!("not __fish_seen_subcommand_from {}", sibling_names.join(" ")));
+        lines.push(format!("complete -c gentor -n {} -a {} -d {}", quote(&offered.join("; and ")), def.name, quote(def.about)));
+        let inside = path.iter().map(|name| format!("__fish_seen_subcommand_from {}", name)).collect::<Vec<_>>().join("; and ");
+        for flag in def.flags {
+            lines.push(flag_line(Some(inside.clone()), flag));
+        }
+        if def.subcommands.is_empty() && !def.args.is_empty() {
+            lines.push(format!("complete -c gentor -n {} -F", quote(&inside)));
+        }
+    }
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,41 @@
+//! `gentor config show [--origin]`: the effective settings after the
+//! command line, environment, `.gentor.toml` and the config file are
+//! layered over the defaults, and with `--origin` where each value came from.
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::settings::{Origin, Settings};
+
+/// Keys whose values are secrets, shown with their last characters only.
+const SECRETS: &[&str] = &["api_key", "api_keys", "token"];
+
+pub fn show(settings: &Settings, origin: bool) -> Result<()> {
+    // through text, as `to_value` would widen f32s to 0.20000000298...
+    let Value::Object(values) = serde_json::from_str(&serde_json::to_string(settings)?)? else { unreachable!("settings serialize to an object") };
+    let width = values.keys().map(String::len).max().unwrap_or(0);
+    for (key, value) in values {
+        let value = masked(&key, value).to_string();
+        if origin {
+            println!("{:width$} = {}  # {}", key, value, settings.origins.get(&key).unwrap_or(&Origin::Default));
+        } else {
+            println!("{:width$} = {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+fn masked(key: &str, value: Value) -> Value {
+    match value {
+        Value::String(secret) if SECRETS.contains(&key) && !secret.is_empty() => hidden(&secret),
+        // header values are often a gateway's own key
+        Value::Object(map) if key == "extra_headers" => Value::Object(map.into_iter().map(|(k, v)| (k, v.as_str().map_or(v.clone(), hidden))).collect()),
+        Value::Object(map) => Value::Object(map.into_iter().map(|(k, v)| (k.clone(), masked(&k, v))).collect()),
+        Value::Array(items) => Value::Array(items.into_iter().map(|v| masked(key, v)).collect()),
+        other => other,
+    }
+}
+
+fn hidden(secret: &str) -> Value {
+    let tail: String = secret.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    Value::String(if secret.chars().count() > 8 { format!("…{}", tail) } else { "…".to_string() })
+}
@@ -0,0 +1,188 @@
+//! Extra context sent along with a prompt: attached files and the project layout.
+use anyhow::Result;
+use std::{fs, path::Path};
+
+use crate::{ignore::IgnoreRules, outline, settings::Settings};
+
+/// Attachment size limit when `max_attach_kb` is unset.
+const DEFAULT_MAX_KB: u64 = 256;
+/// How much of a file is scanned for NUL bytes when detecting binaries.
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Decides which project files may leave the machine.
+pub struct Filter {
+    rules: IgnoreRules,
+    max_bytes: u64,
+    allow_binary: bool,
+}
+
+impl Filter {
+    pub fn new(root: &Path, settings: &Settings) -> Self {
+        Self {
+            rules: IgnoreRules::load(root),
+            max_bytes: settings.max_attach_kb.unwrap_or(DEFAULT_MAX_KB) * 1024,
+            allow_binary: settings.allow_binary,
+        }
+    }
+
+    /// Reads `path` as text, refusing ignored, oversized and binary files.
+    pub fn read(&self, path: &str) -> Result<String> {
+        if self.rules.is_ignored(path, false) {
+            anyhow::bail!("{} is excluded by the ignore rules (.gitignore, .gentorignore)", path);
+        }
+        let size = fs::metadata(path)?.len();
+        if size > self.max_bytes {
+            anyhow::bail!("{} is {} KB, over the {} KB attachment limit", path, size / 1024, self.max_bytes / 1024);
+        }
+        let data = fs::read(path)?;
+        let binary = data.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0) || std::str::from_utf8(&data).is_err();
+        if binary && !self.allow_binary {
+            anyhow::bail!("{} looks like a binary file", path);
+        }
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+}
+
+pub struct Attachment {
+    label: String,
+    lang: String,
+    content: String,
+    /// Cut down to the items the prompt mentions when it is sent.
+    smart: bool,
+}
+
+impl Attachment {
+    /// Reads `path`, keeping only the 1-based inclusive `range` of lines when given.
+    pub fn load(path: &str, range: Option<(usize, usize)>, filter: &Filter) -> Result<Self> {
+        let text = filter.read(path)?;
+        let content = match range {
+            Some((start, end)) => {
+                let lines: Vec<&str> = text.lines().skip(start - 1).take(end - start + 1).collect();
+                if lines.is_empty() {
+                    anyhow::bail!("{} has only {} lines", path, text.lines().count());
+                }
+                lines.join("\n")
+            }
+            None => text,
+        };
+        let label = match range {
+            Some((start, usize::MAX)) => format!("{}:{}-", path, start),
+            Some((start, end)) => format!("{}:{}-{}", path, start, end),
+            None => path.to_string(),
+        };
+        let lang = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default().to_string();
+        Ok(Self { label, lang, content, smart: false })
+    }
+
+    pub fn tree(content: String) -> Self {
+        Self { label: "project tree".to_string(), lang: String::new(), content, smart: false }
+    }
+
+    /// What the language server knows about a symbol.
+    pub fn symbol(name: &str, content: String) -> Self {
+        Self { label: format!("symbol {}", name), lang: String::new(), content, smart: false }
+    }
+
+    pub fn smart(self) -> Self {
+        Self { label: format!("{} (smart)", self.label), smart: true, ..self }
+    }
+
+    /// For smart attachments, only the imports and the items `prompt`
+    /// mentions; the whole file when nothing matches.
+    pub fn focused(self, prompt: &str) -> Self {
+        if !self.smart {
+            return self;
+        }
+        let label = self.label.trim_end_matches(" (smart)").to_string();
+        match outline::focus(&self.content, &self.lang, prompt) {
+            Some((content, shown, total)) => Self { label: format!("{} ({} of {} items)", label, shown, total), content, ..self },
+            None => Self { label, ..self },
+        }
+    }
+
+    /// Only the imports and the items mentioning `item`; unchanged when
+    /// nothing matches.
+    pub fn scoped(self, item: &str) -> Self {
+        match outline::focus(&self.content, &self.lang, item) {
+            Some((content, shown, total)) => Self { label: format!("{} ({} of {} items)", self.label, shown, total), content, ..self },
+            None => self,
+        }
+    }
+
+    /// The first `lines` lines, for a file sent as an example.
+    pub fn truncated(self, lines: usize) -> Self {
+        let total = self.content.lines().count();
+        if total <= lines {
+            return self;
+        }
+        let content = self.content.lines().take(lines).collect::<Vec<_>>().join("\n");
+        Self { label: format!("{} (first {} of {} lines)", self.label, lines, total), content, ..self }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn fenced(&self) -> String {
+        format!("```{} {}\n{}\n```", self.lang, self.label, self.content.trim_end())
+    }
+}
+
+/// Parses `10-20`, `10:20`, `10-` (to the end) or `10` (a single line).
+pub fn parse_range(range: &str) -> Result<(usize, usize)> {
+    let bad = || anyhow::anyhow!("invalid line range '{}'", range);
+    let (start, end) = match range.split_once(['-', ':']) {
+        Some((start, "")) => (start.parse().map_err(|_| bad())?, usize::MAX),
+        Some((start, end)) => (start.parse().map_err(|_| bad())?, end.parse().map_err(|_| bad())?),
+        None => {
+            let line = range.parse().map_err(|_| bad())?;
+            (line, line)
+        }
+    };
+    if start == 0 || end < start {
+        return Err(bad());
+    }
+    Ok((start, end))
+}
+
+/// Condensed listing of `root` down to `max_depth`, skipping hidden and
+/// ignored entries. Stops after `max_entries` lines.
+pub fn project_tree(root: &Path, filter: &Filter, max_depth: usize, max_entries: usize) -> String {
+    let mut lines = Vec::new();
+    walk(root, "", filter, 0, max_depth, max_entries, &mut lines);
+    if lines.len() >= max_entries {
+        lines.push("...".to_string());
+    }
+    lines.join("\n")
+}
+
+fn walk(dir: &Path, rel: &str, filter: &Filter, depth: usize, max_depth: usize, max_entries: usize, lines: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut entries: Vec<_> = entries
+        .flatten()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| (e.path().is_dir(), e.file_name().to_string_lossy().into_owned(), e.path()))
+        .filter(|(is_dir, name, _)| !filter.rules.is_ignored(&format!("{}{}", rel, name), *is_dir))
+        .collect();
+    // directories first, then files, each alphabetically
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    for (is_dir, name, path) in entries {
+        if lines.len() >= max_entries {
+            return;
+        }
+        let indent = "  ".repeat(depth);
+        if is_dir {
+            lines.push(format!("{}{}/", indent, name));
+            if depth + 1 < max_depth {
+                walk(&path, &format!("{}{}/", rel, name), filter, depth + 1, max_depth, max_entries, lines);
+            }
+        } else {
+            lines.push(format!("{}{}", indent, name));
+        }
+    }
+}
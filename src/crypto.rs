@@ -0,0 +1,316 @@
+//! Passphrase encryption for local files: PBKDF2-HMAC-SHA256 key derivation
+//! and ChaCha20-Poly1305, each record sealed on its own (see [`MAGIC`]).
+//!
+//! The passphrase given at startup is kept here so sessions, stats, the
+//! prompt history, recovery data, the response cache and debug logs are
+//! read and written through [`read`], [`write`] and [`append`], encrypted
+//! while it is set.
+use anyhow::{anyhow, Result};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Files sealed record by record: `MAGIC | salt | frame*`, each frame
+/// `length | nonce | ciphertext+tag` with the length of the ciphertext and
+/// tag as a big-endian u32. Appending seals only the new record.
+const MAGIC: &[u8] = b"GENTORENC2";
+/// Files sealed as a whole, `MAGIC_V1 | salt | nonce | ciphertext+tag`,
+/// written before records were sealed one by one. Still read.
+const MAGIC_V1: &[u8] = b"GENTORENC1";
+const SALT_LEN: usize = 16;
+const LENGTH_LEN: usize = 4;
+/// PBKDF2-HMAC-SHA256 rounds, per current OWASP guidance.
+const ITERATIONS: u32 = 600_000;
+/// Rounds used for files in the [`MAGIC_V1`] layout.
+const V1_ITERATIONS: u32 = 100_000;
+
+/// The passphrase local data is encrypted with, and the keys derived from
+/// it so far by salt and rounds: deriving one takes a noticeable moment,
+/// and every file written by this process shares one salt.
+#[derive(Default)]
+struct Keyring {
+    passphrase: Option<String>,
+    salt: Option<[u8; SALT_LEN]>,
+    keys: HashMap<([u8; SALT_LEN], u32), [u8; 32]>,
+}
+
+static KEYRING: Mutex<Option<Keyring>> = Mutex::new(None);
+
+/// Sets the passphrase local data is encrypted with; `None` stores it in
+/// plaintext.
+pub fn unlock(passphrase: Option<&str>) {
+    *KEYRING.lock().unwrap_or_else(|e| e.into_inner()) = Some(Keyring { passphrase: passphrase.map(str::to_string), ..Keyring::default() });
+}
+
+fn passphrase() -> Option<String> {
+    KEYRING.lock().unwrap_or_else(|e| e.into_inner()).as_ref().and_then(|keyring| keyring.passphrase.clone())
+}
+
+/// The contents of `path`, decrypted when encrypted.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    read_with(passphrase().as_deref(), path)
+}
+
+fn read_with(passphrase: Option<&str>, path: &Path) -> Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if !is_encrypted(&data) {
+        return Ok(data);
+    }
+    let passphrase = passphrase.ok_or_else(|| anyhow!("{} is encrypted but no passphrase was given", path.display()))?;
+    decrypt(passphrase, &data)
+}
+
+pub fn read_to_string(path: &Path) -> Result<String> {
+    Ok(String::from_utf8(read(path)?)?)
+}
+
+/// Replaces `path` with `contents`, encrypted while a passphrase is set.
+/// The new contents are written beside it and renamed over it, so a crash
+/// leaves either the old file or the new one.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    write_with(passphrase().as_deref(), path, contents.as_ref())
+}
+
+fn write_with(passphrase: Option<&str>, path: &Path, contents: &[u8]) -> Result<()> {
+    let data = match passphrase {
+        Some(passphrase) => encrypt(passphrase, contents)?,
+        None => contents.to_vec(),
+    };
+    let name = path.file_name().ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+    let written = fs::write(&temp, data).and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(written?)
+}
+
+/// Appends `line` and a newline to `path`. A record is added to a file
+/// already encrypted in the current layout; any other file is sealed anew.
+pub fn append(path: &Path, line: &str) -> Result<()> {
+    append_with(passphrase().as_deref(), path, line)
+}
+
+fn append_with(passphrase: Option<&str>, path: &Path, line: &str) -> Result<()> {
+    let record = format!("{}\n", line);
+    let head = match File::open(path) {
+        Ok(file) => {
+            let mut head = Vec::new();
+            file.take((MAGIC.len() + SALT_LEN) as u64).read_to_end(&mut head)?;
+            Some(head)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    match (passphrase, head) {
+        (None, head) if !head.as_deref().is_some_and(is_encrypted) => {
+            OpenOptions::new().create(true).append(true).open(path)?.write_all(record.as_bytes())?;
+        }
+        (Some(passphrase), Some(head)) if head.starts_with(MAGIC) && head.len() == MAGIC.len() + SALT_LEN => {
+            let salt: &[u8; SALT_LEN] = head[MAGIC.len()..].try_into().expect("SALT_LEN bytes");
+            let frame = seal(&key(passphrase, salt, ITERATIONS), record.as_bytes())?;
+            // one write, so an interrupted append cuts off at most this record
+            OpenOptions::new().append(true).open(path)?.write_all(&frame)?;
+        }
+        (passphrase, head) => {
+            let mut data = if head.is_some() { read_with(passphrase, path)? } else { Vec::new() };
+            data.extend_from_slice(record.as_bytes());
+            write_with(passphrase, path, &data)?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-encrypts `files` with `passphrase`, or stores them in plaintext when
+/// `None`, and makes it the passphrase for later writes. Files that cannot
+/// be read with the current passphrase are left as they are.
+pub fn rekey(passphrase: Option<&str>, files: &[PathBuf]) -> Result<()> {
+    let contents: Vec<(&PathBuf, Vec<u8>)> = files.iter().filter_map(|path| Some((path, read(path).ok()?))).collect();
+    unlock(passphrase);
+    for (path, data) in contents {
+        write(path, data)?;
+    }
+    Ok(())
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.starts_with(MAGIC_V1)
+}
+
+fn key(passphrase: &str, salt: &[u8; SALT_LEN], iterations: u32) -> LessSafeKey {
+    let mut guard = KEYRING.lock().unwrap_or_else(|e| e.into_inner());
+    // keys are only cached for the passphrase set with `unlock`
+    let keyring = guard.as_mut().filter(|keyring| keyring.passphrase.as_deref() == Some(passphrase));
+    let cached = keyring.as_ref().and_then(|keyring| keyring.keys.get(&(*salt, iterations)).copied());
+    let key = cached.unwrap_or_else(|| {
+        let mut key = [0u8; 32];
+        let rounds = NonZeroU32::new(iterations).expect("non-zero iterations");
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, rounds, salt, passphrase.as_bytes(), &mut key);
+        if let Some(keyring) = keyring {
+            keyring.keys.insert((*salt, iterations), key);
+        }
+        key
+    });
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).expect("32-byte key"))
+}
+
+/// The salt for a new file: the process-wide one for the unlocked
+/// passphrase, a fresh one for any other.
+fn salt(passphrase: &str, rng: &SystemRandom) -> Result<[u8; SALT_LEN]> {
+    let mut guard = KEYRING.lock().unwrap_or_else(|e| e.into_inner());
+    let keyring = guard.as_mut().filter(|keyring| keyring.passphrase.as_deref() == Some(passphrase));
+    if let Some(salt) = keyring.as_ref().and_then(|keyring| keyring.salt) {
+        return Ok(salt);
+    }
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill(&mut salt).map_err(|_| anyhow!("no system randomness"))?;
+    if let Some(keyring) = keyring {
+        keyring.salt = Some(salt);
+    }
+    Ok(salt)
+}
+
+/// `plaintext` sealed under a fresh nonce, as one frame.
+fn seal(key: &LessSafeKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| anyhow!("no system randomness"))?;
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+        .map_err(|_| anyhow!("encryption failed"))?;
+    let length = u32::try_from(sealed.len()).map_err(|_| anyhow!("record too large to encrypt"))?;
+    Ok([&length.to_be_bytes()[..], &nonce, &sealed].concat())
+}
+
+fn open(key: &LessSafeKey, nonce: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("bad nonce"))?;
+    let mut buf = sealed.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut buf).map_err(|_| anyhow!("wrong passphrase or corrupted file"))?;
+    Ok(plaintext.to_vec())
+}
+
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let salt = salt(passphrase, &SystemRandom::new())?;
+    let frame = seal(&key(passphrase, &salt, ITERATIONS), plaintext)?;
+    Ok([MAGIC, &salt, &frame].concat())
+}
+
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if let Some(rest) = data.strip_prefix(MAGIC_V1) {
+        if rest.len() < SALT_LEN + NONCE_LEN {
+            return Err(anyhow!("encrypted file is truncated"));
+        }
+        let (salt, rest) = rest.split_at(SALT_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        return open(&key(passphrase, salt.try_into().expect("SALT_LEN bytes"), V1_ITERATIONS), nonce, sealed);
+    }
+    let rest = data.strip_prefix(MAGIC).ok_or_else(|| anyhow!("not an encrypted file"))?;
+    if rest.len() < SALT_LEN {
+        return Err(anyhow!("encrypted file is truncated"));
+    }
+    let (salt, mut frames) = rest.split_at(SALT_LEN);
+    let key = key(passphrase, salt.try_into().expect("SALT_LEN bytes"), ITERATIONS);
+    let mut plaintext = Vec::new();
+    while frames.len() > LENGTH_LEN + NONCE_LEN {
+        let (length, rest) = frames.split_at(LENGTH_LEN);
+        let length = u32::from_be_bytes(length.try_into().expect("LENGTH_LEN bytes")) as usize;
+        let (nonce, rest) = rest.split_at(NONCE_LEN);
+        // a record cut short by an interrupted append is left out
+        if rest.len() < length {
+            break;
+        }
+        let (sealed, rest) = rest.split_at(length);
+        plaintext.extend(open(&key, nonce, sealed)?);
+        frames = rest;
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_data_round_trips() {
+        let sealed = encrypt("hunter2", b"the plaintext").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(13).any(|w| w == b"the plaintext"));
+        assert_eq!(decrypt("hunter2", &sealed).unwrap(), b"the plaintext");
+    }
+
+    #[test]
+    fn a_wrong_passphrase_is_rejected() {
+        let sealed = encrypt("hunter2", b"the plaintext").unwrap();
+        let error = decrypt("hunter3", &sealed).unwrap_err();
+        assert_eq!(error.to_string(), "wrong passphrase or corrupted file");
+    }
+
+    #[test]
+    fn tampered_truncated_and_plain_data_are_rejected() {
+        let mut sealed = encrypt("hunter2", b"the plaintext").unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(decrypt("hunter2", &sealed).is_err());
+        assert_eq!(decrypt("hunter2", &sealed[..MAGIC.len() + 4]).unwrap_err().to_string(), "encrypted file is truncated");
+        assert_eq!(decrypt("hunter2", b"{}").unwrap_err().to_string(), "not an encrypted file");
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gentor-crypto-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn appends_seal_only_the_new_record() {
+        let path = scratch("append");
+        append_with(Some("hunter2"), &path, "one").unwrap();
+        let first = fs::read(&path).unwrap();
+        append_with(Some("hunter2"), &path, "two").unwrap();
+        let both = fs::read(&path).unwrap();
+        assert!(both.starts_with(&first));
+        assert_eq!(decrypt("hunter2", &both).unwrap(), b"one\ntwo\n");
+        assert_eq!(read_with(Some("hunter2"), &path).unwrap(), b"one\ntwo\n");
+        // an append cut short loses only its own record
+        fs::write(&path, &both[..both.len() - 3]).unwrap();
+        assert_eq!(read_with(Some("hunter2"), &path).unwrap(), b"one\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_and_older_files_are_sealed_anew_on_append() {
+        let path = scratch("legacy");
+        append_with(None, &path, "plain").unwrap();
+        append_with(Some("hunter2"), &path, "sealed").unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(read_with(Some("hunter2"), &path).unwrap(), b"plain\nsealed\n");
+
+        let salt = [7u8; SALT_LEN];
+        let frame = seal(&key("hunter2", &salt, V1_ITERATIONS), b"old\n").unwrap();
+        fs::write(&path, [MAGIC_V1, &salt, &frame[LENGTH_LEN..]].concat()).unwrap();
+        append_with(Some("hunter2"), &path, "new").unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(read_with(Some("hunter2"), &path).unwrap(), b"old\nnew\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_leave_no_temporary_file() {
+        let path = scratch("write");
+        write_with(Some("hunter2"), &path, b"first").unwrap();
+        write_with(Some("hunter2"), &path, b"second").unwrap();
+        assert_eq!(read_with(Some("hunter2"), &path).unwrap(), b"second");
+        let temp = path.with_file_name(format!(".{}.{}.tmp", path.file_name().unwrap().to_string_lossy(), std::process::id()));
+        assert!(!temp.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
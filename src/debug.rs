@@ -0,0 +1,203 @@
+//! `/debug on`: every request of the session and the provider's raw answer
+//! (its chunks when streamed, or the error) appended as JSON lines to
+//! `~/.config/gentor/debug/<session>.jsonl`, with keys and tokens redacted.
+use async_openai::error::OpenAIError;
+use futures::future::{self, BoxFuture};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    crypto,
+    middleware::{Exchange, Middleware},
+    settings::{self, Settings},
+};
+
+const REDACTED: &str = "[redacted]";
+/// Keys whose values are secrets wherever they appear.
+const SECRET_KEYS: &[&str] = &["api_key", "authorization", "token", "password"];
+/// Prefixes of well-known token formats, redacted wherever they appear.
+const TOKEN_PREFIXES: &[&str] = &["sk-", "ghp_", "gho_", "github_pat_", "xoxb-", "xoxp-", "AKIA"];
+/// Shortest run of token characters after a prefix taken for a token.
+const MIN_TOKEN: usize = 16;
+
+/// The debug log of the session started at `created`.
+pub fn log_path(created: u64) -> Option<PathBuf> {
+    Some(log_dir()?.join(format!("{}.jsonl", created)))
+}
+
+pub(crate) fn log_dir() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("debug"))
+}
+
+/// Appends one exchange to `settings.debug_log` and returns it: the request
+/// and either the raw response or the error, marked `cached` when the
+/// response cache answered it.
+pub fn record(settings: &Settings, request: &Value, response: Result<Value, String>, cached: bool) -> Value {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let mut entry = json!({ "time": time, "base_url": settings.base_url, "request": request });
+    if cached {
+        entry["cached"] = json!(true);
+    }
+    match response {
+        Ok(response) => entry["response"] = response,
+        Err(error) => entry["error"] = json!(error),
+    }
+    redact(&mut entry, &secrets(settings));
+    if let Some(path) = &settings.debug_log {
+        let _ = path.parent().map(fs::create_dir_all);
+        let _ = crypto::append(path, &entry.to_string());
+    }
+    entry
+}
+
+/// Replaces `text` wherever it appears in the log at `path`, as a JSON
+/// string or part of one, so a deleted prompt does not outlive its exchange.
+pub fn scrub(path: &Path, text: &str) -> anyhow::Result<()> {
+    let escaped = Value::from(text).to_string();
+    let escaped = &escaped[1..escaped.len() - 1];
+    if escaped.is_empty() || !path.exists() {
+        return Ok(());
+    }
+    let log = crypto::read_to_string(path)?;
+    if log.contains(escaped) {
+        crypto::write(path, log.replace(escaped, "[deleted]"))?;
+    }
+    Ok(())
+}
+
+/// Records every exchange while `/debug` is on.
+pub struct Log;
+
+impl Middleware for Log {
+    fn before<'a>(&'a self, exchange: &'a mut Exchange<'_>) -> BoxFuture<'a, anyhow::Result<()>> {
+        if exchange.streamed {
+            exchange.chunks = Some(Vec::new());
+        }
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn after<'a>(&'a self, exchange: &'a mut Exchange<'_>, result: Result<&'a Value, &'a anyhow::Error>) -> BoxFuture<'a, ()> {
+        let response = match (result, &exchange.chunks) {
+            (Ok(_), Some(chunks)) => Ok(json!({ "chunks": chunks })),
+            (Ok(response), None) => Ok(response.clone()),
+            // the provider's error type, code and parameter are in the debug form
+            (Err(e), _) => Err(e.downcast_ref::<OpenAIError>().map_or_else(|| format!("{:#}", e), |e| format!("{:?}", e))),
+        };
+        exchange.record = Some(record(exchange.settings, &exchange.request, response, exchange.cached));
+        Box::pin(future::ready(()))
+    }
+}
+
+/// Keys and tokens configured anywhere in `settings`.
+fn secrets(settings: &Settings) -> Vec<String> {
+    let mut secrets = vec![settings.api_key.clone()];
+    secrets.extend(settings.api_keys.iter().cloned());
+    secrets.extend(settings.providers.values().filter_map(|provider| provider.api_key.clone()));
+    secrets.extend(settings.providers.values().flat_map(|provider| provider.api_keys.iter().cloned()));
+    secrets.extend(settings.extra_headers.values().cloned());
+    secrets.extend(settings.providers.values().flat_map(|provider| provider.extra_headers.values().cloned()));
+    secrets.extend(settings.fallbacks.iter().filter_map(|fallback| fallback.api_key.clone()));
+    secrets.extend(settings.github.as_ref().and_then(|github| github.token.clone()));
+    secrets.extend(settings.web_search.as_ref().and_then(|search| search.api_key.clone()));
+    // too short to be a real key, and replacing it would garble the log
+    secrets.retain(|secret| secret.len() >= 8);
+    secrets
+}
+
+fn redact(value: &mut Value, secrets: &[String]) {
+    match value {
+        Value::String(text) => *text = redact_text(text, secrets),
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, secrets)),
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::String(text) if SECRET_KEYS.contains(&key.to_ascii_lowercase().as_str()) && !text.is_empty() => *text = REDACTED.to_string(),
+                    value => redact(value, secrets),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_text(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_string();
+    for secret in secrets {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some((at, prefix)) = TOKEN_PREFIXES.iter().filter_map(|prefix| rest.find(prefix).map(|at| (at, *prefix))).min() {
+        let after = &rest[at + prefix.len()..];
+        let run = after.find(|c: char| !is_token_char(c)).unwrap_or(after.len());
+        // `sk-` inside a word such as `task-...` is not a key
+        let in_word = rest[..at].chars().next_back().is_some_and(is_token_char);
+        redacted.push_str(&rest[..at]);
+        if run >= MIN_TOKEN && !in_word {
+            redacted.push_str(REDACTED);
+        } else {
+            redacted.push_str(&rest[at..at + prefix.len() + run]);
+        }
+        rest = &after[run..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_keys_are_redacted_unless_too_short() {
+        let mut settings = Settings::defaults();
+        settings.api_key = "corp-key-1234".to_string();
+        settings.api_keys = vec!["backup-key-5678".to_string(), "abc1234".to_string()];
+        let secrets = secrets(&settings);
+        assert!(secrets.contains(&"corp-key-1234".to_string()) && secrets.contains(&"backup-key-5678".to_string()));
+        // seven characters is below the minimum, so "abc1234" stays in the text
+        assert!(!secrets.contains(&"abc1234".to_string()));
+        let text = "keys corp-key-1234 and backup-key-5678, id abc1234";
+        assert_eq!(redact_text(text, &secrets), "keys [redacted] and [redacted], id abc1234");
+    }
+
+    #[test]
+    fn secret_fields_are_redacted_wherever_they_nest() {
+        let mut value = json!({
+            "Authorization": "Bearer anything",
+            "headers": [{"token": "t0", "name": "kept"}],
+            "api_key": "",
+            "password": 42,
+        });
+        redact(&mut value, &[]);
+        assert_eq!(value, json!({
+            "Authorization": "[redacted]",
+            "headers": [{"token": "[redacted]", "name": "kept"}],
+            "api_key": "",
+            "password": 42,
+        }));
+    }
+
+    #[test]
+    fn prefixed_tokens_are_redacted_but_not_inside_words() {
+        let key = "sk-proj-abcdefghijklmnop";
+        assert_eq!(redact_text(&format!("use {} now", key), &[]), "use [redacted] now");
+        assert_eq!(redact_text("ghp_0123456789abcdefXYZ.", &[]), "[redacted].");
+        // too short after the prefix to be a token
+        assert_eq!(redact_text("sk-short", &[]), "sk-short");
+        assert_eq!(redact_text("see task-abcdefghijklmnopqrst", &[]), "see task-abcdefghijklmnopqrst");
+    }
+
+    #[test]
+    fn multibyte_text_around_a_token_survives() {
+        let text = "키는 sk-abcdefghijklmnopqrstu입니다 🔑";
+        assert_eq!(redact_text(text, &[]), "키는 [redacted]입니다 🔑");
+        let secrets = vec!["비밀번호-12345".to_string()];
+        assert_eq!(redact_text("값: 비밀번호-12345 끝", &secrets), "값: [redacted] 끝");
+    }
+}
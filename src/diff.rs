@@ -0,0 +1,114 @@
+//! Line and word diffs from the longest common subsequence.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Same,
+    Added,
+    Removed,
+}
+
+/// Word diffs larger than this many token pairs fall back to lines to bound memory.
+const MAX_WORD_CELLS: usize = 4_000_000;
+
+/// Line-based diff from the longest common subsequence of `old` and `new`.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<(Change, &'a str)> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    diff(&a, &b)
+}
+
+/// Word-level diff. Words and the whitespace between them are separate
+/// pieces, so joining the pieces of either side gives back that text.
+pub fn words<'a>(old: &'a str, new: &'a str) -> Vec<(Change, &'a str)> {
+    let a = split_words(old);
+    let b = split_words(new);
+    if a.len().saturating_mul(b.len()) > MAX_WORD_CELLS {
+        let a: Vec<&str> = old.split_inclusive('\n').collect();
+        let b: Vec<&str> = new.split_inclusive('\n').collect();
+        return diff(&a, &b);
+    }
+    diff(&a, &b)
+}
+
+fn split_words(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (i, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if in_space.is_some_and(|s| s != space) {
+            pieces.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+fn diff<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Change, &'a str)> {
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            out.push((Change::Same, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push((Change::Removed, a[i]));
+            i += 1;
+        } else {
+            out.push((Change::Added, b[j]));
+            j += 1;
+        }
+    }
+    out.extend(a[i..].iter().map(|l| (Change::Removed, *l)));
+    out.extend(b[j..].iter().map(|l| (Change::Added, *l)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked(changes: &[(Change, &str)]) -> String {
+        changes
+            .iter()
+            .map(|(change, text)| match change {
+                Change::Same => text.to_string(),
+                Change::Added => format!("{{+{}}}", text),
+                Change::Removed => format!("{{-{}}}", text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines_keep_the_common_subsequence() {
+        let changes = lines("a\nb\nc\n", "a\nx\nc\nd\n");
+        assert_eq!(marked(&changes), "a{-b}{+x}c{+d}");
+        assert!(lines("same\n", "same\n").iter().all(|(change, _)| *change == Change::Same));
+        assert_eq!(marked(&lines("", "new")), "{+new}");
+    }
+
+    #[test]
+    fn words_rejoin_into_either_side() {
+        let (old, new) = ("the quick  brown fox", "the slow  brown fox jumps");
+        let changes = words(old, new);
+        assert_eq!(marked(&changes), "the {-quick}{+slow}  brown fox{+ }{+jumps}");
+        let side = |keep: Change| changes.iter().filter(|(c, _)| *c == Change::Same || *c == keep).map(|(_, t)| *t).collect::<String>();
+        assert_eq!(side(Change::Removed), old);
+        assert_eq!(side(Change::Added), new);
+    }
+}
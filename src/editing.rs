@@ -0,0 +1,137 @@
+//! Cursor motions for the input box, as byte offsets into its text that
+//! always fall on grapheme cluster boundaries, so an emoji or an accented
+//! letter moves and deletes as one character.
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Start of the line the cursor is on.
+pub fn line_start(text: &str, at: usize) -> usize {
+    text[..at].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// End of the line the cursor is on.
+pub fn line_end(text: &str, at: usize) -> usize {
+    text[at..].find('\n').map_or(text.len(), |i| at + i)
+}
+
+/// Offset of the grapheme before the cursor.
+pub fn prev_char(text: &str, at: usize) -> usize {
+    text[..at].grapheme_indices(true).next_back().map_or(0, |(i, _)| i)
+}
+
+/// Offset just past the grapheme after the cursor.
+pub fn next_char(text: &str, at: usize) -> usize {
+    text[at..].graphemes(true).next().map_or(at, |g| at + g.len())
+}
+
+/// Removes the last grapheme of a field edited only at its end.
+pub fn pop_char(text: &mut String) {
+    text.truncate(prev_char(text, text.len()));
+}
+
+/// Terminal columns `text` takes up.
+pub fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// The same column on the previous (`up`) or next line, or the end of that
+/// line when it is shorter; `None` on the first or last line.
+pub fn vertical(text: &str, at: usize, up: bool) -> Option<usize> {
+    let start = line_start(text, at);
+    let column = width(&text[start..at]);
+    let target = if up {
+        line_start(text, start.checked_sub(1)?)
+    } else {
+        line_end(text, at).checked_add(1).filter(|&next| next <= text.len())?
+    };
+    let end = line_end(text, target);
+    // stop before a wide character that would straddle the column
+    let mut used = 0;
+    for (i, g) in text[target..end].grapheme_indices(true) {
+        used += width(g);
+        if used > column {
+            return Some(target + i);
+        }
+    }
+    Some(end)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Start of the word before the cursor, as Alt+B moves.
+pub fn word_left(text: &str, at: usize) -> usize {
+    let before = text[..at].trim_end_matches(|c| !is_word(c));
+    before.trim_end_matches(is_word).len()
+}
+
+/// End of the word after the cursor, as Alt+F moves.
+pub fn word_right(text: &str, at: usize) -> usize {
+    let after = text[at..].trim_start_matches(|c| !is_word(c));
+    text.len() - after.trim_start_matches(is_word).len()
+}
+
+/// Start of the whitespace-delimited word before the cursor, which Ctrl+W kills.
+pub fn rubout_start(text: &str, at: usize) -> usize {
+    text[..at].trim_end().trim_end_matches(|c: char| !c.is_whitespace()).len()
+}
+
+/// Display column and row of the cursor within the text.
+pub fn cursor_position(text: &str, at: usize) -> (usize, usize) {
+    let row = text[..at].matches('\n').count();
+    (width(&text[line_start(text, at)..at]), row)
+}
+
+/// `at` moved back onto a grapheme boundary.
+pub fn boundary(text: &str, at: usize) -> usize {
+    if at >= text.len() {
+        return text.len();
+    }
+    text.grapheme_indices(true).map(|(i, _)| i).take_while(|&i| i <= at).last().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_are_graphemes() {
+        let text = "ae\u{301}👍🏽";
+        assert_eq!(next_char(text, 0), 1);
+        assert_eq!(next_char(text, 1), 4);
+        assert_eq!(prev_char(text, text.len()), 4);
+        assert_eq!(boundary(text, 2), 1);
+        let mut field = text.to_string();
+        pop_char(&mut field);
+        assert_eq!(field, "ae\u{301}");
+        assert_eq!(width("日本"), 4);
+    }
+
+    #[test]
+    fn lines_and_vertical_motion() {
+        let text = "first line\nab\nthird";
+        assert_eq!(line_start(text, 12), 11);
+        assert_eq!(line_end(text, 12), 13);
+        // down from column 8 lands at the end of the shorter line
+        assert_eq!(vertical(text, 8, false), Some(13));
+        assert_eq!(vertical(text, 13, false), Some(16));
+        assert_eq!(vertical(text, 2, true), None);
+        assert_eq!(vertical(text, 16, false), None);
+        assert_eq!(cursor_position(text, 16), (2, 2));
+        // up from column 2 stops before the wide character covering it
+        assert_eq!(vertical("日本\nabc", 9, true), Some(3));
+        assert_eq!(vertical("日本\nabc", 8, true), Some(0));
+    }
+
+    #[test]
+    fn word_motions() {
+        let text = "let foo_bar = baz.qux";
+        assert_eq!(word_left(text, text.len()), 18);
+        assert_eq!(word_left(text, 18), 14);
+        assert_eq!(word_right(text, 3), 11);
+        assert_eq!(word_right(text, 11), 17);
+        assert_eq!(rubout_start(text, text.len()), 14);
+        assert_eq!(rubout_start("a b   ", 6), 2);
+    }
+}
@@ -0,0 +1,130 @@
+//! `gentor explain -- <command…>`: explains a command line part by part,
+//! with the `--help` output of the programs it runs as reference so flags
+//! are described as the installed versions define them.
+use anyhow::{bail, Result};
+use std::{env, path::Path, process::Stdio, time::Duration};
+
+use crate::{agent, i18n, settings::Settings};
+
+/// Help text kept per program.
+const MAX_HELP_LINES: usize = 150;
+const HELP_TIMEOUT: Duration = Duration::from_secs(3);
+/// Words that run the command after them.
+const WRAPPERS: &[&str] = &["sudo", "doas", "env", "time", "nohup", "exec", "command"];
+/// Programs whose second word is a subcommand with its own help. Others
+/// only get `--help` alone: for `bash script --help` the script would run.
+const WITH_SUBCOMMANDS: &[&str] = &[
+    "git", "cargo", "rustup", "docker", "podman", "kubectl", "helm", "npm", "pnpm", "yarn", "pip", "go", "systemctl", "journalctl", "apt", "brew", "gh",
+    "ip", "openssl",
+];
+const EXPLAIN_PROMPT: &str = "You explain shell command lines to the user. Structure the answer as: a one-line summary of what the whole command does; then a list with one entry per program, subcommand, flag, argument, pipe and redirection, in order, each as `part` — what it does here; then anything surprising or dangerous (deleting files, overwriting data, running as root, piping downloads to a shell). Base flag meanings on the help text given when it covers them. Be concise.";
+
+pub async fn run(settings: Settings, command: &str) -> Result<()> {
+    if command.trim().is_empty() {
+        bail!("{}", i18n::fill("cli.usage", &[&"gentor explain -- <command…>"]));
+    }
+    let mut prompt = format!("Command line:\n```sh\n{}\n```", command);
+    for (name, help) in helps(command).await {
+        prompt.push_str(&format!("\n\n`{}` help:\n```\n{}\n```", name, help));
+    }
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    println!("{}", agent::complete(&client, &settings, EXPLAIN_PROMPT, &prompt).await?.trim());
+    Ok(())
+}
+
+/// Help output of each program in the pipeline, and of its subcommand when
+/// it has one (`git commit --help`), as `(name, help)`.
+async fn helps(command: &str) -> Vec<(String, String)> {
+    let mut found: Vec<(String, String)> = Vec::new();
+    for words in segments(command) {
+        let Some((program, rest)) = words.split_first() else { continue };
+        if found.iter().any(|(name, _)| name == program || name.starts_with(&format!("{} ", program))) {
+            continue;
+        }
+        let sub = rest.first().filter(|word| WITH_SUBCOMMANDS.contains(&program.as_str()) && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') && !word.starts_with('-'));
+        if let Some(sub) = sub {
+            if let Some(help) = help(program, Some(sub)).await {
+                found.push((format!("{} {}", program, sub), help));
+                continue;
+            }
+        }
+        if let Some(help) = help(program, None).await {
+            found.push((program.clone(), help));
+        }
+    }
+    found
+}
+
+/// The words of each simple command, split at pipes and command separators,
+/// with quotes removed and wrappers like `sudo` and `VAR=value` skipped.
+fn segments(command: &str) -> Vec<Vec<String>> {
+    let mut segments = vec![Vec::new()];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '|' | ';' | '&' | '(' | ')' | '`') => {
+                end_word(&mut word, &mut segments);
+                segments.push(Vec::new());
+                while chars.peek().is_some_and(|next| matches!(next, '|' | '&')) {
+                    chars.next();
+                }
+            }
+            (None, c) if c.is_whitespace() => end_word(&mut word, &mut segments),
+            (None, c) => word.push(c),
+        }
+    }
+    end_word(&mut word, &mut segments);
+    segments
+        .into_iter()
+        .map(|words| {
+            let start = words.iter().position(|w| !skipped(w)).unwrap_or(words.len());
+            words[start..].to_vec()
+        })
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+fn end_word(word: &mut String, segments: &mut [Vec<String>]) {
+    if let (false, Some(segment)) = (word.is_empty(), segments.last_mut()) {
+        segment.push(std::mem::take(word));
+    }
+}
+
+/// Wrappers, their flags and `VAR=value` before the program itself.
+fn skipped(word: &str) -> bool {
+    WRAPPERS.contains(&word) || word.starts_with('-') || word.find('=').is_some_and(|at| at > 0)
+}
+
+/// `program [sub] --help`, for programs found on the `PATH` only: a path
+/// like `./install.sh` is a script that would run rather than print help.
+async fn help(program: &str, sub: Option<&str>) -> Option<String> {
+    if program.contains(['/', '\\']) || !on_path(program) {
+        return None;
+    }
+    let mut command = tokio::process::Command::new(program);
+    command.args(sub).arg("--help");
+    command.env("PAGER", "cat").env("GIT_PAGER", "cat").env("MANPAGER", "cat").env("TERM", "dumb");
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+    let output = tokio::time::timeout(HELP_TIMEOUT, command.output()).await.ok()?.ok()?;
+    // some programs print help to stderr, or exit non-zero after printing it
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    let text = String::from_utf8_lossy(&text);
+    let lines: Vec<&str> = text.lines().take(MAX_HELP_LINES).collect();
+    let help = lines.join("\n").trim().to_string();
+    // an unknown subcommand usually answers with a short error instead
+    (lines.len() > 3 || (sub.is_none() && !help.is_empty())).then_some(help)
+}
+
+fn on_path(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else { return false };
+    env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(program);
+        candidate.is_file() || (cfg!(windows) && Path::new(&candidate.with_extension("exe")).is_file())
+    })
+}
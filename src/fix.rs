@@ -0,0 +1,28 @@
+//! `gentor fix`: builds the project and prints an explanation and proposed
+//! fix for each group of compiler errors.
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{agent, cargo, context, i18n, settings::Settings};
+
+/// Asks for the cause and smallest fix of each group of errors; `/fix` in
+/// the chat sends it too.
+pub const PROMPT: &str = "Explain each group of Rust compiler errors below: the cause in a sentence or two, then the smallest fix, as a file-scoped code block with the complete new file or a precise edit.";
+
+pub async fn run(settings: Settings) -> Result<()> {
+    println!("{}", i18n::t("fix.building"));
+    let groups = cargo::group(cargo::diagnostics().await?);
+    if groups.is_empty() {
+        println!("{}", i18n::t("fix.no_errors"));
+        return Ok(());
+    }
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    let filter = context::Filter::new(Path::new("."), &settings);
+    for (key, errors) in &groups {
+        println!("\n{}", i18n::fill("fix.group", &[key, &errors.len()]));
+        println!("{}", agent::complete(&client, &settings, PROMPT, &cargo::describe(key, errors, &filter)).await?);
+    }
+    Ok(())
+}
+
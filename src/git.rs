@@ -0,0 +1,46 @@
+//! The git commands behind `/commit` and `/prdesc`.
+use anyhow::{bail, Result};
+use std::process::Command;
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output()?;
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn staged_diff() -> Result<String> {
+    git(&["diff", "--cached"])
+}
+
+pub fn commit(message: &str) -> Result<String> {
+    git(&["commit", "-m", message])
+}
+
+pub fn current_branch() -> Result<String> {
+    Ok(git(&["rev-parse", "--abbrev-ref", "HEAD"])?.trim().to_string())
+}
+
+/// The branch `origin/HEAD` points at, else `main` or `master`, whichever exists.
+pub fn default_base() -> Result<String> {
+    if let Ok(head) = git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"]) {
+        return Ok(head.trim().to_string());
+    }
+    for base in ["main", "master"] {
+        if git(&["rev-parse", "--verify", "--quiet", base]).is_ok() {
+            return Ok(base.to_string());
+        }
+    }
+    bail!("no base branch found; name one")
+}
+
+/// Subjects and bodies of the commits on HEAD that are not on `base`, oldest first.
+pub fn branch_log(base: &str) -> Result<String> {
+    git(&["log", "--reverse", "--format=%h %s%n%b", &format!("{}..HEAD", base)])
+}
+
+/// Changes on HEAD since it forked from `base`.
+pub fn branch_diff(base: &str) -> Result<String> {
+    git(&["diff", &format!("{}...HEAD", base)])
+}
@@ -0,0 +1,149 @@
+//! GitHub access: the agent's tools for reading issues and pull requests of
+//! the configured repository and commenting on them, and publishing the
+//! descriptions `/prdesc` writes.
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{env, process::Command};
+
+const API_URL: &str = "https://api.github.com";
+/// Comments included with an issue, oldest first.
+const MAX_COMMENTS: usize = 30;
+/// Diffs handed to the model are truncated to this many characters.
+const MAX_DIFF: usize = 60_000;
+
+/// `repo` is `owner/name`, taken from the `origin` remote when unset; the
+/// token falls back to `GITHUB_TOKEN` or `GH_TOKEN`. `api_url` is for
+/// GitHub Enterprise.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GithubConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+impl GithubConfig {
+    fn token(&self) -> Option<String> {
+        self.token.clone().filter(|t| !t.is_empty()).or_else(|| ["GITHUB_TOKEN", "GH_TOKEN"].iter().find_map(|name| env::var(name).ok().filter(|t| !t.is_empty())))
+    }
+
+    pub fn repo(&self) -> Result<String> {
+        if let Some(repo) = self.repo.clone().filter(|r| !r.is_empty()) {
+            return Ok(repo);
+        }
+        let output = Command::new("git").args(["remote", "get-url", "origin"]).output()?;
+        let url = String::from_utf8_lossy(&output.stdout);
+        repo_from_remote(url.trim()).ok_or_else(|| anyhow!("no GitHub repository configured and the origin remote is not on GitHub"))
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> Result<reqwest::RequestBuilder> {
+        let base = self.api_url.as_deref().unwrap_or(API_URL).trim_end_matches('/');
+        let mut request = reqwest::Client::new()
+            .request(method, format!("{}/repos/{}/{}", base, self.repo()?, path))
+            .header("User-Agent", "gentor")
+            .header("X-GitHub-Api-Version", "2022-11-28");
+        if let Some(token) = self.token() {
+            request = request.bearer_auth(token);
+        }
+        Ok(request)
+    }
+
+    async fn get(&self, path: &str) -> Result<Value> {
+        let response = self.request(reqwest::Method::GET, path)?.header("Accept", "application/vnd.github+json").send().await?;
+        Ok(checked(response).await?.json().await?)
+    }
+}
+
+/// `owner/name` of `git@github.com:owner/name.git` or `https://github.com/owner/name`.
+fn repo_from_remote(url: &str) -> Option<String> {
+    let path = url.split_once("github.com").map(|(_, path)| path.trim_start_matches([':', '/']))?;
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    (path.split('/').count() == 2).then(|| path.to_string())
+}
+
+/// The response, or an error with GitHub's message.
+async fn checked(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body: Value = response.json().await.unwrap_or_default();
+    bail!("GitHub answered {}: {}", status, body["message"].as_str().unwrap_or("no details"))
+}
+
+fn login(user: &Value) -> &str {
+    user["login"].as_str().unwrap_or("unknown")
+}
+
+/// An issue or pull request with its labels and comments, as text.
+pub async fn issue(config: &GithubConfig, number: u64) -> Result<String> {
+    let issue = config.get(&format!("issues/{}", number)).await?;
+    let comments = config.get(&format!("issues/{}/comments?per_page={}", number, MAX_COMMENTS)).await?;
+    let labels: Vec<&str> = issue["labels"].as_array().into_iter().flatten().filter_map(|l| l["name"].as_str()).collect();
+    let kind = if issue["pull_request"].is_object() { "Pull request" } else { "Issue" };
+    let mut text = format!(
+        "{} #{}: {}\nState: {}\nAuthor: {}\nLabels: {}\nURL: {}\n\n{}",
+        kind,
+        number,
+        issue["title"].as_str().unwrap_or_default(),
+        issue["state"].as_str().unwrap_or_default(),
+        login(&issue["user"]),
+        if labels.is_empty() { "none".to_string() } else { labels.join(", ") },
+        issue["html_url"].as_str().unwrap_or_default(),
+        issue["body"].as_str().unwrap_or("(no description)").trim()
+    );
+    for comment in comments.as_array().into_iter().flatten() {
+        text.push_str(&format!("\n\n--- {} commented:\n{}", login(&comment["user"]), comment["body"].as_str().unwrap_or_default().trim()));
+    }
+    Ok(text)
+}
+
+/// A pull request's title, description and unified diff.
+pub async fn pr_diff(config: &GithubConfig, number: u64) -> Result<String> {
+    let pr = config.get(&format!("pulls/{}", number)).await?;
+    let response = config.request(reqwest::Method::GET, &format!("pulls/{}", number))?.header("Accept", "application/vnd.github.diff").send().await?;
+    let mut diff = checked(response).await?.text().await?;
+    if let Some((cut, _)) = diff.char_indices().nth(MAX_DIFF) {
+        diff.truncate(cut);
+        diff.push_str("\n[diff truncated]");
+    }
+    Ok(format!(
+        "Pull request #{}: {}\nAuthor: {}\nBranch: {} -> {}\n\n{}\n\n{}",
+        number,
+        pr["title"].as_str().unwrap_or_default(),
+        login(&pr["user"]),
+        pr["head"]["ref"].as_str().unwrap_or_default(),
+        pr["base"]["ref"].as_str().unwrap_or_default(),
+        pr["body"].as_str().unwrap_or("(no description)").trim(),
+        diff
+    ))
+}
+
+/// Sets the title and description of the open pull request from `branch`,
+/// opening one against `base` when there is none. Returns its URL.
+pub async fn publish_pr(config: &GithubConfig, branch: &str, base: &str, title: &str, body: &str) -> Result<String> {
+    if config.token().is_none() {
+        bail!("publishing a pull request needs a GitHub token");
+    }
+    let owner = config.repo()?.split('/').next().unwrap_or_default().to_string();
+    let open = config.get(&format!("pulls?state=open&head={}:{}", owner, branch)).await?;
+    let request = match open.as_array().and_then(|prs| prs.first()).and_then(|pr| pr["number"].as_u64()) {
+        Some(number) => config.request(reqwest::Method::PATCH, &format!("pulls/{}", number))?.json(&json!({ "title": title, "body": body })),
+        None => config.request(reqwest::Method::POST, "pulls")?.json(&json!({ "title": title, "body": body, "head": branch, "base": base })),
+    };
+    let pr: Value = checked(request.send().await?).await?.json().await?;
+    Ok(pr["html_url"].as_str().unwrap_or_default().to_string())
+}
+
+/// Posts a comment on an issue or pull request and returns its URL.
+pub async fn comment(config: &GithubConfig, number: u64, body: &str) -> Result<String> {
+    if config.token().is_none() {
+        bail!("commenting needs a GitHub token");
+    }
+    let response = config.request(reqwest::Method::POST, &format!("issues/{}/comments", number))?.json(&json!({ "body": body })).send().await?;
+    let comment: Value = checked(response).await?.json().await?;
+    Ok(comment["html_url"].as_str().unwrap_or_default().to_string())
+}
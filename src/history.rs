@@ -0,0 +1,175 @@
+//! Earlier prompts for Up/Down and Ctrl+R: the global `~/.gentor_history`
+//! and the prompts saved with the open session.
+use std::path::PathBuf;
+
+use crate::{crypto, settings};
+
+const HISTORY_FILE: &str = ".gentor_history";
+
+pub(crate) fn history_file() -> Option<PathBuf> {
+    settings::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+/// Sent prompts, loaded from the global history file at startup and extended
+/// with everything sent in this session. Entries are stored one JSON string
+/// per line so multi-line prompts survive the round trip. The open session's
+/// own prompts, saved with the session, come first when stepping back.
+pub struct History {
+    /// Every session's prompts, as in the history file.
+    global: Vec<String>,
+    /// Prompts sent in the open session.
+    session: Vec<String>,
+    /// The global prompts not sent in this session, then the session's;
+    /// what `prev`, `next` and `search` walk, newest last.
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    cursor: Option<usize>,
+    draft: String,
+}
+
+impl History {
+    pub fn load() -> Self {
+        let path = history_file();
+        let global: Vec<String> = path
+            .as_ref()
+            .and_then(|p| crypto::read_to_string(p).ok())
+            .map(|s| s.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+            .unwrap_or_default();
+        Self { entries: global.clone(), global, session: Vec::new(), path, cursor: None, draft: String::new() }
+    }
+
+    /// Switches to the prompts of the session just opened.
+    pub fn set_session(&mut self, prompts: &[String]) {
+        self.reset();
+        self.session = prompts.to_vec();
+        self.merge();
+    }
+
+    /// Prompts sent in the open session, to save with it.
+    pub fn session(&self) -> &[String] {
+        &self.session
+    }
+
+    fn merge(&mut self) {
+        let older = self.global.iter().filter(|e| !self.session.contains(e));
+        self.entries = older.chain(&self.session).cloned().collect();
+    }
+
+    pub fn push(&mut self, entry: &str) {
+        self.reset();
+        if entry.trim().is_empty() {
+            return;
+        }
+        if self.session.last().map(String::as_str) != Some(entry) {
+            self.session.push(entry.to_string());
+        }
+        if self.global.last().map(String::as_str) != Some(entry) {
+            self.global.push(entry.to_string());
+            if let Some(path) = &self.path {
+                let _ = crypto::append(path, &serde_json::Value::from(entry).to_string());
+            }
+        }
+        self.merge();
+    }
+
+    /// Forgets every entry equal to `entry`, in the history file too.
+    pub fn remove(&mut self, entry: &str) {
+        self.reset();
+        self.session.retain(|e| e != entry);
+        let before = self.global.len();
+        self.global.retain(|e| e != entry);
+        self.merge();
+        if self.global.len() == before {
+            return;
+        }
+        if let Some(path) = &self.path {
+            let lines: String = self.global.iter().map(|e| format!("{}\n", serde_json::Value::from(e.as_str()))).collect();
+            let _ = crypto::write(path, lines);
+        }
+    }
+
+    /// Steps back in history, remembering `current` so stepping forward past
+    /// the newest entry restores what was being typed.
+    pub fn prev(&mut self, current: &str) -> Option<&str> {
+        let index = match self.cursor {
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+            Some(i) => i.saturating_sub(1),
+        };
+        self.cursor = Some(index);
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn next(&mut self) -> Option<String> {
+        let index = self.cursor? + 1;
+        if index < self.entries.len() {
+            self.cursor = Some(index);
+            Some(self.entries[index].clone())
+        } else {
+            self.cursor = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = None;
+        self.draft.clear();
+    }
+
+    /// Most recent entry containing `query` strictly older than `before`.
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<(usize, &str)> {
+        let end = before.unwrap_or(self.entries.len());
+        self.entries[..end.min(self.entries.len())]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, e)| e.contains(query))
+            .map(|(i, e)| (i, e.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(global: &[&str]) -> History {
+        let global: Vec<String> = global.iter().map(|e| e.to_string()).collect();
+        History { entries: global.clone(), global, session: Vec::new(), path: None, cursor: None, draft: String::new() }
+    }
+
+    #[test]
+    fn session_prompts_come_before_the_global_history() {
+        let mut history = history(&["fix the parser", "add tests", "write docs"]);
+        history.set_session(&["add tests".to_string(), "rename the module".to_string()]);
+        let mut walked = Vec::new();
+        while let Some(entry) = history.prev("typing") {
+            if walked.last().map(String::as_str) == Some(entry) {
+                break;
+            }
+            walked.push(entry.to_string());
+        }
+        assert_eq!(walked, ["rename the module", "add tests", "write docs", "fix the parser"]);
+        assert_eq!(history.next().as_deref(), Some("write docs"));
+
+        history.push("write docs");
+        assert_eq!(history.session(), ["add tests", "rename the module", "write docs"]);
+        assert_eq!(history.prev(""), Some("write docs"));
+        assert_eq!(history.search("add", None).map(|(_, e)| e), Some("add tests"));
+
+        history.set_session(&[]);
+        assert_eq!(history.prev(""), Some("write docs"));
+        assert_eq!(history.prev(""), Some("add tests"));
+    }
+
+    #[test]
+    fn removed_prompts_leave_both_histories() {
+        let mut history = history(&["add tests", "write docs"]);
+        history.set_session(&["add tests".to_string()]);
+        history.remove("add tests");
+        assert!(history.session().is_empty());
+        assert_eq!(history.prev(""), Some("write docs"));
+        assert_eq!(history.prev(""), Some("write docs"));
+    }
+}
@@ -0,0 +1,93 @@
+//! UI strings by locale. The locale files under `locales/` are built into
+//! the binary; the `Language` setting picks one, falling back to `LANG`.
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::settings::FIELDS;
+
+/// Locale codes with their files, English first as the fallback.
+const LOCALES: &[(&str, &str)] = &[("en", include_str!("../locales/en.txt")), ("ko", include_str!("../locales/ko.txt"))];
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static TABLES: OnceLock<Vec<HashMap<&'static str, &'static str>>> = OnceLock::new();
+
+/// Switches to the locale named by `language` (`ko`, `ko_KR.UTF-8`, ...),
+/// or by the environment when it is unset; unknown locales get English.
+pub fn select(language: Option<&str>) {
+    let from_env = || ["LC_ALL", "LC_MESSAGES", "LANG"].iter().find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()));
+    let language = language.map(str::to_string).filter(|l| !l.trim().is_empty()).or_else(from_env).unwrap_or_default();
+    let code = language.trim().split(['_', '-', '.']).next().unwrap_or_default().to_ascii_lowercase();
+    ACTIVE.store(LOCALES.iter().position(|(c, _)| *c == code).unwrap_or(0), Ordering::Relaxed);
+}
+
+fn tables() -> &'static [HashMap<&'static str, &'static str>] {
+    TABLES.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|(_, file)| {
+                file.lines()
+                    .filter(|line| !line.trim_start().starts_with('#'))
+                    .filter_map(|line| line.split_once(" = "))
+                    .map(|(key, value)| (key.trim(), value.trim()))
+                    .collect()
+            })
+            .collect()
+    })
+}
+
+fn lookup(key: &str) -> Option<&'static str> {
+    let tables = tables();
+    tables[ACTIVE.load(Ordering::Relaxed)].get(key).or_else(|| tables[0].get(key)).copied()
+}
+
+/// The string for `key`, or the key itself when no locale has it.
+pub fn t(key: &'static str) -> &'static str {
+    lookup(key).unwrap_or(key)
+}
+
+/// The string for `key` with each `{}` replaced by the next of `args`.
+pub fn fill(key: &'static str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut parts = t(key).split("{}");
+    out.push_str(parts.next().unwrap_or_default());
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// Label of settings field `index`, translated as `field.<index>` where
+/// the locale has one.
+pub fn field(index: usize) -> &'static str {
+    lookup(&format!("field.{}", index)).unwrap_or(FIELDS[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_have_the_same_keys() {
+        let tables = tables();
+        for ((code, _), table) in LOCALES.iter().zip(tables).skip(1) {
+            let mut missing: Vec<_> = tables[0].keys().filter(|key| !table.contains_key(*key)).collect();
+            let mut extra: Vec<_> = table.keys().filter(|key| !tables[0].contains_key(*key)).collect();
+            missing.sort();
+            extra.sort();
+            assert!(missing.is_empty() && extra.is_empty(), "{}: missing {:?}, extra {:?}", code, missing, extra);
+            for (key, value) in table {
+                assert_eq!(value.matches("{}").count(), tables[0][key].matches("{}").count(), "{}: placeholders of {}", code, key);
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! `.gitignore`-style rules deciding which project files may be sent to the API.
+use std::{fs, path::Path};
+
+use crate::project;
+
+/// Read in every directory, with rules relative to it; deeper files win.
+const GITIGNORE: &str = ".gitignore";
+/// Read from the project root after every `.gitignore`, so its rules win.
+const GENTORIGNORE: &str = ".gentorignore";
+/// The repository's own excludes, applied before any `.gitignore`.
+const GIT_EXCLUDE: &str = ".git/info/exclude";
+
+/// Always excluded, before any ignore file is applied: build output,
+/// dependency folders and files that commonly hold secrets.
+const DEFAULT_RULES: &[&str] = &[
+    ".git/",
+    "target/",
+    "node_modules/",
+    "dist/",
+    "build/",
+    "__pycache__/",
+    "vendor/",
+    ".env",
+    ".env.*",
+    "*.pem",
+    "*.key",
+    "*.p12",
+    "*.pfx",
+    "id_rsa*",
+    "id_ed25519*",
+    "settings.json*",
+    "/config.toml*",
+    ".gentor_history",
+];
+
+struct Rule {
+    pattern: String,
+    negate: bool,
+    dir_only: bool,
+    /// Matches against the whole relative path rather than any single name.
+    anchored: bool,
+    /// Directory of the ignore file the rule came from, relative to the
+    /// root with a trailing `/`; empty at the root.
+    base: String,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        Self::parse_in("", line)
+    }
+
+    /// A rule from the ignore file in directory `base`.
+    fn parse_in(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        let base = if base.is_empty() { String::new() } else { format!("{}/", base.trim_matches('/')) };
+        (!pattern.is_empty()).then_some(Self { pattern, negate, dir_only, anchored, base })
+    }
+
+    fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Some(rel) = rel.strip_prefix(self.base.as_str()) else { return false };
+        if self.anchored {
+            glob(&self.pattern, rel)
+        } else {
+            rel.rsplit('/').next().is_some_and(|name| glob(&self.pattern, name))
+        }
+    }
+}
+
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Default rules, `.git/info/exclude`, the `.gitignore` of `root` and of
+    /// every directory below it that is not ignored, `.gentorignore` from
+    /// `root` and then the `[index] exclude` patterns of `.gentor.toml`.
+    pub fn load(root: &Path) -> Self {
+        let mut ignore = Self::from_lines(DEFAULT_RULES.iter().copied());
+        ignore.read("", &root.join(GIT_EXCLUDE));
+        ignore.read_gitignores(root, "");
+        ignore.read("", &root.join(GENTORIGNORE));
+        ignore.rules.extend(project::current().index.exclude.iter().filter_map(|r| Rule::parse(r)));
+        ignore
+    }
+
+    fn from_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        Self { rules: lines.into_iter().filter_map(Rule::parse).collect() }
+    }
+
+    /// Adds the rules of `file`, relative to directory `base`.
+    fn read(&mut self, base: &str, file: &Path) {
+        if let Ok(text) = fs::read_to_string(file) {
+            self.rules.extend(text.lines().filter_map(|line| Rule::parse_in(base, line)));
+        }
+    }
+
+    /// Adds the `.gitignore` of `dir` (at `rel` under the root), then those
+    /// of its subdirectories that its rules leave in. Symlinks are not followed.
+    fn read_gitignores(&mut self, dir: &Path, rel: &str) {
+        self.read(rel, &dir.join(GITIGNORE));
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        let mut subdirs: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        subdirs.sort();
+        for name in subdirs {
+            let sub = if rel.is_empty() { name.clone() } else { format!("{}/{}", rel, name) };
+            if !self.is_ignored(&sub, true) {
+                self.read_gitignores(&dir.join(&name), &sub);
+            }
+        }
+    }
+
+    /// Whether `rel` (relative to the root, `/`-separated) or any of its
+    /// parent directories is ignored.
+    pub fn is_ignored(&self, rel: &str, is_dir: bool) -> bool {
+        let rel = rel.trim_start_matches("./").trim_matches('/');
+        let mut prefix = String::new();
+        let parts: Vec<&str> = rel.split('/').filter(|p| !p.is_empty() && *p != ".").collect();
+        for (i, part) in parts.iter().enumerate() {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            let last = i + 1 == parts.len();
+            if self.decide(&prefix, !last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn decide(&self, rel: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| r.matches(rel, is_dir))
+            .is_some_and(|r| !r.negate)
+    }
+}
+
+/// Shell-style match: `*`, `?` and `[...]` stay within one path segment,
+/// `**` spans segments and `\` makes the next character literal.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    glob_from(&p, &t)
+}
+
+fn glob_from(p: &[char], t: &[char]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some('*') if p.get(1) == Some(&'*') => {
+            if p.get(2) == Some(&'/') {
+                // `**/` also matches zero directories, but only at a segment boundary
+                (0..=t.len()).filter(|&i| i == 0 || t[i - 1] == '/').any(|i| glob_from(&p[3..], &t[i..]))
+            } else {
+                (0..=t.len()).any(|i| glob_from(&p[2..], &t[i..]))
+            }
+        }
+        Some('*') => (0..=t.len())
+            .take_while(|&i| i == 0 || t[i - 1] != '/')
+            .any(|i| glob_from(&p[1..], &t[i..])),
+        Some('?') => t.first().is_some_and(|&c| c != '/') && glob_from(&p[1..], &t[1..]),
+        Some('[') => match (class(&p[1..]), t.first()) {
+            (Some((matches, len)), Some(&c)) => c != '/' && matches(c) && glob_from(&p[1 + len..], &t[1..]),
+            (Some(_), None) => false,
+            // an unclosed `[` is a literal
+            (None, first) => first == Some(&'[') && glob_from(&p[1..], &t[1..]),
+        },
+        Some('\\') if p.len() > 1 => t.first() == Some(&p[1]) && glob_from(&p[2..], &t[1..]),
+        Some(&c) => t.first() == Some(&c) && glob_from(&p[1..], &t[1..]),
+    }
+}
+
+/// The character class starting after a `[`: whether a character is in it,
+/// and how many pattern characters it takes up to and including the `]`.
+fn class(p: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negate = matches!(p.first(), Some('!' | '^'));
+    let start = usize::from(negate);
+    // a `]` right after the opening is part of the set
+    let end = start + 1 + p.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let set = &p[start..end];
+    let matches = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < set.len() {
+            if i + 2 < set.len() && set[i + 1] == '-' {
+                found |= (set[i]..=set[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= set[i] == c;
+                i += 1;
+            }
+        }
+        found != negate
+    };
+    Some((matches, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(glob("**/*.rs", "main.rs"));
+        assert!(glob("file.[ch]", "file.c"));
+        assert!(!glob("file.[ch]", "file.o"));
+        assert!(glob("v[0-9]", "v7"));
+        assert!(glob("v[!0-9]", "vx"));
+        assert!(!glob("v[!0-9]", "v7"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("a[b", "a[b"));
+        assert!(glob("\\*.txt", "*.txt"));
+        assert!(!glob("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn negation_re_includes_unless_a_parent_is_ignored() {
+        let rules = IgnoreRules::from_lines(["*.log", "!keep.log", "logs/", "!logs/keep.txt"]);
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(!rules.is_ignored("keep.log", false));
+        assert!(rules.is_ignored("logs/keep.txt", false));
+    }
+
+    #[test]
+    fn slashes_anchor_to_the_root() {
+        let rules = IgnoreRules::from_lines(["/todo.txt", "docs/*.md", "notes"]);
+        assert!(rules.is_ignored("todo.txt", false));
+        assert!(!rules.is_ignored("src/todo.txt", false));
+        assert!(rules.is_ignored("docs/a.md", false));
+        assert!(!rules.is_ignored("docs/api/a.md", false));
+        assert!(!rules.is_ignored("src/docs/a.md", false));
+        assert!(rules.is_ignored("src/deep/notes", false));
+    }
+
+    #[test]
+    fn double_stars_span_directories() {
+        let rules = IgnoreRules::from_lines(["**/cache", "a/**/b", "out/**"]);
+        assert!(rules.is_ignored("cache", true));
+        assert!(rules.is_ignored("x/y/cache", true));
+        assert!(rules.is_ignored("a/b", false));
+        assert!(rules.is_ignored("a/x/y/b", false));
+        assert!(rules.is_ignored("out/x/y.txt", false));
+        assert!(!rules.is_ignored("out", true));
+    }
+
+    #[test]
+    fn trailing_slashes_match_directories_only() {
+        let rules = IgnoreRules::from_lines(["tmp/"]);
+        assert!(rules.is_ignored("tmp", true));
+        assert!(rules.is_ignored("tmp/file", false));
+        assert!(rules.is_ignored("src/tmp/file", false));
+        assert!(!rules.is_ignored("tmp", false));
+    }
+
+    #[test]
+    fn nested_gitignores_and_the_git_exclude_file_are_read() {
+        let root = env::temp_dir().join(format!("gentor-ignore-{}", std::process::id()));
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("web/static")).unwrap();
+        fs::write(root.join(".git/info/exclude"), "*.local\n").unwrap();
+        fs::write(root.join(".gitignore"), "*.tmp\n").unwrap();
+        fs::write(root.join("web/.gitignore"), "/static\n!important.tmp\n").unwrap();
+        fs::write(root.join(".gentorignore"), "important.tmp\n").unwrap();
+
+        let rules = IgnoreRules::load(&root);
+        assert!(rules.is_ignored("notes.local", false));
+        assert!(rules.is_ignored("web/static/app.js", false));
+        assert!(!rules.is_ignored("static/app.js", false));
+        assert!(rules.is_ignored("web/a.tmp", false));
+        // the nested file re-includes it, .gentorignore has the last word
+        assert!(rules.is_ignored("web/important.tmp", false));
+        fs::remove_file(root.join(".gentorignore")).unwrap();
+        assert!(!IgnoreRules::load(&root).is_ignored("web/important.tmp", false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+//! Images in messages, written as Markdown `![alt](src)` on a line of their
+//! own: drawn inline with the kitty graphics protocol where the terminal
+//! has it, and otherwise shown as a line to open them from with `/images`.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crossterm::{
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    queue,
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+};
+
+/// Rows an inline image takes in the chat pane, below its caption.
+pub const ROWS: u16 = 12;
+/// Base64 bytes per graphics command; the protocol's limit.
+const CHUNK: usize = 4096;
+
+/// Whether the terminal draws kitty graphics. Inside tmux or screen the
+/// escapes would need wrapping, so those get the fallback.
+pub fn kitty_supported() -> bool {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    if !var("TMUX").is_empty() || var("TERM").starts_with("screen") {
+        return false;
+    }
+    var("TERM").contains("kitty") || ["WezTerm", "ghostty"].contains(&var("TERM_PROGRAM").as_str())
+}
+
+/// `(alt, src)` when the whole line is one Markdown image.
+pub fn parse_line(line: &str) -> Option<(String, String)> {
+    let rest = line.trim().strip_prefix("![")?;
+    let (alt, rest) = rest.split_once("](")?;
+    let src = rest.strip_suffix(')')?;
+    (!src.is_empty() && !src.contains(char::is_whitespace)).then(|| (alt.to_string(), src.to_string()))
+}
+
+/// Every image in `text`, inline ones included, in order.
+pub fn find(text: &str) -> Vec<(String, String)> {
+    let mut images = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("![") {
+        let candidate = &rest[start..];
+        let end = candidate.find(')').map_or(candidate.len(), |i| i + 1);
+        if let Some(image) = parse_line(&candidate[..end]) {
+            images.push(image);
+        }
+        rest = &candidate[end.max(2)..];
+    }
+    images
+}
+
+/// `src` for display, with the payload of a data URI left out.
+pub fn label(src: &str) -> String {
+    match src.split_once(',') {
+        Some((head, _)) if src.starts_with("data:") => format!("{},…", head),
+        _ => src.to_string(),
+    }
+}
+
+/// Whether `src` can be drawn inline: a base64 PNG data URI or a PNG file
+/// on disk.
+pub fn drawable(src: &str) -> bool {
+    src.starts_with("data:image/png;base64,") || local_png(src).is_some_and(|path| std::path::Path::new(path).is_file())
+}
+
+fn local_png(src: &str) -> Option<&str> {
+    let path = src.strip_prefix("file://").unwrap_or(src);
+    (!path.contains("://") && path.to_ascii_lowercase().ends_with(".png")).then_some(path)
+}
+
+/// PNG bytes for `src`: a local `.png` file or a base64 PNG data URI.
+/// Remote images are not fetched while drawing.
+fn load_png(src: &str) -> Option<Vec<u8>> {
+    if let Some(data) = src.strip_prefix("data:image/png;base64,") {
+        return STANDARD.decode(data).ok();
+    }
+    fs::read(local_png(src)?).ok()
+}
+
+/// Images sent to the terminal and where they are shown, so a frame only
+/// writes escapes when the placements change.
+#[derive(Default)]
+pub struct Kitty {
+    /// Terminal-side image ids by source; `None` when the source could not be loaded.
+    sent: HashMap<String, Option<u32>>,
+    placed: Vec<(u32, u16, u16)>,
+}
+
+impl Kitty {
+    /// Shows the images at `slots` (source, column, row) and nothing else.
+    pub fn show(&mut self, out: &mut impl Write, slots: &[(String, u16, u16)]) -> io::Result<()> {
+        let mut wanted = Vec::new();
+        for (src, x, y) in slots {
+            let next = self.sent.len() as u32 + 1;
+            let id = match self.sent.get(src) {
+                Some(id) => *id,
+                None => {
+                    let id = load_png(src).map(|png| transmit(out, next, &png).map(|_| next)).transpose()?;
+                    self.sent.insert(src.clone(), id);
+                    id
+                }
+            };
+            if let Some(id) = id {
+                wanted.push((id, *x, *y));
+            }
+        }
+        if wanted == self.placed {
+            return Ok(());
+        }
+        queue!(out, SavePosition)?;
+        // q=2 keeps the terminal from answering, which would arrive as input
+        write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        for &(id, x, y) in &wanted {
+            queue!(out, MoveTo(x, y))?;
+            write!(out, "\x1b_Ga=p,i={},r={},C=1,q=2\x1b\\", id, ROWS)?;
+        }
+        queue!(out, RestorePosition)?;
+        out.flush()?;
+        self.placed = wanted;
+        Ok(())
+    }
+}
+
+fn transmit(out: &mut impl Write, id: u32, png: &[u8]) -> io::Result<()> {
+    let data = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            write!(out, "\x1b_Ga=t,f=100,i={},q=2,m={};{}\x1b\\", id, more, chunk)?;
+        } else {
+            write!(out, "\x1b_Gm={};{}\x1b\\", more, chunk)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Chat shortcuts that `[keybindings]` in the config can move to other keys,
+//! written like `ctrl+b`, `alt+e` or `f1`.
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+
+/// Rebindable actions with their default keys.
+pub const ACTIONS: &[(&str, &str)] = &[
+    ("history_search", "ctrl+r"),
+    ("sidebar", "ctrl+b"),
+    ("context", "ctrl+o"),
+    ("reasoning", "ctrl+t"),
+    ("help", "f1"),
+    ("palette", "ctrl+p"),
+    ("collapse", "alt+e"),
+];
+
+pub struct Keymap {
+    bindings: Vec<(&'static str, KeyCode, KeyModifiers)>,
+}
+
+impl Keymap {
+    /// The default keys with `overrides` applied, and a warning for each
+    /// entry naming an unknown action or key.
+    pub fn new(overrides: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        for (action, key) in overrides {
+            if !ACTIONS.iter().any(|(name, _)| name == action) {
+                let names: Vec<&str> = ACTIONS.iter().map(|(name, _)| *name).collect();
+                warnings.push(format!("⚠️ Unknown action '{}' in keybindings (available: {}).", action, names.join(", ")));
+            } else if parse(key).is_none() {
+                warnings.push(format!("⚠️ Cannot read the key '{}' bound to {}; the default stays.", key, action));
+            }
+        }
+        let bindings = ACTIONS
+            .iter()
+            .filter_map(|(action, default)| {
+                let (code, modifiers) = overrides.get(*action).and_then(|key| parse(key)).or_else(|| parse(default))?;
+                Some((*action, code, modifiers))
+            })
+            .collect();
+        (Self { bindings }, warnings)
+    }
+
+    /// The action bound to `key`, if any.
+    pub fn action(&self, key: &KeyEvent) -> Option<&'static str> {
+        let modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        let code = match key.code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        self.bindings.iter().find(|(_, c, m)| *c == code && *m == modifiers).map(|(action, _, _)| *action)
+    }
+}
+
+/// `ctrl+alt+k`, `f5`, `esc`, ... as a key code and modifiers.
+fn parse(spec: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let spec = spec.trim().to_ascii_lowercase();
+    let mut parts: Vec<&str> = spec.split('+').map(str::trim).collect();
+    let key = parts.pop()?;
+    let mut modifiers = KeyModifiers::NONE;
+    for part in parts {
+        modifiers |= match part {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            _ => return None,
+        };
+    }
+    let mut chars = key.chars();
+    let code = match (chars.next()?, chars.next()) {
+        (c, None) => KeyCode::Char(c),
+        _ => match key {
+            "tab" => KeyCode::Tab,
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" => KeyCode::Enter,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f => KeyCode::F(f.strip_prefix('f')?.parse().ok().filter(|n| (1..=12).contains(n))?),
+        },
+    };
+    Some((code, modifiers))
+}
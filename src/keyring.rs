@@ -0,0 +1,21 @@
+//! API keys in the OS credential store through the `keyring` crate: the
+//! macOS Keychain, the Windows Credential Manager, and the Secret Service
+//! (GNOME Keyring, KWallet) on Linux and the BSDs. Secrets go through the
+//! platform API, never through another process.
+use anyhow::{anyhow, Result};
+use ::keyring::{Entry, Error};
+
+const SERVICE: &str = "gentor";
+
+pub fn get(account: &str) -> Result<String> {
+    match Entry::new(SERVICE, account)?.get_password() {
+        Ok(secret) => Ok(secret),
+        Err(Error::NoEntry) => Err(anyhow!("no keyring entry for '{}'", account)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn set(account: &str, secret: &str) -> Result<()> {
+    Entry::new(SERVICE, account)?.set_password(secret)?;
+    Ok(())
+}
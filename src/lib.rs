@@ -0,0 +1,89 @@
+//! Gentor's engine, shared by the terminal app in `main.rs` and anything
+//! else that wants it, such as an editor plugin or the HTTP server mode.
+//!
+//! - [`settings`]: the layered configuration every other part reads.
+//! - [`agent`]: the provider layer. [`agent::run_agent`] sends a prompt with
+//!   the conversation so far and runs the model's tool calls through a
+//!   [`tools::Toolbox`]; [`agent::complete`] sends a single prompt.
+//!   `provider = "mock"` ([`mock`]) answers without a network.
+//! - [`middleware`]: hooks run before and after every provider call.
+//! - [`session`]: saved conversations on disk.
+//! - [`commands`]: the registry of chat commands shown in help and the palette.
+//! - [`app`]: the TUI state machine, its event loop and rendering, for any
+//!   `tui` backend and event source. [`app::App::handle_event`] takes keys,
+//!   ticks, streamed chunks and task results as [`app::AppEvent`]s, without a
+//!   terminal.
+//!
+//! ```
+//! use gentor::{agent, settings::{self, Settings}};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! # // keeps the usage stats the call records out of the real config dir
+//! # settings::set_home(std::env::temp_dir().join(format!("gentor-doctest-{}", std::process::id())));
+//! let settings = Settings { provider: "mock".to_string(), ..Settings::defaults() };
+//! let client = agent::client_for(&settings);
+//! let answer = agent::complete(&client, &settings, "Be brief.", "What is a lifetime?").await?;
+//! assert!(answer.contains("What is a lifetime?"));
+//! # Ok(())
+//! # }
+//! ```
+pub mod accessible;
+pub mod agent;
+pub mod app;
+pub mod ask;
+mod cache;
+mod cargo;
+pub mod changelog;
+pub mod cli;
+mod clipboard;
+mod codeblock;
+pub mod commands;
+pub mod completions;
+pub mod config;
+mod context;
+pub mod crypto;
+mod debug;
+mod diff;
+mod editing;
+pub mod explain;
+pub mod fix;
+mod git;
+mod github;
+mod history;
+pub mod i18n;
+mod ignore;
+mod images;
+pub mod keymap;
+mod keyring;
+mod links;
+mod local;
+mod lsp;
+pub mod man;
+mod mcp;
+pub mod message;
+pub mod middleware;
+pub mod mock;
+mod models;
+mod notify;
+mod outline;
+mod palette;
+mod plugins;
+pub mod project;
+pub mod recovery;
+mod sandbox;
+mod search;
+pub mod serve;
+pub mod session;
+pub mod settings;
+pub mod shell;
+mod stats;
+mod table;
+mod testgen;
+mod theme;
+mod tokens;
+mod toml;
+pub mod tools;
+pub mod watch;
+mod web;
+mod wrap;
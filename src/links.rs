@@ -0,0 +1,116 @@
+//! URLs in answers: found for `/links`, opened in the browser, and made
+//! clickable with OSC 8 hyperlink escapes on terminals that support them.
+use anyhow::Result;
+use std::{env, process::Stdio};
+use tui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+/// Punctuation that ends a sentence rather than the URL before it.
+const TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"', '*', '_', '`'];
+
+/// Whether the terminal is known to handle OSC 8; the rest would show the
+/// escapes as garbage or drop them, so they get plain text.
+pub fn supported() -> bool {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    let program = var("TERM_PROGRAM");
+    ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&program.as_str())
+        || ["kitty", "alacritty", "foot", "wezterm", "ghostty"].iter().any(|t| term.contains(t))
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+        || !var("WT_SESSION").is_empty()
+        || !var("KONSOLE_VERSION").is_empty()
+}
+
+/// The http(s) URLs in `text`, in order and without duplicates.
+pub fn find(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = ["https://", "http://"].iter().filter_map(|scheme| rest.find(scheme)).min() {
+        let candidate = &rest[start..];
+        let end = candidate.find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`')).unwrap_or(candidate.len());
+        let url = trim(&candidate[..end]);
+        if url.len() > "https://".len() && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end..];
+    }
+    urls
+}
+
+/// Drops trailing punctuation, and closing brackets without an opening
+/// one in the URL, as in `(see https://example.com)` or `[docs](https://…)`.
+fn trim(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(TRAILING);
+        let trimmed = match trimmed.chars().last() {
+            Some(close @ (')' | ']' | '}')) => {
+                let open = match close {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                if trimmed.matches(open).count() < trimmed.matches(close).count() {
+                    &trimmed[..trimmed.len() - 1]
+                } else {
+                    trimmed
+                }
+            }
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Opens `url` with the desktop's default handler.
+pub fn open(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command.arg(url).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    Ok(())
+}
+
+/// Drawn over the finished frame: every cell showing part of one of the
+/// URLs becomes its own OSC 8 link, so a redraw of only some cells never
+/// leaves a link open. A URL cut at the edge of the pane links in full.
+pub struct Hyperlinks<'a>(pub &'a [String]);
+
+impl Widget for Hyperlinks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if self.0.is_empty() {
+            return;
+        }
+        for y in area.top()..area.bottom() {
+            let symbols: Vec<String> = (area.left()..area.right()).map(|x| buf.get(x, y).symbol.clone()).collect();
+            let mut i = 0;
+            while i < symbols.len() {
+                let starts = ["https://", "http://"].iter().any(|scheme| symbols[i..].iter().take(scheme.len()).map(String::as_str).collect::<String>() == *scheme);
+                if !starts {
+                    i += 1;
+                    continue;
+                }
+                // a pane border can follow a URL that fills the row
+                let end = symbols[i..].iter().position(|s| s.trim().is_empty() || s == "│").map_or(symbols.len(), |n| i + n);
+                let shown: String = symbols[i..end].concat();
+                let target = self.0.iter().filter(|url| shown.starts_with(url.as_str()) || url.starts_with(&shown)).max_by_key(|url| url.len());
+                if let Some(url) = target {
+                    let cells = if shown.starts_with(url.as_str()) { url.chars().count() } else { end - i };
+                    for x in i..i + cells {
+                        let cell = buf.get_mut(area.left() + x as u16, y);
+                        cell.symbol = format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, cell.symbol);
+                    }
+                }
+                i = end;
+            }
+        }
+    }
+}
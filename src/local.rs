@@ -0,0 +1,40 @@
+//! Local-only mode: every endpoint must be on this machine, for codebases
+//! that may only be shown to local models.
+use anyhow::{bail, Result};
+use reqwest::Url;
+use std::net::IpAddr;
+
+/// Whether `url` points at `localhost` or a loopback address. Subdomains of
+/// `localhost` are refused: they are only loopback if the resolver says so.
+pub fn is_loopback(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else { return false };
+    let Some(host) = url.host_str() else { return false };
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        // `::ffff:127.0.0.1` too
+        Ok(ip) => ip.to_canonical().is_loopback(),
+        Err(_) => host.eq_ignore_ascii_case("localhost"),
+    }
+}
+
+/// Refuses `url` unless it is loopback.
+pub fn ensure(url: &str) -> Result<()> {
+    if !is_loopback(url) {
+        bail!("Local-only mode: refusing to contact {}", url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_hosts_are_local() {
+        for url in ["http://localhost:11434/v1", "http://LOCALHOST", "http://127.0.0.1:8080", "http://127.1.2.3", "http://[::1]:8080/v1", "http://[::ffff:127.0.0.1]"] {
+            assert!(is_loopback(url), "{}", url);
+        }
+        for url in ["http://foo.localhost", "http://localhost.evil.com", "http://0.0.0.0:8080", "http://[::]", "http://10.0.0.1", "https://api.openai.com/v1", "localhost:11434", "not a url"] {
+            assert!(!is_loopback(url), "{}", url);
+        }
+    }
+}
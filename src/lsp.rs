@@ -0,0 +1,265 @@
+//! Minimal Language Server Protocol client over a child process's stdio,
+//! used by `/explain` to gather a symbol's definition, type and references.
+use anyhow::{anyhow, bail, Result};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::oneshot,
+};
+
+use crate::context::Filter;
+
+pub const DEFAULT_COMMAND: &str = "rust-analyzer";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for a fresh server to index the project.
+const INDEX_TIMEOUT: Duration = Duration::from_secs(90);
+const MAX_REFERENCES: usize = 20;
+/// Definition lines shown when the server reports no enclosing range.
+const FALLBACK_LINES: usize = 20;
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+type Stdin = Arc<tokio::sync::Mutex<ChildStdin>>;
+
+pub struct LspClient {
+    stdin: Stdin,
+    pending: Pending,
+    next_id: AtomicU64,
+    root: PathBuf,
+    _child: Child,
+}
+
+impl LspClient {
+    /// Spawns `command` and performs the initialize handshake for `root`.
+    pub async fn start(command: &[String], root: &Path) -> Result<Self> {
+        let (program, args) = command.split_first().ok_or_else(|| anyhow!("empty language server command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("could not start {}: {}", program, e))?;
+        let stdin: Stdin = Arc::new(tokio::sync::Mutex::new(child.stdin.take().expect("piped stdin")));
+        let pending: Pending = Arc::default();
+        tokio::spawn(read_loop(child.stdout.take().expect("piped stdout"), stdin.clone(), pending.clone()));
+        let root = root.canonicalize()?;
+        let client = Self { stdin, pending, next_id: AtomicU64::new(1), root, _child: child };
+
+        let root_uri = Url::from_directory_path(&client.root).map_err(|_| anyhow!("invalid project root"))?.to_string();
+        let init = json!({
+            "processId": std::process::id(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{ "uri": root_uri, "name": "root" }],
+            "capabilities": {
+                "textDocument": {
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+                },
+            },
+            "clientInfo": { "name": "gentor", "version": env!("CARGO_PKG_VERSION") },
+        });
+        client.request("initialize", init).await?;
+        client.notify("initialized", json!({})).await?;
+        Ok(client)
+    }
+
+    /// Definition, type information and references of the workspace symbol
+    /// `name`, as plain text for a prompt. Files `filter` refuses are not read.
+    pub async fn describe_symbol(&self, name: &str, filter: &Filter) -> Result<String> {
+        let symbol = self.find_symbol(name).await?;
+        let uri = symbol["location"]["uri"].as_str().unwrap_or_default().to_string();
+        let position = symbol["location"]["range"]["start"].clone();
+        let path = Url::parse(&uri).ok().and_then(|u| u.to_file_path().ok()).ok_or_else(|| anyhow!("{} is not a local file", uri))?;
+        let text = filter.read(&self.relative(&path))?;
+        let document = json!({ "uri": uri });
+        self.notify("textDocument/didOpen", json!({ "textDocument": { "uri": uri, "languageId": language_id(&path), "version": 1, "text": text } }))
+            .await?;
+
+        let line = position["line"].as_u64().unwrap_or_default() as usize;
+        let symbols = self.request("textDocument/documentSymbol", json!({ "textDocument": document })).await?;
+        let (start, end) = enclosing_range(&symbols, line).unwrap_or((line, line + FALLBACK_LINES));
+        let definition: Vec<&str> = text.lines().skip(start).take(end - start + 1).collect();
+
+        let at = json!({ "textDocument": document, "position": position });
+        let hover = self.request("textDocument/hover", at.clone()).await.map(|h| hover_text(&h["contents"])).unwrap_or_default();
+        let mut params = at;
+        params["context"] = json!({ "includeDeclaration": false });
+        let references = self.request("textDocument/references", params).await.unwrap_or_default();
+        let references: Vec<String> = references.as_array().into_iter().flatten().map(|r| self.reference_line(r, filter)).collect();
+
+        let mut out = format!("`{}` defined at {}:{}\n", name, self.relative(&path), line + 1);
+        if !hover.trim().is_empty() {
+            out.push_str(&format!("\nType information:\n{}\n", hover.trim()));
+        }
+        out.push_str(&format!("\nDefinition:\n{}\n", definition.join("\n")));
+        out.push_str(&format!("\nReferences ({}):\n", references.len()));
+        for reference in references.iter().take(MAX_REFERENCES) {
+            out.push_str(&format!("{}\n", reference));
+        }
+        if references.len() > MAX_REFERENCES {
+            out.push_str(&format!("... and {} more\n", references.len() - MAX_REFERENCES));
+        }
+        Ok(out)
+    }
+
+    /// The best `workspace/symbol` match for `name`, waiting while the server
+    /// is still indexing.
+    async fn find_symbol(&self, name: &str) -> Result<Value> {
+        let start = Instant::now();
+        loop {
+            let found = self.request("workspace/symbol", json!({ "query": name })).await?;
+            let found = found.as_array().cloned().unwrap_or_default();
+            let exact = found.iter().find(|s| s["name"] == name || s["name"].as_str().is_some_and(|n| n.ends_with(&format!("::{}", name))));
+            if let Some(symbol) = exact.or(found.first()) {
+                return Ok(symbol.clone());
+            }
+            if start.elapsed() > INDEX_TIMEOUT {
+                bail!("no symbol named {} found", name);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// `path:line: source` for a reference location.
+    fn reference_line(&self, location: &Value, filter: &Filter) -> String {
+        let line = location["range"]["start"]["line"].as_u64().unwrap_or_default() as usize;
+        let Some(path) = location["uri"].as_str().and_then(|u| Url::parse(u).ok()).and_then(|u| u.to_file_path().ok()) else {
+            return String::new();
+        };
+        let relative = self.relative(&path);
+        let source = filter.read(&relative).ok().and_then(|t| t.lines().nth(line).map(|l| l.trim().to_string())).unwrap_or_default();
+        format!("{}:{}: {}", relative, line + 1, source)
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root).unwrap_or(path).display().to_string()
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        send(&self.stdin, json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(anyhow!("language server exited")),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(anyhow!("{} timed out", method))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        send(&self.stdin, json!({ "jsonrpc": "2.0", "method": method, "params": params })).await
+    }
+}
+
+async fn send(stdin: &Stdin, message: Value) -> Result<()> {
+    let body = serde_json::to_vec(&message)?;
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes()).await?;
+    stdin.write_all(&body).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Reads `Content-Length` framed messages: responses complete pending
+/// requests, server requests get an empty answer so the server never waits.
+async fn read_loop(stdout: ChildStdout, stdin: Stdin, pending: Pending) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let mut length = None;
+        let mut header = String::new();
+        loop {
+            header.clear();
+            match reader.read_line(&mut header).await {
+                Ok(0) | Err(_) => {
+                    pending.lock().unwrap().clear();
+                    return;
+                }
+                Ok(_) if header.trim().is_empty() => break,
+                Ok(_) => {
+                    if let Some(value) = header.trim().strip_prefix("Content-Length:") {
+                        length = value.trim().parse::<usize>().ok();
+                    }
+                }
+            }
+        }
+        let Some(length) = length else { continue };
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).await.is_err() {
+            pending.lock().unwrap().clear();
+            return;
+        }
+        let Ok(message) = serde_json::from_slice::<Value>(&body) else { continue };
+        match (message.get("id"), message["method"].as_str()) {
+            (Some(id), Some(method)) => {
+                // workspace/configuration wants one entry per requested item
+                let result = match method {
+                    "workspace/configuration" => Value::Array(vec![Value::Null; message["params"]["items"].as_array().map_or(0, Vec::len)]),
+                    _ => Value::Null,
+                };
+                let _ = send(&stdin, json!({ "jsonrpc": "2.0", "id": id, "result": result })).await;
+            }
+            (Some(id), None) => {
+                let Some(reply) = id.as_u64().and_then(|id| pending.lock().unwrap().remove(&id)) else { continue };
+                let result = match message.get("error") {
+                    Some(error) => Err(anyhow!("{}", error["message"].as_str().unwrap_or("language server error"))),
+                    None => Ok(message["result"].clone()),
+                };
+                let _ = reply.send(result);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Lines spanned by the innermost document symbol containing `line`.
+fn enclosing_range(symbols: &Value, line: usize) -> Option<(usize, usize)> {
+    let mut best = None;
+    for symbol in symbols.as_array().into_iter().flatten() {
+        // DocumentSymbol has `range`, SymbolInformation nests it in `location`
+        let range = symbol.get("range").unwrap_or(&symbol["location"]["range"]);
+        let start = range["start"]["line"].as_u64()? as usize;
+        let end = range["end"]["line"].as_u64()? as usize;
+        if (start..=end).contains(&line) {
+            best = enclosing_range(&symbol["children"], line).or(Some((start, end)));
+        }
+    }
+    best
+}
+
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(hover_text).collect::<Vec<_>>().join("\n"),
+        Value::Object(_) => contents["value"].as_str().unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+fn language_id(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("rs") => "rust",
+        Some("py") => "python",
+        Some("ts") => "typescript",
+        Some("js") => "javascript",
+        Some("go") => "go",
+        _ => "plaintext",
+    }
+}
@@ -0,0 +1,74 @@
+//! `gentor man`: a roff man page for section 1, written from the command
+//! definitions in [`cli`] so it stays in step with `--help`.
+use crate::{
+    cli::{self, CommandDef, FlagDef},
+    project, settings,
+};
+
+pub fn page() -> String {
+    let about = env!("CARGO_PKG_DESCRIPTION").trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+    let mut page = format!(
+        ".TH GENTOR 1 \"\" \"gentor {}\" \"User Commands\"\n.SH NAME\ngentor \\- {}\n.SH SYNOPSIS\n\\fBgentor\\fR [\\fIOPTIONS\\fR] [\\fICOMMAND\\fR]\n",
+        env!("CARGO_PKG_VERSION"),
+        escape(about)
+    );
+    page.push_str(".SH DESCRIPTION\nWithout a command, gentor starts the interactive chat in the terminal. The commands below run one task and exit.\n");
+    page.push_str(".SH OPTIONS\n");
+    for flag in cli::GLOBAL_FLAGS {
+        page.push_str(&flag_entry(flag));
+    }
+    page.push_str(".SH COMMANDS\n");
+    for def in cli::COMMANDS {
+        command_entries(&mut page, &[], def);
+    }
+    page.push_str(&format!(
+        ".SH FILES\n.TP\n{}\nSettings, in ~/.config/gentor/ (or $XDG_CONFIG_HOME/gentor/); {} there is still read when there is no {}.\n.TP\n{}\nProject configuration, found in the working directory or above it up to the repository root.\n.TP\n~/.config/gentor/sessions/\nSaved conversations.\n",
+        settings::CONFIG_FILE,
+        escape(settings::SETTINGS_FILE),
+        settings::CONFIG_FILE,
+        escape(project::PROJECT_FILE)
+    ));
+    page.push_str(&format!(
+        ".SH ENVIRONMENT\n.TP\n{}\\fIKEY\\fR\nOverrides the setting \\fIkey\\fR for one run, e.g. {}MODEL=gpt\\-4o.\n",
+        settings::ENV_PREFIX,
+        settings::ENV_PREFIX
+    ));
+    page.push_str(&format!(".SH SEE ALSO\n{}\n", escape(env!("CARGO_PKG_HOMEPAGE"))));
+    page
+}
+
+fn command_entries(page: &mut String, parent: &[&str], def: &CommandDef) {
+    let mut path = parent.to_vec();
+    path.push(def.name);
+    if def.subcommands.is_empty() {
+        let args: String = def.args.iter().map(|arg| format!(" {}", escape(arg))).collect();
+        page.push_str(&format!(".TP\n\\fBgentor {}\\fR{}\n{}\n", path.join(" "), args, escape(def.about)));
+        if !def.flags.is_empty() {
+            page.push_str(".RS\n");
+            for flag in def.flags {
+                page.push_str(&flag_entry(flag));
+            }
+            page.push_str(".RE\n");
+        }
+    }
+    for sub in def.subcommands {
+        command_entries(page, &path, sub);
+    }
+}
+
+fn flag_entry(flag: &FlagDef) -> String {
+    let short = flag.short.map_or(String::new(), |short| format!("\\fB\\-{}\\fR, ", short));
+    let value = flag.value.map_or(String::new(), |value| format!(" \\fI{}\\fR", value));
+    format!(".TP\n{}\\fB\\-\\-{}\\fR{}\n{}\n", short, escape(flag.long), value, escape(flag.help))
+}
+
+/// Text safe in roff: backslashes and dashes escaped, no line starting
+/// with a control character.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,266 @@
+//! Minimal Model Context Protocol client: JSON-RPC 2.0 over a child
+//! process's stdio or over HTTP with server-sent events.
+use anyhow::{anyhow, bail, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::oneshot,
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An MCP server entry in the config: either `command` (+ `args`) for a
+/// stdio server or `url` for an SSE server.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct McpServerConfig {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+pub struct McpTool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+}
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+enum Transport {
+    Stdio { stdin: tokio::sync::Mutex<ChildStdin>, _child: Box<Child> },
+    Sse { http: reqwest::Client, endpoint: String },
+}
+
+pub struct McpServer {
+    pub name: String,
+    pub tools: Vec<McpTool>,
+    pub resources: Vec<McpResource>,
+    transport: Transport,
+    pending: Pending,
+    next_id: AtomicU64,
+}
+
+impl McpServer {
+    /// Starts or connects to the server, performs the initialize handshake
+    /// and discovers its tools and resources.
+    pub async fn connect(config: &McpServerConfig) -> Result<Self> {
+        let pending: Pending = Arc::default();
+        let transport = match (&config.command, &config.url) {
+            (Some(command), _) => stdio(command, config, pending.clone())?,
+            (None, Some(url)) => sse(url, pending.clone()).await?,
+            (None, None) => bail!("needs either a command or a url"),
+        };
+        let mut server = Self {
+            name: config.name.clone(),
+            tools: Vec::new(),
+            resources: Vec::new(),
+            transport,
+            pending,
+            next_id: AtomicU64::new(1),
+        };
+
+        let init = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "gentor", "version": env!("CARGO_PKG_VERSION") },
+        });
+        let info = server.request("initialize", init).await?;
+        server.notify("notifications/initialized").await?;
+
+        if info.pointer("/capabilities/tools").is_some() {
+            let list = server.request("tools/list", json!({})).await?;
+            server.tools = list["tools"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|t| McpTool {
+                    name: t["name"].as_str().unwrap_or_default().to_string(),
+                    description: t["description"].as_str().unwrap_or_default().to_string(),
+                    input_schema: t.get("inputSchema").cloned().unwrap_or_else(|| json!({ "type": "object" })),
+                })
+                .collect();
+        }
+        if info.pointer("/capabilities/resources").is_some() {
+            let list = server.request("resources/list", json!({})).await?;
+            server.resources = list["resources"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|r| McpResource {
+                    uri: r["uri"].as_str().unwrap_or_default().to_string(),
+                    name: r["name"].as_str().unwrap_or_default().to_string(),
+                })
+                .collect();
+        }
+        Ok(server)
+    }
+
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let result = self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?;
+        let text = content_text(&result["content"]);
+        if result["isError"].as_bool().unwrap_or(false) {
+            bail!("{}", text);
+        }
+        Ok(text)
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> Result<String> {
+        let result = self.request("resources/read", json!({ "uri": uri })).await?;
+        Ok(content_text(&result["contents"]))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(anyhow!("server closed the connection")),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(anyhow!("{} timed out", method))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method })).await
+    }
+
+    async fn send(&self, message: Value) -> Result<()> {
+        match &self.transport {
+            Transport::Stdio { stdin, .. } => {
+                let mut line = serde_json::to_vec(&message)?;
+                line.push(b'\n');
+                let mut stdin = stdin.lock().await;
+                stdin.write_all(&line).await?;
+                stdin.flush().await?;
+            }
+            Transport::Sse { http, endpoint } => {
+                http.post(endpoint).json(&message).send().await?.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stdio(command: &str, config: &McpServerConfig, pending: Pending) -> Result<Transport> {
+    let mut child = Command::new(command)
+        .args(&config.args)
+        .envs(&config.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdin = child.stdin.take().expect("piped stdin");
+    let stdout = child.stdout.take().expect("piped stdout");
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Ok(message) = serde_json::from_str(&line) {
+                dispatch(&pending, message);
+            }
+        }
+        pending.lock().unwrap().clear();
+    });
+    Ok(Transport::Stdio { stdin: tokio::sync::Mutex::new(stdin), _child: Box::new(child) })
+}
+
+/// Opens the event stream and waits for the `endpoint` event that names the
+/// URL requests must be posted to.
+async fn sse(url: &str, pending: Pending) -> Result<Transport> {
+    let http = reqwest::Client::new();
+    let base = reqwest::Url::parse(url)?;
+    let response = http.get(base.clone()).header("Accept", "text/event-stream").send().await?.error_for_status()?;
+    let (endpoint_tx, endpoint_rx) = oneshot::channel::<String>();
+
+    tokio::spawn(async move {
+        let mut endpoint_tx = Some(endpoint_tx);
+        let mut stream = response.bytes_stream();
+        let mut buffer = String::new();
+        let (mut event, mut data) = (String::new(), String::new());
+        while let Some(Ok(chunk)) = stream.next().await {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find('\n') {
+                let line: String = buffer.drain(..=end).collect();
+                let line = line.trim_end_matches(['\r', '\n']);
+                if let Some(value) = line.strip_prefix("event:") {
+                    event = value.trim().to_string();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.trim_start());
+                } else if line.is_empty() {
+                    match event.as_str() {
+                        "endpoint" => {
+                            if let (Some(tx), Ok(url)) = (endpoint_tx.take(), base.join(&data)) {
+                                let _ = tx.send(url.to_string());
+                            }
+                        }
+                        _ => {
+                            if let Ok(message) = serde_json::from_str(&data) {
+                                dispatch(&pending, message);
+                            }
+                        }
+                    }
+                    event.clear();
+                    data.clear();
+                }
+            }
+        }
+        pending.lock().unwrap().clear();
+    });
+
+    let endpoint = tokio::time::timeout(Duration::from_secs(10), endpoint_rx)
+        .await
+        .map_err(|_| anyhow!("no endpoint event from {}", url))??;
+    Ok(Transport::Sse { http, endpoint })
+}
+
+fn dispatch(pending: &Pending, message: Value) {
+    let Some(id) = message["id"].as_u64() else { return };
+    let Some(tx) = pending.lock().unwrap().remove(&id) else { return };
+    let result = match message.get("error") {
+        Some(error) => Err(anyhow!("{}", error["message"].as_str().unwrap_or("unknown error"))),
+        None => Ok(message["result"].clone()),
+    };
+    let _ = tx.send(result);
+}
+
+/// Joins the text parts of an MCP content list, summarising anything else.
+fn content_text(content: &Value) -> String {
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|part| match part["text"].as_str() {
+            Some(text) => text.to_string(),
+            None => format!("[{} content]", part["type"].as_str().or(part["mimeType"].as_str()).unwrap_or("binary")),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,129 @@
+//! Entries of the chat pane: who said it, what, when, and for answers the
+//! model and usage behind them. Saved with the session.
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::agent::Usage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// A prompt as typed.
+    User,
+    /// A model's answer.
+    Assistant,
+    /// A model's reasoning trace, shown dimmed and collapsed.
+    Reasoning,
+    /// A note from the agent at work, such as a tool call or a fallback.
+    Tool,
+    /// Anything gentor itself says: status, warnings, command output.
+    Notice,
+}
+
+impl Role {
+    /// What the chat pane shows in front of the text.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Role::User => "> ",
+            Role::Assistant => "🤖 ",
+            Role::Reasoning => "💭 ",
+            Role::Tool | Role::Notice => "",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Part {
+    Text { text: String },
+    /// A file or other context sent along with a prompt, by its label.
+    Attachment { label: String },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Message {
+    /// Unique within a session, and across sessions in practice.
+    pub id: u64,
+    pub role: Role,
+    pub content_parts: Vec<Part>,
+    /// Unix time the message was added.
+    pub timestamp: u64,
+    /// Model that wrote an answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Tokens billed for an answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// Ids count up from the time of the first one, so messages of different
+/// runs do not collide.
+fn next_id() -> u64 {
+    static NEXT: OnceLock<AtomicU64> = OnceLock::new();
+    let micros = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as u64);
+    NEXT.get_or_init(|| AtomicU64::new(micros)).fetch_add(1, Ordering::Relaxed)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Message {
+    pub fn new(role: Role, text: impl Into<String>) -> Self {
+        Self { id: next_id(), role, content_parts: vec![Part::Text { text: text.into() }], timestamp: now(), model: None, usage: None }
+    }
+
+    pub fn notice(text: impl Into<String>) -> Self {
+        Self::new(Role::Notice, text)
+    }
+
+    /// A prompt as typed, with the labels of what was attached to it.
+    pub fn user(text: impl Into<String>, attachments: Vec<String>) -> Self {
+        let mut message = Self::new(Role::User, text);
+        message.content_parts.splice(0..0, attachments.into_iter().map(|label| Part::Attachment { label }));
+        message
+    }
+
+    /// An answer by `model`.
+    pub fn assistant(text: impl Into<String>, model: &str, usage: Usage) -> Self {
+        Self { model: Some(model.to_string()), usage: Some(usage), ..Self::new(Role::Assistant, text) }
+    }
+
+    /// A line as sessions stored the chat pane before messages had roles.
+    pub fn from_line(line: &str, timestamp: u64) -> Self {
+        let (role, text) = [Role::User, Role::Assistant, Role::Reasoning]
+            .into_iter()
+            .find_map(|role| line.strip_prefix(role.prefix()).map(|text| (role, text)))
+            .unwrap_or((Role::Notice, line));
+        Self { timestamp, ..Self::new(role, text) }
+    }
+
+    /// The text parts, without attachments.
+    pub fn text(&self) -> Cow<'_, str> {
+        let mut texts = self.content_parts.iter().filter_map(|part| match part {
+            Part::Text { text } => Some(text.as_str()),
+            Part::Attachment { .. } => None,
+        });
+        match (texts.next(), texts.next()) {
+            (None, _) => Cow::Borrowed(""),
+            (Some(text), None) => Cow::Borrowed(text),
+            (Some(first), Some(second)) => Cow::Owned([first, second].into_iter().chain(texts).collect::<Vec<_>>().join("\n")),
+        }
+    }
+
+    /// The message as one line of the chat pane: the role's prefix and the text.
+    pub fn line(&self) -> String {
+        format!("{}{}", self.role.prefix(), self.text())
+    }
+
+    pub fn is(&self, role: Role) -> bool {
+        self.role == role
+    }
+}
@@ -0,0 +1,125 @@
+//! Hooks around every provider call. Each [`Middleware`] sees the request
+//! before it goes out and the outcome once it is back; the usage stats,
+//! plugins, the response cache, the budgets and the debug log all work this
+//! way, in that order. The budgets come after the cache, as cached answers
+//! cost nothing and are still given once a budget is used up.
+use anyhow::Result;
+use futures::future::{self, BoxFuture};
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{cache, debug, settings::Settings, stats};
+
+/// Hooks registered for the whole process, such as plugins'.
+static REGISTERED: Mutex<Vec<Arc<dyn Middleware>>> = Mutex::new(Vec::new());
+
+/// One provider call as the middleware see it.
+pub struct Exchange<'s> {
+    pub settings: &'s Settings,
+    /// The body sent to the provider; `before` hooks may change it.
+    pub request: Value,
+    /// Whether the reply is streamed.
+    pub streamed: bool,
+    /// The chunks of a streamed reply, kept when a `before` hook sets this.
+    pub chunks: Option<Vec<Value>>,
+    /// An answer given by a `before` hook, as the cache does: the provider is
+    /// not called, and the `after` hooks see it with `cached` set.
+    pub response: Option<Value>,
+    /// Whether a `before` hook answered the request.
+    pub cached: bool,
+    /// The exchange as written to the debug log.
+    pub record: Option<Value>,
+    pub started: Instant,
+    /// Until the first token of a streamed reply, set before the `after` hooks.
+    pub first_token: Option<Duration>,
+    /// Spent receiving a streamed reply's tokens, set before the `after` hooks.
+    pub streaming: Duration,
+}
+
+impl<'s> Exchange<'s> {
+    pub fn new(settings: &'s Settings, request: Value, streamed: bool) -> Self {
+        Self { settings, request, streamed, chunks: None, response: None, cached: false, record: None, started: Instant::now(), first_token: None, streaming: Duration::ZERO }
+    }
+}
+
+pub trait Middleware: Send + Sync {
+    /// Runs before the request goes out: may change `exchange.request`,
+    /// answer it by setting `exchange.response`, or refuse it with an error.
+    fn before<'a>(&'a self, _exchange: &'a mut Exchange<'_>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    /// Runs once the provider answered with the (reassembled) response, or failed.
+    fn after<'a>(&'a self, _exchange: &'a mut Exchange<'_>, _result: Result<&'a Value, &'a anyhow::Error>) -> BoxFuture<'a, ()> {
+        Box::pin(future::ready(()))
+    }
+}
+
+/// Adds `hook` to every later provider call of the process.
+pub fn register(hook: Arc<dyn Middleware>) {
+    REGISTERED.lock().unwrap().push(hook);
+}
+
+/// The hooks a call with `settings` goes through, in order.
+pub fn pipeline(settings: &Settings) -> Vec<Arc<dyn Middleware>> {
+    let mut hooks: Vec<Arc<dyn Middleware>> = vec![Arc::new(stats::Recorder)];
+    hooks.extend(REGISTERED.lock().unwrap().iter().cloned());
+    if settings.response_cache {
+        hooks.push(Arc::new(cache::Cache));
+    }
+    hooks.push(Arc::new(stats::Budget));
+    if settings.debug_log.is_some() {
+        hooks.push(Arc::new(debug::Log));
+    }
+    hooks
+}
+
+/// Runs the `before` hooks until one fails or answers the request.
+pub async fn before(hooks: &[Arc<dyn Middleware>], exchange: &mut Exchange<'_>) -> Result<()> {
+    for hook in hooks {
+        hook.before(exchange).await?;
+        if exchange.response.is_some() {
+            exchange.cached = true;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `after` hooks, also when a `before` hook answered the request.
+pub async fn after(hooks: &[Arc<dyn Middleware>], exchange: &mut Exchange<'_>, result: Result<&Value, &anyhow::Error>) {
+    for hook in hooks {
+        hook.after(exchange, result).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // the guard keeps other tests out of the scratch home, not other tasks
+    #[allow(clippy::await_holding_lock)]
+    #[tokio::test]
+    async fn cached_answers_are_given_over_budget() {
+        let _home = crate::settings::scratch_home();
+        let settings = Settings { response_cache: true, daily_budget: Some(0.0), ..Settings::defaults() };
+        let request = json!({ "model": settings.model, "messages": [{ "role": "user", "content": "hi" }] });
+        let answer = json!({ "choices": [{ "message": { "role": "assistant", "content": "hello" } }] });
+        let mut first = Exchange::new(&settings, request.clone(), false);
+        cache::Cache.after(&mut first, Ok(&answer)).await;
+
+        let hooks = pipeline(&settings);
+        let mut cached = Exchange::new(&settings, request, false);
+        before(&hooks, &mut cached).await.unwrap();
+        assert!(cached.cached);
+        assert_eq!(cached.response, Some(answer));
+
+        let mut uncached = Exchange::new(&settings, json!({ "model": settings.model, "messages": [] }), false);
+        let refused = before(&hooks, &mut uncached).await.unwrap_err();
+        assert!(refused.downcast_ref::<stats::OverBudget>().is_some(), "{}", refused);
+    }
+}
@@ -0,0 +1,275 @@
+//! `provider = "mock"`: replies made up on this machine, for working on
+//! gentor without an API key or network. Each request takes the next step of
+//! the JSON script named by `mock_script`, or a canned echo of the prompt
+//! when there is none or it has run out. Replies are streamed in chunks like
+//! a provider's, so they go through the same reassembly as real ones.
+//!
+//! A script is an array of steps, all fields optional:
+//!
+//! ```json
+//! [
+//!   { "match": "weather", "reasoning": "The user wants a file.", "tool_calls": [{ "name": "read_file", "arguments": { "path": "README.md" } }] },
+//!   { "content": "Here it is.", "delay_ms": 50 },
+//!   { "error": { "message": "Rate limit reached", "code": "rate_limit_exceeded" } }
+//! ]
+//! ```
+//!
+//! A step with `match` is only taken by a request whose last message
+//! contains it. `chunks` replays raw chunk objects as given. An `error`
+//! fails the request, after whatever the step streams first.
+use anyhow::{Context, Result};
+use async_openai::error::{ApiError, OpenAIError};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use crate::{settings::Settings, tokens};
+
+pub const PROVIDER: &str = "mock";
+pub const MODEL: &str = "mock";
+/// Wait before the first chunk of a step that sets none.
+const FIRST_TOKEN_MS: u64 = 300;
+/// Wait between chunks of a step that sets none.
+const DELAY_MS: u64 = 30;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+struct Step {
+    #[serde(rename = "match")]
+    pattern: Option<String>,
+    content: String,
+    reasoning: String,
+    tool_calls: Vec<Call>,
+    /// Raw chunks sent instead of ones made from the fields above.
+    chunks: Option<Vec<Value>>,
+    error: Option<ApiError>,
+    /// Characters per content chunk; a word per chunk when unset.
+    chunk_chars: Option<usize>,
+    first_token_ms: Option<u64>,
+    delay_ms: Option<u64>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Call {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Steps of each script read so far, `None` once taken.
+static SCRIPTS: OnceLock<Mutex<HashMap<String, Vec<Option<Step>>>>> = OnceLock::new();
+
+pub fn is_mock(settings: &Settings) -> bool {
+    settings.provider.eq_ignore_ascii_case(PROVIDER)
+}
+
+/// The reply to `req` as a stream of chunks. Fails up front when the step
+/// is an error with nothing to stream before it.
+pub fn stream(settings: &Settings, req: &Value) -> Result<BoxStream<'static, Result<Value, OpenAIError>>> {
+    let step = next_step(settings, req)?;
+    let usage = req["stream_options"]["include_usage"].as_bool().unwrap_or(false).then(|| usage(req, &step));
+    let chunks = chunks(&step, &req["model"], usage);
+    if chunks.is_empty() {
+        if let Some(error) = step.error {
+            return Err(OpenAIError::ApiError(error).into());
+        }
+    }
+    let first = Duration::from_millis(step.first_token_ms.unwrap_or(FIRST_TOKEN_MS));
+    let delay = Duration::from_millis(step.delay_ms.unwrap_or(DELAY_MS));
+    let items = chunks.into_iter().map(Ok).chain(step.error.map(OpenAIError::ApiError).map(Err));
+    let waits = std::iter::once(first).chain(std::iter::repeat(delay));
+    Ok(stream::unfold(waits.zip(items), |mut items| async move {
+        let (wait, item) = items.next()?;
+        tokio::time::sleep(wait).await;
+        Some((item, items))
+    })
+    .boxed())
+}
+
+/// The reply to `req` whole, as a provider answers when not streaming.
+pub async fn response(settings: &Settings, req: &Value) -> Result<Value> {
+    let step = next_step(settings, req)?;
+    tokio::time::sleep(Duration::from_millis(step.first_token_ms.unwrap_or(FIRST_TOKEN_MS))).await;
+    if let Some(error) = step.error {
+        return Err(OpenAIError::ApiError(error).into());
+    }
+    let mut message = json!({ "role": "assistant", "content": step.content });
+    if !step.reasoning.is_empty() {
+        message["reasoning_content"] = json!(step.reasoning);
+    }
+    if !step.tool_calls.is_empty() {
+        let calls: Vec<Value> = step.tool_calls.iter().enumerate().map(|(index, call)| json!({ "id": call_id(index), "type": "function", "function": { "name": call.name, "arguments": arguments(call) } })).collect();
+        message["tool_calls"] = json!(calls);
+    }
+    Ok(json!({ "id": "mock", "object": "chat.completion", "model": req["model"], "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason(&step) }], "usage": usage(req, &step) }))
+}
+
+/// Takes the first step left in the script that matches the last message
+/// of `req`, or makes up the canned reply.
+fn next_step(settings: &Settings, req: &Value) -> Result<Step> {
+    let last = req["messages"].as_array().and_then(|messages| messages.last()).map(text).unwrap_or_default();
+    let Some(path) = settings.mock_script.as_deref() else { return Ok(canned(&last)) };
+    let mut scripts = SCRIPTS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    if !scripts.contains_key(path) {
+        let data = fs::read_to_string(path).with_context(|| format!("reading mock script {}", path))?;
+        let steps: Vec<Step> = serde_json::from_str(&data).with_context(|| format!("parsing mock script {}", path))?;
+        scripts.insert(path.to_string(), steps.into_iter().map(Some).collect());
+    }
+    let steps = scripts.get_mut(path).expect("script was just read");
+    Ok(take(steps, &last).unwrap_or_else(|| canned(&last)))
+}
+
+fn take(steps: &mut [Option<Step>], last: &str) -> Option<Step> {
+    steps.iter_mut().find(|step| step.as_ref().is_some_and(|step| step.pattern.as_deref().is_none_or(|pattern| last.contains(pattern))))?.take()
+}
+
+/// The text of a request message, whether plain or in parts.
+fn text(message: &Value) -> String {
+    match &message["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|part| part["text"].as_str()).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn canned(prompt: &str) -> Step {
+    let quoted: String = prompt.lines().take(8).map(|line| format!("> {}\n", line)).collect();
+    Step {
+        content: format!("This is a **mock** reply; nothing was sent to a model. You wrote:\n\n{}\nSet `mock_script` to script replies, tool calls and errors.", quoted),
+        ..Step::default()
+    }
+}
+
+fn call_id(index: usize) -> String {
+    format!("call_mock_{}", index)
+}
+
+fn arguments(call: &Call) -> String {
+    match &call.arguments {
+        Value::String(arguments) => arguments.clone(),
+        Value::Null => "{}".to_string(),
+        arguments => arguments.to_string(),
+    }
+}
+
+fn finish_reason(step: &Step) -> &'static str {
+    if step.tool_calls.is_empty() {
+        "stop"
+    } else {
+        "tool_calls"
+    }
+}
+
+/// Token counts made the way the rest of gentor counts them for the model.
+fn usage(req: &Value, step: &Step) -> Value {
+    let model = req["model"].as_str().unwrap_or_default();
+    let prompt: usize = req["messages"].as_array().map_or(0, |messages| messages.iter().map(|message| tokens::count(model, &text(message))).sum());
+    let calls: String = step.tool_calls.iter().map(|call| call.name.clone() + &arguments(call)).collect();
+    let completion = tokens::count(model, &step.content) + tokens::count(model, &step.reasoning) + tokens::count(model, &calls);
+    json!({ "prompt_tokens": prompt, "completion_tokens": completion, "total_tokens": prompt + completion })
+}
+
+/// The chunks a provider would stream for `step`: reasoning, then content,
+/// then tool calls, then the finish reason and, when asked for, usage.
+fn chunks(step: &Step, model: &Value, usage: Option<Value>) -> Vec<Value> {
+    if let Some(chunks) = &step.chunks {
+        return chunks.clone();
+    }
+    if step.error.is_some() && step.content.is_empty() && step.reasoning.is_empty() && step.tool_calls.is_empty() {
+        return Vec::new();
+    }
+    let chunk = |delta: Value, finish_reason: Value| json!({ "id": "mock", "object": "chat.completion.chunk", "model": model, "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }] });
+    let mut chunks = vec![chunk(json!({ "role": "assistant" }), Value::Null)];
+    let pieces = |text: &str| -> Vec<String> {
+        match step.chunk_chars.filter(|&size| size > 0) {
+            Some(size) => text.chars().collect::<Vec<_>>().chunks(size).map(|piece| piece.iter().collect()).collect(),
+            None => text.split_inclusive(' ').map(str::to_string).collect(),
+        }
+    };
+    chunks.extend(pieces(&step.reasoning).into_iter().map(|piece| chunk(json!({ "reasoning_content": piece }), Value::Null)));
+    chunks.extend(pieces(&step.content).into_iter().map(|piece| chunk(json!({ "content": piece }), Value::Null)));
+    for (index, call) in step.tool_calls.iter().enumerate() {
+        chunks.push(chunk(json!({ "tool_calls": [{ "index": index, "id": call_id(index), "type": "function", "function": { "name": call.name, "arguments": "" } }] }), Value::Null));
+        for piece in pieces(&arguments(call)) {
+            chunks.push(chunk(json!({ "tool_calls": [{ "index": index, "function": { "arguments": piece } }] }), Value::Null));
+        }
+    }
+    if step.error.is_none() {
+        chunks.push(chunk(json!({}), json!(finish_reason(step))));
+        if let Some(usage) = usage {
+            chunks.push(json!({ "id": "mock", "object": "chat.completion.chunk", "model": model, "choices": [], "usage": usage }));
+        }
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(value: Value) -> Step {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn streamed(chunks: &[Value], field: &str) -> String {
+        chunks.iter().filter_map(|chunk| chunk["choices"][0]["delta"][field].as_str()).collect()
+    }
+
+    #[test]
+    fn content_is_streamed_a_word_at_a_time() {
+        let chunks = chunks(&step(json!({ "content": "one two three" })), &json!("mock"), Some(json!({ "total_tokens": 1 })));
+        assert_eq!(streamed(&chunks, "content"), "one two three");
+        assert_eq!(chunks.iter().filter(|chunk| chunk["choices"][0]["delta"]["content"].is_string()).count(), 3);
+        assert_eq!(chunks[chunks.len() - 2]["choices"][0]["finish_reason"], "stop");
+        assert!(chunks.last().unwrap()["usage"].is_object());
+    }
+
+    #[test]
+    fn tool_call_arguments_are_split_across_chunks() {
+        let step = step(json!({ "tool_calls": [{ "name": "read_file", "arguments": { "path": "README.md" } }], "chunk_chars": 4 }));
+        let chunks = chunks(&step, &json!("mock"), None);
+        let arguments: String = chunks.iter().filter_map(|chunk| chunk["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"].as_str()).collect();
+        assert_eq!(arguments, r#"{"path":"README.md"}"#);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["tool_calls"][0]["function"]["name"], "read_file");
+        assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn an_error_alone_streams_nothing() {
+        let step = step(json!({ "error": { "message": "Rate limit reached", "code": "rate_limit_exceeded" } }));
+        assert!(chunks(&step, &json!("mock"), None).is_empty());
+        assert_eq!(step.error.unwrap().code.as_deref(), Some("rate_limit_exceeded"));
+    }
+
+    #[test]
+    fn an_error_after_content_has_no_finish_reason() {
+        let chunks = chunks(&step(json!({ "content": "partial", "error": { "message": "connection reset" } })), &json!("mock"), None);
+        assert_eq!(streamed(&chunks, "content"), "partial");
+        assert!(chunks.iter().all(|chunk| chunk["choices"][0]["finish_reason"].is_null()));
+    }
+
+    #[test]
+    fn steps_are_taken_in_order_unless_they_match_elsewhere() {
+        let mut steps: Vec<Option<Step>> = [json!({ "match": "weather", "content": "sunny" }), json!({ "content": "first" }), json!({ "content": "second" })]
+            .into_iter()
+            .map(|value| Some(step(value)))
+            .collect();
+        assert_eq!(take(&mut steps, "hello").unwrap().content, "first");
+        assert_eq!(take(&mut steps, "what's the weather").unwrap().content, "sunny");
+        assert_eq!(take(&mut steps, "hello").unwrap().content, "second");
+        assert!(take(&mut steps, "hello").is_none());
+    }
+
+    #[test]
+    fn canned_reply_quotes_the_prompt() {
+        let req = json!({ "model": "mock", "messages": [{ "role": "system", "content": "be brief" }, { "role": "user", "content": [{ "type": "text", "text": "hi there" }] }] });
+        let step = next_step(&Settings::defaults(), &req).unwrap();
+        assert!(step.content.contains("> hi there"));
+    }
+}
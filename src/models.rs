@@ -0,0 +1,103 @@
+//! What each model can do and what it costs, built in and extendable from
+//! `"models"` in the config.
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+#[derive(Clone, Copy)]
+pub struct ModelInfo {
+    pub context_window: usize,
+    pub vision: bool,
+    pub tools: bool,
+    /// USD per million input and output tokens, when known.
+    pub price: Option<(f64, f64)>,
+}
+
+/// A config entry overriding or adding to the built-in registry.
+/// `name` matches any model whose name contains it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModelOverride {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_price: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_price: Option<f64>,
+}
+
+const fn info(context_window: usize, vision: bool, tools: bool, price: Option<(f64, f64)>) -> ModelInfo {
+    ModelInfo { context_window, vision, tools, price }
+}
+
+/// Built-in models by name fragment, most specific first.
+const BUILTIN: &[(&str, ModelInfo)] = &[
+    ("gpt-4.1-nano", info(1_047_576, true, true, Some((0.10, 0.40)))),
+    ("gpt-4.1-mini", info(1_047_576, true, true, Some((0.40, 1.60)))),
+    ("gpt-4.1", info(1_047_576, true, true, Some((2.00, 8.00)))),
+    ("gpt-4o-mini", info(128_000, true, true, Some((0.15, 0.60)))),
+    ("gpt-4o", info(128_000, true, true, Some((2.50, 10.00)))),
+    ("gpt-4-turbo", info(128_000, true, true, Some((10.00, 30.00)))),
+    ("gpt-4-32k", info(32_768, false, true, Some((60.00, 120.00)))),
+    ("gpt-4", info(8_192, false, true, Some((30.00, 60.00)))),
+    ("gpt-3.5-turbo", info(16_385, false, true, Some((0.50, 1.50)))),
+    ("gpt-5-nano", info(400_000, true, true, Some((0.05, 0.40)))),
+    ("gpt-5-mini", info(400_000, true, true, Some((0.25, 2.00)))),
+    ("gpt-5", info(400_000, true, true, Some((1.25, 10.00)))),
+    ("o1-mini", info(128_000, false, false, Some((1.10, 4.40)))),
+    ("o1", info(200_000, true, true, Some((15.00, 60.00)))),
+    ("o3-mini", info(200_000, false, true, Some((1.10, 4.40)))),
+    ("o3", info(200_000, true, true, Some((2.00, 8.00)))),
+    ("o4-mini", info(200_000, true, true, Some((1.10, 4.40)))),
+    ("haiku", info(200_000, true, true, Some((0.80, 4.00)))),
+    ("sonnet", info(200_000, true, true, Some((3.00, 15.00)))),
+    ("opus", info(200_000, true, true, Some((15.00, 75.00)))),
+    ("claude", info(200_000, true, true, None)),
+    ("gemini", info(1_000_000, true, true, None)),
+    ("llama-3", info(128_000, false, true, None)),
+    ("mistral", info(32_000, false, true, None)),
+];
+/// Assumed for models not listed anywhere.
+const UNKNOWN: ModelInfo = info(8_192, false, true, None);
+
+/// Capabilities of `model`: the built-in entry, with any matching
+/// `settings.models` override applied on top.
+pub fn lookup(settings: &Settings, model: &str) -> ModelInfo {
+    let name = model.to_ascii_lowercase();
+    let mut info = BUILTIN.iter().find(|(fragment, _)| name.contains(fragment)).map_or(UNKNOWN, |&(_, info)| info);
+    if let Some(custom) = settings.models.iter().find(|m| name.contains(&m.name.to_ascii_lowercase())) {
+        info.context_window = custom.context_window.unwrap_or(info.context_window);
+        info.vision = custom.vision.unwrap_or(info.vision);
+        info.tools = custom.tools.unwrap_or(info.tools);
+        if let (Some(input), Some(output)) = (custom.input_price, custom.output_price) {
+            info.price = Some((input, output));
+        }
+    }
+    info
+}
+
+impl ModelInfo {
+    /// Cost in USD of a request, when the price is known.
+    pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> Option<f64> {
+        self.price.map(|(input, output)| (prompt_tokens as f64 * input + completion_tokens as f64 * output) / 1_000_000.0)
+    }
+
+    /// One-line summary for the model picker, e.g. `128k · vision · tools · $2.50/$10.00`.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![crate::tokens::short(self.context_window)];
+        if self.vision {
+            parts.push("vision".to_string());
+        }
+        if self.tools {
+            parts.push("tools".to_string());
+        }
+        if let Some((input, output)) = self.price {
+            parts.push(format!("${:.2}/${:.2}", input, output));
+        }
+        parts.join(" · ")
+    }
+}
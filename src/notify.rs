@@ -0,0 +1,26 @@
+//! Telling the user an answer is ready while they are looking elsewhere:
+//! the terminal bell, which tmux and most terminals flag on the window, and
+//! optionally a desktop notification.
+use notify_rust::Notification;
+use std::{
+    io::{self, Write},
+    thread,
+};
+
+pub fn bell() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x07");
+    let _ = stdout.flush();
+}
+
+/// Shows a desktop notification through D-Bus on Linux and the BSDs, the
+/// Notification Center on macOS and toasts on Windows. It is sent from its
+/// own thread, since a slow notification daemon would otherwise hold up the
+/// UI, and failures are ignored: the bell has already rung.
+pub fn desktop(title: &str, body: &str) {
+    let mut notification = Notification::new();
+    notification.appname("Gentor").summary(title).body(body);
+    thread::spawn(move || {
+        let _ = notification.show();
+    });
+}
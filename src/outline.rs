@@ -0,0 +1,200 @@
+//! Splits source files into top-level items so smart attachments can send
+//! only the imports and the items a prompt mentions. Items come from the
+//! file's tree-sitter syntax tree, for Rust, Python, JavaScript, TypeScript,
+//! Go, C, C++ and Java.
+use std::collections::HashSet;
+use tree_sitter::{Language, Node, Parser};
+
+/// Common words that say nothing about which code is relevant.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "this", "that", "what", "why", "how", "does", "are", "not", "but", "can", "you", "from",
+    "into", "when", "where", "which", "should", "would", "could", "there", "here", "have", "has", "use", "using", "make",
+    "code", "file", "function", "method", "explain", "fix", "add", "change", "please", "about", "all", "any", "its", "out",
+];
+/// Rust items rustdoc documents, as written after `pub `.
+const PUB_ITEMS: &[&str] = &["fn ", "async fn ", "const fn ", "unsafe fn ", "struct ", "enum ", "trait ", "type ", "const ", "static ", "mod ", "union "];
+/// Syntax nodes that bring in other code, across the grammars.
+const IMPORT_KINDS: &[&str] = &[
+    "use_declaration",
+    "extern_crate_declaration",
+    "import_statement",
+    "import_from_statement",
+    "future_import_statement",
+    "import_declaration",
+    "package_clause",
+    "package_declaration",
+    "preproc_include",
+    "using_declaration",
+];
+/// Comments and attributes belong to the item below them.
+const PREAMBLE_KINDS: &[&str] = &["comment", "line_comment", "block_comment", "attribute_item", "inner_attribute_item"];
+
+/// A top-level item as a range of lines, end exclusive.
+struct Item {
+    start: usize,
+    end: usize,
+    import: bool,
+}
+
+/// The grammar for files with extension `lang`.
+fn language(lang: &str) -> Option<Language> {
+    Some(match lang {
+        "rs" => tree_sitter_rust::LANGUAGE.into(),
+        "py" => tree_sitter_python::LANGUAGE.into(),
+        "js" | "jsx" | "mjs" | "cjs" => tree_sitter_javascript::LANGUAGE.into(),
+        "ts" | "mts" | "cts" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        "tsx" => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        "go" => tree_sitter_go::LANGUAGE.into(),
+        "c" | "h" => tree_sitter_c::LANGUAGE.into(),
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => tree_sitter_cpp::LANGUAGE.into(),
+        "java" => tree_sitter_java::LANGUAGE.into(),
+        _ => return None,
+    })
+}
+
+/// Imports plus the items of `text` that mention a word of `query`, with
+/// markers for what was left out; `None` when the language is not
+/// understood or nothing matches.
+pub fn focus(text: &str, lang: &str, query: &str) -> Option<(String, usize, usize)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let items = items(text, lang)?;
+    let words = query_words(query);
+    let matches = |item: &Item| {
+        lines[item.start..item.end].iter().any(|line| identifiers(line).any(|w| words.contains(&w.to_ascii_lowercase())))
+    };
+    let kept: Vec<&Item> = items.iter().filter(|item| item.import || matches(item)).collect();
+    if !kept.iter().any(|item| !item.import) {
+        return None;
+    }
+    let comment = if lang == "py" { "#" } else { "//" };
+    let mut out = Vec::new();
+    let omit = |out: &mut Vec<String>, skipped: &[&str]| {
+        if skipped.iter().any(|l| !l.trim().is_empty()) {
+            out.push(format!("{} ... {} line(s) omitted", comment, skipped.len()));
+        }
+    };
+    let mut next = 0;
+    for item in &kept {
+        omit(&mut out, &lines[next..item.start]);
+        out.extend(lines[item.start..item.end].iter().map(|l| l.to_string()));
+        next = item.end;
+    }
+    omit(&mut out, &lines[next..]);
+    let shown = kept.iter().filter(|item| !item.import).count();
+    let total = items.iter().filter(|item| !item.import).count();
+    Some((out.join("\n"), shown, total))
+}
+
+/// 1-based line numbers and declarations of the `pub` Rust items in `text`
+/// without a doc comment. `pub mod name;` is left out: its docs belong at
+/// the top of the module's own file.
+pub fn undocumented(text: &str) -> Vec<(usize, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut found = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let Some(rest) = line.trim_start().strip_prefix("pub ") else { continue };
+        if !PUB_ITEMS.iter().any(|kind| rest.starts_with(kind)) || (rest.starts_with("mod ") && rest.trim_end().ends_with(';')) {
+            continue;
+        }
+        let above = lines[..i].iter().rev().map(|l| l.trim_start()).find(|l| !l.starts_with("#[") || l.starts_with("#[doc"));
+        if !above.is_some_and(|l| l.starts_with("///") || l.starts_with("#[doc") || l.starts_with("*/")) {
+            found.push((i + 1, line.trim().trim_end_matches('{').trim_end().to_string()));
+        }
+    }
+    found
+}
+
+/// The top-level items of `text`, each with the comments and attributes
+/// above it. Items sharing a line are merged.
+fn items(text: &str, lang: &str) -> Option<Vec<Item>> {
+    let mut parser = Parser::new();
+    parser.set_language(&language(lang)?).ok()?;
+    let tree = parser.parse(text, None)?;
+    let mut items: Vec<Item> = Vec::new();
+    let mut preamble = None;
+    let mut cursor = tree.walk();
+    for node in tree.root_node().named_children(&mut cursor) {
+        let end = end_line(&node);
+        if PREAMBLE_KINDS.contains(&node.kind()) {
+            match items.last_mut() {
+                // a comment trailing the previous item
+                Some(last) if preamble.is_none() && node.start_position().row < last.end => last.end = last.end.max(end),
+                _ => preamble = preamble.or(Some(node.start_position().row)),
+            }
+            continue;
+        }
+        let start = preamble.take().unwrap_or(node.start_position().row);
+        let import = IMPORT_KINDS.contains(&node.kind());
+        match items.last_mut() {
+            Some(last) if start < last.end => {
+                last.end = last.end.max(end);
+                last.import &= import;
+            }
+            _ => items.push(Item { start, end, import }),
+        }
+    }
+    if let Some(start) = preamble {
+        items.push(Item { start, end: text.lines().count(), import: false });
+    }
+    Some(items)
+}
+
+/// The line after `node`, not counting a trailing newline it ends with.
+fn end_line(node: &Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 {
+        end.row
+    } else {
+        end.row + 1
+    }
+}
+
+fn query_words(query: &str) -> HashSet<String> {
+    identifiers(query)
+        .map(str::to_ascii_lowercase)
+        .filter(|w| w.len() >= 3 && !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|w| !w.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &str, lang: &str) -> Vec<(usize, usize, bool)> {
+        items(text, lang).unwrap().into_iter().map(|item| (item.start, item.end, item.import)).collect()
+    }
+
+    #[test]
+    fn rust_items_keep_their_docs_and_attributes() {
+        let text = "use std::fs;\n\n/// Doc.\n#[derive(Debug)]\nstruct Point {\n    x: i32,\n}\n\nfn main() {\n    let s = \"}\";\n    let c = '{';\n}\nconst MAX: usize = 3; // the most";
+        assert_eq!(spans(text, "rs"), [(0, 1, true), (2, 7, false), (8, 12, false), (12, 13, false)]);
+    }
+
+    #[test]
+    fn javascript_items_include_object_literals() {
+        let text = "import fs from 'fs';\nconst config = {\n  port: 8080,\n};\n\nfunction serve() {\n  return fs.readFileSync('x');\n}";
+        assert_eq!(spans(text, "js"), [(0, 1, true), (1, 4, false), (5, 8, false)]);
+        let text = "import { readFileSync } from 'fs';\n\ninterface Config {\n  port: number;\n}\n\nexport function serve(config: Config) {}";
+        assert_eq!(spans(text, "ts"), [(0, 1, true), (2, 5, false), (6, 7, false)]);
+    }
+
+    #[test]
+    fn python_items_keep_their_decorators() {
+        let text = "import os\n\n@cache\ndef load(path):\n    return open(path)\n\n\nclass Store:\n    def get(self):\n        pass";
+        assert_eq!(spans(text, "py"), [(0, 1, true), (2, 5, false), (7, 10, false)]);
+    }
+
+    #[test]
+    fn focus_keeps_imports_and_the_items_a_prompt_names() {
+        let text = "use std::fs;\n\nfn parse() {}\n\nfn render() {\n    draw();\n}";
+        let (focused, shown, total) = focus(text, "rs", "why does render flicker").unwrap();
+        assert_eq!((shown, total), (1, 2));
+        assert_eq!(focused, "use std::fs;\n// ... 3 line(s) omitted\nfn render() {\n    draw();\n}");
+        assert!(focus(text, "rs", "unrelated words").is_none());
+        assert!(focus(text, "md", "render").is_none());
+    }
+}
@@ -0,0 +1,64 @@
+//! The Ctrl+P command palette: commands, settings switches, sessions and
+//! models in one fuzzy-searchable list.
+use std::path::PathBuf;
+
+#[derive(Clone)]
+pub enum Action {
+    /// A slash command, run at once unless it needs arguments.
+    Command { name: String, needs_args: bool },
+    /// Flips the on/off settings field at this index.
+    Toggle(usize),
+    Session(PathBuf),
+    Model(String),
+}
+
+pub struct Entry {
+    pub label: String,
+    pub detail: String,
+    pub action: Action,
+}
+
+pub struct Palette {
+    pub query: String,
+    pub entries: Vec<Entry>,
+    /// Row in the filtered list.
+    pub selected: usize,
+}
+
+impl Palette {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self { query: String::new(), entries, selected: 0 }
+    }
+
+    /// Entries matching the query, best first.
+    pub fn visible(&self) -> Vec<&Entry> {
+        let mut scored: Vec<(i64, &Entry)> = self.entries.iter().filter_map(|e| Some((score(&self.query, &e.label)?, e))).collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, e)| e).collect()
+    }
+}
+
+/// How well `query` matches `text` as a case-insensitive subsequence,
+/// favouring runs of consecutive characters and word starts; `None` when
+/// some character of the query is missing.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut at = 0;
+    let mut previous = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = at + text[at..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        // earlier matches rank above ones deep in a long label
+        score -= (found - at).min(10) as i64;
+        previous = Some(found);
+        at = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,221 @@
+//! WASM plugins from `~/.config/gentor/plugins/`, run under a WASI runtime
+//! CLI (`wasmtime` unless `wasm_runtime` is set in settings).
+//!
+//! Host API: each invocation writes one JSON request to the plugin's stdin
+//! and reads one JSON reply from its stdout.
+//! - `{"describe": true}` → `{"commands": [{"name", "description"}], "tools": [{"name", "description", "parameters"}], "hooks": [...]}`
+//! - `{"kind": "command" | "tool", "name", "input", "confirmed"}` → `{"text"}` or
+//!   `{"confirm": "question"}`, in which case the user is asked and the
+//!   request is repeated with `"confirmed": true`.
+//! - With `"before_request"` in `hooks`, `{"kind": "before_request", "request"}`
+//!   before every provider call → `{"request"}` to send instead, `{"error"}`
+//!   to refuse it, or `{}` to leave it be.
+//! - With `"after_response"` in `hooks`, `{"kind": "after_response", "request",
+//!   "response" | "error", "cached"}` after every provider call, `cached` when
+//!   the response cache answered instead; the reply is ignored.
+use anyhow::{anyhow, bail, Result};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    middleware::{Exchange, Middleware},
+    settings,
+    tools::ToolHost,
+};
+
+pub const DEFAULT_RUNTIME: &str = "wasmtime";
+const BEFORE_REQUEST: &str = "before_request";
+const AFTER_RESPONSE: &str = "after_response";
+/// How long a plugin may take to answer before it is killed. Hooks run on
+/// every provider call, so a plugin that hangs would otherwise hold them all.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct PluginCommand {
+    pub name: String,
+    pub description: String,
+}
+
+pub struct PluginTool {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+pub struct Plugin {
+    pub name: String,
+    pub commands: Vec<PluginCommand>,
+    pub tools: Vec<PluginTool>,
+    /// Provider call hooks the plugin asked for: `before_request`, `after_response`.
+    pub hooks: Vec<String>,
+    path: PathBuf,
+    runtime: String,
+    timeout: Duration,
+}
+
+pub fn plugin_dir() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("plugins"))
+}
+
+pub fn discover() -> Vec<PathBuf> {
+    let Some(entries) = plugin_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+impl Plugin {
+    /// Asks the plugin to describe the commands and tools it registers.
+    pub async fn load(path: &Path, runtime: &str) -> Result<Self> {
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut plugin = Self { name, commands: Vec::new(), tools: Vec::new(), hooks: Vec::new(), path: path.to_path_buf(), runtime: runtime.to_string(), timeout: TIMEOUT };
+        let manifest = plugin.invoke(json!({ "describe": true })).await?;
+        plugin.commands = manifest["commands"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|c| {
+                let name = c["name"].as_str()?;
+                Some(PluginCommand {
+                    name: if name.starts_with('/') { name.to_string() } else { format!("/{}", name) },
+                    description: c["description"].as_str().unwrap_or_default().to_string(),
+                })
+            })
+            .collect();
+        plugin.tools = manifest["tools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| {
+                Some(PluginTool {
+                    name: t["name"].as_str()?.to_string(),
+                    description: t["description"].as_str().unwrap_or_default().to_string(),
+                    parameters: t.get("parameters").cloned().unwrap_or_else(|| json!({ "type": "object" })),
+                })
+            })
+            .collect();
+        plugin.hooks = manifest["hooks"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|hook| [BEFORE_REQUEST, AFTER_RESPONSE].contains(hook))
+            .map(str::to_string)
+            .collect();
+        Ok(plugin)
+    }
+
+    fn hooks(&self, hook: &str) -> bool {
+        self.hooks.iter().any(|h| h == hook)
+    }
+
+    pub async fn run(&self, kind: &str, name: &str, input: &str, host: &dyn ToolHost) -> Result<String> {
+        let mut request = json!({ "kind": kind, "name": name, "input": input, "confirmed": false });
+        let mut reply = self.invoke(request.clone()).await?;
+        if let Some(question) = reply["confirm"].as_str() {
+            if !host.confirm(format!("[{}] {}", self.name, question)).await.unwrap_or(false) {
+                return Ok("Cancelled by the user.".to_string());
+            }
+            request["confirmed"] = json!(true);
+            reply = self.invoke(request).await?;
+        }
+        Ok(reply["text"].as_str().unwrap_or_default().to_string())
+    }
+
+    async fn invoke(&self, request: Value) -> Result<Value> {
+        let mut child = Command::new(&self.runtime)
+            .arg("run")
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("could not start {}: {}", self.runtime, e))?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let request = serde_json::to_vec(&request)?;
+        // the child is killed when the timed-out future drops it
+        let output = tokio::time::timeout(self.timeout, async move {
+            stdin.write_all(&request).await?;
+            drop(stdin);
+            child.wait_with_output().await
+        })
+        .await
+        .map_err(|_| anyhow!("plugin {} did not answer within {}s and was stopped", self.name, self.timeout.as_secs()))??;
+        if !output.status.success() {
+            bail!("plugin {} failed: {}", self.name, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| anyhow!("plugin {} sent invalid JSON: {}", self.name, e))
+    }
+}
+
+impl Middleware for Plugin {
+    fn before<'a>(&'a self, exchange: &'a mut Exchange<'_>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if !self.hooks(BEFORE_REQUEST) {
+                return Ok(());
+            }
+            let reply = self.invoke(json!({ "kind": BEFORE_REQUEST, "request": exchange.request })).await?;
+            if let Some(error) = reply["error"].as_str() {
+                bail!("plugin {} refused the request: {}", self.name, error);
+            }
+            if let Some(request) = reply.get("request").filter(|request| request.is_object()) {
+                exchange.request = request.clone();
+            }
+            Ok(())
+        })
+    }
+
+    fn after<'a>(&'a self, exchange: &'a mut Exchange<'_>, result: Result<&'a Value, &'a anyhow::Error>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            if !self.hooks(AFTER_RESPONSE) {
+                return;
+            }
+            let mut request = json!({ "kind": AFTER_RESPONSE, "request": exchange.request, "cached": exchange.cached });
+            match result {
+                Ok(response) => request["response"] = response.clone(),
+                Err(e) => request["error"] = json!(format!("{:#}", e)),
+            }
+            // an observer; its failure is no reason to lose the answer
+            let _ = self.invoke(request).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_plugin_that_hangs_is_stopped() {
+        use std::os::unix::fs::PermissionsExt;
+        let runtime = std::env::temp_dir().join(format!("gentor-hanging-runtime-{}", std::process::id()));
+        fs::write(&runtime, "#!/bin/sh\nsleep 30\n").unwrap();
+        fs::set_permissions(&runtime, fs::Permissions::from_mode(0o755)).unwrap();
+        let plugin = Plugin {
+            name: "slow".to_string(),
+            commands: Vec::new(),
+            tools: Vec::new(),
+            hooks: vec![BEFORE_REQUEST.to_string()],
+            path: PathBuf::from("slow.wasm"),
+            runtime: runtime.to_string_lossy().into_owned(),
+            timeout: Duration::from_secs(1),
+        };
+        let error = plugin.invoke(json!({ "kind": BEFORE_REQUEST })).await.unwrap_err();
+        assert_eq!(error.to_string(), "plugin slow did not answer within 1s and was stopped");
+        fs::remove_file(&runtime).unwrap();
+    }
+}
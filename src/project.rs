@@ -0,0 +1,128 @@
+//! `.gentor.toml`: configuration a project keeps in its repository, found
+//! by walking up from the working directory to the repository root. It adds
+//! to the system prompt, picks the persona to start with, keeps files out of
+//! the model's view, limits the tools the agent is offered and overrides
+//! settings under `[settings]`.
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use crate::{
+    ignore,
+    settings::{self, Origin, Override},
+    toml,
+};
+
+pub const PROJECT_FILE: &str = ".gentor.toml";
+/// Settings a cloned repository must not choose: where the API key is sent,
+/// the key itself and what goes with it, and commands run on the user's machine.
+const USER_ONLY: &[&str] = &[
+    "provider", "api_key", "api_keys", "base_url", "organization", "project", "providers", "extra_headers", "profiles", "fallbacks", "github", "lsp_command", "mcp_servers", "wasm_runtime", "sandbox",
+];
+
+static CURRENT: OnceLock<ProjectConfig> = OnceLock::new();
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Appended to the system prompt of every request.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Persona active at startup, by name.
+    #[serde(default)]
+    pub persona: Option<String>,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// Settings keys as in the user config, taking precedence over it.
+    #[serde(default)]
+    pub settings: Map<String, Value>,
+    /// Where the file was found; `None` without one.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// What of the project the model gets to see.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct IndexConfig {
+    /// Ignore patterns applied after `.gitignore` and `.gentorignore`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Shorthand for `auto_tree` under `[settings]`.
+    #[serde(default)]
+    pub tree: Option<bool>,
+}
+
+/// Tool names or globs (`github__*` for an MCP server's tools): only those
+/// in `allow` are offered when it is set, and never those in `deny`.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ToolsConfig {
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ToolsConfig {
+    pub fn allows(&self, name: &str) -> bool {
+        let listed = |patterns: &[String]| patterns.iter().any(|p| ignore::glob(p, name));
+        self.allow.as_deref().is_none_or(listed) && !listed(&self.deny)
+    }
+}
+
+/// The nearest `.gentor.toml` from the working directory up to the
+/// repository root.
+pub fn find() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    for dir in cwd.ancestors() {
+        let path = dir.join(PROJECT_FILE);
+        if path.is_file() {
+            return Some(path);
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Reads the project's file, when there is one, as the [`current`] config.
+pub fn load() -> Result<()> {
+    let Some(path) = find() else { return Ok(()) };
+    let text = fs::read_to_string(&path)?;
+    let mut config: ProjectConfig = toml::parse(&text)
+        .and_then(|value| Ok(serde_json::from_value(value)?))
+        .with_context(|| format!("invalid {}", path.display()))?;
+    if let Some(key) = config.settings.keys().find(|key| USER_ONLY.contains(&key.as_str())) {
+        bail!("{}: '{}' can only be set in {}", path.display(), key, settings::user_file().display());
+    }
+    config.path = Some(path);
+    let _ = CURRENT.set(config);
+    Ok(())
+}
+
+pub fn current() -> &'static ProjectConfig {
+    CURRENT.get_or_init(ProjectConfig::default)
+}
+
+/// The settings the project file overrides, `[index] tree` included.
+pub fn overrides() -> Vec<Override> {
+    let config = current();
+    let Some(path) = &config.path else { return Vec::new() };
+    let tree = config.index.tree.map(|tree| ("auto_tree".to_string(), Value::Bool(tree)));
+    config
+        .settings
+        .clone()
+        .into_iter()
+        .chain(tree)
+        .map(|(key, value)| Override { key, value, origin: Origin::Project(path.clone()) })
+        .collect()
+}
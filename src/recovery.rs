@@ -0,0 +1,90 @@
+//! Getting out of a crash cleanly: the terminal is restored before the panic
+//! message is printed, and the open session is remembered so the next launch
+//! can reopen it.
+use anyhow::Result;
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, DisableFocusChange},
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{crypto, settings};
+
+const RECOVERY_FILE: &str = "recovery.json";
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Written when Gentor stops unexpectedly, removed when it is read back.
+#[derive(Serialize, Deserialize)]
+pub struct Marker {
+    pub session: PathBuf,
+    /// Input that had not been sent yet.
+    #[serde(default)]
+    pub input: String,
+}
+
+pub(crate) fn recovery_file() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join(RECOVERY_FILE))
+}
+
+/// Leaves the alternate screen and raw mode before the default hook prints
+/// the panic, so the message is readable and the shell usable. Panics in
+/// background tasks are left to the runtime, which keeps the UI running.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore_terminal();
+        }
+        default(info);
+    }));
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), DisableBracketedPaste, DisableFocusChange, LeaveAlternateScreen, Show);
+}
+
+/// Ends the event loop on SIGTERM or SIGHUP (a closed terminal) so the
+/// session can be saved; Ctrl+C arrives as a key in raw mode.
+pub fn watch_signals() {
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (Ok(mut terminate), Ok(mut hangup)) = (signal(SignalKind::terminate()), signal(SignalKind::hangup())) else {
+            return;
+        };
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = hangup.recv() => {}
+        }
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    });
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+pub fn save(session: &Path, input: &str) -> Result<()> {
+    let path = recovery_file().ok_or_else(|| anyhow::anyhow!("cannot locate the config directory"))?;
+    let marker = Marker { session: session.to_path_buf(), input: input.to_string() };
+    crypto::write(&path, serde_json::to_string_pretty(&marker)?)?;
+    Ok(())
+}
+
+/// The marker left by the last run, removing it so a crash while restoring
+/// does not loop.
+pub fn take() -> Option<Marker> {
+    let path = recovery_file()?;
+    let data = crypto::read_to_string(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    serde_json::from_str(&data).ok()
+}
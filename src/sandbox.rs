@@ -0,0 +1,238 @@
+//! The `run_code` tool: short snippets run in a scratch directory as a
+//! subprocess under resource limits, or in a throwaway container. Only the
+//! container isolates them: without one a snippet can read and write the
+//! user's files and reach the network, and the limits merely stop it from
+//! running away with CPU, memory, disk or processes.
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    process::Stdio,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::process::Command;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+/// CPU seconds, largest file and address space a snippet may use outside a
+/// container. The address space limit is applied after a build step, as
+/// compilers reserve far more than the programs they build.
+const CPU_LIMIT: u64 = 10;
+const FILE_LIMIT_KB: u64 = 10 * 1024;
+const MEMORY_LIMIT_KB: u64 = 1024 * 1024;
+/// Processes outside a container. The limit counts every process of the
+/// user, not just the snippet's, so it is only high enough to stop a fork bomb.
+const USER_PROCESS_LIMIT: u64 = 4096;
+/// Exit status of the shell when a limit could not be set, with
+/// [`LIMIT_FAILED`] and the limit on stderr.
+const LIMIT_STATUS: i32 = 125;
+const LIMIT_FAILED: &str = "gentor: cannot apply ulimit";
+/// Processes a snippet may start inside a container.
+const PROCESS_LIMIT: u64 = 64;
+/// stdout and stderr handed back to the model are each cut to this many characters.
+const MAX_OUTPUT: usize = 10_000;
+
+/// Runs started by this process, numbered to keep their scratch directories
+/// and containers apart.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// `container` is a runtime CLI such as `docker` or `podman`; snippets then
+/// run with no network in `image`, or a stock image for the language.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SandboxConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+struct Language {
+    names: &'static [&'static str],
+    file: &'static str,
+    /// Shell command compiling the snippet first, if it needs one.
+    build: Option<&'static str>,
+    /// Shell command run in the scratch directory.
+    command: &'static str,
+    image: &'static str,
+}
+
+const LANGUAGES: &[Language] = &[
+    Language { names: &["python", "py", "python3"], file: "main.py", build: None, command: "python3 main.py", image: "python:3-alpine" },
+    Language { names: &["javascript", "js", "node"], file: "main.js", build: None, command: "node main.js", image: "node:alpine" },
+    Language { names: &["bash", "sh", "shell"], file: "main.sh", build: None, command: "sh main.sh", image: "alpine" },
+    Language { names: &["rust", "rs"], file: "main.rs", build: Some("rustc -O main.rs -o main"), command: "./main", image: "rust:slim" },
+];
+
+pub fn languages() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|l| l.names[0]).collect()
+}
+
+fn truncate(text: &[u8]) -> String {
+    let text = String::from_utf8_lossy(text);
+    match text.char_indices().nth(MAX_OUTPUT) {
+        Some((end, _)) => format!("{}\n[truncated]", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+/// A name for this run, shared by its scratch directory and its container.
+fn run_name() -> String {
+    let n = RUNS.fetch_add(1, Ordering::Relaxed);
+    format!("gentor-run-{}-{}", std::process::id(), n)
+}
+
+fn command(config: &SandboxConfig, language: &Language, name: &str, dir: &Path) -> Command {
+    match &config.container {
+        Some(runtime) => {
+            let script = language.build.map_or(language.command.to_string(), |build| format!("{} && {}", build, language.command));
+            let mut cmd = Command::new(runtime);
+            cmd.args(["run", "--rm", "--name", name, "--network", "none", "--memory", "512m", "--cpus", "1", "--pids-limit"])
+                .arg(PROCESS_LIMIT.to_string())
+                .arg("-v")
+                .arg(format!("{}:/work", dir.display()))
+                .args(["-w", "/work"])
+                .arg(config.image.as_deref().unwrap_or(language.image))
+                .args(["sh", "-c", script.as_str()]);
+            cmd
+        }
+        None => {
+            // `ulimit -f` counts 512-byte blocks in POSIX shells, and dash
+            // calls the process limit `-p` where other shells say `-u`
+            let limits = format!(
+                "limit() {{ ulimit \"$@\" || {{ echo \"{failed} $*\" >&2; exit {status}; }}; }}; \
+                 case \"$(ulimit -a)\" in *-u*) processes=-u ;; *) processes=-p ;; esac; \
+                 limit -t {} && limit -f {} && limit $processes {} && {}limit -v {} && {}",
+                CPU_LIMIT,
+                FILE_LIMIT_KB * 2,
+                USER_PROCESS_LIMIT,
+                language.build.map_or(String::new(), |build| format!("{} && ", build)),
+                MEMORY_LIMIT_KB,
+                language.command,
+                failed = LIMIT_FAILED,
+                status = LIMIT_STATUS,
+            );
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", &limits])
+                .current_dir(dir)
+                .env_clear()
+                .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+                .env("HOME", dir);
+            cmd
+        }
+    }
+}
+
+/// Runs `source` and reports its exit code, stdout and stderr.
+pub async fn run(config: &SandboxConfig, language: &str, source: &str) -> Result<String> {
+    let lowered = language.to_ascii_lowercase();
+    let Some(language) = LANGUAGES.iter().find(|l| l.names.contains(&lowered.as_str())) else {
+        bail!("unsupported language {} (supported: {})", language, languages().join(", "));
+    };
+    let name = run_name();
+    let dir = std::env::temp_dir().join(&name);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(language.file), source)?;
+    let timeout = Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let mut command = command(config, language, &name, &dir);
+    // its own process group, so a timeout also ends what the snippet started
+    #[cfg(unix)]
+    command.process_group(0);
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("could not start the snippet: {}", e));
+    let result = match child {
+        Ok(child) => {
+            let group = child.id();
+            let result = tokio::time::timeout(timeout, child.wait_with_output()).await;
+            if result.is_err() {
+                // the container outlives its CLI, so it is stopped by name
+                if let Some(runtime) = &config.container {
+                    quietly(Command::new(runtime).args(["kill", &name])).await;
+                }
+                if let Some(group) = group {
+                    kill_group(group).await;
+                }
+            }
+            result
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(e);
+        }
+    };
+    let _ = fs::remove_dir_all(&dir);
+    let Ok(output) = result else {
+        return Ok(format!("Timed out after {}s.", timeout.as_secs()));
+    };
+    let output = output?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.code() == Some(LIMIT_STATUS) {
+        if let Some(line) = stderr.lines().find(|line| line.starts_with(LIMIT_FAILED)) {
+            bail!("the resource limits could not be applied ({}); the snippet was not run", line.trim_start_matches("gentor: "));
+        }
+    }
+    let code = output.status.code().map_or("killed".to_string(), |c| c.to_string());
+    Ok(format!(
+        "exit code: {}\n--- stdout ---\n{}\n--- stderr ---\n{}",
+        code,
+        truncate(&output.stdout),
+        truncate(&output.stderr)
+    ))
+}
+
+/// Kills every process in the group led by `group`.
+async fn kill_group(group: u32) {
+    if cfg!(unix) {
+        quietly(Command::new("kill").args(["-KILL", "--", &format!("-{}", group)])).await;
+    }
+}
+
+/// Runs `command` to completion with no input or output, ignoring how it went.
+async fn quietly(command: &mut Command) {
+    let _ = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snippets_run_under_the_limits() {
+        let output = run(&SandboxConfig::default(), "sh", "ulimit -t; ulimit -v").await.unwrap();
+        assert!(output.starts_with("exit code: 0"), "{}", output);
+        assert!(output.contains(&format!("{}\n{}", CPU_LIMIT, MEMORY_LIMIT_KB)), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn a_timeout_ends_what_the_snippet_started() {
+        let marker = std::env::temp_dir().join(format!("gentor-run-survivor-{}", std::process::id()));
+        let config = SandboxConfig { timeout_secs: Some(1), ..SandboxConfig::default() };
+        let source = format!("(sleep 2; touch '{}') & sleep 30", marker.display());
+        let output = run(&config, "sh", &source).await.unwrap();
+        assert_eq!(output, "Timed out after 1s.");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists(), "a background process outlived the timeout");
+    }
+
+    #[tokio::test]
+    async fn a_timeout_stops_the_container() {
+        let Some(runtime) = ["docker", "podman"].into_iter().find(|runtime| {
+            std::process::Command::new(runtime).arg("info").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok_and(|s| s.success())
+        }) else {
+            return;
+        };
+        let config = SandboxConfig { container: Some(runtime.to_string()), timeout_secs: Some(5), ..SandboxConfig::default() };
+        let output = run(&config, "sh", "sleep 60").await.unwrap();
+        assert_eq!(output, "Timed out after 5s.");
+        let filter = format!("name=gentor-run-{}-", std::process::id());
+        let running = std::process::Command::new(runtime).args(["ps", "-q", "--filter", &filter]).output().unwrap();
+        assert!(running.stdout.is_empty(), "the container outlived the timeout");
+    }
+}
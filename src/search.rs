@@ -0,0 +1,146 @@
+//! The `grep` tool: fixed-string search over project files with the
+//! ripgrep crates, honouring `.gitignore`, Gentor's own ignore rules and
+//! hidden files and not following symlinks, so the agent can find usages
+//! across the repository without leaving it.
+use anyhow::{bail, Result};
+use grep_regex::RegexMatcherBuilder;
+use grep_searcher::{sinks::Lossy, BinaryDetection, SearcherBuilder};
+use std::{
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use crate::ignore::{self, IgnoreRules};
+
+/// Matching lines reported per search.
+const MAX_MATCHES: usize = 100;
+/// Matching lines are cut to this many characters.
+const MAX_LINE: usize = 200;
+/// Larger files are skipped.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Searches files under `root` (or its subdirectory `path`) whose names match
+/// `glob` for `pattern`, case-insensitively when the pattern is all lowercase.
+pub fn grep(root: &Path, pattern: &str, glob: Option<&str>, path: Option<&str>) -> Result<String> {
+    if pattern.is_empty() {
+        bail!("empty pattern");
+    }
+    let sub = path.unwrap_or("").trim_matches('/');
+    if Path::new(sub).components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        bail!("path must stay inside the project: {}", sub);
+    }
+    let rules = Arc::new(IgnoreRules::load(root));
+    if !sub.is_empty() && rules.is_ignored(sub, true) {
+        bail!("{} is excluded by the ignore rules", sub);
+    }
+    // a symlinked directory on the way could lead out of the project
+    if let (Ok(root), Ok(dir)) = (root.canonicalize(), root.join(sub).canonicalize()) {
+        if !dir.starts_with(&root) {
+            bail!("path must stay inside the project: {}", sub);
+        }
+    }
+    let matcher = RegexMatcherBuilder::new().fixed_strings(true).case_insensitive(!pattern.chars().any(char::is_uppercase)).build(pattern)?;
+    let mut searcher = SearcherBuilder::new().binary_detection(BinaryDetection::quit(0)).line_number(true).build();
+    let walk = {
+        let (root, rules) = (root.to_path_buf(), rules.clone());
+        ::ignore::WalkBuilder::new(root.join(sub))
+            .require_git(false)
+            .add_custom_ignore_filename(".gentorignore")
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+                relative(&root, entry.path()).is_none_or(|rel| rel.is_empty() || !rules.is_ignored(&rel, is_dir))
+            })
+            .build()
+    };
+
+    let mut found = Found::default();
+    for entry in walk.flatten() {
+        // symlinks are skipped, as they may point outside the project
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) || entry.metadata().map_or(true, |m| m.len() > MAX_FILE_BYTES) {
+            continue;
+        }
+        let Some(rel) = relative(root, entry.path()) else { continue };
+        let name = rel.rsplit('/').next().unwrap_or(&rel);
+        if glob.is_some_and(|g| !ignore::glob(g, if g.contains('/') { &rel } else { name })) {
+            continue;
+        }
+        let mut hit = false;
+        let sink = Lossy(|number, line: &str| {
+            hit = true;
+            found.total += 1;
+            if found.lines.len() < MAX_MATCHES {
+                let line: String = line.trim().chars().take(MAX_LINE).collect();
+                found.lines.push(format!("{}:{}: {}", rel, number, line));
+            }
+            Ok(true)
+        });
+        if searcher.search_path(&matcher, entry.path(), sink).is_ok() {
+            found.files += usize::from(hit);
+        }
+    }
+
+    if found.lines.is_empty() {
+        return Ok(format!("No matches for {}.", pattern));
+    }
+    let mut out = format!("{} match(es) in {} file(s)", found.total, found.files);
+    if found.total > found.lines.len() {
+        out.push_str(&format!(", showing the first {}", found.lines.len()));
+    }
+    out.push('\n');
+    out.push_str(&found.lines.join("\n"));
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Found {
+    lines: Vec<String>,
+    total: usize,
+    files: usize,
+}
+
+/// `path` relative to `root`, `/`-separated, as the ignore rules expect it.
+fn relative(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    Some(rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_project_are_not_followed() {
+        let scratch = env::temp_dir().join(format!("gentor-search-{}", std::process::id()));
+        let (root, outside) = (scratch.join("project"), scratch.join("outside"));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("src/lib.rs"), "let needle = 1;\n").unwrap();
+        fs::write(outside.join("secret.txt"), "needle in a secret\n").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("linked")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("src/secret.txt")).unwrap();
+
+        let out = grep(&root, "needle", None, None).unwrap();
+        assert!(out.contains("src/lib.rs:1:"), "{}", out);
+        assert!(!out.contains("secret"), "{}", out);
+        assert!(grep(&root, "needle", None, Some("linked")).is_err());
+        fs::remove_dir_all(&scratch).unwrap();
+    }
+
+    #[test]
+    fn gitignored_files_are_skipped() {
+        let root = env::temp_dir().join(format!("gentor-search-ignored-{}", std::process::id()));
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n!keep.log\n").unwrap();
+        fs::write(root.join("logs/run.log"), "needle\n").unwrap();
+        fs::write(root.join("logs/keep.log"), "needle\n").unwrap();
+        fs::write(root.join("main.rs"), "// NEEDLE\n").unwrap();
+
+        let out = grep(&root, "needle", None, None).unwrap();
+        assert_eq!(out, "2 match(es) in 2 file(s)\nlogs/keep.log:1: needle\nmain.rs:1: // NEEDLE");
+        assert_eq!(grep(&root, "Needle", None, None).unwrap(), "No matches for Needle.");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
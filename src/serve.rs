@@ -0,0 +1,548 @@
+//! `gentor serve`: a small HTTP API on localhost so editors and scripts can
+//! use the configured providers, tools and saved sessions. It speaks enough
+//! of the OpenAI chat completions API for its clients (no streaming), plus
+//! a sessions API over the same store as the TUI.
+//!
+//! - `GET /v1/models`
+//! - `POST /v1/chat/completions`
+//! - `GET /sessions`, `POST /sessions`
+//! - `GET /sessions/<id>`, `POST /sessions/<id>/messages` with `{"content": "..."}`
+//!
+//! Every request needs the bearer token printed at startup, which OpenAI
+//! clients take as their API key.
+use anyhow::{anyhow, bail, Result};
+use async_openai::{config::OpenAIConfig, Client};
+use serde_json::{json, Value};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    iter,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{oneshot, Mutex},
+};
+
+use crate::{
+    agent::{self, Reply, Turn},
+    i18n,
+    message::Message,
+    session::{self, Session},
+    settings::Settings,
+    stats,
+    tools::{ToolHost, Toolbox},
+};
+
+pub const DEFAULT_PORT: u16 = 8787;
+/// Largest request body accepted.
+const MAX_BODY: usize = 8 * 1024 * 1024;
+/// Largest request line and headers accepted.
+const MAX_HEAD: u64 = 64 * 1024;
+/// Time a client gets to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Server {
+    settings: Settings,
+    port: u16,
+    /// Bearer token every request must carry.
+    token: String,
+    client: Client<OpenAIConfig>,
+    toolbox: Toolbox,
+    /// Held while a session is read, answered and saved, so two requests
+    /// to the API never interleave their turns.
+    sessions: Mutex<()>,
+}
+
+/// Nobody is there to answer tool confirmations, so they are declined.
+struct Host;
+
+impl ToolHost for Host {
+    fn notify(&self, _line: String) {}
+
+    fn confirm(&self, _question: String) -> oneshot::Receiver<bool> {
+        oneshot::channel().1
+    }
+}
+
+/// An error answered with `status` and an OpenAI-style error body.
+struct Failure(u16, String);
+
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        Failure(500, e.to_string())
+    }
+}
+
+fn bad_request(message: impl Into<String>) -> Failure {
+    Failure(400, message.into())
+}
+
+/// A request as read off the connection; header names are lowercased.
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves on 127.0.0.1:`port` until interrupted. Only this machine can
+/// connect, and only with the token printed here.
+pub async fn run(settings: Settings, port: u16) -> Result<()> {
+    let settings = Settings { response_format: None, ..settings };
+    let toolbox = Toolbox::new(settings.web_search.clone(), settings.sandbox.clone(), settings.github.clone());
+    toolbox.set_local_only(settings.local_only);
+    let server = Arc::new(Server {
+        client: agent::client_for(&settings),
+        settings,
+        port,
+        token: new_token()?,
+        toolbox,
+        sessions: Mutex::new(()),
+    });
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("{}", i18n::fill("serve.serving", &[&server.settings.model, &port]));
+    println!("{}", i18n::fill("serve.token", &[&server.token]));
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server.connection(stream).await {
+                eprintln!("{}", i18n::fill("error", &[&e]));
+            }
+        });
+    }
+}
+
+impl Server {
+    async fn connection(&self, stream: TcpStream) -> Result<()> {
+        let mut stream = BufReader::new(stream);
+        let (status, body) = match self.handle(&mut stream).await {
+            Ok(body) => (200, body),
+            Err(Failure(status, message)) => (status, json!({ "error": { "message": message, "type": error_type(status) } })),
+        };
+        let body = serde_json::to_string(&body)?;
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            reason(status),
+            body.len()
+        );
+        let stream = stream.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn handle(&self, stream: &mut BufReader<TcpStream>) -> Result<Value, Failure> {
+        let request = match tokio::time::timeout(READ_TIMEOUT, read_request(stream)).await {
+            Ok(request) => request.map_err(|e| bad_request(e.to_string()))?,
+            Err(_) => return Err(Failure(408, "timed out reading the request".to_string())),
+        };
+        self.admit(&request)?;
+        self.route(&request.method, &request.path, &request.body).await
+    }
+
+    /// Web pages can reach localhost too: they can post forms here and,
+    /// through DNS rebinding, read the answers. So the Host must name this
+    /// server, there must be no Origin, the token must match and POSTs must
+    /// be JSON, which a form cannot send.
+    fn admit(&self, request: &Request) -> Result<(), Failure> {
+        let host = request.header("host").unwrap_or_default();
+        if ![format!("127.0.0.1:{}", self.port), format!("localhost:{}", self.port)].iter().any(|h| h.eq_ignore_ascii_case(host)) {
+            return Err(Failure(403, format!("unexpected Host \"{}\"", host)));
+        }
+        if request.header("origin").is_some() {
+            return Err(Failure(403, "requests from web pages are not accepted".to_string()));
+        }
+        let token = request.header("authorization").and_then(|value| value.strip_prefix("Bearer ")).unwrap_or_default();
+        // compared in full so the time taken says nothing about the token
+        let differs = token.len() != self.token.len() || token.bytes().zip(self.token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0;
+        if differs {
+            return Err(Failure(401, "missing or wrong bearer token (gentor serve prints it at startup)".to_string()));
+        }
+        let content_type = request.header("content-type").and_then(|value| value.split(';').next()).map(str::trim);
+        if request.method == "POST" && !content_type.is_some_and(|t| t.eq_ignore_ascii_case("application/json")) {
+            return Err(Failure(415, "POST bodies must be sent as application/json".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn route(&self, method: &str, path: &str, body: &[u8]) -> Result<Value, Failure> {
+        let path = path.split('?').next().unwrap_or_default().trim_end_matches('/');
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["v1", "models"]) => Ok(self.models()),
+            ("POST", ["v1", "chat", "completions"]) => self.chat_completions(&parse_json(body)?).await,
+            ("GET", ["sessions"]) => Ok(sessions()),
+            ("POST", ["sessions"]) => self.create_session().await,
+            ("GET", ["sessions", id]) => Ok(serde_json::to_value(Session::load(&session_path(id)?).map_err(not_found)?).map_err(anyhow::Error::from)?),
+            ("POST", ["sessions", id, "messages"]) => self.session_message(id, &parse_json(body)?).await,
+            (_, ["v1", "models"] | ["v1", "chat", "completions"] | ["sessions", ..]) => Err(Failure(405, format!("{} is not allowed here", method))),
+            _ => Err(Failure(404, format!("no such endpoint: {}", path))),
+        }
+    }
+
+    fn models(&self) -> Value {
+        // the configured model first, then the others once each
+        let mut others: Vec<&String> = self.settings.fallbacks.iter().map(|f| &f.model).chain(self.settings.personas.iter().filter_map(|p| p.model.as_ref())).collect();
+        others.retain(|m| !m.is_empty() && **m != self.settings.model);
+        others.sort();
+        others.dedup();
+        let data: Vec<Value> = iter::once(&self.settings.model)
+            .chain(others)
+            .map(|id| json!({ "id": id, "object": "model", "owned_by": self.settings.provider }))
+            .collect();
+        json!({ "object": "list", "data": data })
+    }
+
+    /// `messages` become the history and the prompt, see [`fold_messages`];
+    /// system messages replace the system prompt.
+    async fn chat_completions(&self, request: &Value) -> Result<Value, Failure> {
+        if request["stream"].as_bool() == Some(true) {
+            return Err(bad_request("streaming is not supported"));
+        }
+        let messages = request["messages"].as_array().ok_or_else(|| bad_request("\"messages\" must be an array"))?;
+        let (system, history, prompt) = fold_messages(messages)?;
+        let mut settings = self.settings.clone();
+        if !system.is_empty() {
+            settings.system_prompt = Some(system.join("\n\n"));
+        }
+        if let Some(model) = request["model"].as_str().filter(|m| !m.is_empty()) {
+            settings.model = model.to_string();
+        }
+        let reply = self.answer(&settings, None, &history, &prompt).await?;
+        Ok(json!({
+            "id": format!("chatcmpl-gentor-{}", now()),
+            "object": "chat.completion",
+            "created": now(),
+            "model": reply.model,
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": reply.content }, "finish_reason": "stop" }],
+            "usage": {
+                "prompt_tokens": reply.usage.prompt,
+                "completion_tokens": reply.usage.completion,
+                "total_tokens": reply.usage.prompt + reply.usage.completion,
+            },
+        }))
+    }
+
+    async fn create_session(&self) -> Result<Value, Failure> {
+        let _lock = self.sessions.lock().await;
+        let mut session = Session::new();
+        session.save()?;
+        Ok(json!({ "id": session_id(&session), "created": session.created }))
+    }
+
+    /// Answers `content` in a saved session and saves the turn, so it also
+    /// shows when the session is opened in the TUI.
+    async fn session_message(&self, id: &str, request: &Value) -> Result<Value, Failure> {
+        let content = request["content"].as_str().filter(|c| !c.trim().is_empty()).ok_or_else(|| bad_request("\"content\" must be a non-empty string"))?;
+        let _lock = self.sessions.lock().await;
+        let mut session = Session::load(&session_path(id)?).map_err(not_found)?;
+        let history = request_history(&self.settings, &session, content);
+        let reply = self.answer(&self.settings, session.summary.as_deref(), &history, content).await?;
+        session.conversation.push(Turn { user: content.to_string(), assistant: reply.content.clone(), pinned: false, speed: reply.speed() });
+        session.messages.push(Message::user(content, Vec::new()));
+        session.messages.push(Message::assistant(reply.content.trim(), &reply.model, reply.usage));
+        session.save()?;
+        Ok(json!({ "id": session_id(&session), "model": reply.model, "content": reply.content }))
+    }
+
+    /// Runs the agent with fallbacks. A used-up budget is a 429.
+    async fn answer(&self, settings: &Settings, summary: Option<&str>, history: &[Turn], prompt: &str) -> Result<Reply, Failure> {
+        match agent::run_with_fallbacks(&self.client, settings, &self.toolbox, summary, history, prompt, &Host).await {
+            Ok(reply) => Ok(reply),
+            Err(e) if e.downcast_ref::<stats::OverBudget>().is_some() => Err(Failure(429, e.to_string())),
+            Err(e) => Err(Failure(502, e.to_string())),
+        }
+    }
+}
+
+/// Reads the request line, headers and body.
+async fn read_request(stream: &mut (impl AsyncBufRead + Unpin)) -> Result<Request> {
+    let mut head = (&mut *stream).take(MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("malformed request line");
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    if head.limit() == 0 {
+        bail!("request headers over {} bytes", MAX_HEAD);
+    }
+    let request = Request { method, path, headers, body: Vec::new() };
+    let length = match request.header("content-length") {
+        Some(length) => length.parse().map_err(|_| anyhow!("invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        bail!("request body over {} bytes", MAX_BODY);
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await?;
+    Ok(Request { body, ..request })
+}
+
+/// Splits chat `messages` into the system messages, the turns and the
+/// prompt. Each user message answered by the assistant is a turn,
+/// consecutive user messages are joined, and the last must be the user's.
+fn fold_messages(messages: &[Value]) -> Result<(Vec<String>, Vec<Turn>, String), Failure> {
+    let mut system = Vec::new();
+    let mut history = Vec::new();
+    let mut pending: Option<String> = None;
+    for message in messages {
+        let content = content_text(&message["content"]);
+        match message["role"].as_str() {
+            Some("system" | "developer") => system.push(content),
+            Some("user") => {
+                pending = Some(match pending.take() {
+                    Some(earlier) => format!("{}\n\n{}", earlier, content),
+                    None => content,
+                })
+            }
+            Some("assistant") => match pending.take() {
+                Some(user) => history.push(Turn { user, assistant: content, pinned: false, speed: None }),
+                None => return Err(bad_request("an assistant message must follow a user message")),
+            },
+            role => return Err(bad_request(format!("unsupported role {}", role.map_or("(none)".to_string(), |r| format!("\"{}\"", r))))),
+        }
+    }
+    let prompt = pending.ok_or_else(|| bad_request("the last message must be from the user"))?;
+    Ok((system, history, prompt))
+}
+
+/// The turns of `session` sent along with `content`: the oldest unpinned
+/// ones are left out to fit the context window, as in the TUI.
+fn request_history(settings: &Settings, session: &Session, content: &str) -> Vec<Turn> {
+    let dropped = agent::turns_to_drop(settings, session.summary.as_deref(), &session.conversation, content);
+    agent::drop_oldest(&session.conversation, dropped).0
+}
+
+/// A random token for this run of the server.
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| anyhow!("could not generate a token"))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn parse_json(body: &[u8]) -> Result<Value, Failure> {
+    serde_json::from_slice(body).map_err(|e| bad_request(format!("invalid JSON: {}", e)))
+}
+
+/// Text of a message's content, a string or an array of content parts.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|part| part["text"].as_str()).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn sessions() -> Value {
+    let listed: Vec<Value> = session::list()
+        .iter()
+        .filter_map(|(path, name, created)| Some(json!({ "id": path.file_stem()?.to_str()?, "name": name, "created": created })))
+        .collect();
+    json!({ "sessions": listed })
+}
+
+/// Session ids are file names without `.json`; anything that could leave
+/// the sessions directory is rejected.
+fn session_path(id: &str) -> Result<PathBuf, Failure> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        return Err(Failure(404, format!("no such session: {}", id)));
+    }
+    let dir = session::session_dir().ok_or_else(|| anyhow!("cannot locate the config directory"))?;
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+fn session_id(session: &Session) -> String {
+    session.path().and_then(|p| p.file_stem()).map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn not_found(e: anyhow::Error) -> Failure {
+    Failure(404, format!("no such session ({})", e))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn error_type(status: u16) -> &'static str {
+    match status {
+        400 | 404 | 405 | 408 | 415 => "invalid_request_error",
+        401 | 403 => "authentication_error",
+        429 => "insufficient_quota",
+        _ => "api_error",
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        502 => "Bad Gateway",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        let settings = Settings::defaults();
+        Server {
+            client: agent::client_for(&settings),
+            toolbox: Toolbox::new(settings.web_search.clone(), settings.sandbox.clone(), settings.github.clone()),
+            settings,
+            port: DEFAULT_PORT,
+            token: "secret".to_string(),
+            sessions: Mutex::new(()),
+        }
+    }
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        Request { method: method.to_string(), path: "/v1/models".to_string(), headers, body: Vec::new() }
+    }
+
+    fn status(result: Result<impl Sized, Failure>) -> u16 {
+        result.map_or_else(|Failure(status, _)| status, |_| 200)
+    }
+
+    #[test]
+    fn only_local_token_holders_are_admitted() {
+        let server = server();
+        let host = ("host", "127.0.0.1:8787");
+        let token = ("authorization", "Bearer secret");
+        let json = ("content-type", "application/json; charset=utf-8");
+        assert_eq!(status(server.admit(&request("GET", &[host, token]))), 200);
+        assert_eq!(status(server.admit(&request("GET", &[("host", "LOCALHOST:8787"), token]))), 200);
+        assert_eq!(status(server.admit(&request("POST", &[host, token, json]))), 200);
+        // rebinding leaves the attacker's name in Host
+        assert_eq!(status(server.admit(&request("GET", &[("host", "evil.example:8787"), token]))), 403);
+        assert_eq!(status(server.admit(&request("GET", &[("host", "127.0.0.1:9999"), token]))), 403);
+        assert_eq!(status(server.admit(&request("GET", &[token]))), 403);
+        assert_eq!(status(server.admit(&request("GET", &[host, token, ("origin", "http://127.0.0.1:8787")]))), 403);
+        assert_eq!(status(server.admit(&request("GET", &[host]))), 401);
+        assert_eq!(status(server.admit(&request("GET", &[host, ("authorization", "Bearer secreT")]))), 401);
+        assert_eq!(status(server.admit(&request("GET", &[host, ("authorization", "Bearer secret2")]))), 401);
+        assert_eq!(status(server.admit(&request("POST", &[host, token, ("content-type", "text/plain")]))), 415);
+        assert_eq!(status(server.admit(&request("POST", &[host, token]))), 415);
+    }
+
+    #[test]
+    fn each_model_is_listed_once() {
+        let mut server = server();
+        server.settings.model = "gpt-4o".to_string();
+        server.settings.fallbacks = serde_json::from_value(json!([{ "model": "o3" }, { "model": "gpt-4o" }, { "model": "claude-sonnet-4" }])).unwrap();
+        server.settings.personas = serde_json::from_value(json!([{ "name": "a", "model": "o3" }, { "name": "b" }, { "name": "c", "model": "claude-sonnet-4" }])).unwrap();
+        let models = server.models();
+        let ids: Vec<&str> = models["data"].as_array().unwrap().iter().filter_map(|m| m["id"].as_str()).collect();
+        assert_eq!(ids, ["gpt-4o", "claude-sonnet-4", "o3"]);
+    }
+
+    #[tokio::test]
+    async fn requests_are_routed_by_method_and_path() {
+        let server = server();
+        let models = server.route("GET", "/v1/models/?x=1", b"").await.ok().unwrap();
+        assert_eq!(models["data"][0]["id"], server.settings.model.as_str());
+        assert_eq!(status(server.route("GET", "/v2/models", b"").await), 404);
+        assert_eq!(status(server.route("DELETE", "/v1/models", b"").await), 405);
+        assert_eq!(status(server.route("GET", "/v1/chat/completions", b"").await), 405);
+        assert_eq!(status(server.route("POST", "/v1/chat/completions", b"{").await), 400);
+        let streaming = br#"{"stream": true, "messages": [{"role": "user", "content": "hi"}]}"#;
+        assert_eq!(status(server.route("POST", "/v1/chat/completions", streaming).await), 400);
+        assert_eq!(status(server.route("GET", "/sessions/..", b"").await), 404);
+    }
+
+    #[test]
+    fn messages_fold_into_turns_and_a_prompt() {
+        let messages = json!([
+            { "role": "system", "content": "Be brief." },
+            { "role": "user", "content": "one" },
+            { "role": "assistant", "content": "1" },
+            { "role": "developer", "content": [{ "type": "text", "text": "No prose." }] },
+            { "role": "user", "content": "two" },
+            { "role": "user", "content": [{ "type": "text", "text": "three" }, { "type": "image_url" }] },
+        ]);
+        let (system, history, prompt) = fold_messages(messages.as_array().unwrap()).ok().unwrap();
+        assert_eq!(system, ["Be brief.", "No prose."]);
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].user.as_str(), history[0].assistant.as_str()), ("one", "1"));
+        assert_eq!(prompt, "two\n\nthree");
+
+        let fold = |messages: Value| status(fold_messages(messages.as_array().unwrap()));
+        assert_eq!(fold(json!([{ "role": "assistant", "content": "hi" }])), 400);
+        assert_eq!(fold(json!([{ "role": "user", "content": "hi" }, { "role": "assistant", "content": "hello" }])), 400);
+        assert_eq!(fold(json!([{ "role": "tool", "content": "{}" }, { "role": "user", "content": "hi" }])), 400);
+        assert_eq!(fold(json!([])), 400);
+    }
+
+    #[test]
+    fn session_ids_cannot_leave_the_sessions_dir() {
+        for id in ["", ".", "..", ".hidden", "a/b", "../b", "..\\b", "a\\b"] {
+            assert_eq!(status(session_path(id)), 404, "{}", id);
+        }
+        let path = session_path("1700000000").ok().unwrap();
+        assert_eq!(path.file_name().unwrap(), "1700000000.json");
+        assert_eq!(path.parent(), session::session_dir().as_deref());
+    }
+
+    #[test]
+    fn session_history_keeps_pinned_turns() {
+        let turn = |user: &str, pinned| Turn { user: user.to_string(), assistant: "ok".to_string(), pinned, speed: None };
+        let (first, long, last) = (turn("keep me", true), turn(&"word ".repeat(1000), false), turn("recent", false));
+        let mut settings = Settings::defaults();
+        settings.max_tokens = Some(10);
+        let fits = agent::prompt_tokens(&settings, None, &[first.clone(), last.clone()], "hi") + 10;
+        settings.context_window = Some(fits as u32 + 5);
+        let mut session = Session::new();
+        session.conversation = vec![first, long, last];
+        let history = request_history(&settings, &session, "hi");
+        let users: Vec<&str> = history.iter().map(|t| t.user.as_str()).collect();
+        assert_eq!(users, ["keep me", "recent"]);
+    }
+
+    #[tokio::test]
+    async fn requests_are_read_up_to_their_length() {
+        let mut raw: &[u8] = b"POST /v1/chat/completions HTTP/1.1\r\nHost: 127.0.0.1:8787\r\nContent-Length: 2\r\n\r\n{}trailing";
+        let request = read_request(&mut raw).await.unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/v1/chat/completions"));
+        assert_eq!(request.header("host"), Some("127.0.0.1:8787"));
+        assert_eq!(request.body, b"{}");
+
+        let endless = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(MAX_HEAD as usize));
+        assert!(read_request(&mut endless.as_bytes()).await.is_err());
+        let oversized = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert!(read_request(&mut oversized.as_bytes()).await.is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Conversations saved under `~/.config/gentor/sessions/`, one JSON file each.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{agent::Turn, crypto, message::Message, settings};
+
+/// Longest file-name slug taken from a title.
+const MAX_SLUG: usize = 48;
+/// Sessions shown in the sidebar, newest first.
+const MAX_LISTED: usize = 50;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Session {
+    /// Unix time the session started; also the file name prefix.
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub conversation: Vec<Turn>,
+    /// The chat pane as it was when the session was last saved.
+    #[serde(default)]
+    pub messages: Vec<Message>,
+    /// The chat pane as lines, as sessions stored it before `messages`.
+    #[serde(default, skip_serializing)]
+    transcript: Vec<String>,
+    /// Bookmarked chat pane lines, found again by their text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<String>,
+    /// Unsent input, restored when the session is reopened.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub draft: String,
+    /// Prompts sent in this session, offered before the global history.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<String>,
+    #[serde(skip)]
+    saved_as: Option<PathBuf>,
+}
+
+pub fn session_dir() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("sessions"))
+}
+
+/// Recent sessions as `(file, display name, created)`, newest first.
+pub fn list() -> Vec<(PathBuf, String, u64)> {
+    let Some(entries) = session_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    // file names start with the creation time, so this orders by age
+    paths.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let session = Session::load(&path).ok()?;
+            Some((path, session.display_name(), session.created))
+        })
+        .take(MAX_LISTED)
+        .collect()
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Self { created, ..Self::default() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut session: Self = serde_json::from_str(&crypto::read_to_string(path)?)?;
+        let created = session.created;
+        session.messages.extend(session.transcript.drain(..).map(|line| Message::from_line(&line, created)));
+        session.saved_as = Some(path.to_path_buf());
+        Ok(session)
+    }
+
+    /// File the session was last saved to or loaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.saved_as.as_deref()
+    }
+
+    /// Title, or the start time for sessions not titled yet.
+    pub fn display_name(&self) -> String {
+        self.title.clone().unwrap_or_else(|| timestamp(self.created))
+    }
+
+    fn file_stem(&self) -> String {
+        match self.title.as_deref().map(slug).filter(|s| !s.is_empty()) {
+            Some(slug) => format!("{}-{}", self.created, slug),
+            None => self.created.to_string(),
+        }
+    }
+
+    /// Whether `path` is this session's file name, or that name with the
+    /// number [`Session::save`] adds when it was taken.
+    fn named(&self, path: &Path) -> bool {
+        let stem = self.file_stem();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".json")) else {
+            return false;
+        };
+        match name.strip_prefix(stem.as_str()) {
+            Some("") => true,
+            Some(rest) => rest.strip_prefix('-').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())),
+            None => false,
+        }
+    }
+
+    /// Writes the session, renaming its file when the title changed.
+    pub fn save(&mut self) -> Result<()> {
+        let dir = session_dir().ok_or_else(|| anyhow::anyhow!("cannot locate the config directory"))?;
+        self.save_in(&dir)
+    }
+
+    /// Sessions started in the same second would share a name, so a new
+    /// name is claimed with `create_new`, numbered from 2 while taken.
+    fn save_in(&mut self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let path = match self.saved_as.clone().filter(|old| old.parent() == Some(dir) && self.named(old)) {
+            Some(path) => path,
+            None => claim(dir, &self.file_stem())?,
+        };
+        crypto::write(&path, serde_json::to_string_pretty(self)?)?;
+        if let Some(old) = self.saved_as.replace(path.clone()).filter(|old| *old != path) {
+            let _ = fs::remove_file(old);
+        }
+        Ok(())
+    }
+}
+
+/// Creates the first free `<stem>.json`, `<stem>-2.json`, ... in `dir`.
+fn claim(dir: &Path, stem: &str) -> Result<PathBuf> {
+    for n in 1.. {
+        let name = if n == 1 { format!("{}.json", stem) } else { format!("{}-{}.json", stem, n) };
+        let path = dir.join(name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!()
+}
+
+/// Lowercase words joined with dashes, safe for any file system.
+fn slug(title: &str) -> String {
+    let words: Vec<String> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut slug = words.join("-");
+    if let Some((cut, _)) = slug.char_indices().nth(MAX_SLUG) {
+        slug.truncate(cut);
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC.
+pub fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let (hour, minute) = (secs % 86_400 / 3600, secs % 3600 / 60);
+    // civil-from-days, Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_safe_file_names() {
+        assert_eq!(slug("Fix the Parser: edge cases!"), "fix-the-parser-edge-cases");
+        assert_eq!(slug("Überprüfung der Ausgabe"), "überprüfung-der-ausgabe");
+        assert_eq!(slug("../../etc/passwd"), "etc-passwd");
+        assert_eq!(slug("!!!"), "");
+        let long = slug(&"word ".repeat(40));
+        assert!(long.chars().count() <= MAX_SLUG && !long.ends_with('-'), "{}", long);
+    }
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        assert_eq!(timestamp(0), "1970-01-01 00:00");
+        assert_eq!(timestamp(951_782_400), "2000-02-29 00:00");
+        assert_eq!(timestamp(1_790_000_000), "2026-09-21 14:13");
+    }
+
+    #[test]
+    fn titles_name_the_file() {
+        let mut session = Session { created: 1_700_000_000, ..Session::default() };
+        assert_eq!(session.file_stem(), "1700000000");
+        assert_eq!(session.display_name(), timestamp(1_700_000_000));
+        assert!(session.named(Path::new("1700000000-3.json")));
+        session.title = Some("Borrow checker help".to_string());
+        assert_eq!(session.file_stem(), "1700000000-borrow-checker-help");
+        assert!(!session.named(Path::new("1700000000.json")));
+    }
+
+    #[test]
+    fn sessions_started_together_get_their_own_files() {
+        let dir = std::env::temp_dir().join(format!("gentor-sessions-{}", std::process::id()));
+        let mut first = Session { created: 1_700_000_000, ..Session::default() };
+        let mut second = Session { created: 1_700_000_000, ..Session::default() };
+        first.save_in(&dir).unwrap();
+        second.save_in(&dir).unwrap();
+        assert_eq!(first.path().unwrap().file_name().unwrap(), "1700000000.json");
+        assert_eq!(second.path().unwrap().file_name().unwrap(), "1700000000-2.json");
+        second.draft = "still mine".to_string();
+        second.save_in(&dir).unwrap();
+        assert_eq!(second.path().unwrap().file_name().unwrap(), "1700000000-2.json");
+        assert_eq!(Session::load(first.path().unwrap()).unwrap().draft, "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
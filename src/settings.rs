@@ -0,0 +1,977 @@
+//! The user config: `config.toml` in the config directory, created on
+//! first run, with `GENTOR_*` variables and `--set` overrides applied.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use crate::{agent, crypto, github::GithubConfig, i18n, keyring, mcp::McpServerConfig, models::ModelOverride, sandbox::SandboxConfig, toml, web::WebSearchConfig};
+
+/// The user config, TOML.
+pub const CONFIG_FILE: &str = "config.toml";
+/// The user config of earlier versions, still read when there is no `config.toml`.
+pub const SETTINGS_FILE: &str = "settings.json";
+/// Written on first run: the essentials, and the tables commented out.
+const CONFIG_TEMPLATE: &str = r#"# Gentor configuration. Every key of the settings screen can be set here;
+# see the README for the full list. GENTOR_<KEY> environment variables and
+# --set <key>=<value> override it for one run.
+version = 2
+
+provider = "openai"
+model = "gpt-4o-mini"
+api_key = "sk-your-api-key"
+base_url = "https://api.openai.com/v1"
+
+# Endpoint and key per provider, used when the keys above are left out.
+# [providers.openai]
+# base_url = "https://api.openai.com/v1"
+# api_key = "sk-..."
+# api_keys = ["sk-second...", "sk-third..."]
+# organization = "org-..."
+# project = "proj_..."
+#
+# [providers.ollama]
+# base_url = "http://localhost:11434/v1"
+# model = "qwen2.5-coder"
+#
+# [providers.gateway.extra_headers]
+# cf-aig-authorization = "Bearer ..."
+
+# Named sets of settings, picked with `profile = "..."` or --profile.
+# [profiles.fast]
+# model = "gpt-4o-mini"
+# temperature = 0.2
+
+# Chat shortcuts: history_search, sidebar, context, reasoning, help, palette, collapse.
+# [keybindings]
+# sidebar = "ctrl+g"
+"#;
+/// Prefix marking an `api_key` that lives in the OS keyring under the given profile name.
+const KEYRING_PREFIX: &str = "keyring:";
+/// `GENTOR_MODEL=gpt-4o` sets `model`, and so on for every key.
+pub const ENV_PREFIX: &str = "GENTOR_";
+/// Backends the settings screen accepts as `provider` besides the config's
+/// `[providers.<name>]` entries.
+pub const PROVIDERS: &[&str] = &["openai", "anthropic", "openrouter", "groq", "mistral", "deepseek", "together", "ollama", "lmstudio", "mock"];
+/// Layout of the user config this build writes.
+pub const VERSION: u32 = 2;
+/// Keys renamed between versions, as `(version, old, new)`: files older
+/// than `version` have `old` moved to `new` when loaded.
+const RENAMED: &[(u32, &str, &str)] = &[];
+
+/// Labels of the fields editable in the settings screen, in display order.
+pub const FIELDS: &[&str] = &[
+    "Provider",
+    "Model",
+    "API Key",
+    "Base URL",
+    "Temperature",
+    "Top P",
+    "Max Tokens",
+    "Context Window (tokens, empty to detect from the model)",
+    "Summarize Trimmed History (on/off)",
+    "Reasoning Effort (minimal/low/medium/high, reasoning models only)",
+    "Frequency Penalty",
+    "Presence Penalty",
+    "Stop Sequences (comma-separated, \\n for newline)",
+    "Response Format (empty, json_object, or json_schema:<file>)",
+    "Project Tree in Prompts (on/off)",
+    "Max Attachment Size (KB, default 256)",
+    "Attach Binary Files (on/off)",
+    "Hide Reasoning Traces (on/off)",
+    "Cache Identical Requests (on/off)",
+    "Local Only (on/off)",
+    "Fix Loop Attempts (default 5)",
+    "Vim Keybindings (on/off)",
+    "Collapse Replies Longer Than (lines, default 40, 0 for never)",
+    "Language (en/ko, empty for LANG)",
+    "Daily Budget (USD, empty for none)",
+    "Monthly Budget (USD, empty for none)",
+    "Desktop Notifications (on/off)",
+];
+pub const API_KEY_FIELD: usize = 2;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Settings {
+    /// Layout version of the file, see [`VERSION`].
+    #[serde(default)]
+    pub version: u32,
+    pub provider: String,
+    pub model: String,
+    pub api_key: String,
+    /// More keys for the provider, moved on to in turn after `api_key` when
+    /// one is rate limited or out of quota.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+    pub base_url: String,
+    /// OpenAI organization requests are billed to, for keys in several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    /// OpenAI project requests are billed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// Replaces turns trimmed from the context with a model-written summary.
+    #[serde(default)]
+    pub summarize_history: bool,
+    /// Sent to OpenAI reasoning models (o-series, gpt-5) only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// `json_object` or `json_schema:<path to schema file>`; plain text when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+    /// Prepends a shallow project listing to every prompt.
+    #[serde(default)]
+    pub auto_tree: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_attach_kb: Option<u64>,
+    #[serde(default)]
+    pub allow_binary: bool,
+    #[serde(default)]
+    pub hide_reasoning: bool,
+    /// Answers repeated identical requests from `~/.config/gentor/cache/`.
+    #[serde(default)]
+    pub response_cache: bool,
+    /// Refuses non-loopback endpoints and disables the web tools.
+    #[serde(default)]
+    pub local_only: bool,
+    /// Most fixes `/cargo` asks the model for before giving up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix_attempts: Option<u32>,
+    /// Modal editing in the input box and vim motions in the chat pane.
+    #[serde(default)]
+    pub vim_mode: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collapse_lines: Option<usize>,
+    /// UI locale code; the environment's `LANG` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Estimated spend in USD after which requests are refused until `/budget override`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_budget: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_budget: Option<f64>,
+    /// A desktop notification, besides the bell, when a long answer finishes unwatched.
+    #[serde(default)]
+    pub desktop_notifications: bool,
+    /// Language server `/explain` starts, with its arguments; rust-analyzer when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lsp_command: Vec<String>,
+    /// MCP servers to connect to at startup; only editable in the file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_servers: Vec<McpServerConfig>,
+    /// WASI runtime CLI used to run plugins; `wasmtime` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm_runtime: Option<String>,
+    /// JSON script replayed by `provider = "mock"`; canned replies when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_script: Option<String>,
+    /// Enables the `run_code` tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+    /// Enables the `web_search` tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_search: Option<WebSearchConfig>,
+    /// Enables the `get_issue`, `get_pr_diff` and `comment` tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github: Option<GithubConfig>,
+    /// Models tried in order when the configured one fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<agent::FallbackConfig>,
+    /// Additions and corrections to the built-in model registry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<ModelOverride>,
+    /// Named system prompt, model and temperature presets for `/persona`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub personas: Vec<agent::Persona>,
+    /// Endpoint, key, model and extra headers for each provider name,
+    /// filling in those the other layers leave unset.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// HTTP headers sent with every request to the provider, for gateways
+    /// and proxies that want their own.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_headers: BTreeMap<String, String>,
+    /// The entry of `profiles` applied over the rest of the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Map<String, Value>>,
+    /// Chat shortcuts by action, see [`crate::keymap`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keybindings: BTreeMap<String, String>,
+    /// Set per request from the active persona, never stored.
+    #[serde(skip)]
+    pub system_prompt: Option<String>,
+    /// Set per request while `/debug` is on: where requests and raw responses are logged.
+    #[serde(skip)]
+    pub debug_log: Option<PathBuf>,
+    /// Set per request after `/budget override`: the budgets refuse nothing.
+    #[serde(skip)]
+    pub budget_override: bool,
+    /// Where each key's value came from.
+    #[serde(skip)]
+    pub origins: BTreeMap<String, Origin>,
+    /// For each key not set by the config file itself, the file's own value
+    /// (`None` when unset) and the value loaded. While the key still has the
+    /// loaded value, saving writes back the file's own.
+    #[serde(skip)]
+    pub overridden: BTreeMap<String, (Option<Value>, Value)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProviderConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+/// The source of a setting, in increasing precedence: built-in defaults,
+/// the config file, its active profile, the `[settings]` of `.gentor.toml`,
+/// the environment and the command line. The active provider's entry
+/// fills in what none of them set.
+#[derive(Clone, PartialEq)]
+pub enum Origin {
+    Default,
+    User,
+    Profile(String),
+    Project(PathBuf),
+    Env(String),
+    Cli(String),
+    Provider(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::User => write!(f, "{}", user_file().display()),
+            Origin::Profile(name) => write!(f, "{} (profile {})", user_file().display(), name),
+            Origin::Provider(name) => write!(f, "{} (providers.{})", user_file().display(), name),
+            Origin::Project(path) => write!(f, "{}", path.display()),
+            Origin::Env(name) => write!(f, "environment ({})", name),
+            Origin::Cli(flag) => write!(f, "command line ({})", flag),
+        }
+    }
+}
+
+/// One key set above the config file. Values from the environment and the
+/// command line are given as strings and read as JSON unless the setting
+/// holds a string (`GENTOR_TEMPERATURE=0.2`, `GENTOR_MODEL=gpt-4o`).
+pub struct Override {
+    pub key: String,
+    pub value: Value,
+    pub origin: Origin,
+}
+
+/// Overrides from `GENTOR_*` variables.
+pub fn env_overrides() -> Vec<Override> {
+    let mut overrides: Vec<Override> = env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+            Some(Override { key, value: Value::String(value), origin: Origin::Env(name) })
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.key.cmp(&b.key));
+    overrides
+}
+
+fn coerce(text: &str, current: Option<&Value>) -> Value {
+    match current {
+        Some(Value::String(_)) => Value::String(text.to_string()),
+        _ => serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())),
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Edited fields of the settings screen that cannot be saved, each with
+/// why, by index into [`FIELDS`].
+#[derive(Debug)]
+pub struct FieldErrors(pub BTreeMap<usize, String>);
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<&str> = self.0.values().map(String::as_str).collect();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for FieldErrors {}
+
+/// The value of field `index`, or its default with the error kept in `errors`.
+fn field<T: Default>(errors: &mut BTreeMap<usize, String>, index: usize, result: Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        errors.insert(index, e.to_string());
+        T::default()
+    })
+}
+
+fn parse_optional<T: std::str::FromStr>(label: &str, value: &str) -> Result<Option<T>> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value.parse().map(Some).map_err(|_| anyhow::anyhow!("{} must be a number, got '{}'", label, value))
+}
+
+/// Renders stop sequences for the settings field, escaping what the separator and the field cannot hold.
+pub fn format_stop(stop: &[String]) -> String {
+    stop.iter()
+        .map(|s| s.replace('\\', "\\\\").replace(',', "\\,").replace('\n', "\\n").replace('\t', "\\t"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a comma-separated list of stop sequences, honouring `\n`, `\t`, `\,` and `\\` escapes.
+pub fn parse_stop(value: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => current.push('\n'),
+                Some('t') => current.push('\t'),
+                Some(other) => current.push(other),
+                None => current.push('\\'),
+            },
+            ',' => out.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    out.push(current);
+    out.into_iter()
+        .map(|s| s.trim_matches(' ').to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn parse_base_url(value: &str) -> Result<String> {
+    match reqwest::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(value.to_string()),
+        Ok(_) => Err(anyhow::anyhow!("Base URL must be http or https, got '{}'", value)),
+        Err(e) => Err(anyhow::anyhow!("Base URL is not a URL: {}", e)),
+    }
+}
+
+fn parse_switch(label: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" | "" => Ok(false),
+        other => Err(anyhow::anyhow!("{} must be on or off, got '{}'", label, other)),
+    }
+}
+
+impl Settings {
+    /// `api_key` and then `api_keys`, without blanks or repeats.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = Vec::new();
+        for key in std::iter::once(&self.api_key).chain(&self.api_keys) {
+            if !key.is_empty() && !keys.contains(&key.as_str()) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// Every API key in the settings as `(top-level key, keyring account,
+    /// key)`; extra keys are numbered after the first.
+    fn secrets_mut(&mut self) -> Vec<(&'static str, String, &mut String)> {
+        let mut secrets = vec![("api_key", self.provider.clone(), &mut self.api_key)];
+        for (i, key) in self.api_keys.iter_mut().enumerate() {
+            secrets.push(("api_keys", format!("{}#{}", self.provider, i + 2), key));
+        }
+        for (name, provider) in self.providers.iter_mut() {
+            if let Some(key) = provider.api_key.as_mut() {
+                secrets.push(("providers", format!("providers.{}", name), key));
+            }
+            for (i, key) in provider.api_keys.iter_mut().enumerate() {
+                secrets.push(("providers", format!("providers.{}#{}", name, i + 2), key));
+            }
+        }
+        secrets
+    }
+
+    /// Field values as edited in the settings screen, matching [`FIELDS`].
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.provider.clone(),
+            self.model.clone(),
+            self.api_key.clone(),
+            self.base_url.clone(),
+            optional(self.temperature),
+            optional(self.top_p),
+            optional(self.max_tokens),
+            optional(self.context_window),
+            if self.summarize_history { "on" } else { "off" }.to_string(),
+            self.reasoning_effort.clone().unwrap_or_default(),
+            optional(self.frequency_penalty),
+            optional(self.presence_penalty),
+            format_stop(&self.stop),
+            self.response_format.clone().unwrap_or_default(),
+            if self.auto_tree { "on" } else { "off" }.to_string(),
+            optional(self.max_attach_kb),
+            if self.allow_binary { "on" } else { "off" }.to_string(),
+            if self.hide_reasoning { "on" } else { "off" }.to_string(),
+            if self.response_cache { "on" } else { "off" }.to_string(),
+            if self.local_only { "on" } else { "off" }.to_string(),
+            optional(self.fix_attempts),
+            if self.vim_mode { "on" } else { "off" }.to_string(),
+            optional(self.collapse_lines),
+            self.language.clone().unwrap_or_default(),
+            optional(self.daily_budget),
+            optional(self.monthly_budget),
+            if self.desktop_notifications { "on" } else { "off" }.to_string(),
+        ]
+    }
+
+    /// Builds settings from edited field values, keeping anything the
+    /// settings screen does not show from `self`. Empty optional fields unset
+    /// the value. Fails with [`FieldErrors`] for values that do not parse and
+    /// for an unknown provider, an empty model or a base URL that is not one,
+    /// which would only fail once a request is sent.
+    pub fn with_fields(&self, fields: &[String]) -> Result<Self> {
+        let mut settings = self.clone();
+        let mut errors = BTreeMap::new();
+        let errors = &mut errors;
+        settings.provider = field(errors, 0, self.known_provider(fields[0].trim()));
+        settings.model = fields[1].trim().to_string();
+        if settings.model.is_empty() {
+            errors.insert(1, "Model must not be empty".to_string());
+        }
+        settings.api_key = fields[2].clone();
+        settings.base_url = field(errors, 3, parse_base_url(fields[3].trim()));
+        settings.temperature = field(errors, 4, parse_optional(FIELDS[4], &fields[4]));
+        settings.top_p = field(errors, 5, parse_optional(FIELDS[5], &fields[5]));
+        settings.max_tokens = field(errors, 6, parse_optional(FIELDS[6], &fields[6]));
+        settings.context_window = field(errors, 7, parse_optional(FIELDS[7], &fields[7]));
+        settings.summarize_history = field(errors, 8, parse_switch(FIELDS[8], &fields[8]));
+        settings.reasoning_effort = Some(fields[9].trim().to_ascii_lowercase()).filter(|e| !e.is_empty());
+        if let Some(effort) = &settings.reasoning_effort {
+            field(errors, 9, agent::parse_reasoning_effort(effort).map(|_| ()));
+        }
+        settings.frequency_penalty = field(errors, 10, parse_optional(FIELDS[10], &fields[10]));
+        settings.presence_penalty = field(errors, 11, parse_optional(FIELDS[11], &fields[11]));
+        settings.stop = parse_stop(&fields[12]);
+        settings.response_format = Some(fields[13].trim().to_string()).filter(|f| !f.is_empty());
+        settings.auto_tree = field(errors, 14, parse_switch(FIELDS[14], &fields[14]));
+        settings.max_attach_kb = field(errors, 15, parse_optional(FIELDS[15], &fields[15]));
+        settings.allow_binary = field(errors, 16, parse_switch(FIELDS[16], &fields[16]));
+        settings.hide_reasoning = field(errors, 17, parse_switch(FIELDS[17], &fields[17]));
+        settings.response_cache = field(errors, 18, parse_switch(FIELDS[18], &fields[18]));
+        settings.local_only = field(errors, 19, parse_switch(FIELDS[19], &fields[19]));
+        settings.fix_attempts = field(errors, 20, parse_optional(FIELDS[20], &fields[20]));
+        settings.vim_mode = field(errors, 21, parse_switch(FIELDS[21], &fields[21]));
+        settings.collapse_lines = field(errors, 22, parse_optional(FIELDS[22], &fields[22]));
+        settings.language = Some(fields[23].trim().to_ascii_lowercase()).filter(|l| !l.is_empty());
+        settings.daily_budget = field(errors, 24, parse_optional(FIELDS[24], &fields[24]));
+        settings.monthly_budget = field(errors, 25, parse_optional(FIELDS[25], &fields[25]));
+        settings.desktop_notifications = field(errors, 26, parse_switch(FIELDS[26], &fields[26]));
+        if !errors.is_empty() {
+            return Err(FieldErrors(std::mem::take(errors)).into());
+        }
+        Ok(settings)
+    }
+
+    /// `provider` if it is a built-in backend or has a `[providers.<name>]`
+    /// entry. The one already in use passes too, as a base URL may make it work.
+    fn known_provider(&self, provider: &str) -> Result<String> {
+        if PROVIDERS.contains(&provider) || self.providers.contains_key(provider) || provider == self.provider {
+            return Ok(provider.to_string());
+        }
+        let mut known: Vec<&str> = PROVIDERS.to_vec();
+        known.extend(self.providers.keys().map(String::as_str).filter(|name| !PROVIDERS.contains(name)));
+        Err(anyhow::anyhow!("Provider '{}' is not known; use one of {} or add a [providers.{}] entry", provider, known.join(", "), provider))
+    }
+
+    /// Built-in values of the keys the config file may leave out.
+    pub fn defaults() -> Self {
+        Settings {
+            version: VERSION,
+            provider: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_key: String::new(),
+            api_keys: Vec::new(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            organization: None,
+            project: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            context_window: None,
+            summarize_history: false,
+            reasoning_effort: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: Vec::new(),
+            response_format: None,
+            auto_tree: false,
+            max_attach_kb: None,
+            allow_binary: false,
+            hide_reasoning: false,
+            response_cache: false,
+            local_only: false,
+            fix_attempts: None,
+            vim_mode: false,
+            collapse_lines: None,
+            language: None,
+            daily_budget: None,
+            monthly_budget: None,
+            desktop_notifications: false,
+            lsp_command: Vec::new(),
+            mcp_servers: Vec::new(),
+            wasm_runtime: None,
+            mock_script: None,
+            sandbox: None,
+            web_search: None,
+            github: None,
+            fallbacks: Vec::new(),
+            models: Vec::new(),
+            personas: Vec::new(),
+            providers: BTreeMap::new(),
+            extra_headers: BTreeMap::new(),
+            profile: None,
+            profiles: BTreeMap::new(),
+            keybindings: BTreeMap::new(),
+            system_prompt: None,
+            debug_log: None,
+            budget_override: false,
+            origins: BTreeMap::new(),
+            overridden: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the config file, decrypting it with `passphrase` and bringing
+    /// an older layout up to date, applies its active profile and then
+    /// `overrides` over it in order, fills what is still unset from the
+    /// active provider's entry and resolves a keyring reference into the
+    /// actual key. Values of the wrong type are left at their defaults.
+    /// Returns warnings about those, the upgrade and a keyring reference
+    /// that cannot be resolved.
+    pub fn load(passphrase: Option<&str>, overrides: &[Override]) -> Result<(Self, Option<String>)> {
+        let file = user_file();
+        let mut user = read_user(passphrase)?;
+        let mut warnings = Vec::new();
+        let version = version(&user);
+        if version < VERSION {
+            migrate(&mut user, version);
+            let backup = format!("{}.v{}.bak", file.display(), version);
+            fs::copy(&file, &backup)?;
+            write(&user, passphrase)?;
+            warnings.push(format!("Upgraded {} to version {} (the old file is kept as {}).", file.display(), VERSION, backup));
+        } else if version > VERSION {
+            warnings.push(format!("⚠️ {} is version {}, newer than this build understands ({}); unknown keys are ignored.", file.display(), version, VERSION));
+        }
+        if !is_toml(&file) {
+            warnings.push(format!("{} is the old config format; `gentor config convert` turns it into {}.", SETTINGS_FILE, CONFIG_FILE));
+        }
+        let Value::Object(defaults) = serde_json::to_value(Self::defaults())? else { unreachable!("settings serialize to an object") };
+        let mut merged = defaults.clone();
+        let mut origins: BTreeMap<String, Origin> = merged.keys().map(|key| (key.clone(), Origin::Default)).collect();
+        let valid = |key: &String, value: &Value, source: &str, warnings: &mut Vec<String>| {
+            let mut probe = defaults.clone();
+            probe.insert(key.clone(), value.clone());
+            let result = serde_json::from_value::<Settings>(Value::Object(probe));
+            if let Err(e) = &result {
+                warnings.push(format!("⚠️ Ignored '{}' in {}: {}", key, source, e));
+            }
+            result.is_ok()
+        };
+        for (key, value) in &user {
+            if valid(key, value, &file.display().to_string(), &mut warnings) {
+                merged.insert(key.clone(), value.clone());
+                origins.insert(key.clone(), Origin::User);
+            }
+        }
+        let mut layered: Vec<(String, Value, Origin)> = Vec::new();
+        let profile = overrides.iter().rev().find(|o| o.key == "profile").map(|o| o.value.clone()).or_else(|| merged.get("profile").cloned());
+        if let Some(name) = profile.as_ref().and_then(Value::as_str) {
+            match merged.get("profiles").and_then(|profiles| profiles.get(name)).and_then(Value::as_object) {
+                Some(values) => {
+                    let source = format!("{} (profile {})", file.display(), name);
+                    for (key, value) in values {
+                        if valid(key, value, &source, &mut warnings) {
+                            layered.push((key.clone(), value.clone(), Origin::Profile(name.to_string())));
+                        }
+                    }
+                }
+                None => warnings.push(format!("⚠️ No profile named '{}' in {}.", name, file.display())),
+            }
+        }
+        for Override { key, value, origin } in overrides {
+            let value = match (value, origin) {
+                (Value::String(text), Origin::Env(_) | Origin::Cli(_)) => coerce(text, merged.get(key)),
+                _ => value.clone(),
+            };
+            layered.push((key.clone(), value, origin.clone()));
+        }
+        for (key, value, origin) in layered {
+            merged.insert(key.clone(), value);
+            origins.insert(key, origin);
+        }
+        if let Some(name) = merged.get("provider").and_then(Value::as_str).map(str::to_string) {
+            if let Some(Value::Object(entry)) = merged.get("providers").and_then(|providers| providers.get(&name)).cloned() {
+                for (key, value) in entry {
+                    // keys left out of the defaults, such as empty tables, are unset too
+                    if origins.get(&key).is_none_or(|origin| *origin == Origin::Default) {
+                        merged.insert(key.clone(), value);
+                        origins.insert(key, Origin::Provider(name.clone()));
+                    }
+                }
+            }
+        }
+        let overridden = origins
+            .iter()
+            .filter(|(_, origin)| !matches!(origin, Origin::Default | Origin::User))
+            .map(|(key, _)| (key.clone(), (user.get(key).cloned(), merged.get(key).cloned().unwrap_or_default())))
+            .collect();
+        let mut settings: Settings = serde_json::from_value(Value::Object(merged)).map_err(|e| {
+            let sources: Vec<String> = overrides.iter().map(|o| o.origin.to_string()).collect();
+            if sources.is_empty() {
+                anyhow::anyhow!("{}: {}", file.display(), e)
+            } else {
+                anyhow::anyhow!("{} with overrides from {}: {}", file.display(), sources.join(", "), e)
+            }
+        })?;
+        let Value::Object(known) = serde_json::to_value(&settings)? else { unreachable!("settings serialize to an object") };
+        if let Some(unknown) = overrides.iter().find(|o| matches!(o.origin, Origin::Cli(_)) && !known.contains_key(&o.key) && o.value != "null") {
+            anyhow::bail!("unknown setting '{}' ({})", unknown.key, unknown.origin);
+        }
+        settings.origins = origins;
+        settings.overridden = overridden;
+        for (_, _, key) in settings.secrets_mut() {
+            let Some(account) = key.strip_prefix(KEYRING_PREFIX).map(str::to_string) else { continue };
+            match keyring::get(&account) {
+                Ok(secret) => *key = secret,
+                Err(e) => {
+                    warnings.push(format!("⚠️ Could not read the API key from the keyring: {}", e));
+                    key.clear();
+                }
+            }
+        }
+        for name in settings.extra_headers.iter().filter(|(name, value)| agent::header(name, value).is_none()).map(|(name, _)| name) {
+            warnings.push(format!("⚠️ Ignored extra header '{}': not a valid HTTP header.", name));
+        }
+        Ok((settings, (!warnings.is_empty()).then(|| warnings.join("\n"))))
+    }
+
+    /// Writes the config file, encrypted when `passphrase` is set, moving the
+    /// API keys into the keyring when one is available. Keys set elsewhere
+    /// keep the file's own value unless changed since loading. Returns a
+    /// warning when the keys had to be stored in plaintext.
+    pub fn save(&self, passphrase: Option<&str>) -> Result<Option<String>> {
+        // through text, as `to_value` would widen f32s to 0.20000000298...
+        let Value::Object(current) = serde_json::from_str(&serde_json::to_string(self)?)? else { unreachable!("settings serialize to an object") };
+        let kept: Vec<(&String, &Option<Value>)> = self
+            .overridden
+            .iter()
+            .filter(|(key, (_, loaded))| current.get(*key).unwrap_or(&Value::Null) == loaded)
+            .map(|(key, (own, _))| (key, own))
+            .collect();
+        let mut stored = self.clone();
+        let mut warning = None;
+        for (field, account, key) in stored.secrets_mut() {
+            if key.is_empty() || key.starts_with(KEYRING_PREFIX) || kept.iter().any(|(kept, _)| *kept == field) {
+                continue;
+            }
+            match keyring::set(&account, key) {
+                Ok(()) => *key = format!("{}{}", KEYRING_PREFIX, account),
+                Err(e) => {
+                    warning = Some(format!("⚠️ No keyring available ({}), API keys saved in plaintext.", e));
+                    break;
+                }
+            }
+        }
+        let Value::Object(mut value) = serde_json::from_str(&serde_json::to_string(&stored)?)? else { unreachable!("settings serialize to an object") };
+        for (key, own) in kept {
+            match own {
+                Some(own) => value.insert(key.clone(), own.clone()),
+                None => value.remove(key),
+            };
+        }
+        value.insert("version".to_string(), Value::from(VERSION));
+        write(&value, passphrase)?;
+        Ok(warning)
+    }
+}
+
+/// `name` in [`config_dir`], or in the working directory without a home.
+fn config_path(name: &str) -> PathBuf {
+    config_dir().map_or_else(|| PathBuf::from(name), |dir| dir.join(name))
+}
+
+/// `config.toml` in [`config_dir`], or `settings.json` there when only that exists.
+pub fn user_file() -> PathBuf {
+    let (config, settings) = (config_path(CONFIG_FILE), config_path(SETTINGS_FILE));
+    if !config.exists() && settings.exists() {
+        settings
+    } else {
+        config
+    }
+}
+
+fn is_toml(file: &Path) -> bool {
+    file.extension().is_some_and(|extension| extension == "toml")
+}
+
+/// The text of `file`, decrypted with `passphrase` when encrypted.
+fn read_text(file: &Path, passphrase: Option<&str>) -> Result<String> {
+    let mut data = fs::read(file)?;
+    if crypto::is_encrypted(&data) {
+        let passphrase = passphrase.ok_or_else(|| anyhow::anyhow!("settings are encrypted but no passphrase was given"))?;
+        data = crypto::decrypt(passphrase, &data)?;
+    }
+    Ok(String::from_utf8(data)?)
+}
+
+fn parse(file: &Path, text: &str) -> Result<Map<String, Value>> {
+    let value = if is_toml(file) {
+        toml::parse(text).map_err(|e| anyhow::anyhow!("{} is not valid TOML: {}", file.display(), e))?
+    } else {
+        serde_json::from_str(text).map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", file.display(), e))?
+    };
+    match value {
+        Value::Object(map) => Ok(map),
+        _ => anyhow::bail!("{} does not hold a table of settings", file.display()),
+    }
+}
+
+fn read_user(passphrase: Option<&str>) -> Result<Map<String, Value>> {
+    let file = user_file();
+    parse(&file, &read_text(&file, passphrase)?)
+}
+
+/// Writes the config file in its format. A TOML file keeps its comments
+/// when only plain keys changed.
+fn write(value: &Map<String, Value>, passphrase: Option<&str>) -> Result<()> {
+    let file = user_file();
+    let text = if is_toml(&file) {
+        let old = read_text(&file, passphrase).ok();
+        old.and_then(|old| toml::update(&old, value)).unwrap_or_else(|| toml::to_string(value))
+    } else {
+        serde_json::to_string_pretty(value)?
+    };
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match passphrase {
+        Some(passphrase) => fs::write(&file, crypto::encrypt(passphrase, text.as_bytes())?)?,
+        None => fs::write(&file, text)?,
+    }
+    Ok(())
+}
+
+/// The layout of a config file; files without a `version` are version 1.
+fn version(user: &Map<String, Value>) -> u32 {
+    user.get("version").and_then(Value::as_u64).map_or(1, |v| v as u32)
+}
+
+/// Brings a file of layout `from` up to [`VERSION`]. Keys added since need
+/// no step: missing keys take their defaults when loaded.
+fn migrate(user: &mut Map<String, Value>, from: u32) {
+    for (version, old, new) in RENAMED {
+        if from < *version && !user.contains_key(*new) {
+            if let Some(value) = user.remove(*old) {
+                user.insert(new.to_string(), value);
+            }
+        }
+    }
+    user.insert("version".to_string(), Value::from(VERSION));
+}
+
+/// Rewrites `settings.json` as `config.toml`, encrypted again when it was,
+/// and keeps the old file as `settings.json.bak`.
+pub fn convert(passphrase: Option<&str>) -> Result<String> {
+    let (config, settings) = (config_path(CONFIG_FILE), config_path(SETTINGS_FILE));
+    if config.exists() {
+        anyhow::bail!("{} already exists", config.display());
+    }
+    if !settings.exists() {
+        anyhow::bail!("there is no {} to convert", settings.display());
+    }
+    let mut user = parse(&settings, &read_text(&settings, passphrase)?)?;
+    let from = version(&user);
+    migrate(&mut user, from);
+    let text = format!("# Gentor configuration, converted from {}.\n\n{}", SETTINGS_FILE, toml::to_string(&user));
+    match passphrase {
+        Some(passphrase) => fs::write(&config, crypto::encrypt(passphrase, text.as_bytes())?)?,
+        None => fs::write(&config, text)?,
+    }
+    let backup = settings.with_extension("json.bak");
+    fs::rename(&settings, &backup)?;
+    Ok(format!("✅ Wrote {} (the old file is kept as {}).", config.display(), backup.display()))
+}
+
+pub fn is_encrypted() -> bool {
+    fs::read(user_file()).is_ok_and(|data| crypto::is_encrypted(&data))
+}
+
+/// Whether `file` was written by Gentor: encrypted by it, or a JSON object
+/// with the keys every version wrote that loads as settings. Other tools
+/// use the name too.
+fn is_legacy_settings(file: &Path) -> bool {
+    let Ok(data) = fs::read(file) else {
+        return false;
+    };
+    if crypto::is_encrypted(&data) {
+        return true;
+    }
+    let Ok(Value::Object(user)) = serde_json::from_slice(&data) else {
+        return false;
+    };
+    if !["provider", "model", "api_key", "base_url"].iter().all(|key| user.get(*key).is_some_and(Value::is_string)) {
+        return false;
+    }
+    let Ok(Value::Object(mut merged)) = serde_json::to_value(Settings::defaults()) else {
+        return false;
+    };
+    merged.extend(user);
+    serde_json::from_value::<Settings>(Value::Object(merged)).is_ok()
+}
+
+/// The home directory given with [`set_home`], if any.
+static HOME: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Keeps everything Gentor stores below `home` instead of the user's home
+/// directory, as `GENTOR_HOME` does.
+pub fn set_home(home: PathBuf) {
+    *HOME.write().unwrap_or_else(|e| e.into_inner()) = Some(home);
+}
+
+/// The directory given with [`set_home`] or `GENTOR_HOME`, standing in for
+/// the home directory and `$XDG_CONFIG_HOME` alike.
+fn home_override() -> Option<PathBuf> {
+    let home = HOME.read().unwrap_or_else(|e| e.into_inner()).clone();
+    home.or_else(|| env::var_os("GENTOR_HOME").filter(|home| !home.is_empty()).map(PathBuf::from))
+}
+
+/// `$HOME`, unless another home was given with `GENTOR_HOME` or [`set_home`].
+pub fn home_dir() -> Option<PathBuf> {
+    home_override().or_else(|| env::var_os("HOME").map(PathBuf::from))
+}
+
+/// Gives the tests that use the home directory a scratch one, the same for
+/// the whole test run, and lets them use it one at a time, each starting
+/// with it empty. A test that panicked left its files behind, so they are
+/// cleared here rather than afterwards.
+#[cfg(test)]
+pub(crate) fn scratch_home() -> std::sync::MutexGuard<'static, ()> {
+    static SCRATCH: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let guard = SCRATCH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let home = env::temp_dir().join(format!("gentor-tests-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    set_home(home);
+    guard
+}
+
+/// `~/.config/gentor`, or under `$XDG_CONFIG_HOME` when set.
+pub fn config_dir() -> Option<PathBuf> {
+    match home_override() {
+        Some(home) => Some(home.join(".config").join("gentor")),
+        None => xdg_config_dir(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/gentor` when `xdg` is set, else under `home`. The XDG
+/// spec says a relative `$XDG_CONFIG_HOME` is to be ignored.
+fn xdg_config_dir(xdg: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let config = xdg
+        .map(PathBuf::from)
+        .filter(|xdg| xdg.is_absolute())
+        .or_else(|| home.map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("gentor"))
+}
+
+/// Creates the config file from the template on first run and exits, so
+/// it can be filled in. A `settings.json` that earlier versions kept in the
+/// working directory is copied into [`config_dir`] instead and left where
+/// it is.
+pub fn ensure_settings_file() -> Result<()> {
+    if config_path(CONFIG_FILE).exists() || config_path(SETTINGS_FILE).exists() {
+        return Ok(());
+    }
+    let file = config_path(CONFIG_FILE);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if config_dir().is_some() && is_legacy_settings(Path::new(SETTINGS_FILE)) {
+        let copied = config_path(SETTINGS_FILE);
+        fs::copy(SETTINGS_FILE, &copied)?;
+        println!("{}", i18n::fill("first_run.copied", &[&SETTINGS_FILE, &copied.display()]));
+        return Ok(());
+    }
+    println!("{}", i18n::t("first_run.creating"));
+    fs::write(&file, CONFIG_TEMPLATE)?;
+    println!("{}", i18n::fill("first_run.created", &[&file.display()]));
+    std::process::exit(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_config_dir_follows_xdg_config_home() {
+        let (xdg, home) = (Some(OsString::from("/xdg")), Some(OsString::from("/home/me")));
+        assert_eq!(xdg_config_dir(xdg.clone(), home.clone()), Some(PathBuf::from("/xdg/gentor")));
+        assert_eq!(xdg_config_dir(None, home.clone()), Some(PathBuf::from("/home/me/.config/gentor")));
+        assert_eq!(xdg_config_dir(Some(OsString::from("relative")), home), Some(PathBuf::from("/home/me/.config/gentor")));
+        assert_eq!(xdg_config_dir(Some(OsString::new()), None), None);
+        assert_eq!(xdg_config_dir(xdg, None), Some(PathBuf::from("/xdg/gentor")));
+    }
+
+    #[test]
+    fn only_gentor_settings_count_as_legacy() {
+        let dir = env::temp_dir().join(format!("gentor-legacy-settings-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let legacy = |text: &[u8]| {
+            let file = dir.join(SETTINGS_FILE);
+            fs::write(&file, text).unwrap();
+            is_legacy_settings(&file)
+        };
+        assert!(legacy(br#"{"provider": "openai", "model": "gpt-4o-mini", "api_key": "sk-x", "base_url": "https://api.openai.com/v1"}"#));
+        assert!(legacy(br#"{"version": 2, "provider": "openai", "model": "m", "api_key": "", "base_url": "http://localhost:11434/v1", "temperature": 0.2}"#));
+        assert!(legacy(b"GENTORENC1 and the rest"));
+        // an editor's or another tool's settings.json
+        assert!(!legacy(br#"{"editor.tabSize": 4, "files.exclude": {}}"#));
+        assert!(!legacy(br#"{"provider": "openai", "model": "m", "api_key": "k"}"#));
+        assert!(!legacy(br#"{"provider": "openai", "model": "m", "api_key": "k", "base_url": "u", "temperature": "hot"}"#));
+        assert!(!legacy(b"not json"));
+        assert!(!is_legacy_settings(&dir.join("missing.json")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
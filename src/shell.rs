@@ -0,0 +1,116 @@
+//! Shell commands written by the model from a description, for `gentor cmd`
+//! and `/cmd`. A command is only ever run after the user picks "run".
+use anyhow::{bail, Result};
+use std::{
+    env,
+    io::{self, Write},
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{agent, clipboard, i18n, settings::Settings};
+
+/// Output lines of a command run from `/cmd` kept in the chat.
+const MAX_OUTPUT_LINES: usize = 200;
+const CMD_PROMPT: &str = "You turn a task description into one shell command for the user's system. Reply with the command alone in a ```sh code block, then explain in a few short lines what it does and what each part means. Prefer standard tools, a single line, and safe options; mention anything destructive.";
+
+pub struct Suggestion {
+    pub command: String,
+    pub explanation: String,
+}
+
+/// Asks the model for a command doing `task`, telling it the OS and shell.
+pub async fn suggest(settings: &Settings, task: &str) -> Result<Suggestion> {
+    let client = agent::client_for(settings);
+    let settings = Settings { response_format: None, ..settings.clone() };
+    let shell = env::var("SHELL").ok().filter(|s| !s.is_empty()).unwrap_or_else(|| if cfg!(windows) { "cmd".to_string() } else { "sh".to_string() });
+    let prompt = format!("System: {} ({}), shell: {}, directory: {}\n\nTask: {}", env::consts::OS, env::consts::ARCH, shell, env::current_dir()?.display(), task);
+    let answer = agent::complete(&client, &settings, CMD_PROMPT, &prompt).await?;
+    parse(&answer)
+}
+
+/// The first code block is the command; the text around it explains it.
+fn parse(answer: &str) -> Result<Suggestion> {
+    let mut command = Vec::new();
+    let mut explanation = Vec::new();
+    let mut fence = 0;
+    for line in answer.lines() {
+        if line.trim_start().starts_with("```") {
+            fence += 1;
+        } else if fence == 1 {
+            command.push(line);
+        } else {
+            explanation.push(line);
+        }
+    }
+    let command = command.join("\n").trim().to_string();
+    if command.is_empty() {
+        bail!("the model did not reply with a command");
+    }
+    Ok(Suggestion { command, explanation: explanation.join("\n").trim().to_string() })
+}
+
+/// The command run through the platform shell.
+pub fn shell(command: &str) -> tokio::process::Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = tokio::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Runs `command` without a terminal and returns whether it succeeded with
+/// its combined output, cut to the last lines. The process is killed if
+/// the task is aborted.
+pub async fn capture(command: &str) -> Result<(bool, String)> {
+    let output = shell(command).stdin(std::process::Stdio::null()).kill_on_drop(true).output().await?;
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let tail = lines[lines.len().saturating_sub(MAX_OUTPUT_LINES)..].join("\n");
+    Ok((output.status.success(), tail))
+}
+
+/// `gentor cmd "<task>"`: prints the suggestion and asks what to do with it.
+pub async fn run(settings: Settings, task: &str) -> Result<()> {
+    let mut suggestion = suggest(&settings, task).await?;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    println!("💻 {}", suggestion.command);
+    if !suggestion.explanation.is_empty() {
+        println!("\n{}", suggestion.explanation);
+    }
+    loop {
+        print!("\n{} ", i18n::t("cmd.choices"));
+        io::stdout().flush()?;
+        let Some(choice) = lines.next_line().await? else { return Ok(()) };
+        match choice.trim() {
+            "e" | "edit" => {
+                print!("{} ", i18n::t("cmd.edit"));
+                io::stdout().flush()?;
+                let edited = lines.next_line().await?.unwrap_or_default();
+                if !edited.trim().is_empty() {
+                    suggestion.command = edited.trim().to_string();
+                }
+                println!("💻 {}", suggestion.command);
+            }
+            "r" | "run" => {
+                let status = shell(&suggestion.command).status().await?;
+                if !status.success() {
+                    bail!("{}", i18n::fill("cmd.exited", &[&status]));
+                }
+                return Ok(());
+            }
+            "c" | "copy" => {
+                clipboard::copy(&suggestion.command)?;
+                println!("{}", i18n::t("cmd.copied_clipboard"));
+                return Ok(());
+            }
+            "q" | "quit" | "" => return Ok(()),
+            other => println!("{}", i18n::fill("cmd.unknown_choice", &[&other])),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Per-request latency, throughput, tokens and cost of every provider call,
+//! recorded by the [`Recorder`] middleware, kept for the session and
+//! appended to `~/.config/gentor/stats.jsonl` for comparisons across
+//! sessions and the `/stats full` dashboard, which also splits usage by
+//! key for providers with several.
+use futures::future::{self, BoxFuture};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    agent, crypto,
+    middleware::{Exchange, Middleware},
+    models, session, settings, tokens,
+};
+
+const STATS_FILE: &str = "stats.jsonl";
+/// Days listed in the dashboard, newest first.
+const DASHBOARD_DAYS: usize = 14;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Record {
+    /// Unix time the reply arrived.
+    pub time: u64,
+    pub provider: String,
+    pub model: String,
+    pub latency_ms: u64,
+    /// Time until the first token, or the first complete round for replies
+    /// that were not streamed.
+    pub ttft_ms: u64,
+    pub completion_tokens: u32,
+    /// Completion tokens per second while they arrived, tool calls excluded.
+    pub tokens_per_sec: f64,
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    /// Estimated from the model registry's prices; unknown without them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// The last characters of the key used, when the provider has several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The request ended in an error; only `time`, `provider` and `model` are set.
+    #[serde(default)]
+    pub failed: bool,
+}
+
+impl Record {
+    /// The call of `exchange`, answered with `response`.
+    pub fn new(exchange: &Exchange<'_>, response: &Value) -> Self {
+        let settings = exchange.settings;
+        let tokens = |field: &str| response["usage"][field].as_u64().unwrap_or(0) as u32;
+        let (prompt_tokens, completion_tokens) = (tokens("prompt_tokens"), tokens("completion_tokens"));
+        let latency = exchange.started.elapsed();
+        let generating = if exchange.streaming > Duration::ZERO { exchange.streaming } else { latency }.as_secs_f64();
+        Self {
+            latency_ms: latency.as_millis() as u64,
+            ttft_ms: exchange.first_token.unwrap_or(latency).as_millis() as u64,
+            completion_tokens,
+            tokens_per_sec: if generating > 0.0 { completion_tokens as f64 / generating } else { 0.0 },
+            prompt_tokens,
+            cost: models::lookup(settings, &settings.model).cost(prompt_tokens, completion_tokens),
+            key: Some(agent::key_label(settings)).filter(|_| settings.keys().len() > 1),
+            failed: false,
+            ..Self::failed(&settings.provider, &settings.model)
+        }
+    }
+
+    pub fn failed(provider: &str, model: &str) -> Self {
+        Self {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            latency_ms: 0,
+            ttft_ms: 0,
+            completion_tokens: 0,
+            tokens_per_sec: 0.0,
+            prompt_tokens: 0,
+            cost: None,
+            key: None,
+            failed: true,
+        }
+    }
+}
+
+pub(crate) fn stats_file() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join(STATS_FILE))
+}
+
+/// The process's stats, read from `stats.jsonl` once on first use.
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+fn with<T>(f: impl FnOnce(&mut Stats) -> T) -> T {
+    let mut stats = STATS.lock().unwrap_or_else(|e| e.into_inner());
+    f(stats.get_or_insert_with(Stats::new))
+}
+
+/// Estimated spend today and this month, in UTC.
+pub fn spent() -> (f64, f64) {
+    with(|stats| stats.spent())
+}
+
+pub fn record(record: Record) {
+    with(|stats| stats.record(record))
+}
+
+/// Per-model averages for this session and for everything recorded.
+pub fn report() -> Vec<String> {
+    with(|stats| stats.report())
+}
+
+#[derive(Default)]
+struct Stats {
+    session: Vec<Record>,
+    /// Unix time and estimated cost of every priced request, for budgets.
+    costs: Vec<(u64, f64)>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        let costs = load().iter().filter_map(|r| Some((r.time, r.cost?))).collect();
+        Self { session: Vec::new(), costs }
+    }
+
+    fn spent(&self) -> (f64, f64) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let month = &session::timestamp(now)[..7];
+        // a month is at most 31 days, so older records need no date formatting
+        let recent = self.costs.iter().filter(|(time, _)| now.saturating_sub(*time) < 31 * 86_400);
+        let mut spent = (0.0, 0.0);
+        for (time, cost) in recent {
+            if time / 86_400 == now / 86_400 {
+                spent.0 += cost;
+            }
+            if session::timestamp(*time)[..7] == *month {
+                spent.1 += cost;
+            }
+        }
+        spent
+    }
+
+    fn record(&mut self, record: Record) {
+        if let Some(cost) = record.cost {
+            self.costs.push((record.time, cost));
+        }
+        if let Some(path) = stats_file() {
+            let _ = path.parent().map(fs::create_dir_all);
+            if let Ok(line) = serde_json::to_string(&record) {
+                let _ = crypto::append(&path, &line);
+            }
+        }
+        self.session.push(record);
+    }
+
+    fn report(&self) -> Vec<String> {
+        let history = load();
+        let mut lines = vec!["This session".to_string()];
+        lines.extend(table(&self.session));
+        lines.push(String::new());
+        lines.push("All time".to_string());
+        lines.extend(table(&history));
+        lines
+    }
+}
+
+/// A daily or monthly budget that is used up.
+#[derive(Debug)]
+pub struct OverBudget {
+    pub period: &'static str,
+    pub spent: f64,
+    pub limit: f64,
+}
+
+impl fmt::Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the {} budget of ${:.2} is used up (${:.2} spent)", self.period, self.limit, self.spent)
+    }
+}
+
+impl std::error::Error for OverBudget {}
+
+/// Refuses requests with [`OverBudget`] once a budget is used up, unless
+/// `/budget override` was given.
+pub struct Budget;
+
+impl Middleware for Budget {
+    fn before<'a>(&'a self, exchange: &'a mut Exchange<'_>) -> BoxFuture<'a, anyhow::Result<()>> {
+        let settings = exchange.settings;
+        let result = match (settings.daily_budget, settings.monthly_budget) {
+            _ if settings.budget_override => Ok(()),
+            (None, None) => Ok(()),
+            (daily, monthly) => {
+                let (day, month) = spent();
+                [("daily", day, daily), ("monthly", month, monthly)]
+                    .into_iter()
+                    .find_map(|(period, spent, limit)| Some(OverBudget { period, spent, limit: limit? }).filter(|over| over.spent >= over.limit))
+                    .map_or(Ok(()), |over| Err(over.into()))
+            }
+        };
+        Box::pin(future::ready(result))
+    }
+}
+
+/// Records every call the provider answered or failed; answers from the
+/// response cache cost nothing and are not calls.
+pub struct Recorder;
+
+impl Middleware for Recorder {
+    fn after<'a>(&'a self, exchange: &'a mut Exchange<'_>, result: Result<&'a Value, &'a anyhow::Error>) -> BoxFuture<'a, ()> {
+        if exchange.cached {
+            return Box::pin(future::ready(()));
+        }
+        record(match result {
+            Ok(response) => Record::new(exchange, response),
+            Err(_) => Record::failed(&exchange.settings.provider, &exchange.settings.model),
+        });
+        Box::pin(future::ready(()))
+    }
+}
+
+/// Requests, errors, tokens and cost of everything recorded, per day and
+/// per model.
+pub fn dashboard() -> Vec<String> {
+    let history = load();
+    let day = |r: &Record| session::timestamp(r.time)[..10].to_string();
+    let model = |r: &Record| format!("{}/{}", r.provider, r.model);
+    let mut lines = vec!["Per day".to_string()];
+    let days = totals(&history, day);
+    lines.extend(totals_table("day", days.into_iter().rev().take(DASHBOARD_DAYS)));
+    lines.push(String::new());
+    lines.push("Per model".to_string());
+    lines.extend(totals_table("model", totals(&history, model)));
+    let keyed: Vec<Record> = history.iter().filter(|r| r.key.is_some()).cloned().collect();
+    if !keyed.is_empty() {
+        lines.push(String::new());
+        lines.push("Per key".to_string());
+        lines.extend(totals_table("key", totals(&keyed, |r| format!("{} {}", r.provider, r.key.as_deref().unwrap_or_default()))));
+    }
+    lines.push(String::new());
+    lines.push("All time".to_string());
+    lines.extend(totals_table("", totals(&history, |_| "total".to_string())));
+    lines
+}
+
+fn load() -> Vec<Record> {
+    stats_file()
+        .and_then(|p| crypto::read_to_string(&p).ok())
+        .map(|s| s.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Totals {
+    requests: usize,
+    errors: usize,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+    /// Some requests had no price, so `cost` is a lower bound.
+    unpriced: bool,
+}
+
+fn totals(records: &[Record], key: impl Fn(&Record) -> String) -> BTreeMap<String, Totals> {
+    let mut groups: BTreeMap<String, Totals> = BTreeMap::new();
+    for record in records {
+        let totals = groups.entry(key(record)).or_default();
+        totals.requests += 1;
+        if record.failed {
+            totals.errors += 1;
+            continue;
+        }
+        totals.prompt_tokens += u64::from(record.prompt_tokens);
+        totals.completion_tokens += u64::from(record.completion_tokens);
+        match record.cost {
+            Some(cost) => totals.cost += cost,
+            None => totals.unpriced = true,
+        }
+    }
+    groups
+}
+
+fn totals_table(label: &str, groups: impl IntoIterator<Item = (String, Totals)>) -> Vec<String> {
+    let mut lines = vec![format!("  {:<36} {:>6} {:>6} {:>9} {:>9} {:>10}", label, "reqs", "errors", "in", "out", "cost")];
+    for (name, t) in groups {
+        let cost = format!("{}${:.4}", if t.unpriced { "≥" } else { "" }, t.cost);
+        lines.push(format!(
+            "  {:<36} {:>6} {:>6} {:>9} {:>9} {:>10}",
+            name,
+            t.requests,
+            t.errors,
+            tokens::short(t.prompt_tokens as usize),
+            tokens::short(t.completion_tokens as usize),
+            cost
+        ));
+    }
+    if lines.len() == 1 {
+        return vec!["  no requests yet".to_string()];
+    }
+    lines
+}
+
+fn table(records: &[Record]) -> Vec<String> {
+    let records: Vec<Record> = records.iter().filter(|r| !r.failed).cloned().collect();
+    if records.is_empty() {
+        return vec!["  no requests yet".to_string()];
+    }
+    let mut models: Vec<(&str, &str)> = records.iter().map(|r| (r.provider.as_str(), r.model.as_str())).collect();
+    models.sort();
+    models.dedup();
+    let mut lines = vec![format!("  {:<36} {:>5} {:>10} {:>10} {:>8}", "model", "reqs", "latency", "ttft", "tok/s")];
+    for (provider, model) in models {
+        let runs: Vec<&Record> = records.iter().filter(|r| r.provider == provider && r.model == model).collect();
+        let n = runs.len() as f64;
+        let latency = runs.iter().map(|r| r.latency_ms as f64).sum::<f64>() / n / 1000.0;
+        let ttft = runs.iter().map(|r| r.ttft_ms as f64).sum::<f64>() / n / 1000.0;
+        let tps = runs.iter().map(|r| r.tokens_per_sec).sum::<f64>() / n;
+        let name = format!("{}/{}", provider, model);
+        lines.push(format!("  {:<36} {:>5} {:>9.2}s {:>9.2}s {:>8.1}", name, runs.len(), latency, ttft, tps));
+    }
+    lines
+}
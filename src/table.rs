@@ -0,0 +1,193 @@
+//! Markdown tables laid out with box drawing for the chat pane, columns
+//! sized to their content and narrowed to fit the pane by wrapping cells.
+use crate::{editing::width, wrap::wrap};
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+pub struct Table {
+    pub header: Vec<String>,
+    pub aligns: Vec<Align>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Cells of a `| a | b |` row; the outer pipes are optional but the line
+/// must have at least one, and `\|` stays inside a cell.
+pub fn cells(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.contains('|') {
+        return None;
+    }
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = if line.ends_with('|') && !line.ends_with("\\|") { &line[..line.len() - 1] } else { line };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => cell.push(chars.next().unwrap_or('|')),
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    Some(cells)
+}
+
+/// Column alignments of a `|---|:---:|---:|` line under the header.
+pub fn separator(line: &str) -> Option<Vec<Align>> {
+    cells(line)?
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect()
+}
+
+impl Table {
+    fn columns(&self) -> usize {
+        self.header.len().max(self.aligns.len())
+    }
+
+    /// Box-drawn rows at most `max_width` columns wide when the columns can
+    /// be narrowed that far; cells wrap inside their column.
+    pub fn layout(&self, max_width: usize) -> Vec<String> {
+        let columns = self.columns();
+        let natural: Vec<usize> = (0..columns)
+            .map(|c| self.all_rows().map(|row| row.get(c).map_or(0, |cell| width(cell))).max().unwrap_or(0).max(1))
+            .collect();
+        // a border per column plus one, and a space either side of each cell
+        let available = max_width.saturating_sub(3 * columns + 1);
+        let widths = fit(&natural, available);
+
+        let rule = |left: &str, middle: &str, right: &str| {
+            let parts: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{}{}{}", left, parts.join(middle), right)
+        };
+        let mut lines = vec![rule("┌", "┬", "┐")];
+        lines.extend(self.row_lines(&self.header, &widths));
+        lines.push(rule("├", "┼", "┤"));
+        for row in &self.rows {
+            lines.extend(self.row_lines(row, &widths));
+        }
+        lines.push(rule("└", "┴", "┘"));
+        lines
+    }
+
+    fn all_rows(&self) -> impl Iterator<Item = &Vec<String>> {
+        std::iter::once(&self.header).chain(&self.rows)
+    }
+
+    fn row_lines(&self, row: &[String], widths: &[usize]) -> Vec<String> {
+        let wrapped: Vec<Vec<String>> = widths
+            .iter()
+            .enumerate()
+            .map(|(c, &w)| wrap(row.get(c).map_or("", String::as_str), w, false).into_iter().map(|l| l.trim_end().to_string()).collect())
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+        (0..height)
+            .map(|i| {
+                let cells: Vec<String> = wrapped
+                    .iter()
+                    .zip(widths)
+                    .enumerate()
+                    .map(|(c, (lines, &w))| {
+                        let text = lines.get(i).map_or("", String::as_str);
+                        pad(text, w, self.aligns.get(c).copied().unwrap_or(Align::Left))
+                    })
+                    .collect();
+                format!("│ {} │", cells.join(" │ "))
+            })
+            .collect()
+    }
+}
+
+/// Column widths within `available`: columns narrower than an even share
+/// keep their width and the rest split what is left.
+fn fit(natural: &[usize], available: usize) -> Vec<usize> {
+    if natural.iter().sum::<usize>() <= available {
+        return natural.to_vec();
+    }
+    let mut widths = natural.to_vec();
+    let mut order: Vec<usize> = (0..natural.len()).collect();
+    order.sort_by_key(|&c| natural[c]);
+    let mut left = available;
+    for (done, &c) in order.iter().enumerate() {
+        let share = left / (natural.len() - done);
+        widths[c] = natural[c].min(share).max(1);
+        left = left.saturating_sub(widths[c]);
+    }
+    widths
+}
+
+fn pad(text: &str, column: usize, align: Align) -> String {
+    let space = column.saturating_sub(width(text));
+    let (before, after) = match align {
+        Align::Left => (0, space),
+        Align::Right => (space, 0),
+        Align::Center => (space / 2, space - space / 2),
+    };
+    format!("{}{}{}", " ".repeat(before), text, " ".repeat(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_split_on_unescaped_pipes() {
+        assert_eq!(cells("| a | b |").unwrap(), ["a", "b"]);
+        assert_eq!(cells("a | b").unwrap(), ["a", "b"]);
+        assert_eq!(cells(r"| a \| b | c |").unwrap(), ["a | b", "c"]);
+        assert!(cells("no pipes here").is_none());
+    }
+
+    #[test]
+    fn separators_give_alignments() {
+        let aligns = separator("|---|:--:|--:|:--|").unwrap();
+        let names: Vec<&str> = aligns.iter().map(|a| match a {
+            Align::Left => "left",
+            Align::Center => "center",
+            Align::Right => "right",
+        }).collect();
+        assert_eq!(names, ["left", "center", "right", "left"]);
+        assert!(separator("| a | b |").is_none());
+        assert!(separator("| - | |").is_none());
+    }
+
+    #[test]
+    fn layout_pads_and_aligns() {
+        let table = Table {
+            header: vec!["name".to_string(), "n".to_string()],
+            aligns: vec![Align::Left, Align::Right],
+            rows: vec![vec!["a".to_string(), "10".to_string()]],
+        };
+        assert_eq!(table.layout(80), ["┌──────┬────┐", "│ name │  n │", "├──────┼────┤", "│ a    │ 10 │", "└──────┴────┘"]);
+    }
+
+    #[test]
+    fn wide_tables_wrap_their_widest_column() {
+        let table = Table {
+            header: vec!["id".to_string(), "description".to_string()],
+            aligns: vec![Align::Left, Align::Left],
+            rows: vec![vec!["1".to_string(), "a fairly long cell".to_string()]],
+        };
+        let lines = table.layout(20);
+        assert!(lines.iter().all(|line| width(line) <= 20), "{:?}", lines);
+        assert_eq!(lines[3], "│ 1  │ a fairly    │");
+        assert_eq!(lines[4], "│    │ long cell   │");
+        assert_eq!(fit(&[2, 30], 15), [2, 13]);
+    }
+}
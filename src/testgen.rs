@@ -0,0 +1,78 @@
+//! `/gentest`: where tests for a source file conventionally live, and an
+//! existing test file to show the model the project's testing style.
+use std::{fs, path::Path};
+
+use crate::ignore::{self, IgnoreRules};
+
+/// Files looked at while searching for an example test.
+const MAX_SCANNED: usize = 2_000;
+/// Lines of the example test sent to the model.
+pub const MAX_EXAMPLE_LINES: usize = 150;
+
+pub const GENTEST_PROMPT: &str = "Write unit tests for the code above. Match the project's existing tests: the same framework, helpers, naming, layout and assertion style as the example test file when one is attached. Cover the normal cases, edge cases and error paths of the public behaviour, without testing private details the test file cannot reach. Give the complete new contents of the test file as one file-scoped code block (```lang path), keeping any tests it already has.";
+
+/// The file tests for `path` go in: next to it or in the project's test
+/// directory, as the language's tooling expects. Rust tests go in a
+/// `#[cfg(test)]` module of the file itself, since integration tests only
+/// reach a library's public API.
+pub fn location(path: &str) -> Option<String> {
+    let file = Path::new(path);
+    let stem = file.file_stem()?.to_str()?;
+    let ext = file.extension()?.to_str()?;
+    let dir = file.parent().map(|d| d.to_string_lossy().into_owned()).filter(|d| !d.is_empty());
+    let beside = |name: String| match &dir {
+        Some(dir) => format!("{}/{}", dir, name),
+        None => name,
+    };
+    Some(match ext {
+        "rs" => path.to_string(),
+        "py" if Path::new("tests").is_dir() => format!("tests/test_{}.py", stem),
+        "py" => beside(format!("test_{}.py", stem)),
+        "go" => beside(format!("{}_test.go", stem)),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => beside(format!("{}.test.{}", stem, ext)),
+        "rb" if Path::new("spec").is_dir() => format!("spec/{}_spec.rb", stem),
+        _ => return None,
+    })
+}
+
+/// An existing test file in the language of `path`, other than `except`.
+pub fn example(path: &str, except: Option<&str>) -> Option<String> {
+    let ext = Path::new(path).extension()?.to_str()?.to_string();
+    let rules = IgnoreRules::load(Path::new("."));
+    let mut scanned = 0;
+    let mut found = None;
+    walk(Path::new("."), "", &rules, &mut |rel| {
+        scanned += 1;
+        if found.is_some() || scanned > MAX_SCANNED || Some(rel) == except || !rel.ends_with(&format!(".{}", ext)) {
+            return;
+        }
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        let test_name = ["test_*", "*_test.*", "*.test.*", "*.spec.*", "*_spec.*"].iter().any(|g| ignore::glob(g, name));
+        let is_test = test_name
+            || rel.starts_with("tests/")
+            || (ext == "rs" && fs::read_to_string(rel).is_ok_and(|text| text.contains("#[cfg(test)]")));
+        if is_test {
+            found = Some(rel.to_string());
+        }
+    });
+    found
+}
+
+fn walk(dir: &Path, rel: &str, rules: &IgnoreRules, visit: &mut dyn FnMut(&str)) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{}{}", rel, name);
+        let is_dir = entry.path().is_dir();
+        if name.starts_with('.') || rules.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(&entry.path(), &format!("{}/", path), rules, visit);
+        } else {
+            visit(&path);
+        }
+    }
+}
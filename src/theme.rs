@@ -0,0 +1,90 @@
+//! Fitting the UI's colors to the terminal: `NO_COLOR` and monochrome
+//! terminals get attributes only, 8-color terminals lose the bright shades.
+use std::env;
+use tui::{buffer::Buffer, layout::Rect, style::{Color, Modifier}, widgets::Widget};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Colors {
+    /// No color at all: highlights become reverse video, dim text stays dim.
+    None,
+    /// The eight basic colors.
+    Basic,
+    Full,
+}
+
+/// What the environment says the terminal can show. `NO_COLOR` (any
+/// non-empty value, see no-color.org) wins over everything else.
+pub fn detect() -> Colors {
+    let set = |name: &str| env::var(name).is_ok_and(|v| !v.is_empty());
+    let term = env::var("TERM").unwrap_or_default();
+    if set("NO_COLOR") || term == "dumb" {
+        Colors::None
+    } else if set("COLORTERM") || term.contains("256color") || term.contains("kitty") || term.contains("direct") {
+        Colors::Full
+    } else if term.is_empty() && cfg!(windows) {
+        // the Windows console reports no TERM but handles full color
+        Colors::Full
+    } else {
+        Colors::Basic
+    }
+}
+
+/// Drawn over the finished frame, rewriting each cell's colors for the
+/// terminal so the rest of the UI can keep using the full palette.
+pub struct Adapt(pub Colors);
+
+impl Widget for Adapt {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if self.0 == Colors::Full {
+            return;
+        }
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                match self.0 {
+                    Colors::None => {
+                        if cell.bg != Color::Reset {
+                            cell.modifier |= Modifier::REVERSED;
+                        }
+                        match cell.fg {
+                            Color::Reset | Color::Yellow | Color::Black | Color::White => {}
+                            Color::DarkGray | Color::Gray => cell.modifier |= Modifier::DIM,
+                            _ if cell.bg == Color::Reset => cell.modifier |= Modifier::BOLD,
+                            _ => {}
+                        }
+                        cell.fg = Color::Reset;
+                        cell.bg = Color::Reset;
+                    }
+                    _ => {
+                        cell.fg = basic(cell.fg, &mut cell.modifier);
+                        if cell.bg == Color::DarkGray {
+                            cell.bg = Color::Reset;
+                            cell.modifier |= Modifier::REVERSED;
+                        } else {
+                            cell.bg = basic(cell.bg, &mut cell.modifier);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The nearest of the eight basic colors; gray shades become dimmed text.
+fn basic(color: Color, modifier: &mut Modifier) -> Color {
+    match color {
+        Color::DarkGray => {
+            *modifier |= Modifier::DIM;
+            Color::Reset
+        }
+        Color::Gray => Color::White,
+        Color::LightRed => Color::Red,
+        Color::LightGreen => Color::Green,
+        Color::LightYellow => Color::Yellow,
+        Color::LightBlue => Color::Blue,
+        Color::LightMagenta => Color::Magenta,
+        Color::LightCyan => Color::Cyan,
+        Color::Rgb(..) | Color::Indexed(_) => Color::Reset,
+        other => other,
+    }
+}
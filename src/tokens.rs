@@ -0,0 +1,109 @@
+//! Token counts used to keep requests inside the model's context window.
+//! OpenAI models are counted with their own tiktoken encoding; other models
+//! fall back to a character heuristic that can be off by a fair margin either
+//! way, so counts are shown as estimates wherever they appear.
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, tokenizer::Tokenizer, CoreBPE};
+
+/// Per-message framing overhead in the chat format.
+pub const MESSAGE_OVERHEAD: usize = 4;
+
+/// Compact count for the status bar: `950`, `3.1k`, `128k`, `1.0M`.
+pub fn short(n: usize) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=9_999 => format!("{:.1}k", n as f64 / 1_000.0),
+        10_000..=999_999 => format!("{}k", n / 1_000),
+        _ => format!("{:.1}M", n as f64 / 1_000_000.0),
+    }
+}
+
+/// Tokens `text` takes up when sent to `model`.
+pub fn count(model: &str, text: &str) -> usize {
+    match encoding(model) {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => estimate(text),
+    }
+}
+
+/// The tiktoken encoding of an OpenAI model, also behind a provider prefix
+/// such as `openai/gpt-4o`. Models newer than the tiktoken tables are on o200k.
+fn encoding(model: &str) -> Option<&'static CoreBPE> {
+    let name = model.rsplit('/').next().unwrap_or(model).to_ascii_lowercase();
+    match tiktoken_rs::tokenizer::get_tokenizer(&name) {
+        Some(Tokenizer::O200kBase) => Some(o200k_base_singleton()),
+        Some(Tokenizer::Cl100kBase) => Some(cl100k_base_singleton()),
+        Some(_) => None,
+        None if name.starts_with("gpt-5") || name.starts_with("o4") => Some(o200k_base_singleton()),
+        None => None,
+    }
+}
+
+/// Approximates cl100k/o200k tokenization for models whose tokenizer is not
+/// bundled: text is split the way those encoders pre-tokenize (letter runs,
+/// digit runs of up to three, punctuation runs) and long runs are charged one
+/// token per four characters. Non-ASCII characters are charged one token
+/// each, which errs on the high side.
+fn estimate(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: usize = 0;
+    let mut kind = None;
+    for c in text.chars() {
+        let next = if c.is_ascii_alphabetic() {
+            Some('a')
+        } else if c.is_ascii_digit() {
+            Some('0')
+        } else if c.is_whitespace() {
+            None
+        } else if c.is_ascii() {
+            Some('.')
+        } else {
+            tokens += 1;
+            None
+        };
+        if next != kind || (next == Some('0') && run == 3) {
+            tokens += run.div_ceil(4);
+            run = 0;
+            kind = next;
+        }
+        if next.is_some() {
+            run += 1;
+        }
+    }
+    tokens + run.div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_follow_the_pre_tokenizer_runs() {
+        assert_eq!(estimate(""), 0);
+        assert_eq!(estimate("hello world"), 4);
+        assert_eq!(estimate("fn main() {}"), 4);
+        // digits go in runs of three
+        assert_eq!(estimate("12345678"), 3);
+        assert_eq!(estimate("안녕 세계"), 4);
+        let prose = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let estimated = estimate(&prose);
+        assert!((prose.len() / 6..=prose.len() / 3).contains(&estimated), "{}", estimated);
+    }
+
+    #[test]
+    fn openai_models_are_counted_with_their_encoding() {
+        assert_eq!(count("gpt-4o-mini", "hello world"), 2);
+        assert_eq!(count("gpt-4", "hello world"), 2);
+        assert_eq!(count("openai/gpt-5", "hello world"), 2);
+        assert_eq!(count("o1", "fn main() {}"), count("gpt-4o", "fn main() {}"));
+        assert_eq!(count("llama-3.1-8b", "hello world"), estimate("hello world"));
+        assert_eq!(count("claude-sonnet-4", "hello world"), 4);
+    }
+
+    #[test]
+    fn short_counts_fit_the_status_bar() {
+        assert_eq!(short(950), "950");
+        assert_eq!(short(3_100), "3.1k");
+        assert_eq!(short(128_000), "128k");
+        assert_eq!(short(1_000_000), "1.0M");
+    }
+}
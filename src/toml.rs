@@ -0,0 +1,644 @@
+//! Reading and writing the TOML config files as JSON values, so the serde
+//! types of `settings.json` read them unchanged. Dates and times are kept
+//! as strings; `inf` and `nan` have no JSON form and are refused.
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeSet;
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser { chars: text.chars().collect(), at: 0 };
+    parser.document().map_err(|e| anyhow!("line {}: {}", parser.line(), e))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.chars[..self.at.min(self.chars.len())].iter().filter(|&&c| c == '\n').count() + 1
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.at + i) == Some(&c))
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.starts_with(text);
+        if found {
+            self.at += text.chars().count();
+        }
+        found
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        if !self.eat(text) {
+            bail!("expected '{}', found {}", text, self.found());
+        }
+        Ok(())
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            None => "the end of the file".to_string(),
+            Some('\n' | '\r') => "the end of the line".to_string(),
+            Some(c) => format!("'{}'", c),
+        }
+    }
+
+    /// Spaces and tabs.
+    fn blank(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.at += 1;
+        }
+    }
+
+    /// Whitespace, newlines and comments, as allowed between lines and
+    /// inside arrays.
+    fn gap(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\n' | '\r') => self.at += 1,
+                Some('#') => self.comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn comment(&mut self) {
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.at += 1;
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.blank();
+        if self.peek() == Some('#') {
+            self.comment();
+        }
+        if self.peek().is_some() && !self.eat("\n") && !self.eat("\r\n") {
+            bail!("expected the end of the line, found {}", self.found());
+        }
+        Ok(())
+    }
+
+    fn document(&mut self) -> Result<Value> {
+        let mut root = Map::new();
+        let mut current: Vec<String> = Vec::new();
+        // headers seen, so a table is not opened twice; a new table of an
+        // array starts over for the tables under it
+        let mut defined: BTreeSet<Vec<String>> = BTreeSet::new();
+        loop {
+            self.gap();
+            if self.peek().is_none() {
+                break;
+            }
+            // errors are raised before the end of the line, so they report its number
+            if self.eat("[[") {
+                self.blank();
+                let path = self.key()?;
+                self.blank();
+                self.expect("]]")?;
+                let (last, parents) = path.split_last().expect("keys are never empty");
+                match table(&mut root, parents)?.entry(last.clone()).or_insert_with(|| Value::Array(Vec::new())) {
+                    Value::Array(items) => items.push(Value::Object(Map::new())),
+                    _ => bail!("'{}' is not an array of tables", last),
+                }
+                defined.retain(|seen| !seen.starts_with(&path));
+                self.end_of_line()?;
+                current = path;
+            } else if self.eat("[") {
+                self.blank();
+                let path = self.key()?;
+                self.blank();
+                self.expect("]")?;
+                if !defined.insert(path.clone()) {
+                    bail!("table '{}' is defined twice", path.join("."));
+                }
+                table(&mut root, &path)?;
+                self.end_of_line()?;
+                current = path;
+            } else {
+                let (key, value) = self.pair()?;
+                insert(table(&mut root, &current)?, &key, value)?;
+                self.end_of_line()?;
+            }
+        }
+        Ok(Value::Object(root))
+    }
+
+    fn pair(&mut self) -> Result<(Vec<String>, Value)> {
+        let key = self.key()?;
+        self.blank();
+        self.expect("=")?;
+        self.blank();
+        Ok((key, self.value()?))
+    }
+
+    /// A possibly dotted key, as its parts.
+    fn key(&mut self) -> Result<Vec<String>> {
+        let mut parts = vec![self.simple_key()?];
+        loop {
+            self.blank();
+            if !self.eat(".") {
+                return Ok(parts);
+            }
+            self.blank();
+            parts.push(self.simple_key()?);
+        }
+    }
+
+    fn simple_key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let start = self.at;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                    self.at += 1;
+                }
+                if start == self.at {
+                    bail!("expected a key, found {}", self.found());
+                }
+                Ok(self.chars[start..self.at].iter().collect())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => self.multiline_string(true).map(Value::String),
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') if self.starts_with("'''") => self.multiline_string(false).map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            None => bail!("expected a value, found the end of the file"),
+            _ => self.scalar(),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        self.at += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => bail!("unterminated string"),
+                Some('"') => {
+                    self.at += 1;
+                    return Ok(text);
+                }
+                Some('\\') => text.push(self.escape()?),
+                Some(c) => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char> {
+        self.at += 1;
+        let c = self.peek().ok_or_else(|| anyhow!("unterminated string"))?;
+        self.at += 1;
+        Ok(match c {
+            'b' => '\u{8}',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\u{c}',
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            'u' | 'U' => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = self.chars.iter().skip(self.at).take(len).collect();
+                self.at += len;
+                u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or_else(|| anyhow!("invalid unicode escape '\\{}{}'", c, hex))?
+            }
+            other => bail!("invalid escape '\\{}'", other),
+        })
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.at += 1;
+        let start = self.at;
+        loop {
+            match self.peek() {
+                None | Some('\n') => bail!("unterminated string"),
+                Some('\'') => break,
+                Some(_) => self.at += 1,
+            }
+        }
+        let text = self.chars[start..self.at].iter().collect();
+        self.at += 1;
+        Ok(text)
+    }
+
+    /// A `"""` string when `basic`, a `'''` one otherwise. A newline right
+    /// after the opening quotes is dropped, and in basic strings a `\` at
+    /// the end of a line joins it to the next non-blank text.
+    fn multiline_string(&mut self, basic: bool) -> Result<String> {
+        let quotes = if basic { "\"\"\"" } else { "'''" };
+        let quote = if basic { '"' } else { '\'' };
+        self.at += 3;
+        if !self.eat("\n") {
+            self.eat("\r\n");
+        }
+        let mut text = String::new();
+        loop {
+            if self.eat(quotes) {
+                // up to two quotes may end the content itself
+                for _ in 0..2 {
+                    if self.peek() == Some(quote) {
+                        text.push(quote);
+                        self.at += 1;
+                    }
+                }
+                return Ok(text);
+            }
+            match self.peek() {
+                None => bail!("unterminated string"),
+                Some('\\') if basic => {
+                    let rest = self.chars[self.at + 1..].iter().take_while(|&&c| c == ' ' || c == '\t' || c == '\r').count();
+                    if self.chars.get(self.at + 1 + rest) == Some(&'\n') {
+                        self.at += 1;
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.at += 1;
+                        }
+                    } else {
+                        text.push(self.escape()?);
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.at += 1;
+        let mut items = Vec::new();
+        loop {
+            self.gap();
+            if self.eat("]") {
+                break;
+            }
+            items.push(self.value()?);
+            self.gap();
+            if !self.eat(",") {
+                self.expect("]")?;
+                break;
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.at += 1;
+        let mut map = Map::new();
+        self.blank();
+        if self.eat("}") {
+            return Ok(Value::Object(map));
+        }
+        loop {
+            self.blank();
+            let (key, value) = self.pair()?;
+            insert(&mut map, &key, value)?;
+            self.blank();
+            if !self.eat(",") {
+                self.expect("}")?;
+                return Ok(Value::Object(map));
+            }
+        }
+    }
+
+    /// Booleans, numbers, and dates or times, which stay strings.
+    fn scalar(&mut self) -> Result<Value> {
+        let start = self.at;
+        let token = |p: &mut Self| {
+            while p.peek().is_some_and(|c| !matches!(c, ' ' | '\t' | '\n' | '\r' | ',' | ']' | '}' | '#')) {
+                p.at += 1;
+            }
+        };
+        token(self);
+        let mut text: String = self.chars[start..self.at].iter().collect();
+        // a date and a time may be separated by a space
+        let is_date = |t: &str| t.len() == 10 && t.as_bytes()[4] == b'-' && t.as_bytes()[7] == b'-';
+        if is_date(&text) && self.peek() == Some(' ') && self.chars.get(self.at + 1).is_some_and(char::is_ascii_digit) {
+            self.at += 1;
+            token(self);
+            text = self.chars[start..self.at].iter().collect();
+        }
+        match text.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            "" => bail!("expected a value, found {}", self.found()),
+            _ => {}
+        }
+        if text.get(..10).is_some_and(is_date) || (text.len() >= 8 && text.as_bytes()[2] == b':') {
+            return Ok(Value::String(text));
+        }
+        number(&text).ok_or_else(|| anyhow!("invalid value '{}'", text))
+    }
+}
+
+fn number(text: &str) -> Option<Value> {
+    let digits = text.replace('_', "");
+    if digits.contains("inf") || digits.contains("nan") {
+        return None;
+    }
+    let (negative, unsigned) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let radix = [("0x", 16), ("0o", 8), ("0b", 2)].into_iter().find(|(prefix, _)| unsigned.starts_with(prefix));
+    if let Some((prefix, radix)) = radix {
+        let n = i64::from_str_radix(&unsigned[prefix.len()..], radix).ok()?;
+        return Some(Value::from(if negative { -n } else { n }));
+    }
+    if unsigned.contains(['.', 'e', 'E']) {
+        return digits.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number);
+    }
+    digits.parse::<i64>().ok().map(Value::from)
+}
+
+/// The table at `path` under `map`, created when missing; for an array of
+/// tables, its latest table.
+fn table<'a>(mut map: &'a mut Map<String, Value>, path: &[String]) -> Result<&'a mut Map<String, Value>> {
+    for key in path {
+        map = match map.entry(key.clone()).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(inner) => inner,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(inner)) => inner,
+                _ => bail!("'{}' is not a table", key),
+            },
+            _ => bail!("'{}' is not a table", key),
+        };
+    }
+    Ok(map)
+}
+
+fn insert(map: &mut Map<String, Value>, key: &[String], value: Value) -> Result<()> {
+    let (last, parents) = key.split_last().expect("keys are never empty");
+    let map = table(map, parents)?;
+    if map.contains_key(last) {
+        bail!("duplicate key '{}'", key.join("."));
+    }
+    map.insert(last.clone(), value);
+    Ok(())
+}
+
+/// `map` as a TOML document: plain keys first, then a table for each object
+/// and an array of tables for each array of objects. Nulls are left out.
+pub fn to_string(map: &Map<String, Value>) -> String {
+    let mut out = String::new();
+    write_table(&mut out, &[], map);
+    out.trim_start().to_string()
+}
+
+fn write_table(out: &mut String, path: &[String], map: &Map<String, Value>) {
+    for (key, value) in map {
+        if !value.is_null() && !is_table(value) && !is_table_array(value) {
+            out.push_str(&format!("{} = {}\n", key_text(key), inline(value)));
+        }
+    }
+    for (key, value) in map {
+        let mut path = path.to_vec();
+        path.push(key.clone());
+        let header = path.iter().map(|k| key_text(k)).collect::<Vec<_>>().join(".");
+        match value {
+            Value::Object(inner) => {
+                // a table of tables needs no header of its own
+                if inner.is_empty() || inner.values().any(|v| !v.is_null() && !is_table(v) && !is_table_array(v)) {
+                    out.push_str(&format!("\n[{}]\n", header));
+                }
+                write_table(out, &path, inner);
+            }
+            Value::Array(items) if is_table_array(value) => {
+                for item in items.iter().filter_map(Value::as_object) {
+                    out.push_str(&format!("\n[[{}]]\n", header));
+                    write_table(out, &path, item);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_table(value: &Value) -> bool {
+    value.is_object()
+}
+
+fn is_table_array(value: &Value) -> bool {
+    value.as_array().is_some_and(|items| !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+fn key_text(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A value on one line: arrays and inline tables for nested values.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(text) => quote(text),
+        Value::Array(items) => format!("[{}]", items.iter().filter(|v| !v.is_null()).map(inline).collect::<Vec<_>>().join(", ")),
+        Value::Object(map) => {
+            let pairs: Vec<String> = map.iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| format!("{} = {}", key_text(k), inline(v))).collect();
+            if pairs.is_empty() { "{}".to_string() } else { format!("{{ {} }}", pairs.join(", ")) }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// `text` with its top-level keys changed to match `new`, keeping comments
+/// and layout. `None` when a table would change or the document is not in
+/// a shape this can edit, so the caller writes it out whole instead.
+pub fn update(text: &str, new: &Map<String, Value>) -> Option<String> {
+    let Value::Object(old) = parse(text).ok()? else { return None };
+    let changed: BTreeSet<&String> = old.keys().chain(new.keys()).filter(|key| old.get(*key) != new.get(*key)).collect();
+    if changed.iter().any(|key| [old.get(*key), new.get(*key)].into_iter().flatten().any(|v| is_table(v) || is_table_array(v))) {
+        return None;
+    }
+    let mut parser = Parser { chars: text.chars().collect(), at: 0 };
+    let spans = parser.top_level_spans()?;
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    let mut added = String::new();
+    for key in changed {
+        let span = spans.iter().find(|span| &span.key == key);
+        match (span, new.get(key).filter(|v| !v.is_null())) {
+            (Some(span), Some(value)) => edits.push((span.value.0, span.value.1, inline(value))),
+            (Some(span), None) => edits.push((span.line.0, span.line.1, String::new())),
+            (None, Some(value)) => added.push_str(&format!("{} = {}\n", key_text(key), inline(value))),
+            (None, None) => {}
+        }
+    }
+    if !added.is_empty() {
+        // after the last top-level key, so comments above the first table stay with it
+        let at = spans.iter().map(|span| span.line.1).max().unwrap_or(0);
+        if at > 0 && parser.chars[at - 1] != '\n' {
+            added.insert(0, '\n');
+        }
+        edits.push((at, at, added));
+    }
+    edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    let mut chars = parser.chars;
+    for (start, end, replacement) in edits {
+        chars.splice(start..end, replacement.chars());
+    }
+    Some(chars.into_iter().collect())
+}
+
+/// Where a top-level `key = value` line and its value sit, in characters.
+struct Span {
+    key: String,
+    line: (usize, usize),
+    value: (usize, usize),
+}
+
+impl Parser {
+    /// Spans of the keys before the first table; `None` for dotted keys.
+    fn top_level_spans(&mut self) -> Option<Vec<Span>> {
+        let mut spans = Vec::new();
+        loop {
+            self.gap();
+            // back to the start of the line, so removing it takes its indentation
+            let mut start = self.at;
+            while start > 0 && matches!(self.chars[start - 1], ' ' | '\t') {
+                start -= 1;
+            }
+            if matches!(self.peek(), None | Some('[')) {
+                return Some(spans);
+            }
+            let key = self.key().ok()?;
+            let [key] = <[String; 1]>::try_from(key).ok()?;
+            self.blank();
+            self.expect("=").ok()?;
+            self.blank();
+            let value_start = self.at;
+            self.value().ok()?;
+            let value_end = self.at;
+            self.end_of_line().ok()?;
+            spans.push(Span { key, line: (start, self.at), value: (value_start, value_end) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error(text: &str) -> String {
+        parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn strings_and_escapes() {
+        let text = r#"
+basic = "tab\there \"quoted\" \u00e9 \U0001F600"
+literal = 'C:\path\no escapes'
+multi = """
+first \
+    joined
+second"""
+raw = '''
+kept \n as is'''
+"quoted key" = 1
+"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value["basic"], "tab\there \"quoted\" é 😀");
+        assert_eq!(value["literal"], "C:\\path\\no escapes");
+        assert_eq!(value["multi"], "first joined\nsecond");
+        assert_eq!(value["raw"], "kept \\n as is");
+        assert_eq!(value["quoted key"], 1);
+    }
+
+    #[test]
+    fn scalars_and_arrays() {
+        let text = "ints = [1, -2, 0x1F, 1_000]\nfloats = [0.5, 1e3]\nflags = [true, false]\nnested = [[1], ['a', \"b\"]]\nwhen = 2026-01-02 03:04:05\nlong = [\n  1, # one\n  2,\n]\n";
+        let value = parse(text).unwrap();
+        assert_eq!(value["ints"], json!([1, -2, 31, 1000]));
+        assert_eq!(value["floats"], json!([0.5, 1000.0]));
+        assert_eq!(value["flags"], json!([true, false]));
+        assert_eq!(value["nested"], json!([[1], ["a", "b"]]));
+        assert_eq!(value["when"], "2026-01-02 03:04:05");
+        assert_eq!(value["long"], json!([1, 2]));
+    }
+
+    #[test]
+    fn tables_dotted_keys_and_inline_tables() {
+        let text = "a.b = 1\npoint = { x = 1, y.z = 2 }\n\n[providers.openai]\nmodel = \"gpt\"\n\n[providers.\"my gateway\"]\nextra_headers = {}\n";
+        let value = parse(text).unwrap();
+        assert_eq!(value["a"], json!({ "b": 1 }));
+        assert_eq!(value["point"], json!({ "x": 1, "y": { "z": 2 } }));
+        assert_eq!(value["providers"]["openai"]["model"], "gpt");
+        assert_eq!(value["providers"]["my gateway"]["extra_headers"], json!({}));
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let text = "[[fruit]]\nname = \"apple\"\n[fruit.physical]\ncolor = \"red\"\n\n[[fruit]]\nname = \"plum\"\n[fruit.physical]\ncolor = \"purple\"\n";
+        let value = parse(text).unwrap();
+        assert_eq!(
+            value["fruit"],
+            json!([{ "name": "apple", "physical": { "color": "red" } }, { "name": "plum", "physical": { "color": "purple" } }])
+        );
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert_eq!(error("a = 1\nb = 2\na = 3\n"), "line 3: duplicate key 'a'");
+        assert_eq!(error("[t]\nx = 1\n[other]\n[t]\ny = 2\n"), "line 4: table 't' is defined twice");
+        assert_eq!(error("s = \"open\n"), "line 1: unterminated string");
+        assert_eq!(error("s = \"\\q\"\n"), "line 1: invalid escape '\\q'");
+        assert_eq!(error("x = inf\n"), "line 1: invalid value 'inf'");
+        assert_eq!(error("x = 1 2\n"), "line 1: expected the end of the line, found '2'");
+        assert_eq!(error("a = 1\n[a]\n"), "line 2: 'a' is not a table");
+        assert_eq!(error("[a]\n[[a]]\n"), "line 2: 'a' is not an array of tables");
+        assert!(error("x = [1, 2\n").starts_with("line 2:"));
+    }
+
+    #[test]
+    fn written_documents_read_back() {
+        let value = json!({ "model": "gpt", "stop": ["a\"b"], "providers": { "openai": { "api_key": "k" } }, "mcp_servers": [{ "name": "fs" }] });
+        let Value::Object(map) = &value else { unreachable!() };
+        assert_eq!(parse(&to_string(map)).unwrap(), value);
+    }
+
+    #[test]
+    fn updates_keep_comments() {
+        let text = "# the model\nmodel = \"a\" # inline\n\n[providers.x]\nmodel = \"b\"\n";
+        let Value::Object(mut map) = parse(text).unwrap() else { unreachable!() };
+        map.insert("model".to_string(), json!("c"));
+        map.insert("temperature".to_string(), json!(0.5));
+        assert_eq!(update(text, &map).unwrap(), "# the model\nmodel = \"c\" # inline\ntemperature = 0.5\n\n[providers.x]\nmodel = \"b\"\n");
+    }
+}
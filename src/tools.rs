@@ -0,0 +1,331 @@
+//! Tools offered to the model in the agent loop.
+use anyhow::{anyhow, bail, Result};
+use async_openai::types::{ChatCompletionTool, ChatCompletionToolType, FunctionObject};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::oneshot;
+
+use crate::{
+    agent,
+    github::{self, GithubConfig},
+    mcp::McpServer,
+    plugins::Plugin,
+    project,
+    sandbox::{self, SandboxConfig},
+    search,
+    web::{self, WebSearchConfig},
+};
+
+/// Separates the server name from the tool name in MCP tool names given to the model.
+const MCP_SEPARATOR: &str = "__";
+const READ_RESOURCE: &str = "read_resource";
+const WEB_SEARCH: &str = "web_search";
+const FETCH_URL: &str = "fetch_url";
+const RUN_CODE: &str = "run_code";
+const GREP: &str = "grep";
+const GET_ISSUE: &str = "get_issue";
+const GET_PR_DIFF: &str = "get_pr_diff";
+const COMMENT: &str = "comment";
+
+/// How tools reach the user while the agent runs in the background.
+pub trait ToolHost: Send + Sync {
+    /// Reports a line for the transcript.
+    fn notify(&self, line: String);
+    /// Asks the user a yes/no question; a dropped sender counts as no.
+    fn confirm(&self, question: String) -> oneshot::Receiver<bool>;
+    /// Reports how far a streamed reply has come.
+    fn progress(&self, _progress: agent::Progress) {}
+}
+
+#[derive(Default)]
+pub struct Toolbox {
+    servers: Mutex<Vec<Arc<McpServer>>>,
+    plugins: Mutex<Vec<Arc<Plugin>>>,
+    web_search: Option<WebSearchConfig>,
+    sandbox: Option<SandboxConfig>,
+    github: Option<GithubConfig>,
+    /// Withholds the tools that reach the network.
+    local_only: AtomicBool,
+    /// The project the file tools work in, resolved; the working directory
+    /// until set.
+    root: Mutex<Option<PathBuf>>,
+}
+
+fn function(name: String, description: String, parameters: Value) -> ChatCompletionTool {
+    ChatCompletionTool {
+        r#type: ChatCompletionToolType::Function,
+        function: FunctionObject { name, description: Some(description), parameters: Some(parameters), strict: None },
+    }
+}
+
+/// Tool names are limited to `[a-zA-Z0-9_-]{1,64}`.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+impl Toolbox {
+    pub fn new(web_search: Option<WebSearchConfig>, sandbox: Option<SandboxConfig>, github: Option<GithubConfig>) -> Self {
+        Self { web_search, sandbox, github, ..Self::default() }
+    }
+
+    pub fn set_local_only(&self, local_only: bool) {
+        self.local_only.store(local_only, Ordering::Relaxed);
+    }
+
+    pub fn set_root(&self, root: &Path) {
+        *self.root.lock().unwrap() = Some(root.canonicalize().unwrap_or_else(|_| root.to_path_buf()));
+    }
+
+    fn root(&self) -> PathBuf {
+        self.root.lock().unwrap().clone().unwrap_or_else(|| Path::new(".").canonicalize().unwrap_or_else(|_| PathBuf::from(".")))
+    }
+
+    pub fn add_server(&self, server: Arc<McpServer>) {
+        self.servers.lock().unwrap().push(server);
+    }
+
+    pub fn add_plugin(&self, plugin: Arc<Plugin>) {
+        self.plugins.lock().unwrap().push(plugin);
+    }
+
+    /// The plugin registering the slash command `name`, if any.
+    pub fn plugin_for_command(&self, name: &str) -> Option<Arc<Plugin>> {
+        self.plugins.lock().unwrap().iter().find(|p| p.commands.iter().any(|c| c.name == name)).cloned()
+    }
+
+    pub fn plugin_command_description(&self, name: &str) -> Option<String> {
+        let plugins = self.plugins.lock().unwrap();
+        plugins.iter().flat_map(|p| &p.commands).find(|c| c.name == name).map(|c| c.description.clone())
+    }
+
+    pub fn plugin_commands(&self) -> Vec<String> {
+        self.plugins.lock().unwrap().iter().flat_map(|p| p.commands.iter().map(|c| c.name.clone())).collect()
+    }
+
+    fn builtin_specs(&self) -> Vec<ChatCompletionTool> {
+        let mut specs = vec![function(
+            GREP.to_string(),
+            "Search the project's files for a fixed string (case-insensitive when all lowercase). Returns path:line: text for each match, capped at 100. Ignored and hidden files are skipped.".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "pattern": { "type": "string" },
+                    "glob": { "type": "string", "description": "Only files whose name (or path, if it has a /) matches, e.g. *.rs" },
+                    "path": { "type": "string", "description": "Subdirectory of the project to search" },
+                },
+                "required": ["pattern"],
+            }),
+        )];
+        if self.sandbox.is_some() {
+            specs.push(function(
+                RUN_CODE.to_string(),
+                format!(
+                    "Run a short program in a scratch directory under resource limits and return its exit code, stdout and stderr. Languages: {}.",
+                    sandbox::languages().join(", ")
+                ),
+                json!({
+                    "type": "object",
+                    "properties": { "language": { "type": "string" }, "source": { "type": "string" } },
+                    "required": ["language", "source"],
+                }),
+            ));
+        }
+        if self.local_only.load(Ordering::Relaxed) {
+            return specs;
+        }
+        specs.push(function(
+            FETCH_URL.to_string(),
+            "Download a web page and return its readable text (truncated for long pages).".to_string(),
+            json!({
+                "type": "object",
+                "properties": { "url": { "type": "string" } },
+                "required": ["url"],
+            }),
+        ));
+        if self.web_search.is_some() {
+            specs.push(function(
+                WEB_SEARCH.to_string(),
+                "Search the web. Returns titles, URLs and snippets of the top results.".to_string(),
+                json!({
+                    "type": "object",
+                    "properties": { "query": { "type": "string" } },
+                    "required": ["query"],
+                }),
+            ));
+        }
+        if let Some(repo) = self.github.as_ref().and_then(|config| config.repo().ok()) {
+            let number = json!({
+                "type": "object",
+                "properties": { "number": { "type": "integer" } },
+                "required": ["number"],
+            });
+            specs.push(function(
+                GET_ISSUE.to_string(),
+                format!("Read an issue or pull request of the GitHub repository {} with its labels and comments.", repo),
+                number.clone(),
+            ));
+            specs.push(function(GET_PR_DIFF.to_string(), format!("Read a pull request of {} with its description and diff.", repo), number));
+            specs.push(function(
+                COMMENT.to_string(),
+                format!("Post a Markdown comment on an issue or pull request of {}. The user confirms it first.", repo),
+                json!({
+                    "type": "object",
+                    "properties": { "number": { "type": "integer" }, "body": { "type": "string" } },
+                    "required": ["number", "body"],
+                }),
+            ));
+        }
+        specs
+    }
+
+    async fn call_builtin(&self, name: &str, args: &Value, host: &dyn ToolHost) -> Option<Result<String>> {
+        let local_only = self.local_only.load(Ordering::Relaxed);
+        match name {
+            GREP => {
+                let pattern = args["pattern"].as_str().unwrap_or_default().to_string();
+                let glob = args["glob"].as_str().map(str::to_string);
+                let path = args["path"].as_str().map(str::to_string);
+                let root = self.root();
+                let search = tokio::task::spawn_blocking(move || search::grep(&root, &pattern, glob.as_deref(), path.as_deref()));
+                Some(search.await.map_err(|e| anyhow!(e)).and_then(|r| r))
+            }
+            RUN_CODE => {
+                let config = self.sandbox.as_ref()?;
+                let language = args["language"].as_str().unwrap_or_default();
+                let source = args["source"].as_str().unwrap_or_default();
+                let question = format!("Run this {} snippet ({} lines)?\n\n{}", language, source.lines().count(), source);
+                if !host.confirm(question).await.unwrap_or(false) {
+                    return Some(Ok("Cancelled by the user.".to_string()));
+                }
+                let result = sandbox::run(config, language, source).await;
+                if let Ok(output) = &result {
+                    host.notify(format!("▶️ run_code ({})\n{}", language, output));
+                }
+                Some(result)
+            }
+            _ if local_only => None,
+            WEB_SEARCH => {
+                let config = self.web_search.as_ref()?;
+                let query = args["query"].as_str().unwrap_or_default();
+                Some(web::search(config, query).await.map(|results| {
+                    let text: Vec<String> = results
+                        .iter()
+                        .enumerate()
+                        .map(|(i, r)| format!("{}. {}\n   {}\n   {}", i + 1, r.title, r.url, r.snippet))
+                        .collect();
+                    let text = if text.is_empty() { "No results.".to_string() } else { text.join("\n") };
+                    host.notify(format!("🔎 {}\n{}", query, text));
+                    text
+                }))
+            }
+            FETCH_URL => Some(web::fetch(args["url"].as_str().unwrap_or_default()).await),
+            GET_ISSUE | GET_PR_DIFF | COMMENT => {
+                let config = self.github.as_ref()?;
+                let Some(number) = args["number"].as_u64() else {
+                    return Some(Err(anyhow!("\"number\" must be an issue or pull request number")));
+                };
+                Some(match name {
+                    GET_ISSUE => github::issue(config, number).await,
+                    GET_PR_DIFF => github::pr_diff(config, number).await,
+                    _ => {
+                        let body = args["body"].as_str().unwrap_or_default();
+                        if !host.confirm(format!("Post this comment on #{}?\n\n{}", number, body)).await.unwrap_or(false) {
+                            return Some(Ok("Cancelled by the user.".to_string()));
+                        }
+                        let posted = github::comment(config, number, body).await;
+                        if let Ok(url) = &posted {
+                            host.notify(format!("💬 Commented on #{}: {}", number, url));
+                        }
+                        posted.map(|url| format!("Posted: {}", url))
+                    }
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn specs(&self) -> Vec<ChatCompletionTool> {
+        let mut specs = self.builtin_specs();
+        specs.extend(self
+            .plugins
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|p| p.tools.iter().map(|t| function(sanitize(&t.name), t.description.clone(), t.parameters.clone()))),
+        );
+        let servers = self.servers.lock().unwrap();
+        for server in servers.iter() {
+            for tool in &server.tools {
+                let name = sanitize(&format!("{}{}{}", server.name, MCP_SEPARATOR, tool.name));
+                specs.push(function(name, tool.description.clone(), tool.input_schema.clone()));
+            }
+        }
+        let resources: Vec<String> = servers
+            .iter()
+            .flat_map(|s| s.resources.iter().map(move |r| format!("{} {} ({})", s.name, r.uri, r.name)))
+            .take(50)
+            .collect();
+        if !resources.is_empty() {
+            specs.push(function(
+                READ_RESOURCE.to_string(),
+                format!("Read a resource from an MCP server. Available (server uri (name)):\n{}", resources.join("\n")),
+                json!({
+                    "type": "object",
+                    "properties": { "server": { "type": "string" }, "uri": { "type": "string" } },
+                    "required": ["server", "uri"],
+                }),
+            ));
+        }
+        specs.retain(|spec| project::current().tools.allows(&spec.function.name));
+        specs
+    }
+
+    pub async fn call(&self, name: &str, arguments: &str, host: &dyn ToolHost) -> Result<String> {
+        if !project::current().tools.allows(name) {
+            bail!("the tool {} is not allowed in this project ({})", name, project::PROJECT_FILE);
+        }
+        let plugin = self.plugins.lock().unwrap().iter().find(|p| p.tools.iter().any(|t| sanitize(&t.name) == name)).cloned();
+        if let Some(plugin) = plugin {
+            let tool = plugin.tools.iter().find(|t| sanitize(&t.name) == name).map(|t| t.name.clone()).unwrap_or_default();
+            return plugin.run("tool", &tool, arguments, host).await;
+        }
+        let args: Value = if arguments.trim().is_empty() { json!({}) } else { serde_json::from_str(arguments)? };
+        if let Some(result) = self.call_builtin(name, &args, host).await {
+            return result;
+        }
+        if name == READ_RESOURCE {
+            let server = self.server(|s| Some(s.name.as_str()) == args["server"].as_str())?;
+            return server.read_resource(args["uri"].as_str().unwrap_or_default()).await;
+        }
+        let (server, tool) = {
+            let servers = self.servers.lock().unwrap();
+            servers
+                .iter()
+                .find_map(|s| {
+                    s.tools
+                        .iter()
+                        .find(|t| sanitize(&format!("{}{}{}", s.name, MCP_SEPARATOR, t.name)) == name)
+                        .map(|t| (s.clone(), t.name.clone()))
+                })
+                .ok_or_else(|| anyhow!("unknown tool '{}'", name))?
+        };
+        server.call_tool(&tool, args).await
+    }
+
+    fn server(&self, pred: impl Fn(&McpServer) -> bool) -> Result<Arc<McpServer>> {
+        self.servers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| pred(s))
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown MCP server"))
+    }
+}
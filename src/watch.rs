@@ -0,0 +1,124 @@
+//! `gentor watch`: waits for file system events and re-runs a prompt over the
+//! changed content, e.g. to keep explaining test output or lint a document.
+use anyhow::{bail, Result};
+use notify_debouncer_full::{new_debouncer, notify::RecursiveMode, DebounceEventResult};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    agent,
+    context::{Attachment, Filter},
+    i18n,
+    ignore::IgnoreRules,
+    settings::Settings,
+};
+
+/// Editors often write a file in several steps; changes are gathered until
+/// none has come for this long.
+const SETTLE: Duration = Duration::from_millis(300);
+const WATCH_PROMPT: &str = "The user is editing the files below and wants their instruction applied each time the files are saved. Be concise, and say so in one line when there is nothing to report.";
+
+/// Watches `path` (a file or a directory) until interrupted, sending
+/// `prompt` with every changed file to the model and printing the answer.
+pub async fn run(settings: Settings, path: &str, prompt: &str) -> Result<()> {
+    if !Path::new(path).exists() {
+        bail!("{}", i18n::fill("watch.missing", &[&path]));
+    }
+    let settings = Settings { response_format: None, ..settings };
+    let client = agent::client_for(&settings);
+    let filter = Filter::new(Path::new("."), &settings);
+    let rules = IgnoreRules::load(Path::new("."));
+    let root = std::env::current_dir()?;
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(SETTLE, None, move |result: DebounceEventResult| {
+        let _ = events.send(result);
+    })?;
+    debouncer.watch(path, RecursiveMode::Recursive)?;
+    println!("{}", i18n::fill("watch.watching", &[&path, &count(path, &rules)]));
+    // changes made while a request runs queue up and trigger the next one
+    while let Some(result) = received.recv().await {
+        let mut changed: Vec<String> = match result {
+            // reading a file is an event too, so only writes count
+            Ok(events) => events
+                .iter()
+                .filter(|event| event.kind.is_create() || event.kind.is_modify())
+                .flat_map(|event| &event.paths)
+                .filter_map(|path| relative(&root, path))
+                .filter(|file| watched(file, &rules))
+                .collect(),
+            Err(errors) => {
+                for e in errors {
+                    println!("{}", i18n::fill("error", &[&e]));
+                }
+                continue;
+            }
+        };
+        changed.sort();
+        changed.dedup();
+        let mut attachments = Vec::new();
+        for file in &changed {
+            match Attachment::load(file, None, &filter) {
+                Ok(attachment) => attachments.push(attachment.fenced()),
+                Err(e) => println!("{}", i18n::fill("error", &[&e])),
+            }
+        }
+        if attachments.is_empty() {
+            continue;
+        }
+        println!("\n{}", i18n::fill("watch.changed", &[&changed.join(", ")]));
+        let text = format!("{}\n\n{}", prompt, attachments.join("\n\n"));
+        match agent::complete(&client, &settings, WATCH_PROMPT, &text).await {
+            Ok(answer) => println!("{}", answer),
+            Err(e) => println!("{}", i18n::fill("error", &[&e])),
+        }
+    }
+    Ok(())
+}
+
+/// `path` relative to `root`, `/`-separated, as the ignore rules and
+/// attachments expect it.
+fn relative(root: &Path, path: &Path) -> Option<String> {
+    let path: PathBuf = path.strip_prefix(root).unwrap_or(path).components().collect();
+    let path = path.to_str()?.replace('\\', "/");
+    Some(path.trim_start_matches("./").to_string())
+}
+
+/// Whether a change to `file` is reported: it still exists and is neither
+/// hidden nor ignored.
+fn watched(file: &str, rules: &IgnoreRules) -> bool {
+    !file.split('/').any(|part| part.starts_with('.')) && !rules.is_ignored(file, false) && Path::new(file).is_file()
+}
+
+/// Files at `path` that changes are reported for.
+fn count(path: &str, rules: &IgnoreRules) -> usize {
+    let path = path.trim_start_matches("./").trim_end_matches('/');
+    if path.is_empty() || path == "." {
+        walk(Path::new("."), "", rules)
+    } else if Path::new(path).is_dir() {
+        walk(Path::new(path), &format!("{}/", path), rules)
+    } else {
+        1
+    }
+}
+
+fn walk(dir: &Path, rel: &str, rules: &IgnoreRules) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else { return 0 };
+    let mut files = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{}{}", rel, name);
+        let is_dir = entry.path().is_dir();
+        if name.starts_with('.') || rules.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            files += walk(&entry.path(), &format!("{}/", path), rules);
+        } else {
+            files += 1;
+        }
+    }
+    files
+}
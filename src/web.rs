@@ -0,0 +1,254 @@
+//! Web access for the agent: search through a configured provider and
+//! fetching pages as readable text.
+use anyhow::{bail, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const MAX_RESULTS: usize = 5;
+/// Pages are cut off after this many downloaded bytes.
+const MAX_DOWNLOAD: usize = 2 * 1024 * 1024;
+/// Text handed back to the model is truncated to this many characters.
+const MAX_TEXT: usize = 20_000;
+
+/// `provider` is `searxng` (needs `url`), `brave` or `tavily` (both need `api_key`).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebSearchConfig {
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+fn results(list: &Value, snippet_key: &str) -> Vec<SearchResult> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .take(MAX_RESULTS)
+        .map(|r| SearchResult {
+            title: r["title"].as_str().unwrap_or_default().to_string(),
+            url: r["url"].as_str().unwrap_or_default().to_string(),
+            snippet: r[snippet_key].as_str().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+pub async fn search(config: &WebSearchConfig, query: &str) -> Result<Vec<SearchResult>> {
+    let http = reqwest::Client::new();
+    let api_key = config.api_key.as_deref().unwrap_or_default();
+    match config.provider.as_str() {
+        "searxng" => {
+            let Some(url) = config.url.as_deref() else { bail!("searxng needs a url") };
+            let body: Value = http
+                .get(format!("{}/search", url.trim_end_matches('/')))
+                .query(&[("q", query), ("format", "json")])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            Ok(results(&body["results"], "content"))
+        }
+        "brave" => {
+            let body: Value = http
+                .get("https://api.search.brave.com/res/v1/web/search")
+                .query(&[("q", query)])
+                .header("X-Subscription-Token", api_key)
+                .header("Accept", "application/json")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            Ok(results(&body["web"]["results"], "description"))
+        }
+        "tavily" => {
+            let body: Value = http
+                .post("https://api.tavily.com/search")
+                .json(&json!({ "api_key": api_key, "query": query, "max_results": MAX_RESULTS }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            Ok(results(&body["results"], "content"))
+        }
+        other => bail!("unknown search provider '{}'", other),
+    }
+}
+
+/// Downloads `url` and reduces HTML to markdown-ish text, within size limits.
+pub async fn fetch(url: &str) -> Result<String> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("User-Agent", concat!("gentor/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await?
+        .error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !(content_type.is_empty() || content_type.starts_with("text/") || content_type.contains("json") || content_type.contains("xml")) {
+        bail!("{} is {}, not text", url, content_type);
+    }
+
+    let mut body = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk?);
+        if body.len() >= MAX_DOWNLOAD {
+            body.truncate(MAX_DOWNLOAD);
+            break;
+        }
+    }
+    let body = String::from_utf8_lossy(&body);
+    let text = if content_type.contains("html") || body.trim_start().starts_with('<') { html_to_text(&body) } else { body.into_owned() };
+
+    if text.chars().count() > MAX_TEXT {
+        let cut: String = text.chars().take(MAX_TEXT).collect();
+        return Ok(format!("{}\n\n[truncated at {} characters]", cut, MAX_TEXT));
+    }
+    Ok(text)
+}
+
+/// Strips markup from an HTML page, keeping headings, list items, links and
+/// code blocks in markdown form and dropping scripts, styles and page chrome.
+pub fn html_to_text(html: &str) -> String {
+    const SKIP: &[&str] = &["script", "style", "head", "nav", "footer", "noscript", "svg", "iframe"];
+    let mut out = String::new();
+    let mut skip_depth = 0usize;
+    let mut pre = false;
+    let mut href: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skip_depth == 0 {
+            push_text(&mut out, &rest[..start], pre);
+        }
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else { break };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if SKIP.contains(&name.as_str()) {
+            if closing {
+                skip_depth = skip_depth.saturating_sub(1);
+            } else if !tag.ends_with('/') {
+                skip_depth += 1;
+            }
+            continue;
+        }
+        if skip_depth > 0 {
+            continue;
+        }
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                let level = name[1..].parse().unwrap_or(1);
+                out.push_str(&format!("\n\n{} ", "#".repeat(level)));
+            }
+            ("p" | "div" | "section" | "article" | "table" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6", _) => out.push_str("\n\n"),
+            ("br" | "tr", _) => out.push('\n'),
+            ("li", false) => out.push_str("\n- "),
+            ("pre", false) => {
+                pre = true;
+                out.push_str("\n\n```\n");
+            }
+            ("pre", true) => {
+                pre = false;
+                out.push_str("\n```\n\n");
+            }
+            ("code", _) if !pre => out.push('`'),
+            ("a", false) => href = attribute(tag, "href"),
+            ("a", true) => {
+                if let Some(href) = href.take().filter(|h| h.starts_with("http")) {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+            _ => {}
+        }
+    }
+    if skip_depth == 0 {
+        push_text(&mut out, rest, pre);
+    }
+
+    // collapse the blank lines left behind by nested block elements
+    let mut text = String::new();
+    let mut blank = 0;
+    for line in out.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.trim().to_string()
+}
+
+fn push_text(out: &mut String, text: &str, pre: bool) {
+    let text = decode_entities(text);
+    if pre {
+        out.push_str(&text);
+        return;
+    }
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+        out.push(' ');
+    }
+    out.push_str(&collapsed);
+    if text.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(char::is_whitespace).next()?,
+    };
+    Some(decode_entities(value))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,110 @@
+//! Wrapping chat text to the pane width before drawing, so row counts (and
+//! with them scrolling) are exact after a resize and code wraps between
+//! tokens instead of in the middle of one.
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::editing::width;
+
+/// Splits `text` into rows of at most `columns`, breaking after whitespace
+/// or, in `code`, also between identifiers and punctuation. Only a piece
+/// wider than a whole row is cut, between graphemes.
+pub fn wrap(text: &str, columns: usize, code: bool) -> Vec<String> {
+    let columns = columns.max(1);
+    if width(text) <= columns {
+        return vec![text.to_string()];
+    }
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut used = 0;
+    for piece in pieces(text, code) {
+        let size = width(piece);
+        // trailing whitespace may hang past the edge
+        if used + width(piece.trim_end()) > columns && used > 0 {
+            rows.push(std::mem::take(&mut row));
+            used = 0;
+        }
+        if width(piece.trim_end()) <= columns {
+            row.push_str(piece);
+            used += size;
+            continue;
+        }
+        for grapheme in piece.graphemes(true) {
+            let size = width(grapheme);
+            if used + size > columns && used > 0 {
+                rows.push(std::mem::take(&mut row));
+                used = 0;
+            }
+            row.push_str(grapheme);
+            used += size;
+        }
+    }
+    rows.push(row);
+    rows
+}
+
+#[derive(PartialEq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    if c.is_whitespace() {
+        Class::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+/// Runs that stay on one row, each carrying the whitespace after it.
+fn pieces(text: &str, code: bool) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let Some(&(next_at, next)) = chars.peek() else { break };
+        let (here, there) = (class(c), class(next));
+        let boundary = there != Class::Space
+            && (here == Class::Space || (code && (here != there || here == Class::Punctuation)));
+        if boundary {
+            pieces.push(&text[start..next_at]);
+            start = next_at;
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_one_row() {
+        assert_eq!(wrap("hello", 10, false), ["hello"]);
+        assert_eq!(wrap("", 10, false), [""]);
+    }
+
+    #[test]
+    fn prose_breaks_after_whitespace() {
+        assert_eq!(wrap("the quick brown fox", 10, false), ["the quick ", "brown fox"]);
+        assert_eq!(wrap("a  b", 1, false), ["a  ", "b"]);
+    }
+
+    #[test]
+    fn code_breaks_between_tokens() {
+        assert_eq!(wrap("foo.bar(baz)", 8, true), ["foo.bar(", "baz)"]);
+        assert_eq!(wrap("foo.bar(baz)", 8, false), ["foo.bar(", "baz)"]);
+        assert_eq!(wrap("let value = compute();", 12, true), ["let value = ", "compute();"]);
+    }
+
+    #[test]
+    fn only_words_wider_than_a_row_are_cut() {
+        assert_eq!(wrap("abcdefghij", 4, false), ["abcd", "efgh", "ij"]);
+        // wide characters are never split across rows
+        assert_eq!(wrap("日本語です", 5, false), ["日本", "語で", "す"]);
+    }
+}